@@ -0,0 +1,250 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Derive macros for `Envoy SDK`.
+//!
+//! Not meant to be used directly; enable `derive` feature of `envoy-sdk` instead.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr, Path};
+
+/// Generates constructors of a struct with `Envoy` metrics
+/// out of a [`Stats`] implementation.
+///
+/// Every field of the struct becomes a metric named after the field.
+/// Metric type is inferred from the field type, e.g. `Box<dyn Counter>`,
+/// `Rc<dyn Gauge>` or `Box<dyn Histogram>`.
+///
+/// The following constructors are generated:
+///
+/// * `new(stats: &dyn Stats) -> Result<Self>` that uses the prefix configured on the struct.
+/// * `with_prefix(stats: &dyn Stats, prefix: &str) -> Result<Self>` that uses a given prefix instead.
+///
+/// # Attributes
+///
+/// On the struct:
+///
+/// * `#[stats(prefix = "...")]` - prefix of metric names, e.g. `examples.http_filter`;
+///   a `.` separator is added automatically.
+/// * `#[stats(crate = "...")]` - path to `envoy-sdk` crate, `envoy` by default.
+///
+/// On a field:
+///
+/// * `#[stats(name = "...")]` - metric name to use instead of the field name.
+/// * `#[stats(counter)]`, `#[stats(gauge)]`, `#[stats(histogram)]` - metric type
+///   to use when it cannot be inferred from the field type.
+///
+/// # Examples
+///
+/// ```
+/// use envoy::host::stats::{Counter, Gauge, Histogram, Stats};
+///
+/// #[derive(Stats)]
+/// #[stats(prefix = "examples.http_filter")]
+/// pub struct SampleHttpFilterStats {
+///     requests_total: Box<dyn Counter>,
+///     requests_active: Box<dyn Gauge>,
+///     #[stats(name = "response_body_size_bytes")]
+///     response_body_size: Box<dyn Histogram>,
+/// }
+///
+/// # fn action() -> envoy::host::Result<()> {
+/// let stats = SampleHttpFilterStats::new(Stats::default())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Stats`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/host/stats/trait.Stats.html
+#[proc_macro_derive(Stats, attributes(stats))]
+pub fn derive_stats(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_stats(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Kind of a metric.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+    fn from_ident(ident: &str) -> Option<Self> {
+        match ident {
+            "Counter" => Some(MetricKind::Counter),
+            "Gauge" => Some(MetricKind::Gauge),
+            "Histogram" => Some(MetricKind::Histogram),
+            _ => None,
+        }
+    }
+
+    fn method(self) -> syn::Ident {
+        let name = match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        };
+        syn::Ident::new(name, Span::call_site())
+    }
+}
+
+fn expand_stats(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut prefix = String::new();
+    let mut krate: Path = syn::parse_quote!(envoy);
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("stats"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                prefix = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported attribute; expected `prefix` or `crate`"))
+            }
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "`#[derive(Stats)]` only supports structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`#[derive(Stats)]` only supports structs",
+            ))
+        }
+    };
+
+    let mut initializers = Vec::with_capacity(fields.len());
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let mut name = ident.to_string();
+        let mut kind = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("stats"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    return Ok(());
+                }
+                let explicit =
+                    meta.path
+                        .get_ident()
+                        .and_then(|ident| match ident.to_string().as_str() {
+                            "counter" => Some(MetricKind::Counter),
+                            "gauge" => Some(MetricKind::Gauge),
+                            "histogram" => Some(MetricKind::Histogram),
+                            _ => None,
+                        });
+                match explicit {
+                    Some(explicit) => {
+                        kind = Some(explicit);
+                        Ok(())
+                    }
+                    None => Err(meta.error(
+                        "unsupported attribute; expected `name`, `counter`, `gauge` or `histogram`",
+                    )),
+                }
+            })?;
+        }
+        let kind = match kind {
+            Some(kind) => kind,
+            None => infer_kind(&field.ty)?,
+        };
+        let method = kind.method();
+        initializers.push(quote! {
+            #ident: ::core::convert::Into::into(stats.#method(&stat_name(#name))?)
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Creates metrics through a given `Stats` implementation.
+            pub fn new(stats: &dyn #krate::host::stats::Stats) -> #krate::host::Result<Self> {
+                Self::with_prefix(stats, #prefix)
+            }
+
+            /// Creates metrics through a given `Stats` implementation
+            /// using a given prefix for metric names.
+            pub fn with_prefix(
+                stats: &dyn #krate::host::stats::Stats,
+                prefix: &str,
+            ) -> #krate::host::Result<Self> {
+                let prefix = prefix.trim_end_matches('.');
+                let stat_name = |name: &str| -> ::std::string::String {
+                    if prefix.is_empty() {
+                        ::std::string::String::from(name)
+                    } else {
+                        ::std::format!("{}.{}", prefix, name)
+                    }
+                };
+                ::core::result::Result::Ok(#ident {
+                    #(#initializers,)*
+                })
+            }
+        }
+    })
+}
+
+/// Infers metric kind from the field type, e.g. `Box<dyn Counter>`.
+fn infer_kind(ty: &syn::Type) -> syn::Result<MetricKind> {
+    fn visit(tokens: proc_macro2::TokenStream, kinds: &mut Vec<MetricKind>) {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident) => {
+                    if let Some(kind) = MetricKind::from_ident(&ident.to_string()) {
+                        if !kinds.contains(&kind) {
+                            kinds.push(kind);
+                        }
+                    }
+                }
+                TokenTree::Group(group) => visit(group.stream(), kinds),
+                _ => {}
+            }
+        }
+    }
+    let mut kinds = Vec::new();
+    visit(ty.to_token_stream(), &mut kinds);
+    match kinds.as_slice() {
+        [kind] => Ok(*kind),
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            "cannot infer metric type; use `#[stats(counter)]`, `#[stats(gauge)]` or `#[stats(histogram)]`",
+        )),
+    }
+}
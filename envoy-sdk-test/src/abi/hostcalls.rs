@@ -0,0 +1,565 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Host functions of the fake `Proxy Wasm` ABI.
+//!
+//! Every function mirrors the signature of the import declared by `Proxy Wasm` SDK
+//! or by `Envoy` SDK itself, and returns `Status` as its underlying `u32` value.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::slice;
+use std::time::{Duration, UNIX_EPOCH};
+
+use envoy::host::log::LogLevel;
+use envoy::host::ByteString;
+
+use super::{decode_map, encode_map, stream_type, with_host};
+use crate::extension::filter::http::FakeLocalReply;
+use crate::host::http::client::FakeHttpClientRequest;
+use crate::host::http::FakeHttpMessage;
+
+/// Values of `Status` returned by the host functions.
+mod status {
+    pub const OK: u32 = 0;
+    pub const NOT_FOUND: u32 = 1;
+    pub const BAD_ARGUMENT: u32 = 2;
+    pub const EMPTY: u32 = 7;
+    pub const CAS_MISMATCH: u32 = 8;
+    pub const INTERNAL_FAILURE: u32 = 10;
+}
+
+/// Returns `true` if a given host function has been configured to fail.
+fn is_rejected(function: &str) -> bool {
+    with_host(|host| host.rejected_calls.contains(&function))
+}
+
+unsafe fn bytes<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    if size == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, size)
+    }
+}
+
+unsafe fn string(data: *const u8, size: usize) -> String {
+    String::from_utf8_lossy(bytes(data, size)).into_owned()
+}
+
+/// Hands over ownership of a given value to the extension, which is expected to free it.
+unsafe fn give(value: Option<Vec<u8>>, return_data: *mut *mut u8, return_size: *mut usize) {
+    match value {
+        Some(value) => {
+            *return_size = value.len();
+            *return_data = Box::into_raw(value.into_boxed_slice()) as *mut u8;
+        }
+        None => {
+            *return_size = 0;
+            *return_data = std::ptr::null_mut();
+        }
+    }
+}
+
+// Logging
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_log(
+    level: LogLevel,
+    message_data: *const u8,
+    message_size: usize,
+) -> u32 {
+    let message = string(message_data, message_size);
+    if let LogLevel::Critical = level {
+        // keep panic messages visible in the test output
+        eprintln!("{}", message);
+    }
+    with_host(|host| host.logs.push((level, message)));
+    status::OK
+}
+
+// Time
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_current_time_nanoseconds(return_time: *mut u64) -> u32 {
+    let time = with_host(|host| host.time);
+    *return_time = time.duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+    status::OK
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_set_tick_period_milliseconds(period: u32) -> u32 {
+    with_host(|host| host.effective_context().tick_period = Duration::from_millis(period as u64));
+    status::OK
+}
+
+// Buffers
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_buffer_bytes(
+    buffer_type: u32,
+    start: usize,
+    max_size: usize,
+    return_buffer_data: *mut *mut u8,
+    return_buffer_size: *mut usize,
+) -> u32 {
+    let value = with_host(|host| {
+        host.buffers.get(&buffer_type).map(|buf| {
+            let start = start.min(buf.len());
+            let end = start.saturating_add(max_size).min(buf.len());
+            buf[start..end].to_vec()
+        })
+    });
+    match value {
+        Some(value) => {
+            give(Some(value), return_buffer_data, return_buffer_size);
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_buffer_bytes(
+    buffer_type: u32,
+    start: usize,
+    size: usize,
+    buffer_data: *const u8,
+    buffer_size: usize,
+) -> u32 {
+    let data = bytes(buffer_data, buffer_size);
+    with_host(|host| {
+        let buf = host.buffers.entry(buffer_type).or_default();
+        let start = start.min(buf.len());
+        let end = start.saturating_add(size).min(buf.len());
+        buf.splice(start..end, data.iter().cloned());
+    });
+    status::OK
+}
+
+// Header maps
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_header_map_pairs(
+    map_type: u32,
+    return_map_data: *mut *mut u8,
+    return_map_size: *mut usize,
+) -> u32 {
+    let value = with_host(|host| host.maps.get(&map_type).map(encode_map));
+    give(value, return_map_data, return_map_size);
+    status::OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_header_map_pairs(
+    map_type: u32,
+    map_data: *const u8,
+    map_size: usize,
+) -> u32 {
+    let map = decode_map(bytes(map_data, map_size));
+    with_host(|host| host.maps.insert(map_type, map));
+    status::OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_header_map_value(
+    map_type: u32,
+    key_data: *const u8,
+    key_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> u32 {
+    let key = ByteString::from(bytes(key_data, key_size));
+    let value = with_host(|host| {
+        host.maps
+            .get(&map_type)
+            .and_then(|map| map.get(&key))
+            .map(|value| value.as_bytes().to_vec())
+    });
+    match value {
+        Some(value) => {
+            give(Some(value), return_value_data, return_value_size);
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_replace_header_map_value(
+    map_type: u32,
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> u32 {
+    let key = ByteString::from(bytes(key_data, key_size));
+    let value = ByteString::from(bytes(value_data, value_size));
+    with_host(|host| host.maps.entry(map_type).or_default().insert(key, value));
+    status::OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_remove_header_map_value(
+    map_type: u32,
+    key_data: *const u8,
+    key_size: usize,
+) -> u32 {
+    let key = ByteString::from(bytes(key_data, key_size));
+    with_host(|host| host.maps.entry(map_type).or_default().remove(&key));
+    status::OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_add_header_map_value(
+    map_type: u32,
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> u32 {
+    proxy_replace_header_map_value(map_type, key_data, key_size, value_data, value_size)
+}
+
+// Properties
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_property(
+    path_data: *const u8,
+    path_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> u32 {
+    let path = bytes(path_data, path_size);
+    let value = with_host(|host| {
+        if path == b"plugin_root_id" {
+            host.plugin_root_id()
+                .map(|root_id| root_id.as_bytes().to_vec())
+        } else {
+            host.properties.get(path).cloned()
+        }
+    });
+    match value {
+        Some(value) => {
+            give(Some(value), return_value_data, return_value_size);
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_property(
+    path_data: *const u8,
+    path_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> u32 {
+    let path = bytes(path_data, path_size).to_vec();
+    let value = bytes(value_data, value_size).to_vec();
+    with_host(|host| host.properties.insert(path, value));
+    status::OK
+}
+
+// Shared data
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_shared_data(
+    key_data: *const u8,
+    key_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+    return_cas: *mut u32,
+) -> u32 {
+    let key = string(key_data, key_size);
+    match with_host(|host| host.shared_data.get(&key).cloned()) {
+        Some((value, cas)) => {
+            give(Some(value), return_value_data, return_value_size);
+            *return_cas = cas;
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_shared_data(
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+    cas: u32,
+) -> u32 {
+    let key = string(key_data, key_size);
+    let value = bytes(value_data, value_size).to_vec();
+    // implementation based on `Envoy`: `cas` is only checked against an existing entry
+    with_host(|host| match host.shared_data.get_mut(&key) {
+        Some(entry) if cas != 0 && cas != entry.1 => status::CAS_MISMATCH,
+        Some(entry) => {
+            *entry = (value, entry.1 + 1);
+            status::OK
+        }
+        None => {
+            host.shared_data.insert(key, (value, 1));
+            status::OK
+        }
+    })
+}
+
+// Shared queues
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_register_shared_queue(
+    name_data: *const u8,
+    name_size: usize,
+    return_id: *mut u32,
+) -> u32 {
+    let name = string(name_data, name_size);
+    *return_id = with_host(
+        |host| match host.shared_queues.iter().position(|(n, _)| *n == name) {
+            Some(index) => index,
+            None => {
+                host.shared_queues.push((name, Default::default()));
+                host.shared_queues.len() - 1
+            }
+        },
+    ) as u32
+        + 1;
+    status::OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_resolve_shared_queue(
+    _vm_id_data: *const u8,
+    _vm_id_size: usize,
+    name_data: *const u8,
+    name_size: usize,
+    return_id: *mut u32,
+) -> u32 {
+    let name = string(name_data, name_size);
+    match with_host(|host| host.shared_queues.iter().position(|(n, _)| *n == name)) {
+        Some(index) => {
+            *return_id = index as u32 + 1;
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_dequeue_shared_queue(
+    queue_id: u32,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> u32 {
+    let value = with_host(|host| {
+        host.shared_queues
+            .get_mut((queue_id as usize).wrapping_sub(1))
+            .map(|(_, queue)| queue.pop_front())
+    });
+    match value {
+        Some(Some(value)) => {
+            give(Some(value), return_value_data, return_value_size);
+            status::OK
+        }
+        Some(None) => status::EMPTY,
+        None => status::NOT_FOUND,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_enqueue_shared_queue(
+    queue_id: u32,
+    value_data: *const u8,
+    value_size: usize,
+) -> u32 {
+    let value = bytes(value_data, value_size).to_vec();
+    with_host(|host| {
+        match host
+            .shared_queues
+            .get_mut((queue_id as usize).wrapping_sub(1))
+        {
+            Some((_, queue)) => {
+                queue.push_back(value);
+                status::OK
+            }
+            None => status::NOT_FOUND,
+        }
+    })
+}
+
+// Stream control
+
+#[no_mangle]
+pub extern "C" fn proxy_continue_stream(stream: u32) -> u32 {
+    if is_rejected("proxy_continue_stream") {
+        return status::INTERNAL_FAILURE;
+    }
+    with_host(|host| {
+        let context = host.effective_context();
+        match stream {
+            stream_type::REQUEST => context.resumed_requests += 1,
+            stream_type::RESPONSE => context.resumed_responses += 1,
+            _ => return status::BAD_ARGUMENT,
+        }
+        status::OK
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_close_stream(stream: u32) -> u32 {
+    if is_rejected("proxy_close_stream") {
+        return status::INTERNAL_FAILURE;
+    }
+    with_host(|host| host.effective_context().closed_streams.push(stream));
+    status::OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_send_local_response(
+    status_code: u32,
+    _status_code_details_data: *const u8,
+    _status_code_details_size: usize,
+    body_data: *const u8,
+    body_size: usize,
+    headers_data: *const u8,
+    headers_size: usize,
+    _grpc_status: i32,
+) -> u32 {
+    if is_rejected("proxy_send_local_response") {
+        return status::INTERNAL_FAILURE;
+    }
+    let reply = FakeLocalReply {
+        status_code,
+        headers: decode_map(bytes(headers_data, headers_size)),
+        body: bytes(body_data, body_size).into(),
+    };
+    with_host(|host| host.effective_context().local_reply = Some(reply));
+    status::OK
+}
+
+// HTTP Client
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_http_call(
+    upstream_data: *const u8,
+    upstream_size: usize,
+    headers_data: *const u8,
+    headers_size: usize,
+    body_data: *const u8,
+    body_size: usize,
+    trailers_data: *const u8,
+    trailers_size: usize,
+    timeout: u32,
+    return_token: *mut u32,
+) -> u32 {
+    let request = FakeHttpClientRequest {
+        upstream: string(upstream_data, upstream_size),
+        message: FakeHttpMessage {
+            headers: decode_map(bytes(headers_data, headers_size)),
+            body: bytes(body_data, body_size).into(),
+            trailers: decode_map(bytes(trailers_data, trailers_size)),
+        },
+        timeout: Duration::from_millis(timeout as u64),
+    };
+    *return_token = with_host(|host| host.dispatch_http_call(request));
+    status::OK
+}
+
+// Context
+
+#[no_mangle]
+pub extern "C" fn proxy_set_effective_context(context_id: u32) -> u32 {
+    with_host(|host| host.effective_context = context_id);
+    status::OK
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_done() -> u32 {
+    with_host(|host| host.effective_context().done += 1);
+    status::OK
+}
+
+// Foreign functions
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_call_foreign_function(
+    _function_name_data: *const u8,
+    _function_name_size: usize,
+    _arguments_data: *const u8,
+    _arguments_size: usize,
+    results_data: *mut *mut u8,
+    results_size: *mut usize,
+) -> u32 {
+    give(None, results_data, results_size);
+    status::OK
+}
+
+// Stats
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_define_metric(
+    _metric_type: u32,
+    metric_name_data: *const u8,
+    metric_name_size: usize,
+    return_metric_id: *mut u32,
+) -> u32 {
+    let name = string(metric_name_data, metric_name_size);
+    *return_metric_id = with_host(
+        |host| match host.metrics.iter().position(|(n, _)| *n == name) {
+            Some(index) => index,
+            None => {
+                host.metrics.push((name, 0));
+                host.metrics.len() - 1
+            }
+        },
+    ) as u32;
+    status::OK
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_increment_metric(metric_id: u32, offset: i64) -> u32 {
+    with_host(|host| match host.metrics.get_mut(metric_id as usize) {
+        Some((_, value)) => {
+            *value = (*value as i64).wrapping_add(offset) as u64;
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_record_metric(metric_id: u32, value: u64) -> u32 {
+    with_host(|host| match host.metrics.get_mut(metric_id as usize) {
+        Some((_, current)) => {
+            *current = value;
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_metric(metric_id: u32, return_metric_value: *mut u64) -> u32 {
+    match with_host(|host| {
+        host.metrics
+            .get(metric_id as usize)
+            .map(|(_, value)| *value)
+    }) {
+        Some(value) => {
+            *return_metric_value = value;
+            status::OK
+        }
+        None => status::NOT_FOUND,
+    }
+}
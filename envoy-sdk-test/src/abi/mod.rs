@@ -0,0 +1,232 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fake `Proxy Wasm` ABI.
+//!
+//! Provides host functions that `Envoy` would otherwise provide to a WebAssembly module,
+//! so that extensions can be driven through the actual ABI in unit tests.
+//!
+//! State of the fake host is thread-local, just like the state of the `Proxy Wasm`
+//! dispatcher, so that tests running in parallel don't interfere with each other.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use envoy::host::log::LogLevel;
+use envoy::host::{ByteString, HeaderMap};
+
+use crate::extension::filter::http::FakeLocalReply;
+use crate::host::http::client::{FakeHttpClientRequest, FakePendingRequest};
+use crate::host::http::FakeHttpMessage;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod hostcalls;
+
+thread_local! {
+    static HOST: RefCell<FakeHost> = RefCell::new(FakeHost::default());
+}
+
+/// Runs a given function against the state of the fake host.
+pub(crate) fn with_host<T, F>(f: F) -> T
+where
+    F: FnOnce(&mut FakeHost) -> T,
+{
+    HOST.with(|host| f(&mut host.borrow_mut()))
+}
+
+/// State of the fake host.
+#[derive(Debug)]
+pub(crate) struct FakeHost {
+    pub time: SystemTime,
+    pub effective_context: u32,
+    /// `root_id` of every root context.
+    pub root_ids: HashMap<u32, String>,
+    /// Root context of every child context.
+    pub parents: HashMap<u32, u32>,
+    pub properties: HashMap<Vec<u8>, Vec<u8>>,
+    pub buffers: HashMap<u32, Vec<u8>>,
+    pub maps: HashMap<u32, HeaderMap>,
+    pub contexts: HashMap<u32, FakeContextState>,
+    pub http_calls: Vec<FakePendingRequest>,
+    /// HTTP requests that haven't received a response yet.
+    pub pending_tokens: Vec<u32>,
+    pub next_token: u32,
+    pub logs: Vec<(LogLevel, String)>,
+    pub shared_data: HashMap<String, (Vec<u8>, u32)>,
+    pub shared_queues: Vec<(String, VecDeque<Vec<u8>>)>,
+    pub metrics: Vec<(String, u64)>,
+    /// Host functions that fail with `Status::InternalFailure`.
+    pub rejected_calls: Vec<&'static str>,
+}
+
+/// Effects of host calls made on behalf of a single context.
+#[derive(Debug, Default)]
+pub(crate) struct FakeContextState {
+    pub tick_period: Duration,
+    pub done: usize,
+    pub resumed_requests: usize,
+    pub resumed_responses: usize,
+    pub closed_streams: Vec<u32>,
+    pub local_reply: Option<FakeLocalReply>,
+}
+
+/// Values of `StreamType` understood by `proxy_continue_stream` and `proxy_close_stream`.
+pub(crate) mod stream_type {
+    pub const REQUEST: u32 = 0;
+    pub const RESPONSE: u32 = 1;
+}
+
+/// Values of `BufferType` understood by `proxy_get_buffer_bytes`.
+pub(crate) mod buffer_type {
+    pub const HTTP_REQUEST_BODY: u32 = 0;
+    pub const HTTP_RESPONSE_BODY: u32 = 1;
+    pub const HTTP_CALL_RESPONSE_BODY: u32 = 4;
+    pub const VM_CONFIGURATION: u32 = 6;
+    pub const PLUGIN_CONFIGURATION: u32 = 7;
+}
+
+/// Values of `MapType` understood by `proxy_get_header_map_pairs` and alike.
+pub(crate) mod map_type {
+    pub const HTTP_REQUEST_HEADERS: u32 = 0;
+    pub const HTTP_REQUEST_TRAILERS: u32 = 1;
+    pub const HTTP_RESPONSE_HEADERS: u32 = 2;
+    pub const HTTP_RESPONSE_TRAILERS: u32 = 3;
+    pub const HTTP_CALL_RESPONSE_HEADERS: u32 = 6;
+    pub const HTTP_CALL_RESPONSE_TRAILERS: u32 = 7;
+}
+
+impl Default for FakeHost {
+    fn default() -> Self {
+        FakeHost {
+            time: SystemTime::UNIX_EPOCH,
+            effective_context: 0,
+            root_ids: HashMap::new(),
+            parents: HashMap::new(),
+            properties: HashMap::new(),
+            buffers: HashMap::new(),
+            maps: HashMap::new(),
+            contexts: HashMap::new(),
+            http_calls: Vec::new(),
+            pending_tokens: Vec::new(),
+            next_token: 1,
+            logs: Vec::new(),
+            shared_data: HashMap::new(),
+            shared_queues: Vec::new(),
+            metrics: Vec::new(),
+            rejected_calls: Vec::new(),
+        }
+    }
+}
+
+impl FakeHost {
+    /// Forgets effects of previous host calls.
+    ///
+    /// Tokens of HTTP requests are never reused since `Proxy Wasm` SDK keeps
+    /// track of them in a thread-local state that outlives the fake host.
+    pub fn reset(&mut self) {
+        let next_token = self.next_token;
+        *self = FakeHost {
+            next_token,
+            ..FakeHost::default()
+        };
+    }
+
+    /// Returns the state of a given context.
+    pub fn context(&mut self, context_id: u32) -> &mut FakeContextState {
+        self.contexts.entry(context_id).or_default()
+    }
+
+    /// Returns the state of the context the host calls are made on behalf of.
+    pub fn effective_context(&mut self) -> &mut FakeContextState {
+        let context_id = self.effective_context;
+        self.context(context_id)
+    }
+
+    /// Returns `root_id` of the plugin the effective context belongs to.
+    pub fn plugin_root_id(&self) -> Option<&str> {
+        let context_id = self.effective_context;
+        let root_context_id = self.parents.get(&context_id).unwrap_or(&context_id);
+        self.root_ids.get(root_context_id).map(String::as_str)
+    }
+
+    /// Records an HTTP request dispatched by the effective context.
+    pub fn dispatch_http_call(&mut self, request: FakeHttpClientRequest) -> u32 {
+        let token = self.next_token;
+        self.next_token += 1;
+        self.http_calls.push(FakePendingRequest {
+            request,
+            handle: token.into(),
+        });
+        self.pending_tokens.push(token);
+        token
+    }
+
+    /// Replaces message exposed to the extension through a given buffer and maps.
+    pub fn set_message(
+        &mut self,
+        headers: u32,
+        body: u32,
+        trailers: u32,
+        message: &FakeHttpMessage,
+    ) {
+        self.maps.insert(headers, message.headers.clone());
+        self.buffers.insert(body, message.body.as_bytes().to_vec());
+        self.maps.insert(trailers, message.trailers.clone());
+    }
+}
+
+/// Encodes a map the way `Envoy` passes it to a WebAssembly module.
+pub(crate) fn encode_map(map: &HeaderMap) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(map.len() as u32).to_le_bytes());
+    for (name, value) in map.iter() {
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    }
+    for (name, value) in map.iter() {
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+/// Decodes a map the way `Proxy Wasm` SDK encodes it on a native target,
+/// i.e. with sizes of `usize` rather than `u32`.
+pub(crate) fn decode_map(bytes: &[u8]) -> HeaderMap {
+    const SIZE: usize = std::mem::size_of::<usize>();
+    let read = |offset: usize| {
+        let mut buf = [0u8; SIZE];
+        buf.copy_from_slice(&bytes[offset..offset + SIZE]);
+        usize::from_le_bytes(buf)
+    };
+    let mut map = HeaderMap::default();
+    if bytes.is_empty() {
+        return map;
+    }
+    let count = read(0);
+    let mut p = SIZE + count * 2 * SIZE;
+    for n in 0..count {
+        let name_size = read(SIZE + n * 2 * SIZE);
+        let value_size = read(SIZE + n * 2 * SIZE + SIZE);
+        let name = ByteString::from(&bytes[p..p + name_size]);
+        p += name_size + 1;
+        let value = ByteString::from(&bytes[p..p + value_size]);
+        p += value_size + 1;
+        map.insert(name, value);
+    }
+    map
+}
@@ -0,0 +1,715 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fake `HTTP` stream for driving an [`HttpFilter`] in unit tests.
+//!
+//! # Examples
+//!
+//! #### Basic usage of [`FakeHttpStream`]:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use envoy::extension::{HttpFilter, Result};
+//! use envoy::extension::filter::http::{FilterHeadersStatus, RequestHeadersOps};
+//! use envoy_test::FakeHttpStream;
+//!
+//! /// Rejects requests without `authorization` header.
+//! struct MyHttpFilter;
+//!
+//! impl HttpFilter for MyHttpFilter {
+//!     fn on_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool, ops: &dyn RequestHeadersOps) -> Result<FilterHeadersStatus> {
+//!         if ops.request_header("authorization")?.is_none() {
+//!             ops.send_response(401, &[("www-authenticate", "Basic")], None)?;
+//!             return Ok(FilterHeadersStatus::StopIteration);
+//!         }
+//!         ops.remove_request_header("authorization")?;
+//!         Ok(FilterHeadersStatus::Continue)
+//!     }
+//! }
+//!
+//! # fn main() -> envoy::extension::Result<()> {
+//! let stream = FakeHttpStream::new();
+//! stream.send_request_headers(&mut MyHttpFilter, &[(":path", "/"), ("authorization", "Basic YQ==")], true)?;
+//!
+//! let request = stream.upstream_request().unwrap();
+//! assert_eq!(request.headers.get(":path"), Some(&"/".into()));
+//! assert_eq!(request.headers.get("authorization"), None);
+//!
+//! let stream = FakeHttpStream::new();
+//! stream.send_request_headers(&mut MyHttpFilter, &[(":path", "/")], true)?;
+//!
+//! assert!(stream.upstream_request().is_none());
+//! assert_eq!(stream.local_reply().unwrap().status_code, 401);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! #### Delivering responses to requests made through [`FakeHttpClient`]:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use std::time::Duration;
+//! use envoy::extension::{HttpFilter, Result};
+//! use envoy::extension::filter::http::{self, FilterHeadersStatus, RequestHeadersOps};
+//! use envoy::host::{HttpClient, HttpClientRequestHandle, HttpClientResponseOps};
+//! use envoy_test::{FakeHttpClient, FakeHttpClientResponse, FakeHttpStream};
+//!
+//! /// Holds requests until an external service approves them.
+//! struct MyHttpFilter<'a> {
+//!     http_client: &'a dyn HttpClient,
+//! }
+//!
+//! impl<'a> HttpFilter for MyHttpFilter<'a> {
+//!     fn on_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool, _ops: &dyn RequestHeadersOps) -> Result<FilterHeadersStatus> {
+//!         self.http_client.send_request("authz", &[(":path", "/check")], None, None, Duration::from_secs(1))?;
+//!         Ok(FilterHeadersStatus::StopIteration)
+//!     }
+//!
+//!     fn on_http_call_response(&mut self, _request: HttpClientRequestHandle, _num_headers: usize, _body_size: usize, _num_trailers: usize, filter_ops: &dyn http::Ops, http_client_ops: &dyn HttpClientResponseOps) -> Result<()> {
+//!         match http_client_ops.http_call_response_header(":status")? {
+//!             Some(status) if status == "200" => filter_ops.resume_request(),
+//!             _ => filter_ops.send_response(403, &[], None),
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() -> envoy::extension::Result<()> {
+//! let http_client = FakeHttpClient::default();
+//! let mut filter = MyHttpFilter { http_client: &http_client };
+//!
+//! let stream = FakeHttpStream::new();
+//! stream.send_request_headers(&mut filter, &[(":path", "/")], true)?;
+//! assert!(stream.is_request_paused());
+//!
+//! let pending_requests = http_client.drain_pending_requests();
+//! assert_eq!(pending_requests.len(), 1);
+//!
+//! let response = FakeHttpClientResponse::builder()
+//!     .header(":status", "200")
+//!     .build();
+//! stream.deliver_http_call_response(&mut filter, pending_requests[0].handle, &response)?;
+//!
+//! assert!(!stream.is_request_paused());
+//! assert!(stream.upstream_request().is_some());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`HttpFilter`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/filter/http/trait.HttpFilter.html
+//! [`FakeHttpStream`]: struct.FakeHttpStream.html
+//! [`FakeHttpClient`]: ../../../host/http/client/struct.FakeHttpClient.html
+
+use std::cell::{Cell, RefCell};
+
+use envoy::error::{bail, format_err};
+use envoy::extension::filter::http::{
+    ExchangeCompleteOps, FilterDataStatus, FilterHeadersStatus, FilterTrailersStatus, HttpFilter,
+    RequestBodyOps, RequestFlowOps, RequestHeadersOps, RequestTrailersOps, ResponseBodyOps,
+    ResponseFlowOps, ResponseHeadersOps, ResponseTrailersOps,
+};
+use envoy::extension::Result;
+use envoy::host::{self, ByteString, HeaderMap, HttpClientRequestHandle};
+
+use crate::host::http::client::FakeHttpClientResponse;
+use crate::host::http::FakeHttpMessage;
+use crate::host::simulate;
+
+/// Fake `HTTP` stream, i.e. a request/response pair proxied by an [`HttpFilter`].
+///
+/// Drives an [`HttpFilter`] through the lifecycle of an `HTTP` stream and
+/// simulates the way `Envoy` reacts to the filter:
+/// * request and response only pass the filter while their iteration is not
+///   stopped, i.e. until the filter returns `StopIteration` or `StopIterationAndBuffer`;
+/// * body data is buffered while iteration is stopped and gets forwarded
+///   once the filter returns `Continue` or calls `resume_request`/`resume_response`;
+/// * a local reply or a stream reset terminates the stream.
+///
+/// Local replies are recorded as is rather than run through the response path
+/// of the filter.
+///
+/// [`HttpFilter`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/filter/http/trait.HttpFilter.html
+#[derive(Debug, Default)]
+pub struct FakeHttpStream {
+    request: RefCell<FakeHalfStream>,
+    response: RefCell<FakeHalfStream>,
+    local_reply: RefCell<Option<FakeLocalReply>>,
+    reset: Cell<bool>,
+    complete: Cell<bool>,
+}
+
+/// Local reply sent by an [`HttpFilter`] through `send_response`.
+///
+/// [`HttpFilter`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/filter/http/trait.HttpFilter.html
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct FakeLocalReply {
+    pub status_code: u32,
+    pub headers: HeaderMap,
+    pub body: ByteString,
+}
+
+/// Change made by an [`HttpFilter`] to headers or trailers.
+///
+/// [`HttpFilter`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/filter/http/trait.HttpFilter.html
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FakeHeaderMutation {
+    /// All entries have been replaced.
+    Replace(HeaderMap),
+    /// A single entry has been added or updated.
+    Set(String, ByteString),
+    /// A single entry has been removed.
+    Remove(String),
+}
+
+/// State of either request or response.
+#[derive(Debug, Default)]
+struct FakeHalfStream {
+    headers: Option<HeaderMap>,
+    buffer: Vec<u8>,
+    trailers: Option<HeaderMap>,
+    end_of_stream: bool,
+    paused: bool,
+    forwarded: Option<FakeHttpMessage>,
+    header_mutations: Vec<FakeHeaderMutation>,
+    trailer_mutations: Vec<FakeHeaderMutation>,
+}
+
+impl FakeHttpStream {
+    /// Creates a new stream.
+    pub fn new() -> Self {
+        FakeHttpStream::default()
+    }
+
+    /// Delivers request headers to a given filter.
+    pub fn send_request_headers(
+        &self,
+        filter: &mut dyn HttpFilter,
+        headers: &[(&str, &str)],
+        end_of_stream: bool,
+    ) -> Result<FilterHeadersStatus> {
+        self.ensure_active()?;
+        let num_headers =
+            self.request
+                .borrow_mut()
+                .receive_headers("request", headers.into(), end_of_stream)?;
+        let status = filter.on_request_headers(num_headers, end_of_stream, self)?;
+        self.proceed(&self.request, status == FilterHeadersStatus::Continue);
+        Ok(status)
+    }
+
+    /// Delivers a chunk of request body to a given filter.
+    pub fn send_request_body(
+        &self,
+        filter: &mut dyn HttpFilter,
+        data: &[u8],
+        end_of_stream: bool,
+    ) -> Result<FilterDataStatus> {
+        self.ensure_active()?;
+        let data_size = self
+            .request
+            .borrow_mut()
+            .receive_body("request", data, end_of_stream)?;
+        let status = filter.on_request_body(data_size, end_of_stream, self)?;
+        self.proceed(&self.request, status == FilterDataStatus::Continue);
+        Ok(status)
+    }
+
+    /// Delivers request trailers to a given filter, ending the request.
+    pub fn send_request_trailers(
+        &self,
+        filter: &mut dyn HttpFilter,
+        trailers: &[(&str, &str)],
+    ) -> Result<FilterTrailersStatus> {
+        self.ensure_active()?;
+        let num_trailers = self
+            .request
+            .borrow_mut()
+            .receive_trailers("request", trailers.into())?;
+        let status = filter.on_request_trailers(num_trailers, self)?;
+        self.proceed(&self.request, status == FilterTrailersStatus::Continue);
+        Ok(status)
+    }
+
+    /// Delivers response headers to a given filter.
+    ///
+    /// Fails unless request headers have already been forwarded upstream.
+    pub fn send_response_headers(
+        &self,
+        filter: &mut dyn HttpFilter,
+        headers: &[(&str, &str)],
+        end_of_stream: bool,
+    ) -> Result<FilterHeadersStatus> {
+        self.ensure_active()?;
+        if self.request.borrow().forwarded.is_none() {
+            bail!("request headers have not been forwarded upstream yet");
+        }
+        let num_headers = self.response.borrow_mut().receive_headers(
+            "response",
+            headers.into(),
+            end_of_stream,
+        )?;
+        let status = filter.on_response_headers(num_headers, end_of_stream, self)?;
+        self.proceed(&self.response, status == FilterHeadersStatus::Continue);
+        Ok(status)
+    }
+
+    /// Delivers a chunk of response body to a given filter.
+    pub fn send_response_body(
+        &self,
+        filter: &mut dyn HttpFilter,
+        data: &[u8],
+        end_of_stream: bool,
+    ) -> Result<FilterDataStatus> {
+        self.ensure_active()?;
+        let data_size = self
+            .response
+            .borrow_mut()
+            .receive_body("response", data, end_of_stream)?;
+        let status = filter.on_response_body(data_size, end_of_stream, self)?;
+        self.proceed(&self.response, status == FilterDataStatus::Continue);
+        Ok(status)
+    }
+
+    /// Delivers response trailers to a given filter, ending the response.
+    pub fn send_response_trailers(
+        &self,
+        filter: &mut dyn HttpFilter,
+        trailers: &[(&str, &str)],
+    ) -> Result<FilterTrailersStatus> {
+        self.ensure_active()?;
+        let num_trailers = self
+            .response
+            .borrow_mut()
+            .receive_trailers("response", trailers.into())?;
+        let status = filter.on_response_trailers(num_trailers, self)?;
+        self.proceed(&self.response, status == FilterTrailersStatus::Continue);
+        Ok(status)
+    }
+
+    /// Delivers a response to a request made through [`FakeHttpClient`] to a given filter.
+    ///
+    /// [`FakeHttpClient`]: ../../../host/http/client/struct.FakeHttpClient.html
+    pub fn deliver_http_call_response(
+        &self,
+        filter: &mut dyn HttpFilter,
+        request: HttpClientRequestHandle,
+        response: &FakeHttpClientResponse,
+    ) -> Result<()> {
+        self.ensure_not_complete()?;
+        filter.on_http_call_response(
+            request,
+            response.message.headers.len(),
+            response.message.body.len(),
+            response.message.trailers.len(),
+            self,
+            response,
+        )
+    }
+
+    /// Notifies a given filter that the stream is complete.
+    ///
+    /// Unlike other callbacks, it is also delivered after a local reply or a stream reset.
+    pub fn complete(&self, filter: &mut dyn HttpFilter) -> Result<()> {
+        self.ensure_not_complete()?;
+        self.complete.set(true);
+        filter.on_exchange_complete(self)
+    }
+
+    /// Returns request as it has been forwarded upstream so far.
+    ///
+    /// Returns `None` if request headers haven't passed the filter yet.
+    pub fn upstream_request(&self) -> Option<FakeHttpMessage> {
+        self.request.borrow().forwarded.clone()
+    }
+
+    /// Returns response as it has been forwarded downstream so far.
+    ///
+    /// Returns `None` if response headers haven't passed the filter yet.
+    pub fn downstream_response(&self) -> Option<FakeHttpMessage> {
+        self.response.borrow().forwarded.clone()
+    }
+
+    /// Returns a local reply sent by the filter, if any.
+    pub fn local_reply(&self) -> Option<FakeLocalReply> {
+        self.local_reply.borrow().clone()
+    }
+
+    /// Returns `true` if request iteration is stopped.
+    pub fn is_request_paused(&self) -> bool {
+        self.request.borrow().paused
+    }
+
+    /// Returns `true` if response iteration is stopped.
+    pub fn is_response_paused(&self) -> bool {
+        self.response.borrow().paused
+    }
+
+    /// Returns `true` if the filter has reset the stream.
+    pub fn is_reset(&self) -> bool {
+        self.reset.get()
+    }
+
+    /// Returns `true` if the stream is complete.
+    pub fn is_complete(&self) -> bool {
+        self.complete.get()
+    }
+
+    /// Returns changes made by the filter to request headers.
+    pub fn request_header_mutations(&self) -> Vec<FakeHeaderMutation> {
+        self.request.borrow().header_mutations.clone()
+    }
+
+    /// Returns changes made by the filter to request trailers.
+    pub fn request_trailer_mutations(&self) -> Vec<FakeHeaderMutation> {
+        self.request.borrow().trailer_mutations.clone()
+    }
+
+    /// Returns changes made by the filter to response headers.
+    pub fn response_header_mutations(&self) -> Vec<FakeHeaderMutation> {
+        self.response.borrow().header_mutations.clone()
+    }
+
+    /// Returns changes made by the filter to response trailers.
+    pub fn response_trailer_mutations(&self) -> Vec<FakeHeaderMutation> {
+        self.response.borrow().trailer_mutations.clone()
+    }
+
+    fn ensure_not_complete(&self) -> Result<()> {
+        if self.complete.get() {
+            bail!("the stream is already complete");
+        }
+        Ok(())
+    }
+
+    fn ensure_active(&self) -> Result<()> {
+        self.ensure_not_complete()?;
+        if self.reset.get() {
+            bail!("the stream has been reset by the filter");
+        }
+        if self.local_reply.borrow().is_some() {
+            bail!("the stream has been terminated by a local reply");
+        }
+        Ok(())
+    }
+
+    fn proceed(&self, half: &RefCell<FakeHalfStream>, proceed: bool) {
+        if self.ensure_active().is_err() {
+            return;
+        }
+        let mut half = half.borrow_mut();
+        if proceed {
+            half.resume();
+        } else {
+            half.paused = true;
+        }
+    }
+
+    fn resume(&self, half: &RefCell<FakeHalfStream>) {
+        if self.ensure_active().is_err() {
+            return;
+        }
+        let mut half = half.borrow_mut();
+        if half.paused {
+            half.resume();
+        }
+    }
+}
+
+impl FakeHalfStream {
+    fn receive_headers(
+        &mut self,
+        kind: &str,
+        headers: HeaderMap,
+        end_of_stream: bool,
+    ) -> Result<usize> {
+        if self.headers.is_some() {
+            bail!("{} headers have already been sent", kind);
+        }
+        let num_headers = headers.len();
+        self.headers = Some(headers);
+        self.end_of_stream = end_of_stream;
+        Ok(num_headers)
+    }
+
+    fn receive_body(&mut self, kind: &str, data: &[u8], end_of_stream: bool) -> Result<usize> {
+        self.ensure_open(kind)?;
+        self.buffer.extend_from_slice(data);
+        self.end_of_stream = end_of_stream;
+        Ok(self.buffer.len())
+    }
+
+    fn receive_trailers(&mut self, kind: &str, trailers: HeaderMap) -> Result<usize> {
+        self.ensure_open(kind)?;
+        let num_trailers = trailers.len();
+        self.trailers = Some(trailers);
+        self.end_of_stream = true;
+        Ok(num_trailers)
+    }
+
+    fn ensure_open(&self, kind: &str) -> Result<()> {
+        if self.headers.is_none() {
+            bail!("{} headers have not been sent yet", kind);
+        }
+        if self.end_of_stream {
+            bail!("{} has already ended", kind);
+        }
+        Ok(())
+    }
+
+    /// Forwards everything received so far past the filter.
+    fn resume(&mut self) {
+        self.paused = false;
+        if self.forwarded.is_none() {
+            self.forwarded = Some(FakeHttpMessage {
+                headers: self.headers.clone().unwrap_or_default(),
+                ..FakeHttpMessage::default()
+            });
+        }
+        if let Some(forwarded) = self.forwarded.as_mut() {
+            if !self.buffer.is_empty() {
+                let mut body = forwarded.body.as_bytes().to_vec();
+                body.append(&mut self.buffer);
+                forwarded.body = body.into();
+            }
+            if let Some(trailers) = &self.trailers {
+                forwarded.trailers = trailers.clone();
+            }
+        }
+    }
+
+    fn headers(&self, kind: &str) -> host::Result<&HeaderMap> {
+        self.headers
+            .as_ref()
+            .ok_or_else(|| format_err!("{} headers have not been sent yet", kind))
+    }
+
+    fn headers_mut(&mut self, kind: &str) -> host::Result<&mut HeaderMap> {
+        self.headers
+            .as_mut()
+            .ok_or_else(|| format_err!("{} headers have not been sent yet", kind))
+    }
+
+    fn trailers(&self, kind: &str) -> host::Result<&HeaderMap> {
+        self.trailers
+            .as_ref()
+            .ok_or_else(|| format_err!("{} trailers have not been sent yet", kind))
+    }
+
+    fn trailers_mut(&mut self, kind: &str) -> host::Result<&mut HeaderMap> {
+        self.trailers
+            .as_mut()
+            .ok_or_else(|| format_err!("{} trailers have not been sent yet", kind))
+    }
+
+    fn set_headers(&mut self, kind: &str, headers: &HeaderMap) -> host::Result<()> {
+        *self.headers_mut(kind)? = headers.clone();
+        self.header_mutations
+            .push(FakeHeaderMutation::Replace(headers.clone()));
+        Ok(())
+    }
+
+    fn set_header(&mut self, kind: &str, name: &str, value: &[u8]) -> host::Result<()> {
+        self.headers_mut(kind)?.insert(name, value);
+        self.header_mutations
+            .push(FakeHeaderMutation::Set(name.to_owned(), value.into()));
+        Ok(())
+    }
+
+    fn remove_header(&mut self, kind: &str, name: &str) -> host::Result<()> {
+        self.headers_mut(kind)?.remove(name);
+        self.header_mutations
+            .push(FakeHeaderMutation::Remove(name.to_owned()));
+        Ok(())
+    }
+
+    fn set_trailers(&mut self, kind: &str, trailers: &HeaderMap) -> host::Result<()> {
+        *self.trailers_mut(kind)? = trailers.clone();
+        self.trailer_mutations
+            .push(FakeHeaderMutation::Replace(trailers.clone()));
+        Ok(())
+    }
+
+    fn set_trailer(&mut self, kind: &str, name: &str, value: &[u8]) -> host::Result<()> {
+        self.trailers_mut(kind)?.insert(name, value);
+        self.trailer_mutations
+            .push(FakeHeaderMutation::Set(name.to_owned(), value.into()));
+        Ok(())
+    }
+
+    fn remove_trailer(&mut self, kind: &str, name: &str) -> host::Result<()> {
+        self.trailers_mut(kind)?.remove(name);
+        self.trailer_mutations
+            .push(FakeHeaderMutation::Remove(name.to_owned()));
+        Ok(())
+    }
+}
+
+impl RequestFlowOps for FakeHttpStream {
+    fn resume_request(&self) -> host::Result<()> {
+        self.resume(&self.request);
+        Ok(())
+    }
+
+    fn send_response(
+        &self,
+        status_code: u32,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> host::Result<()> {
+        self.ensure_active()?;
+        *self.local_reply.borrow_mut() = Some(FakeLocalReply {
+            status_code,
+            headers: headers.into(),
+            body: body.unwrap_or_default().into(),
+        });
+        Ok(())
+    }
+
+    fn reset_stream(&self) -> host::Result<()> {
+        self.reset.set(true);
+        Ok(())
+    }
+}
+
+impl RequestHeadersOps for FakeHttpStream {
+    fn request_headers(&self) -> host::Result<HeaderMap> {
+        self.request.borrow().headers("request").cloned()
+    }
+
+    fn request_header(&self, name: &str) -> host::Result<Option<ByteString>> {
+        Ok(self.request.borrow().headers("request")?.get(name).cloned())
+    }
+
+    fn set_request_headers(&self, headers: &HeaderMap) -> host::Result<()> {
+        self.request.borrow_mut().set_headers("request", headers)
+    }
+
+    fn set_request_header_bytes(&self, name: &str, value: &[u8]) -> host::Result<()> {
+        self.request.borrow_mut().set_header("request", name, value)
+    }
+
+    fn remove_request_header(&self, name: &str) -> host::Result<()> {
+        self.request.borrow_mut().remove_header("request", name)
+    }
+}
+
+impl RequestBodyOps for FakeHttpStream {
+    fn request_data(&self, start: usize, max_size: usize) -> host::Result<ByteString> {
+        simulate::get_buffer_bytes(&self.request.borrow().buffer, start, max_size)
+    }
+}
+
+impl RequestTrailersOps for FakeHttpStream {
+    fn request_trailers(&self) -> host::Result<HeaderMap> {
+        self.request.borrow().trailers("request").cloned()
+    }
+
+    fn request_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        Ok(self
+            .request
+            .borrow()
+            .trailers("request")?
+            .get(name)
+            .cloned())
+    }
+
+    fn set_request_trailers(&self, trailers: &HeaderMap) -> host::Result<()> {
+        self.request.borrow_mut().set_trailers("request", trailers)
+    }
+
+    fn set_request_trailer_bytes(&self, name: &str, value: &[u8]) -> host::Result<()> {
+        self.request
+            .borrow_mut()
+            .set_trailer("request", name, value)
+    }
+
+    fn remove_request_trailer(&self, name: &str) -> host::Result<()> {
+        self.request.borrow_mut().remove_trailer("request", name)
+    }
+}
+
+impl ResponseFlowOps for FakeHttpStream {
+    fn resume_response(&self) -> host::Result<()> {
+        self.resume(&self.response);
+        Ok(())
+    }
+}
+
+impl ResponseHeadersOps for FakeHttpStream {
+    fn response_headers(&self) -> host::Result<HeaderMap> {
+        self.response.borrow().headers("response").cloned()
+    }
+
+    fn response_header(&self, name: &str) -> host::Result<Option<ByteString>> {
+        Ok(self
+            .response
+            .borrow()
+            .headers("response")?
+            .get(name)
+            .cloned())
+    }
+
+    fn set_response_headers(&self, headers: &HeaderMap) -> host::Result<()> {
+        self.response.borrow_mut().set_headers("response", headers)
+    }
+
+    fn set_response_header_bytes(&self, name: &str, value: &[u8]) -> host::Result<()> {
+        self.response
+            .borrow_mut()
+            .set_header("response", name, value)
+    }
+
+    fn remove_response_header(&self, name: &str) -> host::Result<()> {
+        self.response.borrow_mut().remove_header("response", name)
+    }
+}
+
+impl ResponseBodyOps for FakeHttpStream {
+    fn response_data(&self, start: usize, max_size: usize) -> host::Result<ByteString> {
+        simulate::get_buffer_bytes(&self.response.borrow().buffer, start, max_size)
+    }
+}
+
+impl ResponseTrailersOps for FakeHttpStream {
+    fn response_trailers(&self) -> host::Result<HeaderMap> {
+        self.response.borrow().trailers("response").cloned()
+    }
+
+    fn response_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        Ok(self
+            .response
+            .borrow()
+            .trailers("response")?
+            .get(name)
+            .cloned())
+    }
+
+    fn set_response_trailers(&self, trailers: &HeaderMap) -> host::Result<()> {
+        self.response
+            .borrow_mut()
+            .set_trailers("response", trailers)
+    }
+
+    fn set_response_trailer_bytes(&self, name: &str, value: &[u8]) -> host::Result<()> {
+        self.response
+            .borrow_mut()
+            .set_trailer("response", name, value)
+    }
+
+    fn remove_response_trailer(&self, name: &str) -> host::Result<()> {
+        self.response.borrow_mut().remove_trailer("response", name)
+    }
+}
+
+impl ExchangeCompleteOps for FakeHttpStream {}
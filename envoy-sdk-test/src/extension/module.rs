@@ -0,0 +1,428 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fake `Envoy` that drives a [`Module`] through the `Proxy Wasm` ABI.
+//!
+//! Unlike [`FakeHttpStream`] that calls an [`HttpFilter`] directly,
+//! [`FakeEnvoy`] exercises the entire SDK stack that sits between `Envoy`
+//! and an extension, e.g. error reporting, failure policies and draining.
+//!
+//! # Examples
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! # use envoy::extension::{self, ExtensionFactory, HttpFilter, InstanceId};
+//! #
+//! # struct MyHttpFilter;
+//! # impl HttpFilter for MyHttpFilter {}
+//! #
+//! # struct MyHttpFilterFactory;
+//! # impl ExtensionFactory for MyHttpFilterFactory {
+//! #     type Extension = MyHttpFilter;
+//! #
+//! #     fn name() -> &'static str { "my_http_filter" }
+//! #
+//! #     fn new_extension(&mut self, _: InstanceId) -> extension::Result<Self::Extension> {
+//! #         Ok(MyHttpFilter)
+//! #     }
+//! # }
+//! #
+//! use envoy::extension::Module;
+//! use envoy_test::{FakeAction, FakeEnvoy};
+//!
+//! let envoy = FakeEnvoy::start(Module::new().add_http_filter(|_| Ok(MyHttpFilterFactory)));
+//!
+//! let root = envoy.new_root_context("my_http_filter");
+//! assert!(root.configure(b"{}"));
+//!
+//! let stream = root.new_http_stream();
+//! assert_eq!(stream.request_headers(&[(":path", "/")], true), FakeAction::Continue);
+//! ```
+//!
+//! [`Module`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/struct.Module.html
+//! [`HttpFilter`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/filter/http/trait.HttpFilter.html
+//! [`FakeHttpStream`]: ../filter/http/struct.FakeHttpStream.html
+//! [`FakeEnvoy`]: struct.FakeEnvoy.html
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::time::Duration;
+
+use envoy::extension::{self, Module};
+use envoy::host::http::client::HttpClientRequestHandle;
+use envoy::host::log::{self, LogLevel};
+use envoy::host::HeaderMap;
+
+use crate::abi::{buffer_type, map_type, stream_type, with_host, FakeHost};
+use crate::extension::filter::http::FakeLocalReply;
+use crate::host::http::client::{FakeHttpClientResponse, FakePendingRequest};
+
+extern "C" {
+    fn proxy_on_context_create(context_id: u32, root_context_id: u32);
+    fn proxy_on_done(context_id: u32) -> bool;
+    fn proxy_on_log(context_id: u32);
+    fn proxy_on_delete(context_id: u32);
+    fn proxy_on_vm_start(context_id: u32, vm_configuration_size: usize) -> bool;
+    fn proxy_on_configure(context_id: u32, plugin_configuration_size: usize) -> bool;
+    fn proxy_on_tick(context_id: u32);
+    fn proxy_on_queue_ready(context_id: u32, queue_id: u32);
+    fn proxy_on_request_headers(context_id: u32, num_headers: usize, end_of_stream: bool) -> u32;
+    fn proxy_on_request_body(context_id: u32, body_size: usize, end_of_stream: bool) -> u32;
+    fn proxy_on_request_trailers(context_id: u32, num_trailers: usize) -> u32;
+    fn proxy_on_response_headers(context_id: u32, num_headers: usize, end_of_stream: bool) -> u32;
+    fn proxy_on_response_body(context_id: u32, body_size: usize, end_of_stream: bool) -> u32;
+    fn proxy_on_response_trailers(context_id: u32, num_trailers: usize) -> u32;
+    fn proxy_on_http_call_response(
+        context_id: u32,
+        token_id: u32,
+        num_headers: usize,
+        body_size: usize,
+        num_trailers: usize,
+    );
+}
+
+thread_local! {
+    // `Proxy Wasm` SDK keeps contexts in a thread-local state that outlives
+    // a `FakeEnvoy`, so context ids must never be reused.
+    static NEXT_CONTEXT_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+fn next_context_id() -> u32 {
+    NEXT_CONTEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    })
+}
+
+/// Makes a host call on behalf of a given context.
+fn on_behalf_of<T, F>(context_id: u32, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    with_host(|host| host.effective_context = context_id);
+    f()
+}
+
+/// Action returned by an HTTP stream callback.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FakeAction {
+    Continue,
+    Pause,
+}
+
+impl From<u32> for FakeAction {
+    fn from(action: u32) -> Self {
+        match action {
+            0 => FakeAction::Continue,
+            _ => FakeAction::Pause,
+        }
+    }
+}
+
+/// Fake `Envoy` that drives a [`Module`] through the `Proxy Wasm` ABI.
+///
+/// State of the fake `Envoy` is thread-local, so a `FakeEnvoy` must be used
+/// in the thread it has been started in.
+///
+/// [`Module`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/extension/struct.Module.html
+#[derive(Debug)]
+pub struct FakeEnvoy {
+    _not_send: PhantomData<Rc<()>>,
+}
+
+impl FakeEnvoy {
+    /// Starts a WebAssembly module with a given set of extensions,
+    /// similarly to what `_start` function generated by `entrypoint!` does.
+    pub fn start(module: extension::Result<Module>) -> Self {
+        with_host(FakeHost::reset);
+        log::set_max_level(LogLevel::Info);
+        extension::install(module);
+        FakeEnvoy {
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Creates a new root context with a given `root_id`.
+    pub fn new_root_context(&self, root_id: &str) -> FakeRootContext {
+        let id = next_context_id();
+        with_host(|host| host.root_ids.insert(id, root_id.to_owned()));
+        on_behalf_of(id, || unsafe { proxy_on_context_create(id, 0) });
+        FakeRootContext { id }
+    }
+
+    /// Makes a given host function fail with `Status::InternalFailure`.
+    ///
+    /// Only stream control functions, i.e. `proxy_continue_stream`,
+    /// `proxy_close_stream` and `proxy_send_local_response`, can be made to fail.
+    pub fn reject_host_call(&self, function: &'static str) {
+        with_host(|host| host.rejected_calls.push(function));
+    }
+
+    /// Advances the current time.
+    pub fn advance_time(&self, duration: Duration) {
+        with_host(|host| host.time += duration);
+    }
+
+    /// Returns messages logged by the WebAssembly module so far.
+    pub fn logs(&self) -> Vec<String> {
+        with_host(|host| {
+            host.logs
+                .iter()
+                .map(|(_, message)| message.clone())
+                .collect()
+        })
+    }
+
+    /// Returns HTTP requests made by the WebAssembly module since the last call.
+    pub fn drain_http_calls(&self) -> Vec<FakePendingRequest> {
+        with_host(|host| host.http_calls.drain(..).collect())
+    }
+
+    /// Delivers a response to an HTTP request made by the WebAssembly module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no pending request with a given handle.
+    pub fn respond_to_http_call(
+        &self,
+        handle: HttpClientRequestHandle,
+        response: &FakeHttpClientResponse,
+    ) {
+        let token = with_host(|host| {
+            let index = host
+                .pending_tokens
+                .iter()
+                .position(|&token| HttpClientRequestHandle::from(token) == handle)
+                .unwrap_or_else(|| panic!("there is no pending HTTP request {}", handle));
+            host.set_message(
+                map_type::HTTP_CALL_RESPONSE_HEADERS,
+                buffer_type::HTTP_CALL_RESPONSE_BODY,
+                map_type::HTTP_CALL_RESPONSE_TRAILERS,
+                &response.message,
+            );
+            host.pending_tokens.remove(index)
+        });
+        let message = &response.message;
+        unsafe {
+            proxy_on_http_call_response(
+                0,
+                token,
+                message.headers.len(),
+                message.body.len(),
+                message.trailers.len(),
+            )
+        }
+    }
+}
+
+/// Root context created by a [`FakeEnvoy`].
+///
+/// [`FakeEnvoy`]: struct.FakeEnvoy.html
+#[derive(Debug)]
+pub struct FakeRootContext {
+    id: u32,
+}
+
+impl FakeRootContext {
+    /// Returns the context id.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Simulates `proxy_on_vm_start` with a given VM configuration.
+    pub fn start_vm(&self, config: &[u8]) -> bool {
+        let id = self.id;
+        with_host(|host| {
+            host.buffers
+                .insert(buffer_type::VM_CONFIGURATION, config.to_vec())
+        });
+        on_behalf_of(id, || unsafe { proxy_on_vm_start(id, config.len()) })
+    }
+
+    /// Simulates `proxy_on_configure` with a given plugin configuration.
+    pub fn configure(&self, config: &[u8]) -> bool {
+        let id = self.id;
+        with_host(|host| {
+            host.buffers
+                .insert(buffer_type::PLUGIN_CONFIGURATION, config.to_vec())
+        });
+        on_behalf_of(id, || unsafe { proxy_on_configure(id, config.len()) })
+    }
+
+    /// Simulates `proxy_on_tick`.
+    pub fn tick(&self) {
+        let id = self.id;
+        on_behalf_of(id, || unsafe { proxy_on_tick(id) })
+    }
+
+    /// Simulates `proxy_on_queue_ready` for a given shared queue.
+    pub fn queue_ready(&self, queue_id: u32) {
+        let id = self.id;
+        on_behalf_of(id, || unsafe { proxy_on_queue_ready(id, queue_id) })
+    }
+
+    /// Simulates `proxy_on_done`, i.e. starts draining.
+    ///
+    /// Returns `true` if the context has completed draining right away.
+    pub fn drain(&self) -> bool {
+        let id = self.id;
+        on_behalf_of(id, || unsafe { proxy_on_done(id) })
+    }
+
+    /// Returns how many times the context has reported completion of draining
+    /// through `proxy_done`.
+    pub fn done_count(&self) -> usize {
+        with_host(|host| host.context(self.id).done)
+    }
+
+    /// Returns the tick period set by the context.
+    pub fn tick_period(&self) -> Duration {
+        with_host(|host| host.context(self.id).tick_period)
+    }
+
+    /// Creates a new HTTP stream handled by this root context.
+    pub fn new_http_stream(&self) -> FakeHttpContext {
+        let id = next_context_id();
+        let root_id = self.id;
+        with_host(|host| host.parents.insert(id, root_id));
+        on_behalf_of(id, || unsafe { proxy_on_context_create(id, root_id) });
+        FakeHttpContext { id }
+    }
+}
+
+/// HTTP stream created by a [`FakeRootContext`].
+///
+/// [`FakeRootContext`]: struct.FakeRootContext.html
+#[derive(Debug)]
+pub struct FakeHttpContext {
+    id: u32,
+}
+
+impl FakeHttpContext {
+    /// Returns the context id.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Simulates `proxy_on_request_headers`.
+    pub fn request_headers(&self, headers: &[(&str, &str)], end_of_stream: bool) -> FakeAction {
+        let id = self.id;
+        let headers = HeaderMap::from(headers);
+        let num_headers = headers.len();
+        with_host(|host| host.maps.insert(map_type::HTTP_REQUEST_HEADERS, headers));
+        on_behalf_of(id, || unsafe {
+            proxy_on_request_headers(id, num_headers, end_of_stream)
+        })
+        .into()
+    }
+
+    /// Simulates `proxy_on_request_body`.
+    pub fn request_body(&self, body: &[u8], end_of_stream: bool) -> FakeAction {
+        let id = self.id;
+        with_host(|host| {
+            host.buffers
+                .insert(buffer_type::HTTP_REQUEST_BODY, body.to_vec())
+        });
+        on_behalf_of(id, || unsafe {
+            proxy_on_request_body(id, body.len(), end_of_stream)
+        })
+        .into()
+    }
+
+    /// Simulates `proxy_on_request_trailers`.
+    pub fn request_trailers(&self, trailers: &[(&str, &str)]) -> FakeAction {
+        let id = self.id;
+        let trailers = HeaderMap::from(trailers);
+        let num_trailers = trailers.len();
+        with_host(|host| host.maps.insert(map_type::HTTP_REQUEST_TRAILERS, trailers));
+        on_behalf_of(id, || unsafe {
+            proxy_on_request_trailers(id, num_trailers)
+        })
+        .into()
+    }
+
+    /// Simulates `proxy_on_response_headers`.
+    pub fn response_headers(&self, headers: &[(&str, &str)], end_of_stream: bool) -> FakeAction {
+        let id = self.id;
+        let headers = HeaderMap::from(headers);
+        let num_headers = headers.len();
+        with_host(|host| host.maps.insert(map_type::HTTP_RESPONSE_HEADERS, headers));
+        on_behalf_of(id, || unsafe {
+            proxy_on_response_headers(id, num_headers, end_of_stream)
+        })
+        .into()
+    }
+
+    /// Simulates `proxy_on_response_body`.
+    pub fn response_body(&self, body: &[u8], end_of_stream: bool) -> FakeAction {
+        let id = self.id;
+        with_host(|host| {
+            host.buffers
+                .insert(buffer_type::HTTP_RESPONSE_BODY, body.to_vec())
+        });
+        on_behalf_of(id, || unsafe {
+            proxy_on_response_body(id, body.len(), end_of_stream)
+        })
+        .into()
+    }
+
+    /// Simulates `proxy_on_response_trailers`.
+    pub fn response_trailers(&self, trailers: &[(&str, &str)]) -> FakeAction {
+        let id = self.id;
+        let trailers = HeaderMap::from(trailers);
+        let num_trailers = trailers.len();
+        with_host(|host| host.maps.insert(map_type::HTTP_RESPONSE_TRAILERS, trailers));
+        on_behalf_of(id, || unsafe {
+            proxy_on_response_trailers(id, num_trailers)
+        })
+        .into()
+    }
+
+    /// Simulates completion of the HTTP stream, i.e. `proxy_on_done`,
+    /// `proxy_on_log` and `proxy_on_delete`.
+    pub fn complete(&self) {
+        let id = self.id;
+        on_behalf_of(id, || unsafe {
+            proxy_on_done(id);
+            proxy_on_log(id);
+            proxy_on_delete(id);
+        })
+    }
+
+    /// Returns a local reply sent by the extension, if any.
+    pub fn local_reply(&self) -> Option<FakeLocalReply> {
+        with_host(|host| host.context(self.id).local_reply.clone())
+    }
+
+    /// Returns `true` if the extension has reset the HTTP stream.
+    pub fn is_reset(&self) -> bool {
+        with_host(|host| {
+            host.context(self.id)
+                .closed_streams
+                .contains(&stream_type::REQUEST)
+        })
+    }
+
+    /// Returns how many times the extension has resumed the HTTP request.
+    pub fn resumed_requests(&self) -> usize {
+        with_host(|host| host.context(self.id).resumed_requests)
+    }
+
+    /// Returns how many times the extension has resumed the HTTP response.
+    pub fn resumed_responses(&self) -> usize {
+        with_host(|host| host.context(self.id).resumed_responses)
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fake `Shared Data API`.
+//!
+//! # Examples
+//!
+//! #### Basic usage of [`FakeSharedData`]:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use envoy::host::SharedData;
+//! use envoy_test::FakeSharedData;
+//!
+//! # fn main() -> envoy::host::Result<()> {
+//! let shared_data = FakeSharedData::default();
+//!
+//! shared_data.set("my.key", b"1", None)?;
+//!
+//! let (value, version) = shared_data.get("my.key")?;
+//! assert_eq!(value, Some("1".into()));
+//!
+//! shared_data.set("my.key", b"2", version)?;
+//!
+//! // the version has changed, so the stale write is rejected
+//! assert!(shared_data.set("my.key", b"3", version).is_err());
+//! assert_eq!(shared_data.value("my.key"), Some("2".into()));
+//! assert_eq!(shared_data.cas_mismatches(), 1);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! #### Simulating concurrent writes by other VMs:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use envoy::host::SharedData;
+//! use envoy_test::FakeSharedData;
+//!
+//! # fn main() -> envoy::host::Result<()> {
+//! let shared_data = FakeSharedData::default().with_entry("my.key", "1");
+//!
+//! let (_, version) = shared_data.get("my.key")?;
+//!
+//! shared_data.simulate_concurrent_writes(1);
+//!
+//! assert!(shared_data.set("my.key", b"2", version).is_err());
+//! assert_eq!(shared_data.value("my.key"), Some("1".into()));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`FakeSharedData`]: struct.FakeSharedData.html
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use envoy::host::shared_data::{CasMismatchError, OptimisticLockVersion, SharedData};
+use envoy::host::{ByteString, Result};
+
+/// Fake `Shared Data`.
+///
+/// Mimics `Envoy` semantics:
+/// * every write assigns a new version to the key, unique across all keys,
+/// * a write with a version only succeeds if the key hasn't been written since,
+/// * a write with a version of a key that doesn't exist yet always succeeds.
+#[derive(Debug, Default)]
+pub struct FakeSharedData {
+    entries: RefCell<HashMap<String, (ByteString, OptimisticLockVersion)>>,
+    last_version: Cell<OptimisticLockVersion>,
+    concurrent_writes: Cell<usize>,
+    cas_mismatches: Cell<u64>,
+}
+
+impl SharedData for FakeSharedData {
+    /// Returns shared data by key.
+    fn get(&self, key: &str) -> Result<(Option<ByteString>, Option<OptimisticLockVersion>)> {
+        Ok(self
+            .entries
+            .borrow()
+            .get(key)
+            .map_or((None, None), |(value, version)| {
+                (Some(value.clone()), Some(*version))
+            }))
+    }
+
+    /// Sets shared data by key.
+    fn set(&self, key: &str, value: &[u8], version: Option<OptimisticLockVersion>) -> Result<()> {
+        if self.concurrent_writes.get() > 0 {
+            self.concurrent_writes.set(self.concurrent_writes.get() - 1);
+            let current = self.value(key);
+            if let Some(current) = current {
+                self.write(key, current);
+            }
+        }
+        let current_version = self.version(key);
+        if let (Some(version), Some(current_version)) = (version, current_version) {
+            if version != current_version {
+                self.cas_mismatches.set(self.cas_mismatches.get() + 1);
+                return Err(CasMismatchError::new(key).into());
+            }
+        }
+        self.write(key, value.into());
+        Ok(())
+    }
+}
+
+impl FakeSharedData {
+    /// Adds an entry as if it was written by another VM earlier.
+    pub fn with_entry<K, V>(self, key: K, value: V) -> Self
+    where
+        K: AsRef<str>,
+        V: Into<ByteString>,
+    {
+        self.write(key.as_ref(), value.into());
+        self
+    }
+
+    /// Makes the next `n` calls to `set` race with a concurrent write by another VM.
+    ///
+    /// The concurrent write leaves the value of the key as is but changes its version,
+    /// so that a write conditioned on the version read earlier fails with
+    /// [`CasMismatchError`].
+    ///
+    /// [`CasMismatchError`]: https://docs.rs/envoy-sdk/latest/envoy_sdk/host/shared_data/struct.CasMismatchError.html
+    pub fn simulate_concurrent_writes(&self, n: usize) -> &Self {
+        self.concurrent_writes.set(n);
+        self
+    }
+
+    /// Returns the current value of a given key.
+    pub fn value(&self, key: &str) -> Option<ByteString> {
+        self.entries
+            .borrow()
+            .get(key)
+            .map(|(value, _)| value.clone())
+    }
+
+    /// Returns the current version of a given key.
+    pub fn version(&self, key: &str) -> Option<OptimisticLockVersion> {
+        self.entries.borrow().get(key).map(|(_, version)| *version)
+    }
+
+    /// Returns all keys in lexicographical order.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.entries.borrow().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Returns all entries that have a given key prefix, in lexicographical order of keys.
+    pub fn entries_with_prefix(&self, prefix: &str) -> Vec<(String, ByteString)> {
+        let mut entries: Vec<(String, ByteString)> = self
+            .entries
+            .borrow()
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries
+    }
+
+    /// Returns the number of writes rejected due to a version mismatch so far.
+    pub fn cas_mismatches(&self) -> u64 {
+        self.cas_mismatches.get()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    fn write(&self, key: &str, value: ByteString) {
+        let version = self.last_version.get() + 1;
+        self.last_version.set(version);
+        self.entries
+            .borrow_mut()
+            .insert(key.to_owned(), (value, version));
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fake `Shared Queue API`.
+//!
+//! # Examples
+//!
+//! #### Basic usage of [`FakeSharedQueue`]:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use envoy::host::SharedQueue;
+//! use envoy_test::FakeSharedQueue;
+//!
+//! # fn main() -> envoy::host::Result<()> {
+//! let shared_queue = FakeSharedQueue::new("my_vm");
+//!
+//! let queue_id = shared_queue.register("events")?;
+//! assert_eq!(shared_queue.lookup("my_vm", "events")?, Some(queue_id));
+//!
+//! shared_queue.enqueue(queue_id, b"hello")?;
+//!
+//! assert_eq!(shared_queue.drain_ready_notifications(), vec![queue_id]);
+//! assert_eq!(shared_queue.dequeue(queue_id)?, Some("hello".into()));
+//! assert_eq!(shared_queue.dequeue(queue_id)?, None);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! #### Talking to a queue registered by another VM:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use envoy::host::SharedQueue;
+//! use envoy_test::FakeSharedQueue;
+//!
+//! # fn main() -> envoy::host::Result<()> {
+//! let shared_queue = FakeSharedQueue::new("my_vm");
+//!
+//! let queue_id = shared_queue.register_in_vm("singleton", "events");
+//!
+//! assert_eq!(shared_queue.lookup("my_vm", "events")?, None);
+//! assert_eq!(shared_queue.lookup("singleton", "events")?, Some(queue_id));
+//!
+//! shared_queue.enqueue(queue_id, b"hello")?;
+//!
+//! // only the VM that has registered the queue can dequeue from it
+//! assert!(shared_queue.dequeue(queue_id).is_err());
+//! assert_eq!(shared_queue.messages(queue_id), vec!["hello"]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`FakeSharedQueue`]: struct.FakeSharedQueue.html
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+
+use envoy::error::format_err;
+use envoy::host::shared_queue::{SharedQueue, SharedQueueHandle};
+use envoy::host::{ByteString, Result};
+
+/// Fake `Shared Queue`.
+///
+/// Simulates queues of several VMs:
+/// * [`register`] registers a queue on behalf of the VM under test,
+/// * [`register_in_vm`] registers a queue on behalf of any other VM,
+/// * [`lookup`] resolves queues registered by any VM,
+/// * [`dequeue`] only succeeds on queues registered by the VM under test.
+///
+/// Every successful [`enqueue`] produces a queue-ready notification,
+/// which can be observed with [`drain_ready_notifications`].
+///
+/// [`register`]: #method.register
+/// [`register_in_vm`]: #method.register_in_vm
+/// [`lookup`]: #method.lookup
+/// [`dequeue`]: #method.dequeue
+/// [`enqueue`]: #method.enqueue
+/// [`drain_ready_notifications`]: #method.drain_ready_notifications
+#[derive(Debug)]
+pub struct FakeSharedQueue {
+    vm_id: String,
+    capacity: Option<usize>,
+    counter: Cell<u32>,
+    lookups: Cell<usize>,
+    names: RefCell<HashMap<(String, String), SharedQueueHandle>>,
+    queues: RefCell<HashMap<SharedQueueHandle, FakeQueue>>,
+    notifications: RefCell<Vec<SharedQueueHandle>>,
+}
+
+#[derive(Debug)]
+struct FakeQueue {
+    vm_id: String,
+    messages: VecDeque<ByteString>,
+}
+
+impl Default for FakeSharedQueue {
+    /// Returns a fake on behalf of a VM with an empty id.
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl SharedQueue for FakeSharedQueue {
+    /// Registers a queue on behalf of the VM under test.
+    fn register(&self, name: &str) -> Result<SharedQueueHandle> {
+        Ok(self.register_in_vm(&self.vm_id, name))
+    }
+
+    /// Looks up a queue registered by a given VM.
+    fn lookup(&self, vm_id: &str, name: &str) -> Result<Option<SharedQueueHandle>> {
+        self.lookups.set(self.lookups.get() + 1);
+        Ok(self
+            .names
+            .borrow()
+            .get(&(vm_id.to_owned(), name.to_owned()))
+            .copied())
+    }
+
+    /// Dequeues a message from a queue registered by the VM under test.
+    fn dequeue(&self, queue_id: SharedQueueHandle) -> Result<Option<ByteString>> {
+        let mut queues = self.queues.borrow_mut();
+        match queues.get_mut(&queue_id) {
+            Some(queue) if queue.vm_id == self.vm_id => Ok(queue.messages.pop_front()),
+            _ => Err(format_err!("unknown queue {}", queue_id)),
+        }
+    }
+
+    /// Enqueues a message to a queue registered by any VM.
+    fn enqueue(&self, queue_id: SharedQueueHandle, value: &[u8]) -> Result<()> {
+        let mut queues = self.queues.borrow_mut();
+        let queue = queues
+            .get_mut(&queue_id)
+            .ok_or_else(|| format_err!("unknown queue {}", queue_id))?;
+        if self
+            .capacity
+            .is_some_and(|capacity| queue.messages.len() >= capacity)
+        {
+            return Err(format_err!("queue {} is full", queue_id));
+        }
+        queue.messages.push_back(value.into());
+        self.notifications.borrow_mut().push(queue_id);
+        Ok(())
+    }
+}
+
+impl FakeSharedQueue {
+    /// Returns a fake on behalf of a VM with a given id.
+    pub fn new<T>(vm_id: T) -> Self
+    where
+        T: Into<String>,
+    {
+        FakeSharedQueue {
+            vm_id: vm_id.into(),
+            capacity: None,
+            counter: Cell::new(0),
+            lookups: Cell::new(0),
+            names: RefCell::default(),
+            queues: RefCell::default(),
+            notifications: RefCell::default(),
+        }
+    }
+
+    /// Limits the number of messages a single queue can hold.
+    ///
+    /// `Envoy` doesn't have such a limit, but it is useful to simulate
+    /// a failure to enqueue.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Returns id of the VM under test.
+    pub fn vm_id(&self) -> &str {
+        &self.vm_id
+    }
+
+    /// Registers a queue on behalf of a given VM.
+    ///
+    /// Registering the same name twice returns the same queue.
+    pub fn register_in_vm(&self, vm_id: &str, name: &str) -> SharedQueueHandle {
+        let key = (vm_id.to_owned(), name.to_owned());
+        if let Some(queue_id) = self.names.borrow().get(&key) {
+            return *queue_id;
+        }
+        let queue_id = SharedQueueHandle::from(self.counter.get() + 1);
+        self.counter.set(self.counter.get() + 1);
+        self.names.borrow_mut().insert(key, queue_id);
+        self.queues.borrow_mut().insert(
+            queue_id,
+            FakeQueue {
+                vm_id: vm_id.to_owned(),
+                messages: VecDeque::new(),
+            },
+        );
+        queue_id
+    }
+
+    /// Returns messages currently in a given queue, without dequeuing them.
+    pub fn messages(&self, queue_id: SharedQueueHandle) -> Vec<ByteString> {
+        self.queues
+            .borrow()
+            .get(&queue_id)
+            .map(|queue| queue.messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of messages currently in a given queue.
+    pub fn len(&self, queue_id: SharedQueueHandle) -> usize {
+        self.queues
+            .borrow()
+            .get(&queue_id)
+            .map_or(0, |queue| queue.messages.len())
+    }
+
+    /// Returns `true` if a given queue has no messages.
+    pub fn is_empty(&self, queue_id: SharedQueueHandle) -> bool {
+        self.len(queue_id) == 0
+    }
+
+    /// Returns the number of `lookup` calls made so far.
+    pub fn lookups(&self) -> usize {
+        self.lookups.get()
+    }
+
+    /// Returns queue-ready notifications `Envoy` would have delivered since the last call,
+    /// one per enqueued message, in order.
+    pub fn drain_ready_notifications(&self) -> Vec<SharedQueueHandle> {
+        self.notifications.replace(Vec::new())
+    }
+}
@@ -15,17 +15,51 @@
 //! Simulates the exact behaviour of `Envoy` or `Proxy Wasm` inside `Envoy`.
 
 use std::cmp;
+use std::fmt;
 
-use envoy::error::format_err;
 use envoy::host::{self, ByteString};
 
+/// An error returned from the call to `Proxy Wasm` inside Envoy.
+///
+/// Mirrors the error `Proxy Wasm` SDK returns when a host function
+/// responds with a status other than `Status::Ok`.
+#[derive(Debug)]
+pub struct HostCallError {
+    function: &'static str,
+    status: Status,
+}
+
+/// Status codes returned by `Proxy Wasm` inside Envoy.
+#[derive(Debug, Clone, Copy)]
+pub enum Status {
+    BadArgument = 2,
+}
+
+impl HostCallError {
+    pub fn new(function: &'static str, status: Status) -> Self {
+        HostCallError { function, status }
+    }
+}
+
+impl fmt::Display for HostCallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "call to the host ABI function \"env.{}\" has failed with status code {}",
+            self.function, self.status as u32
+        )
+    }
+}
+
+impl std::error::Error for HostCallError {}
+
 /// Reads buffer similarly to `Proxy Wasm` inside Envoy.
 pub fn get_buffer_bytes(buf: &[u8], offset: usize, max_size: usize) -> host::Result<ByteString> {
     // implementation based on `proxy-wasm/proxy-wasm-cpp-host`
 
     // Check for overflow.
     if let (_, true) = offset.overflowing_add(max_size) {
-        return Err(format_err!("Status::BadArgument"));
+        return Err(HostCallError::new("proxy_get_buffer_bytes", Status::BadArgument).into());
     }
     let max_size = cmp::min(max_size, buf.len() - offset);
     if max_size > 0 {
@@ -155,6 +155,103 @@ impl FakeStats {
     }
 }
 
+impl FakeStats {
+    /// Returns value of a counter that belongs to a given `MetricFamily`
+    /// and has given tags.
+    ///
+    /// Tags can be given in any order.
+    pub fn counter_with_tags(&self, family: &str, tags: &[(&str, &str)]) -> Option<u64> {
+        find_tagged(&self.counters.borrow(), family, tags).map(|counter| *counter.0.borrow())
+    }
+
+    /// Returns value of a gauge that belongs to a given `MetricFamily`
+    /// and has given tags.
+    ///
+    /// Tags can be given in any order.
+    pub fn gauge_with_tags(&self, family: &str, tags: &[(&str, &str)]) -> Option<u64> {
+        find_tagged(&self.gauges.borrow(), family, tags).map(|gauge| *gauge.0.borrow())
+    }
+
+    /// Returns values recorded by a histogram that belongs to a given `MetricFamily`
+    /// and has given tags.
+    ///
+    /// Tags can be given in any order.
+    pub fn histogram_with_tags(&self, family: &str, tags: &[(&str, &str)]) -> Option<Vec<u64>> {
+        find_tagged(&self.histograms.borrow(), family, tags)
+            .map(|histogram| histogram.0.borrow().clone())
+    }
+
+    /// Returns values recorded by a histogram with a given name.
+    pub fn histogram_values(&self, name: &str) -> Option<Vec<u64>> {
+        self.histograms
+            .borrow()
+            .get(name)
+            .map(|histogram| histogram.0.borrow().clone())
+    }
+
+    /// Returns tags of all metrics that belong to a given `MetricFamily`.
+    pub fn family_tags(&self, family: &str) -> Vec<Vec<(String, String)>> {
+        let mut tags: Vec<Vec<(String, String)>> = Vec::new();
+        let names = self
+            .counters
+            .borrow()
+            .keys()
+            .chain(self.gauges.borrow().keys())
+            .chain(self.histograms.borrow().keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            if let Some(metric_tags) = parse_tags(&name, family) {
+                if !tags.contains(&metric_tags) {
+                    tags.push(metric_tags);
+                }
+            }
+        }
+        tags.sort();
+        tags
+    }
+}
+
+/// Finds a metric of a given family with given tags.
+fn find_tagged<'a, M>(
+    metrics: &'a HashMap<String, Rc<M>>,
+    family: &str,
+    tags: &[(&str, &str)],
+) -> Option<&'a Rc<M>> {
+    let mut expected: Vec<(String, String)> = tags
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.replace('.', "_")))
+        .collect();
+    expected.sort();
+    metrics.iter().find_map(|(name, metric)| {
+        let mut actual = parse_tags(name, family)?;
+        actual.sort();
+        if actual == expected {
+            Some(metric)
+        } else {
+            None
+        }
+    })
+}
+
+/// Parses tags out of a stat name of a given family, e.g. `family.tag1.value1.tag2.value2`.
+fn parse_tags(name: &str, family: &str) -> Option<Vec<(String, String)>> {
+    if name == family {
+        return Some(Vec::new());
+    }
+    let rest = name.strip_prefix(family)?.strip_prefix('.')?;
+    let parts: Vec<&str> = rest.split('.').collect();
+    let pairs = parts.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    Some(
+        pairs
+            .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+            .collect(),
+    )
+}
+
 impl FakeCounter {
     /// Resets the counter to `0`.
     pub fn reset(&self) {
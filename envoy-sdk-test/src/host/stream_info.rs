@@ -61,15 +61,59 @@
 //! # }
 //! ```
 //!
+//! #### Filter state:
+//!
+//! ```
+//! # use envoy_sdk_test as envoy_test;
+//! use envoy::host::StreamInfo;
+//! use envoy::host::stream_info::{FilterStateOptions, LifeSpan};
+//! use envoy_test::FakeStreamInfo;
+//!
+//! # fn main() -> envoy::host::Result<()> {
+//! let fake_info = FakeStreamInfo::new().with(|info| {
+//!     info.filter_state()
+//!         .value("my_extension.tenant", &"tenant-a");
+//! });
+//! let stream_info: &dyn StreamInfo = &fake_info;
+//!
+//! assert_eq!(
+//!     stream_info.filter_state().get::<String>("my_extension.tenant")?,
+//!     Some("tenant-a".to_owned())
+//! );
+//!
+//! stream_info.filter_state().declare(
+//!     "my_extension.user",
+//!     FilterStateOptions::new().read_only(true),
+//! )?;
+//! stream_info.filter_state().put("my_extension.user", &"alice")?;
+//!
+//! assert!(stream_info.filter_state().put("my_extension.user", &"bob").is_err());
+//! assert_eq!(
+//!     fake_info.declared_filter_state("my_extension.user"),
+//!     Some(FilterStateOptions::new().read_only(true))
+//! );
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! [`FakeStreamInfo`]: struct.FakeStreamInfo.html
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::time::{Duration, SystemTime};
 
+use serde::Serialize;
+
 use envoy::extension::access_logger;
-use envoy::host::stream_info::{ResponseFlags, StreamInfo, TrafficDirection};
+use envoy::host::stream_info::encoding::Value;
+use envoy::host::stream_info::{
+    FilterStateOptions, Metadata, Property, ResponseFlags, StreamInfo, TrafficDirection,
+};
 use envoy::host::{self, ByteString, HeaderMap};
 
 use crate::host::http::FakeHttpMessage;
+use crate::host::simulate::{HostCallError, Status};
 
 /// Represents fake `Stream Info`.
 #[derive(Debug, Default, Clone)]
@@ -84,6 +128,17 @@ pub struct FakeStreamInfo {
     route: Option<FakeRouteInfo>,
     cluster: Option<FakeClusterInfo>,
     plugin: Option<FakePluginInfo>,
+    xds: Option<FakeXdsInfo>,
+    metadata: Option<Metadata>,
+    properties: RefCell<HashMap<Vec<String>, ByteString>>,
+    filter_state: RefCell<FakeFilterState>,
+}
+
+/// Represents `filter state`.
+#[derive(Debug, Default, Clone)]
+struct FakeFilterState {
+    values: HashMap<String, ByteString>,
+    declarations: HashMap<String, FilterStateOptions>,
 }
 
 /// Represents `connection` info.
@@ -91,6 +146,7 @@ pub struct FakeStreamInfo {
 struct FakeConnectionInfo {
     id: u64,
     requested_server_name: String,
+    termination_details: Option<String>,
     tls: Option<FakeTlsInfo>,
 }
 
@@ -104,6 +160,7 @@ struct FakeTlsInfo {
     uri_san_peer_certificate: Option<String>,
     dns_san_local_certificate: Option<String>,
     dns_san_peer_certificate: Option<String>,
+    sha256_peer_certificate_digest: Option<String>,
 }
 
 /// Represents `request` info.
@@ -125,6 +182,7 @@ struct FakeResponseInfo {
     size: u64,
     total_size: u64,
     flags: ResponseFlags,
+    code_details: Option<String>,
 }
 
 /// Represents `upstream` info.
@@ -134,6 +192,7 @@ struct FakeUpstreamInfo {
     port: u32,
     local_address: Option<String>,
     transport_failure_reason: Option<String>,
+    request_attempt_count: Option<u32>,
     tls: Option<FakeTlsInfo>,
 }
 
@@ -170,6 +229,16 @@ struct FakePluginInfo {
     vm_id: String,
 }
 
+/// Represents `xds` info.
+#[derive(Debug, Default, Clone)]
+struct FakeXdsInfo {
+    node_id: Option<String>,
+    cluster_metadata: Option<Metadata>,
+    route_metadata: Option<Metadata>,
+    upstream_host_metadata: Option<Metadata>,
+    listener_metadata: Option<Metadata>,
+}
+
 /// Builder for `connection` properties within [`FakeStreamInfo`].
 ///
 /// [`FakeStreamInfo`]: struct.FakeStreamInfo.html
@@ -247,6 +316,20 @@ pub struct FakePluginInfoBuilder<'a> {
     plugin: &'a mut Option<FakePluginInfo>,
 }
 
+/// Builder for `xds` properties within [`FakeStreamInfo`].
+///
+/// [`FakeStreamInfo`]: struct.FakeStreamInfo.html
+pub struct FakeXdsInfoBuilder<'a> {
+    xds: &'a mut Option<FakeXdsInfo>,
+}
+
+/// Builder for `filter state` within [`FakeStreamInfo`].
+///
+/// [`FakeStreamInfo`]: struct.FakeStreamInfo.html
+pub struct FakeFilterStateBuilder<'a> {
+    filter_state: &'a mut FakeFilterState,
+}
+
 impl FakeStreamInfo {
     /// Returns a new instance.
     pub fn new() -> Self {
@@ -345,6 +428,70 @@ impl FakeStreamInfo {
         }
     }
 
+    /// Returns a builder for `xds` properties.
+    pub fn xds(&mut self) -> FakeXdsInfoBuilder<'_> {
+        FakeXdsInfoBuilder { xds: &mut self.xds }
+    }
+
+    /// Sets the value of dynamic `metadata` property.
+    pub fn metadata(&mut self, value: Metadata) -> &mut Self {
+        self.metadata = Some(value);
+        self
+    }
+
+    /// Sets the value of an arbitrary property, e.g. a vendor-specific attribute.
+    ///
+    /// Takes precedence over values of built-in properties.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use envoy_sdk_test as envoy_test;
+    /// use envoy::host::StreamInfo;
+    /// use envoy::host::stream_info::{encoding, Property};
+    /// use envoy_test::FakeStreamInfo;
+    ///
+    /// const RETRY_BUDGET: &Property<i64, encoding::Int64> =
+    ///     &Property::new(&["vendor", "retry_budget"]);
+    ///
+    /// # fn main() -> envoy::host::Result<()> {
+    /// let fake_info = FakeStreamInfo::new().with(|info| {
+    ///     info.property(RETRY_BUDGET, 5);
+    /// });
+    /// let stream_info: &dyn StreamInfo = &fake_info;
+    ///
+    /// assert_eq!(stream_info.property(RETRY_BUDGET)?, Some(5));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn property<T, W>(&mut self, prop: &Property<'_, T, W>, value: T) -> &mut Self
+    where
+        Value<W>: TryFrom<T, Error = host::Error>,
+    {
+        let encoded = Value::<W>::try_from(value).expect("value must be encodable");
+        let path = prop
+            .path()
+            .iter()
+            .map(|&segment| segment.to_owned())
+            .collect();
+        self.properties
+            .get_mut()
+            .insert(path, encoded.into_bytes().into());
+        self
+    }
+
+    /// Returns a builder for `filter state`.
+    pub fn filter_state(&mut self) -> FakeFilterStateBuilder<'_> {
+        FakeFilterStateBuilder {
+            filter_state: self.filter_state.get_mut(),
+        }
+    }
+
+    /// Returns options a given `filter state` entry has been declared with.
+    pub fn declared_filter_state(&self, key: &str) -> Option<FilterStateOptions> {
+        self.filter_state.borrow().declarations.get(key).copied()
+    }
+
     /// Returns a builder for `plugin` properties.
     pub fn plugin(&mut self) -> FakePluginInfoBuilder<'_> {
         FakePluginInfoBuilder {
@@ -366,6 +513,7 @@ impl FakeTlsInfo {
         self.subject_peer_certificate.is_some()
             || self.uri_san_peer_certificate.is_some()
             || self.dns_san_peer_certificate.is_some()
+            || self.sha256_peer_certificate_digest.is_some()
     }
 
     /// Returns `true` if both local and peer certificate properties have been set.
@@ -427,6 +575,17 @@ impl<'a> FakeConnectionInfoBuilder<'a> {
         self
     }
 
+    /// Sets the value of connection `termination_details` property.
+    pub fn termination_details<T>(&mut self, value: T) -> &mut Self
+    where
+        T: AsRef<str>,
+    {
+        self.connection
+            .get_or_insert_with(Default::default)
+            .termination_details = Some(value.as_ref().to_owned());
+        self
+    }
+
     /// Returns a builder for `tls` properties of the downstream connection.
     pub fn tls(&mut self) -> FakeTlsInfoBuilder<'_> {
         FakeTlsInfoBuilder {
@@ -510,6 +669,17 @@ impl<'a> FakeTlsInfoBuilder<'a> {
             .dns_san_peer_certificate = Some(value.as_ref().to_owned());
         self
     }
+
+    /// Sets the value of `sha256_peer_certificate_digest` property.
+    pub fn sha256_peer_certificate_digest<T>(&mut self, value: T) -> &mut Self
+    where
+        T: AsRef<str>,
+    {
+        self.tls
+            .get_or_insert_with(Default::default)
+            .sha256_peer_certificate_digest = Some(value.as_ref().to_owned());
+        self
+    }
 }
 
 impl<'a> FakeRequestInfoBuilder<'a> {
@@ -527,6 +697,20 @@ impl<'a> FakeRequestInfoBuilder<'a> {
         self
     }
 
+    /// Sets the value of an HTTP request trailer.
+    pub fn trailer<K, V>(&mut self, name: K, value: V) -> &mut Self
+    where
+        K: Into<ByteString>,
+        V: Into<ByteString>,
+    {
+        self.request
+            .get_or_insert_with(Default::default)
+            .message
+            .trailers
+            .insert(name, value);
+        self
+    }
+
     /// Sets the value of `:method` pseudo-header.
     pub fn method<V>(&mut self, value: V) -> &mut Self
     where
@@ -690,6 +874,17 @@ impl<'a> FakeResponseInfoBuilder<'a> {
             .insert("grpc-status", value.to_string());
         self
     }
+
+    /// Sets the value of response `code_details` property.
+    pub fn code_details<T>(&mut self, value: T) -> &mut Self
+    where
+        T: AsRef<str>,
+    {
+        self.response
+            .get_or_insert_with(Default::default)
+            .code_details = Some(value.as_ref().to_owned());
+        self
+    }
 }
 
 impl<'a> FakeUpstreamInfoBuilder<'a> {
@@ -730,6 +925,14 @@ impl<'a> FakeUpstreamInfoBuilder<'a> {
         self
     }
 
+    /// Sets the value of upstream `request_attempt_count` property.
+    pub fn request_attempt_count(&mut self, value: u32) -> &mut Self {
+        self.upstream
+            .get_or_insert_with(Default::default)
+            .request_attempt_count = Some(value);
+        self
+    }
+
     /// Returns a builder for `tls` properties of the upstream connection.
     pub fn tls(&mut self) -> FakeTlsInfoBuilder<'_> {
         FakeTlsInfoBuilder {
@@ -835,8 +1038,68 @@ impl<'a> FakePluginInfoBuilder<'a> {
     }
 }
 
+impl<'a> FakeXdsInfoBuilder<'a> {
+    /// Sets the value of `node` `id` property.
+    pub fn node_id<T>(&mut self, value: T) -> &mut Self
+    where
+        T: AsRef<str>,
+    {
+        self.xds.get_or_insert_with(Default::default).node_id = Some(value.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the value of `cluster_metadata` property.
+    pub fn cluster_metadata(&mut self, value: Metadata) -> &mut Self {
+        self.xds
+            .get_or_insert_with(Default::default)
+            .cluster_metadata = Some(value);
+        self
+    }
+
+    /// Sets the value of `route_metadata` property.
+    pub fn route_metadata(&mut self, value: Metadata) -> &mut Self {
+        self.xds.get_or_insert_with(Default::default).route_metadata = Some(value);
+        self
+    }
+
+    /// Sets the value of `upstream_host_metadata` property.
+    pub fn upstream_host_metadata(&mut self, value: Metadata) -> &mut Self {
+        self.xds
+            .get_or_insert_with(Default::default)
+            .upstream_host_metadata = Some(value);
+        self
+    }
+
+    /// Sets the value of `listener_metadata` property.
+    pub fn listener_metadata(&mut self, value: Metadata) -> &mut Self {
+        self.xds
+            .get_or_insert_with(Default::default)
+            .listener_metadata = Some(value);
+        self
+    }
+}
+
+impl<'a> FakeFilterStateBuilder<'a> {
+    /// Sets the value of a given `filter state` entry.
+    pub fn value<K, T>(&mut self, key: K, value: &T) -> &mut Self
+    where
+        K: AsRef<str>,
+        T: Serialize + ?Sized,
+    {
+        let encoded = serde_json::to_vec(value).expect("value must be serializable into JSON");
+        self.filter_state
+            .values
+            .insert(key.as_ref().to_owned(), encoded.into());
+        self
+    }
+}
+
 impl StreamInfo for FakeStreamInfo {
     fn stream_property(&self, path: &[&str]) -> host::Result<Option<ByteString>> {
+        let custom_path: Vec<String> = path.iter().map(|&segment| segment.to_owned()).collect();
+        if let Some(value) = self.properties.borrow().get(&custom_path) {
+            return Ok(Some(value.clone()));
+        }
         let encoded = match path {
             // connection
             ["connection_id"] => self
@@ -856,6 +1119,11 @@ impl StreamInfo for FakeStreamInfo {
                 .as_ref()
                 .map(|con| &con.requested_server_name)
                 .map(Encoder::encode_str),
+            ["connection", "termination_details"] => self
+                .connection
+                .as_ref()
+                .and_then(|con| con.termination_details.as_ref())
+                .map(Encoder::encode_str),
             ["connection", "tls_version"] => self
                 .connection
                 .as_ref()
@@ -912,7 +1180,18 @@ impl StreamInfo for FakeStreamInfo {
                 .map(|tls| tls.dns_san_peer_certificate.as_ref())
                 .flatten()
                 .map(Encoder::encode_str),
+            ["connection", "sha256_peer_certificate_digest"] => self
+                .connection
+                .as_ref()
+                .and_then(|con| con.tls.as_ref())
+                .and_then(|tls| tls.sha256_peer_certificate_digest.as_ref())
+                .map(Encoder::encode_str),
             // request
+            ["request", "headers"] => self
+                .request
+                .as_ref()
+                .map(|request| &request.message.headers)
+                .map(Encoder::encode_map),
             ["request", "headers", name] => self
                 .request
                 .as_ref()
@@ -997,6 +1276,16 @@ impl StreamInfo for FakeStreamInfo {
                 .flatten()
                 .map(Encoder::encode_str),
             // response
+            ["response", "headers"] => self
+                .response
+                .as_ref()
+                .map(|response| &response.message.headers)
+                .map(Encoder::encode_map),
+            ["response", "trailers"] => self
+                .response
+                .as_ref()
+                .map(|response| &response.message.trailers)
+                .map(Encoder::encode_map),
             ["response", "headers", name] => self
                 .response
                 .as_ref()
@@ -1040,6 +1329,11 @@ impl StreamInfo for FakeStreamInfo {
                 .flatten()
                 .map(|status_code| status_code as i64)
                 .map(Encoder::encode_i64),
+            ["response", "code_details"] => self
+                .response
+                .as_ref()
+                .and_then(|response| response.code_details.as_ref())
+                .map(Encoder::encode_str),
             // upstream
             ["upstream", "address"] => self
                 .upstream
@@ -1119,6 +1413,17 @@ impl StreamInfo for FakeStreamInfo {
                 .map(|tls| tls.dns_san_peer_certificate.as_ref())
                 .flatten()
                 .map(Encoder::encode_str),
+            ["upstream", "sha256_peer_certificate_digest"] => self
+                .upstream
+                .as_ref()
+                .and_then(|upstream| upstream.tls.as_ref())
+                .and_then(|tls| tls.sha256_peer_certificate_digest.as_ref())
+                .map(Encoder::encode_str),
+            ["upstream", "request_attempt_count"] => self
+                .upstream
+                .as_ref()
+                .and_then(|upstream| upstream.request_attempt_count)
+                .map(|count| Encoder::encode_u64(count as u64)),
             // source
             ["source", "address"] => self
                 .source
@@ -1175,12 +1480,77 @@ impl StreamInfo for FakeStreamInfo {
                 .as_ref()
                 .map(|plugin| &plugin.vm_id)
                 .map(Encoder::encode_str),
+            // xds
+            ["xds", "node", "id"] => self
+                .xds
+                .as_ref()
+                .and_then(|xds| xds.node_id.as_ref())
+                .map(Encoder::encode_str),
+            ["xds", "cluster_metadata"] => self
+                .xds
+                .as_ref()
+                .and_then(|xds| xds.cluster_metadata.as_ref())
+                .map(Encoder::encode_metadata),
+            ["xds", "route_metadata"] => self
+                .xds
+                .as_ref()
+                .and_then(|xds| xds.route_metadata.as_ref())
+                .map(Encoder::encode_metadata),
+            ["xds", "upstream_host_metadata"] => self
+                .xds
+                .as_ref()
+                .and_then(|xds| xds.upstream_host_metadata.as_ref())
+                .map(Encoder::encode_metadata),
+            ["xds", "listener_metadata"] => self
+                .xds
+                .as_ref()
+                .and_then(|xds| xds.listener_metadata.as_ref())
+                .map(Encoder::encode_metadata),
+            // metadata
+            ["metadata"] => self.metadata.as_ref().map(Encoder::encode_metadata),
+            // filter state
+            [key] => self
+                .filter_state
+                .borrow()
+                .values
+                .get(*key)
+                .cloned()
+                .map(|value| Ok(Some(value))),
             _ => None,
         };
         encoded.unwrap_or_else(|| Ok(None))
     }
 
-    fn set_stream_property(&self, _path: &[&str], _value: &[u8]) -> host::Result<()> {
+    fn set_stream_property(&self, path: &[&str], value: &[u8]) -> host::Result<()> {
+        // `Envoy` only saves values under a single-segment path
+        let key = match path {
+            [key] => *key,
+            _ => return Err(HostCallError::new("proxy_set_property", Status::BadArgument).into()),
+        };
+        let mut filter_state = self.filter_state.borrow_mut();
+        let options = filter_state.declarations.get(key).copied();
+        if options.is_none() && !filter_state.values.contains_key(key) {
+            // not a `filter state` entry
+            self.properties
+                .borrow_mut()
+                .insert(vec![key.to_owned()], value.to_vec().into());
+            return Ok(());
+        }
+        let read_only = options.is_some_and(|options| options.read_only);
+        if read_only && filter_state.values.contains_key(key) {
+            return Err(HostCallError::new("proxy_set_property", Status::BadArgument).into());
+        }
+        filter_state
+            .values
+            .insert(key.to_owned(), value.to_vec().into());
+        Ok(())
+    }
+
+    fn declare_stream_property(&self, name: &str, options: FilterStateOptions) -> host::Result<()> {
+        self.filter_state
+            .borrow_mut()
+            .declarations
+            .insert(name.to_owned(), options);
         Ok(())
     }
 }
@@ -1202,6 +1572,21 @@ impl access_logger::LogOps for FakeStreamInfo {
             .flatten())
     }
 
+    fn request_trailers(&self) -> host::Result<HeaderMap> {
+        Ok(self
+            .request
+            .as_ref()
+            .map(|request| request.message.trailers.clone())
+            .unwrap_or_default())
+    }
+
+    fn request_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        Ok(self
+            .request
+            .as_ref()
+            .and_then(|request| request.message.trailers.get(name).cloned()))
+    }
+
     fn response_headers(&self) -> host::Result<HeaderMap> {
         Ok(self
             .response
@@ -1267,4 +1652,96 @@ impl Encoder {
         let value = value.as_secs() * 1_000_000_000 + value.subsec_nanos() as u64;
         Self::encode_i64(value as i64)
     }
+
+    /// Encodes a map in the same format as HTTP headers.
+    pub fn encode_map(value: &HeaderMap) -> host::Result<Option<ByteString>> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        for (name, value) in value {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        }
+        for (name, value) in value {
+            bytes.extend_from_slice(name);
+            bytes.push(0);
+            bytes.extend_from_slice(value);
+            bytes.push(0);
+        }
+        Ok(Some(bytes.into()))
+    }
+
+    /// Encodes `envoy.config.core.v3.Metadata` message in `Protobuf` binary wire format.
+    pub fn encode_metadata(value: &Metadata) -> host::Result<Option<ByteString>> {
+        let mut bytes = Vec::new();
+        for (filter, metadata) in value.iter() {
+            let mut entry = Vec::new();
+            protobuf::put_bytes(&mut entry, 1, filter.as_bytes());
+            protobuf::put_bytes(&mut entry, 2, &protobuf::encode_struct(metadata));
+            protobuf::put_bytes(&mut bytes, 1, &entry);
+        }
+        Ok(Some(bytes.into()))
+    }
+}
+
+/// Minimal encoder of `Protobuf` binary wire format.
+mod protobuf {
+    use serde_json::Value;
+
+    pub fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    pub fn put_bytes(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+        put_varint(buf, field << 3 | 2);
+        put_varint(buf, value.len() as u64);
+        buf.extend_from_slice(value);
+    }
+
+    /// Encodes `google.protobuf.Struct` message.
+    pub fn encode_struct(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if let Value::Object(fields) = value {
+            for (key, value) in fields {
+                let mut entry = Vec::new();
+                put_bytes(&mut entry, 1, key.as_bytes());
+                put_bytes(&mut entry, 2, &encode_value(value));
+                put_bytes(&mut buf, 1, &entry);
+            }
+        }
+        buf
+    }
+
+    /// Encodes `google.protobuf.Value` message.
+    fn encode_value(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match value {
+            Value::Null => {
+                put_varint(&mut buf, 1 << 3);
+                put_varint(&mut buf, 0);
+            }
+            Value::Number(number) => {
+                put_varint(&mut buf, 2 << 3 | 1);
+                let number = number.as_f64().unwrap_or_default();
+                buf.extend_from_slice(&number.to_bits().to_le_bytes());
+            }
+            Value::String(string) => put_bytes(&mut buf, 3, string.as_bytes()),
+            Value::Bool(flag) => {
+                put_varint(&mut buf, 4 << 3);
+                put_varint(&mut buf, *flag as u64);
+            }
+            Value::Object(_) => put_bytes(&mut buf, 5, &encode_struct(value)),
+            Value::Array(values) => {
+                let mut list = Vec::new();
+                for value in values {
+                    put_bytes(&mut list, 1, &encode_value(value));
+                }
+                put_bytes(&mut buf, 6, &list);
+            }
+        }
+        buf
+    }
 }
@@ -18,16 +18,31 @@
 //!
 //! * [`FakeClock`]
 //! * [`FakeHttpClient`]
+//! * [`FakeSharedData`]
+//! * [`FakeSharedQueue`]
 //! * [`FakeStats`]
 //! * [`FakeStreamInfo`]
 //!
 //! [`FakeClock`]: host/time/index.html
 //! [`FakeHttpClient`]: host/http/client/index.html
+//! [`FakeSharedData`]: host/shared_data/index.html
+//! [`FakeSharedQueue`]: host/shared_queue/index.html
 //! [`FakeStats`]: host/stats/index.html
 //! [`FakeStreamInfo`]: host/stream_info/index.html
+//!
+//! ## Supported test harnesses
+//!
+//! * [`FakeEnvoy`]
+//! * [`FakeHttpStream`]
+//!
+//! [`FakeEnvoy`]: extension/module/index.html
+//! [`FakeHttpStream`]: extension/filter/http/index.html
 
 #![doc(html_root_url = "https://docs.rs/envoy-sdk-test/0.0.1")]
 
+pub use self::extension::*;
 pub use self::host::*;
 
+mod abi;
+pub mod extension;
 pub mod host;
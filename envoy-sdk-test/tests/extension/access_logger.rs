@@ -0,0 +1,752 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::Cell;
+use std::time::{Duration, SystemTime};
+
+use envoy::error::bail;
+use envoy::extension::access_logger::{
+    BatchConfig, BatchEncoding, BatchSink, DrainOps, FilteredAccessLogger, JsonLogFormat,
+    LogFilter, LogFormat, LogOps, LogView,
+};
+use envoy::extension::filter::http::{
+    BodyCapture, RequestBodyOps, RequestFlowOps, ResponseBodyOps, ResponseFlowOps,
+};
+use envoy::extension::{self, AccessLogger, DrainStatus};
+use envoy::host::stream_info::{FilterStateOptions, LifeSpan, Metadata, ResponseFlags};
+use envoy::host::{ByteString, HeaderMap, HttpClient, HttpClientRequestHandle, Result, StreamInfo};
+
+use envoy_sdk_test as envoy_test;
+use envoy_test::{FakeClock, FakeHttpClient, FakeHttpClientResponse, FakeStreamInfo};
+
+#[test]
+fn test_log_view_http() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request()
+            .time(SystemTime::UNIX_EPOCH + Duration::from_secs(12))
+            .duration(Duration::from_millis(30))
+            .protocol("HTTP/2")
+            .method("GET")
+            .size(0)
+            .total_size(120);
+        info.response()
+            .status_code(200)
+            .header("content-type", "text/plain")
+            .size(10)
+            .total_size(90);
+        info.upstream().address("10.0.0.2:8080");
+        info.cluster().name("backend");
+    });
+    let view = LogView::new(&fake_info);
+
+    assert!(view.is_http()?);
+    assert_eq!(
+        view.start_time()?,
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(12))
+    );
+    assert_eq!(view.duration()?, Some(Duration::from_millis(30)));
+    assert_eq!(view.bytes_received()?, Some(120));
+    assert_eq!(view.bytes_sent()?, Some(90));
+    assert_eq!(view.upstream_host()?, Some("10.0.0.2:8080".to_owned()));
+    assert_eq!(view.upstream_cluster()?, Some("backend".to_owned()));
+    assert_eq!(view.response_code()?, Some(200));
+    assert_eq!(
+        view.request_headers()?,
+        Some(HeaderMap::builder().header(":method", "GET").build())
+    );
+    assert_eq!(view.request_header(":method")?, Some("GET".into()));
+    assert_eq!(
+        view.response_header("content-type")?,
+        Some("text/plain".into())
+    );
+    assert_eq!(view.response_header("content-length")?, None);
+    assert_eq!(view.response_trailers()?, Some(HeaderMap::default()));
+
+    Ok(())
+}
+
+#[test]
+fn test_log_view_tcp() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.connection()
+            .termination_details("idle timeout")
+            .tls()
+            .version("TLSv1.3");
+        info.request()
+            .time(SystemTime::UNIX_EPOCH + Duration::from_secs(12))
+            .duration(Duration::from_secs(5))
+            .size(1024);
+        info.response()
+            .size(2048)
+            .response_flags(ResponseFlags::STREAM_IDLE_TIMEOUT);
+        info.upstream()
+            .address("10.0.0.3:5432")
+            .transport_failure_reason("connection refused");
+    });
+    let view = LogView::new(&fake_info);
+
+    assert!(!view.is_http()?);
+    assert_eq!(view.duration()?, Some(Duration::from_secs(5)));
+    assert_eq!(view.bytes_received()?, Some(1024));
+    assert_eq!(view.bytes_sent()?, Some(2048));
+    assert_eq!(view.upstream_host()?, Some("10.0.0.3:5432".to_owned()));
+    assert_eq!(view.termination_details()?, Some("idle timeout".to_owned()));
+    assert_eq!(
+        view.response_flags()?,
+        Some(ResponseFlags::STREAM_IDLE_TIMEOUT)
+    );
+    assert_eq!(
+        view.upstream_transport_failure_reason()?,
+        Some("connection refused".to_owned())
+    );
+    assert_eq!(
+        view.connection().tls().version()?,
+        Some("TLSv1.3".to_owned())
+    );
+
+    // HTTP-specific data is not available
+    assert_eq!(view.request_headers()?, None);
+    assert_eq!(view.request_header(":method")?, None);
+    assert_eq!(view.response_headers()?, None);
+    assert_eq!(view.response_trailers()?, None);
+    assert_eq!(view.response_code()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_format_http() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request()
+            .time(SystemTime::UNIX_EPOCH + Duration::from_millis(1_604_406_896_789))
+            .duration(Duration::from_millis(30))
+            .protocol("HTTP/1.1")
+            .method("GET")
+            .path("/api/v1/items")
+            .header("user-agent", "curl/7.64.1")
+            .total_size(120);
+        info.response()
+            .status_code(200)
+            .header("x-request-id", "0123456789abcdef")
+            .total_size(90)
+            .response_flags(ResponseFlags::empty());
+        info.source().address("[::1]:54321");
+        info.upstream().address("10.0.0.2:8080");
+        info.cluster().name("backend");
+        info.metadata(
+            Metadata::builder()
+                .filter_metadata(
+                    "envoy.lb",
+                    serde_json::json!({"canary": true, "version": "v2"}),
+                )
+                .build(),
+        );
+    });
+
+    let format = LogFormat::parse(
+        r#"[%START_TIME%] "%REQ(:METHOD)% %REQ(X-ENVOY-ORIGINAL-PATH?:PATH)% %PROTOCOL%" %RESPONSE_CODE% %RESPONSE_FLAGS% %BYTES_RECEIVED% %BYTES_SENT% %DURATION% "%REQ(USER-AGENT):4%" "%RESP(X-REQUEST-ID):8%" "%REQ(REFERER)%" %UPSTREAM_HOST% %UPSTREAM_CLUSTER% %DOWNSTREAM_REMOTE_ADDRESS_WITHOUT_PORT% %DYNAMIC_METADATA(envoy.lb:version)% 100%%"#,
+    )?;
+    assert_eq!(
+        format.format(&fake_info)?,
+        r#"[2020-11-03T12:34:56.789Z] "GET /api/v1/items HTTP/1.1" 200 - 120 90 30 "curl" "01234567" "-" 10.0.0.2:8080 backend ::1 v2 100%"#,
+    );
+
+    let format = LogFormat::parse("%START_TIME(%Y/%m/%d %H:%M:%S %s.%6f)%")?;
+    assert_eq!(
+        format.format(&fake_info)?,
+        "2020/11/03 12:34:56 1604406896.789000"
+    );
+
+    let format = JsonLogFormat::parse(vec![
+        ("method", "%REQ(:METHOD)%"),
+        ("code", "%RESPONSE_CODE%"),
+        ("agent", "\"%REQ(USER-AGENT)%\""),
+        ("canary", "%DYNAMIC_METADATA(envoy.lb:canary)%"),
+        ("lb", "%DYNAMIC_METADATA(envoy.lb)%"),
+        ("duration", "%DURATION%"),
+        ("referer", "%REQ(REFERER)%"),
+        ("flags", "flags: %RESPONSE_FLAGS%"),
+    ])?;
+    assert_eq!(
+        format.format(&fake_info)?,
+        r#"{"method":"GET","code":200,"agent":"\"curl/7.64.1\"","canary":true,"lb":{"canary":true,"version":"v2"},"duration":30,"referer":null,"flags":"flags: -"}"#,
+    );
+
+    let format = JsonLogFormat::parse(vec![("code", "%RESPONSE_CODE%"), ("ua", "%REQ(X-UA)%")])?;
+    assert_eq!(format.format(&fake_info)?, r#"{"code":200,"ua":null}"#);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_format_tcp() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.connection().id(7).tls().version("TLSv1.3");
+        info.request().size(1024).duration(Duration::from_secs(5));
+        info.response().size(2048);
+        info.destination().address("10.0.0.1:443");
+        info.filter_state().value("tenant", &"acme");
+    });
+
+    let format = LogFormat::parse(
+        "%CONNECTION_ID% %DOWNSTREAM_LOCAL_ADDRESS% %DOWNSTREAM_LOCAL_ADDRESS_WITHOUT_PORT% %DOWNSTREAM_TLS_VERSION% %BYTES_RECEIVED% %BYTES_SENT% %DURATION% %RESPONSE_CODE% %REQ(:METHOD)% %FILTER_STATE(tenant)%",
+    )?;
+    assert_eq!(
+        format.format(&fake_info)?,
+        r#"7 10.0.0.1:443 10.0.0.1 TLSv1.3 1024 2048 5000 0 - "acme""#,
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_log_format_errors() {
+    for format in &[
+        "%UNKNOWN%",
+        "%PROTOCOL",
+        "100% sure",
+        "%REQ%",
+        "%REQ(:METHOD%",
+        "%PROTOCOL(arg)%",
+        "%FILTER_STATE()%",
+        "%DYNAMIC_METADATA(:key)%",
+    ] {
+        assert!(LogFormat::parse(format).is_err(), "{}", format);
+    }
+}
+
+#[derive(Default)]
+struct FakeDrainOps {
+    done: Cell<bool>,
+}
+
+impl DrainOps for FakeDrainOps {
+    fn done(&self) -> Result<()> {
+        self.done.set(true);
+        Ok(())
+    }
+}
+
+/// `HttpClient` that fails to send requests on demand.
+#[derive(Default)]
+struct FlakyHttpClient {
+    inner: FakeHttpClient,
+    failing: Cell<bool>,
+}
+
+impl HttpClient for FlakyHttpClient {
+    fn send_request(
+        &self,
+        upstream: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+        trailers: Option<&[(&str, &str)]>,
+        timeout: Duration,
+    ) -> Result<HttpClientRequestHandle> {
+        if self.failing.get() {
+            bail!("Status::InternalFailure");
+        }
+        self.inner
+            .send_request(upstream, headers, body, trailers, timeout)
+    }
+}
+
+fn response(status: &str) -> FakeHttpClientResponse {
+    FakeHttpClientResponse::builder()
+        .header(":status", status)
+        .build()
+}
+
+#[test]
+fn test_batch_sink_thresholds() -> Result<()> {
+    let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+    let http_client = FakeHttpClient::default();
+    let drain_ops = FakeDrainOps::default();
+    let config = BatchConfig::new("log_collector")
+        .path("/v1/logs")
+        .header("authorization", "Bearer token")
+        .encoding(BatchEncoding::JsonArray)
+        .max_batch_entries(2)
+        .max_batch_age(Duration::from_secs(10));
+    let mut sink = BatchSink::new(config, &clock, &http_client, &drain_ops);
+
+    // size threshold
+    sink.push(r#"{"code":"200"}"#)?;
+    assert!(http_client.drain_pending_requests().is_empty());
+    sink.push(r#"{"code":"404"}"#)?;
+    let requests = http_client.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0].request;
+    assert_eq!(request.upstream, "log_collector");
+    assert_eq!(
+        request.message.headers,
+        HeaderMap::builder()
+            .header(":method", "POST")
+            .header(":path", "/v1/logs")
+            .header(":authority", "log_collector")
+            .header("content-type", "application/json")
+            .header("authorization", "Bearer token")
+            .build()
+    );
+    assert_eq!(request.message.body, r#"[{"code":"200"},{"code":"404"}]"#);
+
+    // only one request in flight
+    sink.push(r#"{"code":"500"}"#)?;
+    sink.push(r#"{"code":"503"}"#)?;
+    assert!(http_client.drain_pending_requests().is_empty());
+    assert_eq!(sink.buffered_entries(), 4);
+
+    // the next batch is sent as soon as the previous one is delivered
+    assert!(sink.on_http_call_response(requests[0].handle, &response("200"))?);
+    let requests = http_client.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].request.message.body,
+        r#"[{"code":"500"},{"code":"503"}]"#
+    );
+    assert!(sink.on_http_call_response(requests[0].handle, &response("204"))?);
+    assert_eq!(sink.buffered_entries(), 0);
+
+    // age threshold
+    sink.push(r#"{"code":"200"}"#)?;
+    assert!(!sink.flush_if_due()?);
+    clock.advance(Duration::from_secs(10));
+    assert!(sink.flush_if_due()?);
+    assert_eq!(http_client.drain_pending_requests().len(), 1);
+
+    assert!(!drain_ops.done.get());
+    Ok(())
+}
+
+#[test]
+fn test_batch_sink_retries_and_drops() -> Result<()> {
+    let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+    let http_client = FakeHttpClient::default();
+    let drain_ops = FakeDrainOps::default();
+    let config = BatchConfig::new("log_collector")
+        .max_batch_entries(2)
+        .max_buffered_bytes(12)
+        .max_attempts(2)
+        .retry_backoff(Duration::from_secs(1));
+    let mut sink = BatchSink::new(config, &clock, &http_client, &drain_ops);
+
+    sink.push("first")?;
+    sink.push("second")?;
+    let requests = http_client.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].request.message.body, "first\nsecond\n");
+
+    // buffer is full
+    sink.push("third")?;
+    assert_eq!(sink.dropped_entries(), 1);
+
+    // failed batch is retried after a backoff
+    assert!(sink.on_http_call_response(requests[0].handle, &response("503"))?);
+    assert!(!sink.flush_if_due()?);
+    clock.advance(Duration::from_secs(1));
+    assert!(sink.flush_if_due()?);
+    let requests = http_client.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].request.message.body, "first\nsecond\n");
+
+    // batch is dropped once attempts are exhausted
+    assert!(sink.on_http_call_response(requests[0].handle, &response("500"))?);
+    assert_eq!(sink.dropped_entries(), 3);
+    assert_eq!(sink.buffered_entries(), 0);
+
+    // responses to unrelated requests are ignored
+    assert!(!sink.on_http_call_response(requests[0].handle, &response("200"))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_sink_send_failure_drops() -> Result<()> {
+    let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+    let http_client = FlakyHttpClient::default();
+    let drain_ops = FakeDrainOps::default();
+    let config = BatchConfig::new("log_collector")
+        .max_batch_entries(2)
+        .max_attempts(2)
+        .retry_backoff(Duration::from_secs(1));
+    let mut sink = BatchSink::new(config, &clock, &http_client, &drain_ops);
+    http_client.failing.set(true);
+
+    sink.push("a")?;
+    assert!(sink.push("b").is_err());
+    assert_eq!(sink.buffered_entries(), 2);
+
+    // failed batch is retried after a backoff
+    assert!(!sink.flush_if_due()?);
+    clock.advance(Duration::from_secs(1));
+    assert!(sink.flush_if_due().is_err());
+
+    // batch is dropped once attempts are exhausted
+    assert_eq!(sink.dropped_entries(), 2);
+    assert_eq!(sink.buffered_entries(), 0);
+    clock.advance(Duration::from_secs(1));
+    assert!(!sink.flush_if_due()?);
+    assert!(http_client.inner.drain_pending_requests().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_sink_drain() -> Result<()> {
+    let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+    let http_client = FakeHttpClient::default();
+    let drain_ops = FakeDrainOps::default();
+    let config = BatchConfig::new("log_collector").max_batch_entries(2);
+    let mut sink = BatchSink::new(config, &clock, &http_client, &drain_ops);
+
+    assert_eq!(sink.on_drain()?, DrainStatus::Complete);
+
+    let mut sink = BatchSink::new(
+        BatchConfig::new("log_collector").max_batch_entries(2),
+        &clock,
+        &http_client,
+        &drain_ops,
+    );
+    sink.push("a")?;
+    sink.push("b")?;
+    sink.push("c")?;
+
+    assert_eq!(sink.on_drain()?, DrainStatus::Ongoing);
+    let requests = http_client.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].request.message.body, "a\nb\n");
+
+    sink.on_http_call_response(requests[0].handle, &response("200"))?;
+    assert!(!drain_ops.done.get());
+    let requests = http_client.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].request.message.body, "c\n");
+
+    sink.on_http_call_response(requests[0].handle, &response("200"))?;
+    assert!(drain_ops.done.get());
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_sink_drain_send_failure() -> Result<()> {
+    let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+    let http_client = FlakyHttpClient::default();
+    let drain_ops = FakeDrainOps::default();
+    let config = BatchConfig::new("log_collector").max_batch_entries(2);
+    let mut sink = BatchSink::new(config, &clock, &http_client, &drain_ops);
+    sink.push("a")?;
+    sink.push("b")?;
+    sink.push("c")?;
+
+    assert_eq!(sink.on_drain()?, DrainStatus::Ongoing);
+    let requests = http_client.inner.drain_pending_requests();
+    assert_eq!(requests.len(), 1);
+
+    // the next batch cannot be sent
+    http_client.failing.set(true);
+    sink.on_http_call_response(requests[0].handle, &response("200"))?;
+
+    assert!(drain_ops.done.get());
+    assert_eq!(sink.buffered_entries(), 0);
+    assert_eq!(sink.dropped_entries(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_ops_defaults() -> Result<()> {
+    let fake_info = FakeStreamInfo::new();
+    let ops = HeadersOnlyLogOps {
+        stream_info: &fake_info,
+    };
+
+    assert_eq!(ops.request_trailers()?, HeaderMap::default());
+    assert_eq!(ops.request_trailer("grpc-status")?, None);
+    assert_eq!(ops.request_body()?, None);
+    assert_eq!(ops.response_body()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_filter() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request()
+            .protocol("HTTP/1.1")
+            .path("/api/v1/items")
+            .header("x-debug", "1")
+            .duration(Duration::from_millis(1500));
+        info.response()
+            .status_code(503)
+            .response_flags(ResponseFlags::UPSTREAM_OVERFLOW);
+        info.route().name("api");
+        info.cluster().name("backend");
+    });
+
+    let matches = |filter: LogFilter| filter.matches(&fake_info);
+
+    assert!(matches(LogFilter::status_code(500..=599))?);
+    assert!(!matches(LogFilter::status_code(200..=299))?);
+    assert!(matches(LogFilter::min_duration(Duration::from_secs(1)))?);
+    assert!(!matches(LogFilter::min_duration(Duration::from_secs(2)))?);
+    assert!(matches(LogFilter::response_flags(ResponseFlags::empty()))?);
+    assert!(matches(LogFilter::response_flags(
+        ResponseFlags::UPSTREAM_OVERFLOW | ResponseFlags::NO_ROUTE_FOUND
+    ))?);
+    assert!(!matches(LogFilter::response_flags(
+        ResponseFlags::NO_ROUTE_FOUND
+    ))?);
+    assert!(matches(LogFilter::header_present("X-Debug"))?);
+    assert!(!matches(LogFilter::header_present("x-trace"))?);
+    assert!(matches(LogFilter::header_matches(":path", "/api/.*")?)?);
+    assert!(!matches(LogFilter::header_matches(":path", "/admin/.*")?)?);
+    // the regex must match the entire value
+    assert!(!matches(LogFilter::header_matches(":path", "/api/")?)?);
+    assert!(!matches(LogFilter::header_matches(":path", "items")?)?);
+    assert!(!matches(LogFilter::header_matches(":path", "/api|items")?)?);
+    assert!(LogFilter::header_matches(":path", "(").is_err());
+    assert!(matches(LogFilter::route_name("api"))?);
+    assert!(!matches(LogFilter::cluster_name("frontend"))?);
+    assert!(matches(!LogFilter::cluster_name("frontend"))?);
+    assert!(matches(LogFilter::all(vec![
+        LogFilter::status_code(500..=599),
+        LogFilter::route_name("api"),
+    ]))?);
+    assert!(!matches(LogFilter::all(vec![
+        LogFilter::status_code(500..=599),
+        LogFilter::route_name("admin"),
+    ]))?);
+    assert!(matches(LogFilter::any(vec![
+        LogFilter::status_code(200..=299),
+        LogFilter::route_name("api"),
+    ]))?);
+    assert!(matches(LogFilter::sample(1.0))?);
+    assert!(!matches(LogFilter::sample(0.0))?);
+
+    // TCP connections have no response code
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request().duration(Duration::from_secs(5));
+    });
+    assert!(!LogFilter::status_code(0..=599).matches(&fake_info)?);
+    assert!(LogFilter::min_duration(Duration::from_secs(5)).matches(&fake_info)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_filter_sampling() -> Result<()> {
+    // without a request id, entries are sampled evenly
+    let fake_info = FakeStreamInfo::new();
+    let filter = LogFilter::sample(0.25);
+    let sampled = (0..100)
+        .map(|_| filter.matches(&fake_info))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(sampled.iter().filter(|&&sampled| sampled).count(), 25);
+    assert_eq!(&sampled[..4], &[true, false, false, false]);
+
+    // with a request id, the decision is consistent
+    let filter = LogFilter::sample(0.5);
+    let mut count = 0;
+    for i in 0..1000 {
+        let fake_info = FakeStreamInfo::new().with(|info| {
+            info.request()
+                .protocol("HTTP/1.1")
+                .header("x-request-id", format!("request-{}", i));
+        });
+        let sampled = filter.matches(&fake_info)?;
+        assert_eq!(filter.matches(&fake_info)?, sampled);
+        if sampled {
+            count += 1;
+        }
+    }
+    assert!(count > 400 && count < 600, "{}", count);
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct CountingAccessLogger {
+    entries: usize,
+}
+
+impl AccessLogger for CountingAccessLogger {
+    fn name() -> &'static str {
+        "counting"
+    }
+
+    fn on_log(&mut self, _ops: &dyn LogOps) -> extension::Result<()> {
+        self.entries += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_filtered_access_logger() -> extension::Result<()> {
+    let mut logger = FilteredAccessLogger::new(
+        CountingAccessLogger::default(),
+        LogFilter::status_code(500..=599),
+    );
+    assert_eq!(
+        <FilteredAccessLogger<CountingAccessLogger>>::name(),
+        "counting"
+    );
+
+    for status_code in &[200, 500, 404, 503] {
+        let fake_info = FakeStreamInfo::new().with(|info| {
+            info.request().protocol("HTTP/2");
+            info.response().status_code(*status_code);
+        });
+        logger.on_log(&fake_info)?;
+    }
+    assert_eq!(logger.logger().entries, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_ops_request_trailers() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request()
+            .protocol("HTTP/2")
+            .trailer("grpc-timeout", "1S");
+    });
+    let view = LogView::new(&fake_info);
+
+    assert_eq!(
+        view.request_trailers()?,
+        Some(HeaderMap::builder().header("grpc-timeout", "1S").build())
+    );
+    assert_eq!(view.request_trailer("grpc-timeout")?, Some("1S".into()));
+    assert_eq!(view.request_trailer("grpc-encoding")?, None);
+
+    Ok(())
+}
+
+/// Body chunk passed to `on_request_body` / `on_response_body`.
+struct FakeBodyChunk(&'static str);
+
+impl RequestFlowOps for FakeBodyChunk {
+    fn resume_request(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn send_response(
+        &self,
+        _status_code: u32,
+        _headers: &[(&str, &str)],
+        _body: Option<&[u8]>,
+    ) -> Result<()> {
+        Ok(())
+    }
+    fn reset_stream(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl RequestBodyOps for FakeBodyChunk {
+    fn request_data(&self, start: usize, max_size: usize) -> Result<ByteString> {
+        let end = self.0.len().min(start + max_size);
+        Ok(self.0[start..end].into())
+    }
+}
+
+impl ResponseFlowOps for FakeBodyChunk {
+    fn resume_response(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ResponseBodyOps for FakeBodyChunk {
+    fn response_data(&self, start: usize, max_size: usize) -> Result<ByteString> {
+        let end = self.0.len().min(start + max_size);
+        Ok(self.0[start..end].into())
+    }
+}
+
+#[test]
+fn test_log_ops_body_capture() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request().protocol("HTTP/1.1");
+    });
+    let ops: &dyn LogOps = &fake_info;
+
+    // body is not captured by default
+    assert_eq!(ops.request_body()?, None);
+    assert_eq!(ops.response_body()?, None);
+
+    BodyCapture::declare(&fake_info)?;
+    assert_eq!(
+        fake_info.declared_filter_state("envoy_sdk.request_body"),
+        Some(
+            FilterStateOptions::new()
+                .life_span(LifeSpan::DownstreamRequest)
+                .read_only(true)
+        )
+    );
+    let mut capture = BodyCapture::new(&fake_info, 10, 4);
+
+    // prefix is saved once it is complete
+    capture.on_request_body(6, false, &FakeBodyChunk("hello "))?;
+    assert_eq!(ops.request_body()?, None);
+    capture.on_request_body(6, false, &FakeBodyChunk("world!"))?;
+    capture.on_request_body(4, true, &FakeBodyChunk(" bye"))?;
+    assert_eq!(capture.request_body(), b"hello worl");
+    assert_eq!(ops.request_body()?, Some("hello worl".into()));
+
+    capture.on_response_body(2, false, &FakeBodyChunk("OK"))?;
+    capture.on_response_body(0, true, &FakeBodyChunk(""))?;
+    assert_eq!(ops.response_body()?, Some("OK".into()));
+
+    let view = LogView::new(&fake_info);
+    assert_eq!(view.request_body()?, Some("hello worl".into()));
+    assert_eq!(view.response_body()?, Some("OK".into()));
+
+    Ok(())
+}
+
+/// `LogOps` that implements only the required methods.
+struct HeadersOnlyLogOps<'a> {
+    stream_info: &'a dyn StreamInfo,
+}
+
+impl<'a> LogOps for HeadersOnlyLogOps<'a> {
+    fn request_headers(&self) -> Result<HeaderMap> {
+        Ok(HeaderMap::default())
+    }
+
+    fn request_header(&self, _name: &str) -> Result<Option<ByteString>> {
+        Ok(None)
+    }
+
+    fn response_headers(&self) -> Result<HeaderMap> {
+        Ok(HeaderMap::default())
+    }
+
+    fn response_header(&self, _name: &str) -> Result<Option<ByteString>> {
+        Ok(None)
+    }
+
+    fn response_trailers(&self) -> Result<HeaderMap> {
+        Ok(HeaderMap::default())
+    }
+
+    fn response_trailer(&self, _name: &str) -> Result<Option<ByteString>> {
+        Ok(None)
+    }
+
+    fn stream_info(&self) -> &dyn StreamInfo {
+        self.stream_info
+    }
+}
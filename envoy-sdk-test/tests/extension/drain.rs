@@ -0,0 +1,416 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use envoy::error::bail;
+use envoy::extension::error::{ErrorCategory, ErrorReport, ErrorSink};
+use envoy::extension::factory;
+use envoy::extension::filter::http::{FilterHeadersStatus, RequestHeadersOps};
+use envoy::extension::service::{self, ConfigureOps};
+use envoy::extension::{
+    ConfigStatus, DrainStatus, ExtensionFactory, HttpFilter, InstanceId, Module, Result, Service,
+};
+use envoy::host::http::client::{HttpClient, HttpClientRequestHandle, HttpClientResponseOps};
+use envoy::host::ByteString;
+
+use envoy_sdk_test::{FakeEnvoy, FakeHttpClientResponse, FakeRootContext};
+
+#[derive(Default, Clone)]
+struct RecordingErrorSink {
+    reports: Rc<RefCell<Vec<(ErrorCategory, String)>>>,
+}
+
+impl ErrorSink for RecordingErrorSink {
+    fn observe(&self, report: &ErrorReport<'_>) {
+        self.reports.borrow_mut().push((
+            report.category,
+            format!("{}: {}", report.context, report.error),
+        ));
+    }
+}
+
+fn send_request() -> Result<()> {
+    <dyn HttpClient>::default().send_request(
+        "upstream",
+        &[(":method", "POST"), (":path", "/events")],
+        None,
+        None,
+        Duration::from_secs(1),
+    )?;
+    Ok(())
+}
+
+/// Controls behaviour of `DrainingService`.
+struct Behaviour {
+    /// Number of HTTP requests to make on configure.
+    requests: Cell<usize>,
+    /// Outcome of `on_drain`; `None` means failure.
+    drain_status: Cell<Option<DrainStatus>>,
+    /// Whether to report completion of draining once a response is received.
+    done_on_response: Cell<bool>,
+    /// Whether to report completion of draining on a timer tick.
+    done_on_tick: Cell<bool>,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Behaviour {
+            requests: Cell::new(0),
+            drain_status: Cell::new(Some(DrainStatus::Complete)),
+            done_on_response: Cell::new(false),
+            done_on_tick: Cell::new(false),
+        }
+    }
+}
+
+/// Sets the tick period to the number of seconds in its config.
+struct DrainingService {
+    behaviour: Rc<Behaviour>,
+}
+
+impl Service for DrainingService {
+    fn name() -> &'static str {
+        "draining_service"
+    }
+
+    fn on_configure(&mut self, config: ByteString, ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+        let seconds = config.to_string().parse().unwrap_or_default();
+        ops.set_tick_period(Duration::from_secs(seconds))?;
+        for _ in 0..self.behaviour.requests.get() {
+            send_request()?;
+        }
+        Ok(ConfigStatus::Accepted)
+    }
+
+    fn on_tick(&mut self) -> Result<()> {
+        if self.behaviour.done_on_tick.get() {
+            <dyn service::DrainOps>::default().done()?;
+        }
+        Ok(())
+    }
+
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        match self.behaviour.drain_status.get() {
+            Some(status) => Ok(status),
+            None => bail!("drain is not supported"),
+        }
+    }
+
+    fn on_http_call_response(
+        &mut self,
+        _request_id: HttpClientRequestHandle,
+        _num_headers: usize,
+        _body_size: usize,
+        _num_trailers: usize,
+        _http_client_ops: &dyn HttpClientResponseOps,
+    ) -> Result<()> {
+        if self.behaviour.done_on_response.get() {
+            <dyn service::DrainOps>::default().done()?;
+        }
+        Ok(())
+    }
+}
+
+struct Setup {
+    envoy: FakeEnvoy,
+    sink: RecordingErrorSink,
+    behaviour: Rc<Behaviour>,
+}
+
+impl Setup {
+    fn new(drain_timeout: Option<Duration>) -> Self {
+        let sink = RecordingErrorSink::default();
+        let behaviour = Rc::new(Behaviour::default());
+        let service_behaviour = Rc::clone(&behaviour);
+        let mut module = Module::new().with_error_sink(sink.clone());
+        if let Some(timeout) = drain_timeout {
+            module = module.with_drain_timeout(timeout);
+        }
+        let envoy = FakeEnvoy::start(module.add_service(move |_| {
+            Ok(DrainingService {
+                behaviour: Rc::clone(&service_behaviour),
+            })
+        }));
+        Setup {
+            envoy,
+            sink,
+            behaviour,
+        }
+    }
+
+    fn new_root_context(&self, config: &str) -> FakeRootContext {
+        let root = self.envoy.new_root_context("draining_service");
+        assert!(root.configure(config.as_bytes()));
+        root
+    }
+
+    fn reports(&self) -> Vec<(ErrorCategory, String)> {
+        self.sink.reports.borrow().clone()
+    }
+
+    /// Responds to all pending HTTP requests one by one.
+    fn respond(&self, mut on_response: impl FnMut(usize)) {
+        let calls = self.envoy.drain_http_calls();
+        for (index, call) in calls.iter().enumerate() {
+            self.envoy
+                .respond_to_http_call(call.handle, &FakeHttpClientResponse::builder().build());
+            on_response(index);
+        }
+    }
+}
+
+#[test]
+fn test_drain_complete() {
+    let setup = Setup::new(Some(Duration::from_secs(5)));
+    let root = setup.new_root_context("");
+
+    assert!(root.drain());
+    root.tick();
+
+    assert_eq!(root.done_count(), 0);
+    assert_eq!(root.tick_period(), Duration::default());
+}
+
+#[test]
+fn test_drain_waits_for_http_calls() {
+    let setup = Setup::new(None);
+    setup.behaviour.requests.set(2);
+    let root = setup.new_root_context("");
+
+    assert!(!root.drain());
+
+    // `done` is sent automatically once the last request completes
+    setup.respond(|index| assert_eq!(root.done_count(), index));
+    assert_eq!(root.done_count(), 1);
+}
+
+#[test]
+fn test_drain_done_requested_with_http_calls_in_flight() {
+    let setup = Setup::new(None);
+    setup.behaviour.requests.set(2);
+    setup.behaviour.drain_status.set(Some(DrainStatus::Ongoing));
+    setup.behaviour.done_on_response.set(true);
+    let root = setup.new_root_context("");
+
+    assert!(!root.drain());
+
+    // `Envoy` must not be notified until all requests complete
+    setup.respond(|index| assert_eq!(root.done_count(), index));
+    assert_eq!(root.done_count(), 1);
+
+    // late calls of `DrainOps::done` don't notify `Envoy` again
+    setup.behaviour.done_on_tick.set(true);
+    root.tick();
+    assert_eq!(root.done_count(), 1);
+}
+
+#[test]
+fn test_drain_done_requested_without_http_calls() {
+    let setup = Setup::new(None);
+    setup.behaviour.drain_status.set(Some(DrainStatus::Ongoing));
+    setup.behaviour.done_on_tick.set(true);
+    let root = setup.new_root_context("1");
+
+    assert!(!root.drain());
+    root.tick();
+    assert_eq!(root.done_count(), 1);
+
+    // `Envoy` must be notified only once
+    root.tick();
+    assert_eq!(root.done_count(), 1);
+}
+
+#[test]
+fn test_drain_timeout() {
+    let setup = Setup::new(Some(Duration::from_secs(5)));
+    setup.behaviour.requests.set(1);
+    let root = setup.new_root_context("");
+
+    assert!(!root.drain());
+    assert_eq!(root.tick_period(), Duration::from_secs(5));
+
+    setup.envoy.advance_time(Duration::from_secs(4));
+    root.tick();
+    assert_eq!(root.done_count(), 0);
+
+    setup.envoy.advance_time(Duration::from_secs(1));
+    root.tick();
+    assert_eq!(root.done_count(), 1);
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::Drain,
+            "failed to drain the extension: drain timeout of 5s has expired with 1 HTTP request(s) still in flight"
+                .to_owned()
+        )]
+    );
+
+    // the timer installed for the drain timeout is no longer needed
+    assert_eq!(root.tick_period(), Duration::default());
+
+    // `Envoy` must be notified only once
+    setup.envoy.advance_time(Duration::from_secs(5));
+    root.tick();
+    setup.respond(|_| ());
+    assert_eq!(root.done_count(), 1);
+}
+
+#[test]
+fn test_drain_timeout_after_on_drain_failure() {
+    let setup = Setup::new(Some(Duration::from_secs(5)));
+    setup.behaviour.drain_status.set(None);
+    let root = setup.new_root_context("");
+
+    assert!(!root.drain());
+    assert_eq!(root.tick_period(), Duration::from_secs(5));
+
+    setup.envoy.advance_time(Duration::from_secs(5));
+    root.tick();
+    assert_eq!(root.done_count(), 1);
+    assert_eq!(setup.reports().len(), 2);
+    assert_eq!(
+        setup.reports()[0].1,
+        "failed to initiate draining of the extension: drain is not supported"
+    );
+}
+
+#[test]
+fn test_drain_timeout_keeps_extension_tick_period() {
+    let setup = Setup::new(Some(Duration::from_secs(5)));
+    setup.behaviour.requests.set(1);
+    let root = setup.new_root_context("1");
+
+    assert!(!root.drain());
+    assert_eq!(root.tick_period(), Duration::from_secs(1));
+
+    // the timer is re-enabled if the extension disables it while being drained
+    setup.behaviour.requests.set(0);
+    assert!(root.configure(b"0"));
+    assert_eq!(root.tick_period(), Duration::from_secs(5));
+
+    setup.envoy.advance_time(Duration::from_secs(5));
+    root.tick();
+    assert_eq!(root.done_count(), 1);
+    assert_eq!(root.tick_period(), Duration::default());
+}
+
+#[test]
+fn test_tick_period_before_draining() {
+    let setup = Setup::new(Some(Duration::from_secs(5)));
+    let root = setup.new_root_context("1");
+
+    assert!(root.configure(b"0"));
+
+    assert_eq!(root.tick_period(), Duration::default());
+}
+
+#[test]
+fn test_drain_done_requested_by_extension_stops_drain_ticks() {
+    let setup = Setup::new(Some(Duration::from_secs(5)));
+    setup.behaviour.requests.set(1);
+    setup.behaviour.drain_status.set(Some(DrainStatus::Ongoing));
+    setup.behaviour.done_on_response.set(true);
+    let root = setup.new_root_context("");
+
+    assert!(!root.drain());
+    setup.respond(|_| ());
+    assert_eq!(root.done_count(), 1);
+
+    root.tick();
+    assert_eq!(root.tick_period(), Duration::default());
+    assert_eq!(root.done_count(), 1);
+    assert!(setup.reports().is_empty());
+}
+
+/// Reports completion of draining of its factory and makes an HTTP request
+/// on request headers.
+struct DrainingFilter;
+
+impl HttpFilter for DrainingFilter {
+    fn on_request_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        _ops: &dyn RequestHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        <dyn factory::Ops>::default().done()?;
+        send_request()?;
+        Ok(FilterHeadersStatus::Continue)
+    }
+}
+
+struct DrainingFilterFactory {
+    drain_status: DrainStatus,
+}
+
+impl ExtensionFactory for DrainingFilterFactory {
+    type Extension = DrainingFilter;
+
+    fn name() -> &'static str {
+        "draining_filter"
+    }
+
+    fn new_extension(&mut self, _instance_id: InstanceId) -> Result<Self::Extension> {
+        Ok(DrainingFilter)
+    }
+
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        Ok(self.drain_status)
+    }
+}
+
+fn start_filter(drain_status: DrainStatus, sink: &RecordingErrorSink) -> FakeEnvoy {
+    FakeEnvoy::start(
+        Module::new()
+            .with_error_sink(sink.clone())
+            .with_drain_timeout(Duration::from_secs(5))
+            .add_http_filter(move |_| Ok(DrainingFilterFactory { drain_status })),
+    )
+}
+
+#[test]
+fn test_drain_done_requested_by_child_context() {
+    let sink = RecordingErrorSink::default();
+    let envoy = start_filter(DrainStatus::Ongoing, &sink);
+    let root = envoy.new_root_context("draining_filter");
+    assert!(root.configure(b""));
+
+    assert!(!root.drain());
+    root.new_http_stream().request_headers(&[], true);
+
+    // completion requested by a child context counts for its root context,
+    // so the drain timeout must not notify `Envoy` once again
+    envoy.advance_time(Duration::from_secs(5));
+    root.tick();
+    assert_eq!(root.done_count(), 0);
+    assert_eq!(root.tick_period(), Duration::default());
+    assert!(sink.reports.borrow().is_empty());
+}
+
+#[test]
+fn test_drain_ignores_http_calls_of_child_contexts() {
+    let sink = RecordingErrorSink::default();
+    let envoy = start_filter(DrainStatus::Complete, &sink);
+    let root = envoy.new_root_context("draining_filter");
+    assert!(root.configure(b""));
+
+    // responses to requests made by a child context are delivered to that context
+    root.new_http_stream().request_headers(&[], true);
+    assert_eq!(envoy.drain_http_calls().len(), 1);
+
+    assert!(root.drain());
+}
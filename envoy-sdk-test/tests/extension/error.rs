@@ -0,0 +1,187 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use envoy::error::bail;
+use envoy::extension::error::{ErrorCategory, ErrorReport, ErrorSink};
+use envoy::extension::filter::http::{
+    FilterDataStatus, FilterHeadersStatus, RequestBodyOps, RequestHeadersOps,
+};
+use envoy::extension::{
+    factory, ConfigStatus, ExtensionFactory, HttpFilter, InstanceId, Module, Result,
+};
+use envoy::host::ByteString;
+
+use envoy_sdk_test::FakeEnvoy;
+
+type Record = (ErrorCategory, String, Option<String>, Option<InstanceId>);
+
+#[derive(Default, Clone)]
+struct RecordingErrorSink {
+    reports: Rc<RefCell<Vec<Record>>>,
+}
+
+impl ErrorSink for RecordingErrorSink {
+    fn observe(&self, report: &ErrorReport<'_>) {
+        self.reports.borrow_mut().push((
+            report.category,
+            format!("{}: {}", report.context, report.error),
+            report.root_id.map(String::from),
+            report.instance_id,
+        ));
+    }
+}
+
+/// Fails to handle request headers and panics on request body.
+struct MyHttpFilter;
+
+impl HttpFilter for MyHttpFilter {
+    fn on_request_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        _ops: &dyn RequestHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        bail!("unexpected header")
+    }
+
+    fn on_request_body(
+        &mut self,
+        _body_size: usize,
+        _end_of_stream: bool,
+        _ops: &dyn RequestBodyOps,
+    ) -> Result<FilterDataStatus> {
+        panic!("oops")
+    }
+}
+
+/// Rejects invalid config and fails to create the first extension instance.
+#[derive(Default)]
+struct MyHttpFilterFactory {
+    instances: usize,
+}
+
+impl ExtensionFactory for MyHttpFilterFactory {
+    type Extension = MyHttpFilter;
+
+    fn name() -> &'static str {
+        "my_filter"
+    }
+
+    fn on_configure(
+        &mut self,
+        config: ByteString,
+        _ops: &dyn factory::ConfigureOps,
+    ) -> Result<ConfigStatus> {
+        if config == "invalid" {
+            bail!("invalid config")
+        }
+        Ok(ConfigStatus::Accepted)
+    }
+
+    fn new_extension(&mut self, _instance_id: InstanceId) -> Result<Self::Extension> {
+        self.instances += 1;
+        if self.instances == 1 {
+            bail!("out of resources")
+        }
+        Ok(MyHttpFilter)
+    }
+}
+
+fn start(sink: &RecordingErrorSink) -> FakeEnvoy {
+    FakeEnvoy::start(
+        Module::new()
+            .with_error_sink(sink.clone())
+            .add_http_filter(|_| Ok(MyHttpFilterFactory::default())),
+    )
+}
+
+#[test]
+fn test_error_reporter() {
+    let sink = RecordingErrorSink::default();
+    let envoy = start(&sink);
+
+    let unknown = envoy.new_root_context("unknown_filter");
+    assert!(!unknown.configure(b""));
+
+    let root = envoy.new_root_context("my_filter");
+    assert!(!root.configure(b"invalid"));
+    assert!(root.configure(b"valid"));
+
+    let failed_stream = root.new_http_stream();
+    failed_stream.request_headers(&[], false);
+
+    let stream = root.new_http_stream();
+    stream.request_headers(&[], false);
+
+    assert_eq!(
+        *sink.reports.borrow(),
+        vec![
+            (
+                ErrorCategory::Initialization,
+                r#"failed to create Proxy Wasm Root Context: WebAssembly module has no extension with `root_id` "unknown_filter"; valid `root_id` values are: ["my_filter"]"#.to_owned(),
+                None,
+                Some(InstanceId::from(unknown.id())),
+            ),
+            (
+                ErrorCategory::Configuration,
+                "failed to configure extension: invalid config".to_owned(),
+                Some("my_filter".to_owned()),
+                Some(InstanceId::from(root.id())),
+            ),
+            (
+                ErrorCategory::ExtensionCreation,
+                "failed to create Proxy Wasm Http Context: out of resources".to_owned(),
+                Some("my_filter".to_owned()),
+                Some(InstanceId::from(failed_stream.id())),
+            ),
+            (
+                ErrorCategory::Callback,
+                "failed to handle HTTP request headers: unexpected header".to_owned(),
+                Some("my_filter".to_owned()),
+                Some(InstanceId::from(stream.id())),
+            ),
+        ]
+    );
+}
+
+#[test]
+#[cfg(panic = "unwind")]
+fn test_error_reporter_panic_category() {
+    let sink = RecordingErrorSink::default();
+    let envoy = start(&sink);
+
+    let root = envoy.new_root_context("my_filter");
+    assert!(root.configure(b""));
+    root.new_http_stream(); // the first extension instance fails to be created
+
+    let stream = root.new_http_stream();
+    stream.request_body(b"hello", false);
+    stream.request_body(b"world", true);
+
+    let reports = sink.reports.borrow();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].0, ErrorCategory::Panic);
+    assert_eq!(
+        reports[0].1,
+        "failed to handle HTTP request body: extension has panicked: oops"
+    );
+    assert_eq!(reports[1].0, ErrorCategory::Callback);
+    assert_eq!(
+        reports[1].1,
+        "failed to handle HTTP request body: extension instance can no longer be used since it has panicked earlier"
+    );
+}
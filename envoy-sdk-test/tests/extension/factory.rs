@@ -0,0 +1,192 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::rc::Rc;
+
+use envoy::extension::factory::{
+    ConfigChange, ConfigureOps, Reconfigurable, ReconfigurableFactory,
+};
+use envoy::extension::filter::FailurePolicy;
+use envoy::extension::{ConfigStatus, ExtensionFactory, InstanceId, Result};
+use envoy::host::ByteString;
+
+struct NoOps;
+
+impl ConfigureOps for NoOps {}
+
+#[derive(Debug, PartialEq)]
+struct MyConfig {
+    upstream: String,
+    reset_on_failure: bool,
+}
+
+struct MyFilter {
+    config: Rc<MyConfig>,
+}
+
+#[derive(Default)]
+struct MyFactory {
+    changes: Vec<(Option<String>, String, bool)>,
+}
+
+impl ReconfigurableFactory for MyFactory {
+    type Config = MyConfig;
+    type Extension = MyFilter;
+
+    fn name() -> &'static str {
+        "my_filter"
+    }
+
+    fn parse_config(&mut self, config: ByteString) -> Result<Self::Config> {
+        let config = String::from_utf8(config.into_bytes())?;
+        let mut parts = config.splitn(2, ',');
+        Ok(MyConfig {
+            upstream: parts.next().unwrap_or_default().to_owned(),
+            reset_on_failure: parts
+                .next()
+                .map(|value| value.parse())
+                .transpose()?
+                .unwrap_or(false),
+        })
+    }
+
+    fn on_config_change(
+        &mut self,
+        change: ConfigChange<'_, Self::Config>,
+        _ops: &dyn ConfigureOps,
+    ) -> Result<ConfigStatus> {
+        self.changes.push((
+            change.previous().map(|config| config.upstream.clone()),
+            change.current().upstream.clone(),
+            change.changed(|config| &config.upstream),
+        ));
+        if change.current().upstream.is_empty() {
+            Ok(ConfigStatus::Rejected)
+        } else {
+            Ok(ConfigStatus::Accepted)
+        }
+    }
+
+    fn new_extension(
+        &mut self,
+        config: Rc<Self::Config>,
+        _instance_id: InstanceId,
+    ) -> Result<Self::Extension> {
+        Ok(MyFilter { config })
+    }
+
+    fn failure_policy(&self, config: &Self::Config) -> FailurePolicy {
+        if config.reset_on_failure {
+            FailurePolicy::Reset
+        } else {
+            FailurePolicy::default()
+        }
+    }
+}
+
+#[test]
+fn test_reconfigurable_requires_config() {
+    let mut factory = Reconfigurable::new(MyFactory::default());
+
+    assert!(factory.config().is_none());
+    assert_eq!(
+        factory
+            .new_extension(InstanceId::from(1))
+            .err()
+            .map(|err| err.to_string()),
+        Some("extension has not been configured yet".to_owned()),
+    );
+}
+
+#[test]
+fn test_reconfigurable_swaps_config_for_new_extensions_only() -> Result<()> {
+    let mut factory = Reconfigurable::new(MyFactory::default());
+
+    assert_eq!(
+        factory.on_configure(ByteString::from("cluster_a"), &NoOps)?,
+        ConfigStatus::Accepted,
+    );
+    let old_filter = factory.new_extension(InstanceId::from(1))?;
+    assert_eq!(old_filter.config.upstream, "cluster_a");
+
+    assert_eq!(
+        factory.on_configure(ByteString::from("cluster_b,true"), &NoOps)?,
+        ConfigStatus::Accepted,
+    );
+    let new_filter = factory.new_extension(InstanceId::from(2))?;
+
+    assert_eq!(old_filter.config.upstream, "cluster_a");
+    assert_eq!(new_filter.config.upstream, "cluster_b");
+    assert_eq!(factory.failure_policy(), FailurePolicy::Reset);
+    assert_eq!(
+        factory.factory().changes,
+        vec![
+            (None, "cluster_a".to_owned(), true),
+            (Some("cluster_a".to_owned()), "cluster_b".to_owned(), true),
+        ],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_reconfigurable_keeps_config_on_rejection() -> Result<()> {
+    let mut factory = Reconfigurable::new(MyFactory::default());
+
+    factory.on_configure(ByteString::from("cluster_a"), &NoOps)?;
+
+    // rejected by `on_config_change`
+    assert_eq!(
+        factory.on_configure(ByteString::from(""), &NoOps)?,
+        ConfigStatus::Rejected,
+    );
+    // rejected by `parse_config`
+    assert!(factory
+        .on_configure(ByteString::from("cluster_b,maybe"), &NoOps)
+        .is_err());
+
+    assert_eq!(
+        factory.config().map(|config| config.upstream.as_str()),
+        Some("cluster_a"),
+    );
+    assert_eq!(
+        factory.factory().changes,
+        vec![
+            (None, "cluster_a".to_owned(), true),
+            (Some("cluster_a".to_owned()), "".to_owned(), true),
+        ],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_config_change_diff() {
+    let previous = MyConfig {
+        upstream: "cluster_a".to_owned(),
+        reset_on_failure: false,
+    };
+    let current = MyConfig {
+        upstream: "cluster_a".to_owned(),
+        reset_on_failure: true,
+    };
+
+    let initial = ConfigChange::new(None, &current);
+    assert!(initial.is_initial());
+    assert!(initial.changed(|config| &config.upstream));
+
+    let change = ConfigChange::new(Some(&previous), &current);
+    assert!(!change.is_initial());
+    assert!(!change.changed(|config| &config.upstream));
+    assert!(change.changed(|config| &config.reset_on_failure));
+    assert!(change.changed(|config| config));
+}
@@ -0,0 +1,335 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use envoy::extension::filter::http::{
+    self, FilterDataStatus, FilterHeadersStatus, FilterTrailersStatus, HttpFilter, RequestBodyOps,
+    RequestHeadersOps, ResponseHeadersOps, ResponseTrailersOps,
+};
+use envoy::extension::Result;
+use envoy::host::{HttpClient, HttpClientRequestHandle, HttpClientResponseOps};
+
+use envoy_sdk_test as envoy_test;
+use envoy_test::host::http::FakeHttpMessage;
+use envoy_test::{FakeHeaderMutation, FakeHttpClient, FakeHttpClientResponse, FakeHttpStream};
+
+/// Buffers the entire request body and rejects requests that mention "secret".
+struct BufferingFilter;
+
+impl HttpFilter for BufferingFilter {
+    fn on_request_headers(
+        &mut self,
+        _num_headers: usize,
+        end_of_stream: bool,
+        ops: &dyn RequestHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        ops.set_request_header("x-buffered", "true")?;
+        if end_of_stream {
+            return Ok(FilterHeadersStatus::Continue);
+        }
+        Ok(FilterHeadersStatus::StopIteration)
+    }
+
+    fn on_request_body(
+        &mut self,
+        data_size: usize,
+        end_of_stream: bool,
+        ops: &dyn RequestBodyOps,
+    ) -> Result<FilterDataStatus> {
+        if !end_of_stream {
+            return Ok(FilterDataStatus::StopIterationAndBuffer);
+        }
+        let body = ops.request_data(0, data_size)?;
+        if body.windows(6).any(|window| window == b"secret") {
+            ops.send_response(403, &[("x-reason", "secret")], Some(b"Forbidden"))?;
+            return Ok(FilterDataStatus::StopIterationAndBuffer);
+        }
+        Ok(FilterDataStatus::Continue)
+    }
+
+    fn on_response_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        ops: &dyn ResponseHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        ops.remove_response_header("server")?;
+        Ok(FilterHeadersStatus::Continue)
+    }
+
+    fn on_response_trailers(
+        &mut self,
+        _num_trailers: usize,
+        ops: &dyn ResponseTrailersOps,
+    ) -> Result<FilterTrailersStatus> {
+        ops.set_response_trailer("x-checked", "true")?;
+        Ok(FilterTrailersStatus::Continue)
+    }
+}
+
+/// Authorizes requests through an external service.
+struct AuthzFilter<'a> {
+    http_client: &'a dyn HttpClient,
+    active_request: Option<HttpClientRequestHandle>,
+}
+
+impl<'a> HttpFilter for AuthzFilter<'a> {
+    fn on_request_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        _ops: &dyn RequestHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        self.active_request = Some(self.http_client.send_request(
+            "authz",
+            &[(":method", "GET"), (":path", "/check")],
+            None,
+            None,
+            Duration::from_secs(1),
+        )?);
+        Ok(FilterHeadersStatus::StopIteration)
+    }
+
+    fn on_http_call_response(
+        &mut self,
+        request: HttpClientRequestHandle,
+        _num_headers: usize,
+        _body_size: usize,
+        _num_trailers: usize,
+        filter_ops: &dyn http::Ops,
+        http_client_ops: &dyn HttpClientResponseOps,
+    ) -> Result<()> {
+        assert_eq!(self.active_request.take(), Some(request));
+        match http_client_ops.http_call_response_header(":status")? {
+            Some(status) if status == "200" => filter_ops.resume_request(),
+            _ => filter_ops.reset_stream(),
+        }
+    }
+}
+
+#[test]
+fn test_entire_stream() -> Result<()> {
+    let mut filter = BufferingFilter;
+    let stream = FakeHttpStream::new();
+
+    assert_eq!(
+        stream.send_request_headers(&mut filter, &[(":path", "/upload")], false)?,
+        FilterHeadersStatus::StopIteration
+    );
+    assert!(stream.is_request_paused());
+    assert_eq!(stream.upstream_request(), None);
+
+    assert_eq!(
+        stream.send_request_body(&mut filter, b"hello ", false)?,
+        FilterDataStatus::StopIterationAndBuffer
+    );
+    assert_eq!(stream.upstream_request(), None);
+
+    assert_eq!(
+        stream.send_request_body(&mut filter, b"world", true)?,
+        FilterDataStatus::Continue
+    );
+    assert!(!stream.is_request_paused());
+    assert_eq!(
+        stream.upstream_request(),
+        Some(
+            FakeHttpMessage::builder()
+                .header(":path", "/upload")
+                .header("x-buffered", "true")
+                .body("hello world")
+                .build()
+        )
+    );
+    assert_eq!(
+        stream.request_header_mutations(),
+        vec![FakeHeaderMutation::Set(
+            "x-buffered".to_owned(),
+            "true".into()
+        )]
+    );
+
+    stream.send_response_headers(
+        &mut filter,
+        &[(":status", "200"), ("server", "envoy")],
+        false,
+    )?;
+    stream.send_response_body(&mut filter, b"ok", false)?;
+    stream.send_response_trailers(&mut filter, &[("grpc-status", "0")])?;
+    assert_eq!(
+        stream.downstream_response(),
+        Some(
+            FakeHttpMessage::builder()
+                .header(":status", "200")
+                .body("ok")
+                .trailer("grpc-status", "0")
+                .trailer("x-checked", "true")
+                .build()
+        )
+    );
+    assert_eq!(
+        stream.response_header_mutations(),
+        vec![FakeHeaderMutation::Remove("server".to_owned())]
+    );
+
+    stream.complete(&mut filter)?;
+    assert!(stream.is_complete());
+    assert!(stream.send_response_body(&mut filter, b"", true).is_err());
+    assert!(stream.local_reply().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_local_reply() -> Result<()> {
+    let mut filter = BufferingFilter;
+    let stream = FakeHttpStream::new();
+
+    stream.send_request_headers(&mut filter, &[(":path", "/upload")], false)?;
+    stream.send_request_body(&mut filter, b"top secret", true)?;
+
+    let local_reply = stream.local_reply().unwrap();
+    assert_eq!(local_reply.status_code, 403);
+    assert_eq!(local_reply.headers.get("x-reason"), Some(&"secret".into()));
+    assert_eq!(local_reply.body, "Forbidden");
+    assert_eq!(stream.upstream_request(), None);
+
+    let err = stream
+        .send_response_headers(&mut filter, &[(":status", "200")], true)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the stream has been terminated by a local reply"
+    );
+
+    // `on_exchange_complete` is still delivered
+    stream.complete(&mut filter)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_lifecycle_errors() -> Result<()> {
+    let mut filter = BufferingFilter;
+    let stream = FakeHttpStream::new();
+
+    let err = stream
+        .send_request_body(&mut filter, b"data", true)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "request headers have not been sent yet");
+
+    stream.send_request_headers(&mut filter, &[(":path", "/upload")], false)?;
+    let err = stream
+        .send_response_headers(&mut filter, &[(":status", "200")], true)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "request headers have not been forwarded upstream yet"
+    );
+
+    stream.send_request_trailers(&mut filter, &[("x-checksum", "abc")])?;
+    let err = stream
+        .send_request_body(&mut filter, b"data", true)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "request has already ended");
+
+    Ok(())
+}
+
+#[test]
+fn test_http_call_response_resumes_request() -> Result<()> {
+    let http_client = FakeHttpClient::default();
+    let mut filter = AuthzFilter {
+        http_client: &http_client,
+        active_request: None,
+    };
+    let stream = FakeHttpStream::new();
+
+    stream.send_request_headers(&mut filter, &[(":path", "/")], true)?;
+    assert!(stream.is_request_paused());
+    assert_eq!(stream.upstream_request(), None);
+
+    let pending_requests = http_client.drain_pending_requests();
+    assert_eq!(pending_requests.len(), 1);
+    assert_eq!(pending_requests[0].request.upstream, "authz");
+
+    let response = FakeHttpClientResponse::builder()
+        .header(":status", "200")
+        .build();
+    stream.deliver_http_call_response(&mut filter, pending_requests[0].handle, &response)?;
+
+    assert!(!stream.is_request_paused());
+    assert_eq!(
+        stream.upstream_request(),
+        Some(FakeHttpMessage::builder().header(":path", "/").build())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_request_body_continue_resumes_request() -> Result<()> {
+    let http_client = FakeHttpClient::default();
+    let mut filter = AuthzFilter {
+        http_client: &http_client,
+        active_request: None,
+    };
+    let stream = FakeHttpStream::new();
+
+    stream.send_request_headers(&mut filter, &[(":path", "/")], false)?;
+    assert!(stream.is_request_paused());
+
+    // just like in `Envoy`, `FilterDataStatus::Continue` resumes iteration
+    // stopped by `FilterHeadersStatus::StopIteration`
+    stream.send_request_body(&mut filter, b"a", false)?;
+    assert!(!stream.is_request_paused());
+    assert_eq!(
+        stream.upstream_request(),
+        Some(
+            FakeHttpMessage::builder()
+                .header(":path", "/")
+                .body("a")
+                .build()
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_http_call_response_resets_stream() -> Result<()> {
+    let http_client = FakeHttpClient::default();
+    let mut filter = AuthzFilter {
+        http_client: &http_client,
+        active_request: None,
+    };
+    let stream = FakeHttpStream::new();
+
+    stream.send_request_headers(&mut filter, &[(":path", "/")], true)?;
+    assert!(stream.is_request_paused());
+
+    let pending_requests = http_client.drain_pending_requests();
+    let response = FakeHttpClientResponse::builder()
+        .header(":status", "403")
+        .build();
+    stream.deliver_http_call_response(&mut filter, pending_requests[0].handle, &response)?;
+
+    assert!(stream.is_reset());
+    assert_eq!(stream.upstream_request(), None);
+    assert!(stream
+        .send_request_trailers(&mut filter, &[("x-checksum", "abc")])
+        .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,385 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use envoy::error::bail;
+use envoy::extension::error::{ErrorCategory, ErrorReport, ErrorSink};
+use envoy::extension::filter::http::{
+    FilterDataStatus, FilterHeadersStatus, Ops, RequestBodyOps, RequestHeadersOps,
+};
+use envoy::extension::filter::{FailurePolicy, LocalReply};
+use envoy::extension::{ExtensionFactory, HttpFilter, InstanceId, Module, Result};
+use envoy::host::http::client::{HttpClient, HttpClientRequestHandle, HttpClientResponseOps};
+use envoy::host::HeaderMap;
+
+use envoy_sdk_test::{FakeAction, FakeEnvoy, FakeHttpClientResponse, FakeLocalReply};
+
+#[derive(Default, Clone)]
+struct RecordingErrorSink {
+    reports: Rc<RefCell<Vec<(ErrorCategory, String)>>>,
+}
+
+impl ErrorSink for RecordingErrorSink {
+    fn observe(&self, report: &ErrorReport<'_>) {
+        self.reports
+            .borrow_mut()
+            .push((report.category, report.context.to_owned()));
+    }
+}
+
+/// Outcome of `on_request_headers`.
+#[derive(Copy, Clone)]
+enum Outcome {
+    /// Returns an error.
+    Fail,
+    /// Panics.
+    Panic,
+    /// Makes a call to the auth service and pauses the request.
+    Pause,
+    /// Makes a call to the auth service and lets the request proceed.
+    Continue,
+}
+
+/// Fails to handle a response to its own HTTP request.
+struct AuthFilter {
+    outcome: Outcome,
+    calls: Rc<Cell<usize>>,
+}
+
+impl HttpFilter for AuthFilter {
+    fn on_request_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        _ops: &dyn RequestHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        self.calls.set(self.calls.get() + 1);
+        let status = match self.outcome {
+            Outcome::Fail => bail!("unexpected header"),
+            Outcome::Panic => panic!("unexpected state"),
+            Outcome::Pause => FilterHeadersStatus::StopIteration,
+            Outcome::Continue => FilterHeadersStatus::Continue,
+        };
+        <dyn HttpClient>::default().send_request(
+            "auth_service",
+            &[(":method", "GET"), (":path", "/auth")],
+            None,
+            None,
+            Duration::from_secs(1),
+        )?;
+        Ok(status)
+    }
+
+    fn on_request_body(
+        &mut self,
+        _body_size: usize,
+        _end_of_stream: bool,
+        _ops: &dyn RequestBodyOps,
+    ) -> Result<FilterDataStatus> {
+        self.calls.set(self.calls.get() + 1);
+        Ok(FilterDataStatus::Continue)
+    }
+
+    fn on_http_call_response(
+        &mut self,
+        _request_id: HttpClientRequestHandle,
+        _num_headers: usize,
+        _body_size: usize,
+        _num_trailers: usize,
+        _filter_ops: &dyn Ops,
+        _http_client_ops: &dyn HttpClientResponseOps,
+    ) -> Result<()> {
+        bail!("auth service is unavailable")
+    }
+}
+
+struct AuthFilterFactory {
+    outcome: Outcome,
+    failure_policy: FailurePolicy,
+    is_broken: bool,
+    calls: Rc<Cell<usize>>,
+}
+
+impl ExtensionFactory for AuthFilterFactory {
+    type Extension = AuthFilter;
+
+    fn name() -> &'static str {
+        "auth_filter"
+    }
+
+    fn new_extension(&mut self, _instance_id: InstanceId) -> Result<Self::Extension> {
+        if self.is_broken {
+            bail!("out of resources")
+        }
+        Ok(AuthFilter {
+            outcome: self.outcome,
+            calls: Rc::clone(&self.calls),
+        })
+    }
+
+    fn failure_policy(&self) -> FailurePolicy {
+        self.failure_policy.clone()
+    }
+}
+
+struct Setup {
+    envoy: FakeEnvoy,
+    sink: RecordingErrorSink,
+    calls: Rc<Cell<usize>>,
+}
+
+impl Setup {
+    fn new(outcome: Outcome, failure_policy: FailurePolicy) -> Self {
+        Self::with_factory(outcome, failure_policy, false)
+    }
+
+    fn with_broken_factory(failure_policy: FailurePolicy) -> Self {
+        Self::with_factory(Outcome::Continue, failure_policy, true)
+    }
+
+    fn with_factory(outcome: Outcome, failure_policy: FailurePolicy, is_broken: bool) -> Self {
+        let sink = RecordingErrorSink::default();
+        let calls = Rc::new(Cell::new(0));
+        let factory_calls = Rc::clone(&calls);
+        let envoy = FakeEnvoy::start(Module::new().with_error_sink(sink.clone()).add_http_filter(
+            move |_| {
+                Ok(AuthFilterFactory {
+                    outcome,
+                    failure_policy: failure_policy.clone(),
+                    is_broken,
+                    calls: Rc::clone(&factory_calls),
+                })
+            },
+        ));
+        Setup { envoy, sink, calls }
+    }
+
+    fn reports(&self) -> Vec<(ErrorCategory, String)> {
+        self.sink.reports.borrow().clone()
+    }
+
+    /// Fails the only pending HTTP request to the auth service.
+    fn respond(&self) {
+        let calls = self.envoy.drain_http_calls();
+        assert_eq!(calls.len(), 1);
+        self.envoy
+            .respond_to_http_call(calls[0].handle, &FakeHttpClientResponse::builder().build());
+    }
+}
+
+#[test]
+fn test_failure_policy_local_reply() {
+    let setup = Setup::new(
+        Outcome::Fail,
+        FailurePolicy::LocalReply(
+            LocalReply::new(503)
+                .header("content-type", "text/plain")
+                .body("unavailable"),
+        ),
+    );
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+
+    let mut reply = FakeLocalReply::default();
+    reply.status_code = 503;
+    reply.headers = HeaderMap::from(&[("content-type", "text/plain")][..]);
+    reply.body = "unavailable".into();
+    assert_eq!(stream.local_reply(), Some(reply));
+    assert!(!stream.is_reset());
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::Callback,
+            "failed to handle HTTP request headers".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn test_failure_policy_reset() {
+    let setup = Setup::new(Outcome::Fail, FailurePolicy::Reset);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+
+    assert!(stream.is_reset());
+    assert_eq!(stream.local_reply(), None);
+    assert_eq!(setup.reports().len(), 1);
+}
+
+#[test]
+fn test_failure_policy_reset_falls_back_to_local_reply() {
+    let setup = Setup::new(Outcome::Fail, FailurePolicy::Reset);
+    setup.envoy.reject_host_call("proxy_close_stream");
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+
+    assert!(!stream.is_reset());
+    assert_eq!(
+        stream.local_reply().map(|reply| reply.status_code),
+        Some(500)
+    );
+    assert_eq!(
+        setup.reports(),
+        vec![
+            (
+                ErrorCategory::Callback,
+                "failed to handle HTTP request headers".to_owned()
+            ),
+            (
+                ErrorCategory::Callback,
+                "failed to reset the HTTP stream, sending a local reply instead".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_failure_policy_continue() {
+    let setup = Setup::new(Outcome::Fail, FailurePolicy::Continue);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Continue);
+
+    assert!(!stream.is_reset());
+    assert_eq!(stream.local_reply(), None);
+    assert_eq!(setup.reports().len(), 1);
+}
+
+#[test]
+fn test_failed_http_call_response_resumes_paused_stream() {
+    let setup = Setup::new(Outcome::Pause, FailurePolicy::Continue);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+    setup.respond();
+
+    assert_eq!(stream.resumed_requests(), 1);
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::Callback,
+            "failed to process a response to an HTTP request made by the extension".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn test_failed_http_call_response_does_not_resume_running_stream() {
+    let setup = Setup::new(Outcome::Continue, FailurePolicy::Continue);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Continue);
+    setup.respond();
+
+    assert_eq!(stream.resumed_requests(), 0);
+    assert_eq!(setup.reports().len(), 1);
+}
+
+#[test]
+fn test_failed_http_call_response_terminates_stream() {
+    let setup = Setup::new(Outcome::Pause, FailurePolicy::Reset);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+    setup.respond();
+
+    assert!(stream.is_reset());
+    assert_eq!(stream.resumed_requests(), 0);
+}
+
+#[test]
+fn test_failed_extension_creation() {
+    let setup = Setup::with_broken_factory(FailurePolicy::default());
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+
+    assert_eq!(
+        stream.local_reply().map(|reply| reply.status_code),
+        Some(500)
+    );
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::ExtensionCreation,
+            "failed to create Proxy Wasm Http Context".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn test_failed_extension_creation_continue() {
+    let setup = Setup::with_broken_factory(FailurePolicy::Continue);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Continue);
+
+    assert_eq!(stream.local_reply(), None);
+    assert_eq!(setup.reports().len(), 1);
+}
+
+#[test]
+#[cfg(panic = "unwind")]
+fn test_panicking_filter() {
+    let setup = Setup::new(Outcome::Panic, FailurePolicy::Reset);
+    let root = setup.envoy.new_root_context("auth_filter");
+    assert!(root.configure(b""));
+
+    let stream = root.new_http_stream();
+    assert_eq!(stream.request_headers(&[], false), FakeAction::Pause);
+    assert_eq!(setup.calls.get(), 1);
+    assert!(stream.is_reset());
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::Panic,
+            "failed to handle HTTP request headers".to_owned()
+        )]
+    );
+
+    // the filter has failed and must not be called any longer
+    assert_eq!(stream.request_body(b"hello", true), FakeAction::Pause);
+    assert_eq!(setup.calls.get(), 1);
+    assert_eq!(
+        setup.reports()[1],
+        (
+            ErrorCategory::Callback,
+            "failed to handle HTTP request body".to_owned()
+        )
+    );
+}
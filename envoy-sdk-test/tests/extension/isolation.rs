@@ -0,0 +1,158 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use envoy::error::bail;
+use envoy::extension::error::{ErrorCategory, ErrorReport, ErrorSink};
+use envoy::extension::service::ConfigureOps;
+use envoy::extension::{ConfigStatus, Module, Result, Service};
+use envoy::host::ByteString;
+
+use envoy_sdk_test::FakeEnvoy;
+
+#[derive(Default, Clone)]
+struct RecordingErrorSink {
+    reports: Rc<RefCell<Vec<(ErrorCategory, String)>>>,
+}
+
+impl ErrorSink for RecordingErrorSink {
+    fn observe(&self, report: &ErrorReport<'_>) {
+        self.reports.borrow_mut().push((
+            report.category,
+            format!("{}: {}", report.context, report.error),
+        ));
+    }
+}
+
+/// Panics on `panic` config and fails to handle the first tick.
+struct MyService {
+    ticks: Rc<Cell<usize>>,
+}
+
+impl Service for MyService {
+    fn name() -> &'static str {
+        "my_service"
+    }
+
+    fn on_configure(&mut self, config: ByteString, ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+        ops.set_tick_period(Duration::from_secs(1))?;
+        if config == "panic" {
+            panic!("index {} is out of range", 7);
+        }
+        Ok(ConfigStatus::Accepted)
+    }
+
+    fn on_tick(&mut self) -> Result<()> {
+        self.ticks.set(self.ticks.get() + 1);
+        if self.ticks.get() == 1 {
+            bail!("invalid state")
+        }
+        Ok(())
+    }
+}
+
+fn start(sink: &RecordingErrorSink, ticks: &Rc<Cell<usize>>) -> FakeEnvoy {
+    let ticks = Rc::clone(ticks);
+    FakeEnvoy::start(
+        Module::new()
+            .with_error_sink(sink.clone())
+            .add_service(move |_| {
+                Ok(MyService {
+                    ticks: Rc::clone(&ticks),
+                })
+            }),
+    )
+}
+
+#[test]
+fn test_isolation_error() {
+    let sink = RecordingErrorSink::default();
+    let ticks = Rc::new(Cell::new(0));
+    let envoy = start(&sink, &ticks);
+
+    let root = envoy.new_root_context("my_service");
+    assert!(root.configure(b""));
+    root.tick();
+    root.tick();
+
+    // an error doesn't prevent the service from being called again
+    assert_eq!(ticks.get(), 2);
+    assert_eq!(
+        *sink.reports.borrow(),
+        vec![(
+            ErrorCategory::Callback,
+            "failed to handle a timer tick: invalid state".to_owned()
+        )]
+    );
+}
+
+#[test]
+#[cfg(panic = "unwind")]
+fn test_isolation_panic() {
+    let sink = RecordingErrorSink::default();
+    let ticks = Rc::new(Cell::new(0));
+    let envoy = start(&sink, &ticks);
+
+    let root = envoy.new_root_context("my_service");
+    assert!(!root.configure(b"panic"));
+
+    // the service must not be called again
+    root.tick();
+    assert_eq!(ticks.get(), 0);
+    assert!(root.drain());
+
+    assert_eq!(
+        *sink.reports.borrow(),
+        vec![
+            (
+                ErrorCategory::Panic,
+                "failed to configure extension: extension has panicked: index 7 is out of range"
+                    .to_owned()
+            ),
+            (
+                ErrorCategory::Callback,
+                "failed to handle a timer tick: extension instance can no longer be used since it has panicked earlier"
+                    .to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_isolation_disabled() {
+    let sink = RecordingErrorSink::default();
+    let ticks = Rc::new(Cell::new(0));
+    let ticks_clone = Rc::clone(&ticks);
+    let envoy = FakeEnvoy::start(
+        Module::new()
+            .with_error_sink(sink.clone())
+            .with_panic_isolation(false)
+            .add_service(move |_| {
+                Ok(MyService {
+                    ticks: Rc::clone(&ticks_clone),
+                })
+            }),
+    );
+
+    let root = envoy.new_root_context("my_service");
+    assert!(root.configure(b""));
+    root.tick();
+    root.tick();
+
+    assert_eq!(ticks.get(), 2);
+    assert_eq!(sink.reports.borrow().len(), 1);
+}
@@ -0,0 +1,250 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use envoy::error::bail;
+use envoy::extension::error::{ErrorReport, ErrorSink};
+use envoy::extension::{service, ConfigStatus, Module, Result, RootId, Service};
+use envoy::host::ByteString;
+
+use envoy_sdk_test::FakeEnvoy;
+
+#[test]
+fn test_root_id_matches() {
+    let exact = RootId::from("my_http_filter");
+    assert!(exact.matches("my_http_filter"));
+    assert!(!exact.matches("my_http_filter.ingress"));
+
+    let prefix = RootId::prefix("my_http_filter.");
+    assert!(prefix.matches("my_http_filter.ingress"));
+    assert!(prefix.matches("my_http_filter.egress"));
+    assert!(!prefix.matches("my_http_filter"));
+
+    assert_eq!(exact.to_string(), "my_http_filter");
+    assert_eq!(prefix.to_string(), "my_http_filter.*");
+}
+
+/// Rejects any configuration.
+struct MyService;
+
+impl Service for MyService {
+    fn name() -> &'static str {
+        "my_service"
+    }
+
+    fn on_configure(
+        &mut self,
+        _config: ByteString,
+        _ops: &dyn service::ConfigureOps,
+    ) -> Result<ConfigStatus> {
+        bail!("invalid config")
+    }
+}
+
+struct RecordingErrorSink(Rc<RefCell<Vec<String>>>);
+
+impl ErrorSink for RecordingErrorSink {
+    fn observe(&self, report: &ErrorReport<'_>) {
+        self.0.borrow_mut().push(format!(
+            "{} {:?}: {}: {}",
+            report.category, report.root_id, report.context, report.error
+        ));
+    }
+}
+
+#[test]
+fn test_module_with_error_sink() {
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let envoy = FakeEnvoy::start(
+        Module::new()
+            .with_error_sink(RecordingErrorSink(Rc::clone(&reports)))
+            .add_service(|_| Ok(MyService)),
+    );
+
+    let root = envoy.new_root_context("my_service");
+    assert!(!root.configure(b"{}"));
+
+    assert_eq!(
+        *reports.borrow(),
+        vec![r#"configuration Some("my_service"): failed to configure extension: invalid config"#]
+    );
+    assert!(envoy.logs().is_empty());
+}
+
+#[test]
+fn test_module_with_default_error_sink() {
+    let envoy = FakeEnvoy::start(Module::new().add_service(|_| Ok(MyService)));
+
+    let root = envoy.new_root_context("my_service");
+    assert!(!root.configure(b"{}"));
+
+    assert_eq!(
+        envoy.logs(),
+        vec!["failed to configure extension: invalid config"]
+    );
+}
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+/// Registers `MyService` under given `root_id`s; every registration records
+/// its tag along with the `root_id` it gets created for.
+fn start(registrations: Vec<(RootId, &'static str)>) -> (FakeEnvoy, Log, Log) {
+    let created = Log::default();
+    let reports = Log::default();
+    let mut module = Module::new().with_error_sink(RecordingErrorSink(Rc::clone(&reports)));
+    for (root_id, tag) in registrations {
+        let created = Rc::clone(&created);
+        module = module
+            .add_service_as(root_id, move |root_id, _| {
+                created.borrow_mut().push(format!("{} {}", tag, root_id));
+                Ok(MyService)
+            })
+            .unwrap();
+    }
+    (FakeEnvoy::start(Ok(module)), created, reports)
+}
+
+/// Returns the tag of the registration a given `root_id` resolves to, if any.
+fn lookup(envoy: &FakeEnvoy, created: &Log, root_id: &str) -> Option<String> {
+    let count = created.borrow().len();
+    envoy.new_root_context(root_id);
+    let created = created.borrow();
+    created[count..].first().map(|entry| {
+        let (tag, actual) = entry.split_at(entry.find(' ').unwrap());
+        assert_eq!(&actual[1..], root_id);
+        tag.to_owned()
+    })
+}
+
+#[test]
+fn test_root_id_exact_over_prefix() {
+    let (envoy, created, _) = start(vec![
+        (RootId::prefix("my_filter."), "prefix"),
+        (RootId::exact("my_filter.ingress"), "exact"),
+    ]);
+
+    assert_eq!(
+        lookup(&envoy, &created, "my_filter.ingress").as_deref(),
+        Some("exact")
+    );
+    assert_eq!(
+        lookup(&envoy, &created, "my_filter.egress").as_deref(),
+        Some("prefix")
+    );
+    assert_eq!(lookup(&envoy, &created, "my_filter").as_deref(), None);
+}
+
+#[test]
+fn test_root_id_longest_prefix() {
+    let (envoy, created, _) = start(vec![
+        (RootId::prefix("my_filter."), "short"),
+        (RootId::prefix("my_filter.ingress."), "long"),
+        (RootId::prefix(""), "catch-all"),
+    ]);
+
+    assert_eq!(
+        lookup(&envoy, &created, "my_filter.ingress.v1").as_deref(),
+        Some("long")
+    );
+    assert_eq!(
+        lookup(&envoy, &created, "my_filter.ingress").as_deref(),
+        Some("short")
+    );
+    assert_eq!(
+        lookup(&envoy, &created, "other_filter").as_deref(),
+        Some("catch-all")
+    );
+}
+
+#[test]
+fn test_root_id_empty() {
+    let (envoy, created, _) = start(vec![(RootId::exact("my_filter"), "only")]);
+    assert_eq!(lookup(&envoy, &created, "").as_deref(), Some("only"));
+
+    let (envoy, created, _) = start(vec![
+        (RootId::exact("my_filter"), "first"),
+        (RootId::exact("other_filter"), "second"),
+    ]);
+    assert_eq!(lookup(&envoy, &created, "").as_deref(), None);
+
+    let (envoy, created, _) = start(vec![
+        (RootId::exact("my_filter"), "exact"),
+        (RootId::prefix("other_filter."), "prefix"),
+    ]);
+    assert_eq!(lookup(&envoy, &created, "").as_deref(), None);
+
+    let (envoy, created, _) = start(vec![]);
+    assert_eq!(lookup(&envoy, &created, "").as_deref(), None);
+}
+
+#[test]
+fn test_root_id_duplicate_registration() {
+    let err = Module::new()
+        .add_service_as("my_filter", |_, _| Ok(MyService))
+        .and_then(|module| module.add_service_as(RootId::prefix("my_filter"), |_, _| Ok(MyService)))
+        .and_then(|module| module.add_service_as("my_filter", |_, _| Ok(MyService)))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        r#"WebAssembly module attempted to register 2 different extensions under the same `root_id` "my_filter""#
+    );
+
+    let err = Module::new()
+        .add_service_as(RootId::prefix("my_filter"), |_, _| Ok(MyService))
+        .and_then(|module| module.add_service_as(RootId::prefix("my_filter"), |_, _| Ok(MyService)))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        r#"WebAssembly module attempted to register 2 different extensions under the same `root_id` "my_filter*""#
+    );
+}
+
+#[test]
+fn test_root_id_unknown() {
+    let (envoy, _, reports) = start(vec![
+        (RootId::exact("my_service"), "exact"),
+        (RootId::prefix("my_filter."), "prefix"),
+    ]);
+
+    assert!(!envoy.new_root_context("other_service").configure(b""));
+    assert!(!envoy.new_root_context("").configure(b""));
+
+    assert_eq!(
+        *reports.borrow(),
+        vec![
+            r#"initialization None: failed to create Proxy Wasm Root Context: WebAssembly module has no extension with `root_id` "other_service"; valid `root_id` values are: ["my_filter.*", "my_service"]"#,
+            r#"initialization None: failed to create Proxy Wasm Root Context: WebAssembly module has no extension with `root_id` ""; valid `root_id` values are: ["my_filter.*", "my_service"]"#,
+        ]
+    );
+}
+
+#[test]
+fn test_root_id_pattern_resolves_to_actual_root_id() {
+    let (envoy, created, reports) = start(vec![(RootId::prefix("my_service."), "prefix")]);
+
+    let root = envoy.new_root_context("my_service.ingress");
+    assert!(!root.configure(b"{}"));
+
+    assert_eq!(*created.borrow(), vec!["prefix my_service.ingress"]);
+    assert_eq!(
+        *reports.borrow(),
+        vec![
+            r#"configuration Some("my_service.ingress"): failed to configure extension: invalid config"#
+        ]
+    );
+}
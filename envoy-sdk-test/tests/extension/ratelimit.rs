@@ -0,0 +1,256 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use envoy::extension::filter::http::ratelimit::{
+    Descriptor, RateLimit, RateLimitFilterFactory, RateLimitPolicy, RateLimiter,
+};
+use envoy::extension::filter::http::{FilterHeadersStatus, HttpFilter};
+use envoy::extension::filter::FailurePolicy;
+use envoy::extension::{ExtensionFactory, InstanceId};
+use envoy::host::Result;
+
+use envoy_sdk_test as envoy_test;
+use envoy_test::{FakeClock, FakeHttpStream, FakeSharedData, FakeStreamInfo};
+
+#[test]
+fn test_token_bucket() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let limiter = RateLimiter::new(
+        &shared_data,
+        &clock,
+        "test",
+        RateLimit::token_bucket(2, 1, Duration::from_secs(1)),
+    );
+
+    let decision = limiter.check("a")?;
+    assert!(decision.is_allowed());
+    assert_eq!(decision.limit(), 2);
+    assert_eq!(decision.remaining(), 1);
+    assert_eq!(decision.reset_after(), Duration::from_secs(1));
+
+    assert!(limiter.check("a")?.is_allowed());
+
+    let decision = limiter.check("a")?;
+    assert!(!decision.is_allowed());
+    assert_eq!(decision.remaining(), 0);
+    assert_eq!(decision.retry_after(), Duration::from_secs(1));
+
+    // other keys have their own quota
+    assert!(limiter.check("b")?.is_allowed());
+
+    clock.advance(Duration::from_millis(500));
+    let decision = limiter.check("a")?;
+    assert!(!decision.is_allowed());
+    assert_eq!(decision.retry_after(), Duration::from_millis(500));
+
+    clock.advance(Duration::from_millis(500));
+    assert!(limiter.check("a")?.is_allowed());
+    assert!(!limiter.check("a")?.is_allowed());
+
+    // the bucket never holds more than its capacity
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(limiter.check("a")?.remaining(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_sliding_window() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let limiter = RateLimiter::new(
+        &shared_data,
+        &clock,
+        "test",
+        RateLimit::sliding_window(4, Duration::from_secs(10)),
+    );
+
+    for remaining in (0..4).rev() {
+        let decision = limiter.check("a")?;
+        assert!(decision.is_allowed());
+        assert_eq!(decision.remaining(), remaining);
+    }
+    let decision = limiter.check("a")?;
+    assert!(!decision.is_allowed());
+    assert_eq!(decision.retry_after(), Duration::from_secs(10));
+
+    // half of the previous window still counts
+    clock.advance(Duration::from_secs(15));
+    assert!(limiter.check("a")?.is_allowed());
+    assert!(limiter.check("a")?.is_allowed());
+    assert!(!limiter.check("a")?.is_allowed());
+
+    // both windows have passed
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(limiter.check("a")?.remaining(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_limiter_retries_on_concurrent_updates() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let limiter = RateLimiter::new(
+        &shared_data,
+        &clock,
+        "test",
+        RateLimit::sliding_window(10, Duration::from_secs(1)),
+    )
+    .with_max_attempts(2);
+
+    limiter.check("a")?;
+
+    shared_data.simulate_concurrent_writes(1);
+    assert_eq!(limiter.check("a")?.remaining(), 8);
+
+    shared_data.simulate_concurrent_writes(2);
+    assert!(limiter.check("a").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_descriptor() -> Result<()> {
+    let stream_info = FakeStreamInfo::new().with(|info| {
+        info.source().address("10.0.0.1:54321");
+        info.route().name("api");
+    });
+    let request = FakeHttpStream::new();
+    request.send_request_headers(&mut PassThroughFilter, &[("x-api-key", "123")], true)?;
+
+    let descriptor = Descriptor::new()
+        .constant("service", "backend")
+        .source_address("client")
+        .route_name("route")
+        .request_header("api_key", "x-api-key");
+    assert_eq!(
+        descriptor.resolve(&request, &stream_info)?,
+        Some("service=backend|client=10.0.0.1|route=api|api_key=123".to_owned())
+    );
+
+    let descriptor = Descriptor::new().request_header("user", "x-user");
+    assert_eq!(descriptor.resolve(&request, &stream_info)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_descriptor_escapes_entries() -> Result<()> {
+    let stream_info = FakeStreamInfo::new();
+    let descriptor = Descriptor::new()
+        .request_header("a", "x-a")
+        .request_header("b", "x-b");
+
+    let request = FakeHttpStream::new();
+    request.send_request_headers(
+        &mut PassThroughFilter,
+        &[("x-a", "1|b=2"), ("x-b", "3")],
+        true,
+    )?;
+    let first = descriptor.resolve(&request, &stream_info)?;
+    assert_eq!(first, Some("a=1%7Cb%3D2|b=3".to_owned()));
+
+    // values that used to collide with the ones above
+    let request = FakeHttpStream::new();
+    request.send_request_headers(
+        &mut PassThroughFilter,
+        &[("x-a", "1"), ("x-b", "2|b=3")],
+        true,
+    )?;
+    let second = descriptor.resolve(&request, &stream_info)?;
+    assert_eq!(second, Some("a=1|b=2%7Cb%3D3".to_owned()));
+    assert_ne!(first, second);
+
+    let descriptor = Descriptor::new().constant("100%", "a=b");
+    assert_eq!(
+        descriptor.resolve(&request, &stream_info)?,
+        Some("100%25=a%3Db".to_owned())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rate_limit_filter() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let stream_info = FakeStreamInfo::new();
+    let mut factory = RateLimitFilterFactory::new(
+        vec![
+            RateLimitPolicy::new(
+                "per_api_key",
+                RateLimit::token_bucket(1, 1, Duration::from_secs(60)),
+                Descriptor::new().request_header("api_key", "x-api-key"),
+            ),
+            RateLimitPolicy::new(
+                "global",
+                RateLimit::sliding_window(10, Duration::from_secs(60)),
+                Descriptor::new(),
+            ),
+        ],
+        &shared_data,
+        &clock,
+        &stream_info,
+    );
+    assert_eq!(factory.failure_policy(), FailurePolicy::Continue);
+
+    // the first request is allowed
+    let mut filter = factory.new_extension(InstanceId::from(1))?;
+    let stream = FakeHttpStream::new();
+    assert_eq!(
+        stream.send_request_headers(&mut filter, &[("x-api-key", "123")], true)?,
+        FilterHeadersStatus::Continue
+    );
+    assert!(stream.local_reply().is_none());
+
+    stream.send_response_headers(&mut filter, &[(":status", "200")], true)?;
+    let response = stream.downstream_response().unwrap();
+    assert_eq!(response.headers.get("x-ratelimit-limit"), Some(&"1".into()));
+    assert_eq!(
+        response.headers.get("x-ratelimit-remaining"),
+        Some(&"0".into())
+    );
+
+    // the second request with the same API key is rejected
+    let mut filter = factory.new_extension(InstanceId::from(2))?;
+    let stream = FakeHttpStream::new();
+    assert_eq!(
+        stream.send_request_headers(&mut filter, &[("x-api-key", "123")], true)?,
+        FilterHeadersStatus::StopIteration
+    );
+    assert!(stream.upstream_request().is_none());
+    let local_reply = stream.local_reply().unwrap();
+    assert_eq!(local_reply.status_code, 429);
+    assert_eq!(local_reply.headers.get("retry-after"), Some(&"60".into()));
+
+    // requests without API key are only subject to the global limit
+    let mut filter = factory.new_extension(InstanceId::from(3))?;
+    let stream = FakeHttpStream::new();
+    assert_eq!(
+        stream.send_request_headers(&mut filter, &[], true)?,
+        FilterHeadersStatus::Continue
+    );
+    assert_eq!(filter.decision().unwrap().remaining(), 7);
+
+    Ok(())
+}
+
+/// Lets requests through untouched.
+struct PassThroughFilter;
+
+impl HttpFilter for PassThroughFilter {}
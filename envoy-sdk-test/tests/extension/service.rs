@@ -0,0 +1,309 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use envoy::error::bail;
+use envoy::extension::error::{ErrorCategory, ErrorReport, ErrorSink};
+use envoy::extension::service::ConfigureOps;
+use envoy::extension::{ConfigStatus, DrainStatus, Module, Result, Service};
+use envoy::host::http::client::{HttpClient, HttpClientRequestHandle, HttpClientResponseOps};
+use envoy::host::shared_queue::SharedQueueHandle;
+use envoy::host::ByteString;
+
+use envoy_sdk_test::{FakeEnvoy, FakeHttpClientResponse};
+
+type Events = Rc<RefCell<Vec<String>>>;
+
+#[derive(Default, Clone)]
+struct RecordingErrorSink {
+    reports: Rc<RefCell<Vec<(ErrorCategory, String)>>>,
+}
+
+impl ErrorSink for RecordingErrorSink {
+    fn observe(&self, report: &ErrorReport<'_>) {
+        self.reports
+            .borrow_mut()
+            .push((report.category, report.context.to_owned()));
+    }
+}
+
+/// Records every call made by the SDK.
+struct RecordingService {
+    events: Events,
+    drain_status: Rc<Cell<DrainStatus>>,
+    fail: Rc<Cell<bool>>,
+}
+
+impl RecordingService {
+    fn record(&self, event: String) -> Result<()> {
+        self.events.borrow_mut().push(event);
+        if self.fail.get() {
+            bail!("service is unavailable");
+        }
+        Ok(())
+    }
+}
+
+impl Service for RecordingService {
+    fn name() -> &'static str {
+        "recording_service"
+    }
+
+    fn on_configure(&mut self, config: ByteString, ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+        self.record(format!("on_configure({})", config))?;
+        ops.set_tick_period(Duration::from_secs(10))?;
+        Ok(ConfigStatus::Accepted)
+    }
+
+    fn on_tick(&mut self) -> Result<()> {
+        <dyn HttpClient>::default().send_request(
+            "config_service",
+            &[(":method", "GET"), (":path", "/config")],
+            None,
+            None,
+            Duration::from_secs(1),
+        )?;
+        self.record("on_tick".to_owned())
+    }
+
+    fn on_queue_ready(&mut self, queue: SharedQueueHandle) -> Result<()> {
+        self.record(format!("on_queue_ready({})", queue))
+    }
+
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        self.record("on_drain".to_owned())?;
+        Ok(self.drain_status.get())
+    }
+
+    fn on_http_call_response(
+        &mut self,
+        request_id: HttpClientRequestHandle,
+        num_headers: usize,
+        body_size: usize,
+        num_trailers: usize,
+        _http_client_ops: &dyn HttpClientResponseOps,
+    ) -> Result<()> {
+        self.record(format!(
+            "on_http_call_response({}, {}, {}, {})",
+            request_id, num_headers, body_size, num_trailers
+        ))
+    }
+}
+
+struct Setup {
+    envoy: FakeEnvoy,
+    sink: RecordingErrorSink,
+    events: Events,
+    drain_status: Rc<Cell<DrainStatus>>,
+    fail: Rc<Cell<bool>>,
+}
+
+impl Setup {
+    fn new() -> Self {
+        Self::with_module(Module::new())
+    }
+
+    fn with_module(module: Module) -> Self {
+        let sink = RecordingErrorSink::default();
+        let events = Events::default();
+        let drain_status = Rc::new(Cell::new(DrainStatus::Complete));
+        let fail = Rc::new(Cell::new(false));
+        let (service_events, service_drain_status, service_fail) = (
+            Rc::clone(&events),
+            Rc::clone(&drain_status),
+            Rc::clone(&fail),
+        );
+        let envoy = FakeEnvoy::start(module.with_error_sink(sink.clone()).add_service(move |_| {
+            Ok(RecordingService {
+                events: Rc::clone(&service_events),
+                drain_status: Rc::clone(&service_drain_status),
+                fail: Rc::clone(&service_fail),
+            })
+        }));
+        Setup {
+            envoy,
+            sink,
+            events,
+            drain_status,
+            fail,
+        }
+    }
+
+    fn events(&self) -> Vec<String> {
+        self.events.borrow().clone()
+    }
+
+    fn reports(&self) -> Vec<(ErrorCategory, String)> {
+        self.sink.reports.borrow().clone()
+    }
+}
+
+#[test]
+fn test_service_on_configure() {
+    let setup = Setup::new();
+    let root = setup.envoy.new_root_context("recording_service");
+
+    assert!(root.configure(br#"{"interval": "10s"}"#));
+    assert!(root.configure(b""));
+
+    assert_eq!(
+        setup.events(),
+        vec![r#"on_configure({"interval": "10s"})"#, "on_configure()"]
+    );
+    assert_eq!(root.tick_period(), Duration::from_secs(10));
+    assert!(setup.reports().is_empty());
+}
+
+#[test]
+fn test_service_on_configure_error() {
+    let setup = Setup::new();
+    setup.fail.set(true);
+    let root = setup.envoy.new_root_context("recording_service");
+
+    assert!(!root.configure(b""));
+
+    assert_eq!(root.tick_period(), Duration::default());
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::Configuration,
+            "failed to configure extension".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn test_service_callbacks() {
+    let setup = Setup::new();
+    let root = setup.envoy.new_root_context("recording_service");
+
+    root.tick();
+    root.queue_ready(3);
+    let calls = setup.envoy.drain_http_calls();
+    assert_eq!(calls.len(), 1);
+    setup.envoy.respond_to_http_call(
+        calls[0].handle,
+        &FakeHttpClientResponse::builder()
+            .header(":status", "200")
+            .body("{}")
+            .build(),
+    );
+
+    assert_eq!(
+        setup.events(),
+        vec![
+            "on_tick".to_owned(),
+            "on_queue_ready(3)".to_owned(),
+            format!("on_http_call_response({}, 1, 2, 0)", calls[0].handle),
+        ]
+    );
+    assert!(setup.reports().is_empty());
+}
+
+#[test]
+fn test_service_callback_errors() {
+    let setup = Setup::new();
+    let root = setup.envoy.new_root_context("recording_service");
+    setup.fail.set(true);
+
+    root.tick();
+    root.queue_ready(3);
+    let calls = setup.envoy.drain_http_calls();
+    assert_eq!(calls.len(), 1);
+    setup
+        .envoy
+        .respond_to_http_call(calls[0].handle, &FakeHttpClientResponse::builder().build());
+
+    // errors don't prevent the service from being called again
+    assert_eq!(setup.events().len(), 3);
+    assert_eq!(
+        setup.reports(),
+        vec![
+            (
+                ErrorCategory::Callback,
+                "failed to handle a timer tick".to_owned()
+            ),
+            (
+                ErrorCategory::Callback,
+                "failed to process messages in a shared queue".to_owned()
+            ),
+            (
+                ErrorCategory::Callback,
+                "failed to process a response to an HTTP request made by the extension".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_service_on_drain() {
+    let setup = Setup::new();
+
+    let complete = setup.envoy.new_root_context("recording_service");
+    assert!(complete.drain());
+
+    setup.drain_status.set(DrainStatus::Ongoing);
+    let ongoing = setup.envoy.new_root_context("recording_service");
+    assert!(!ongoing.drain());
+
+    setup.fail.set(true);
+    let failed = setup.envoy.new_root_context("recording_service");
+    assert!(!failed.drain());
+
+    assert_eq!(setup.events(), vec!["on_drain", "on_drain", "on_drain"]);
+    for root in &[complete, ongoing, failed] {
+        assert_eq!(root.done_count(), 0);
+    }
+    assert_eq!(
+        setup.reports(),
+        vec![(
+            ErrorCategory::Drain,
+            "failed to initiate draining of the extension".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn test_service_drain_timeout_keeps_tick_period() {
+    let setup = Setup::with_module(Module::new().with_drain_timeout(Duration::from_secs(5)));
+    setup.drain_status.set(DrainStatus::Ongoing);
+    let root = setup.envoy.new_root_context("recording_service");
+
+    assert!(root.configure(b""));
+    assert!(!root.drain());
+    root.tick();
+
+    assert_eq!(root.tick_period(), Duration::from_secs(10));
+    assert_eq!(
+        setup.events(),
+        vec!["on_configure()", "on_drain", "on_tick"]
+    );
+}
+
+#[test]
+fn test_service_drain_timeout_ticks() {
+    let setup = Setup::with_module(Module::new().with_drain_timeout(Duration::from_secs(5)));
+    setup.drain_status.set(DrainStatus::Ongoing);
+    let root = setup.envoy.new_root_context("recording_service");
+
+    assert!(!root.drain());
+    root.tick();
+
+    // ticks caused by the drain timeout are not forwarded to the service
+    assert_eq!(root.tick_period(), Duration::from_secs(5));
+    assert_eq!(setup.events(), vec!["on_drain"]);
+}
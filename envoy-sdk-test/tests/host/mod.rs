@@ -13,6 +13,8 @@
 // limitations under the License.
 
 mod http;
+mod shared_data;
+mod shared_queue;
 mod stats;
 mod stream_info;
 mod time;
@@ -0,0 +1,304 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use envoy::host::shared_data::{
+    CacheLookup, CasMismatchError, SharedCache, SharedCounter, SharedMap, SharedValue,
+};
+use envoy::host::{Result, SharedData};
+
+use envoy_sdk_test as envoy_test;
+use envoy_test::{FakeClock, FakeSharedData};
+
+#[test]
+fn test_shared_value() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let value: SharedValue<Vec<String>> = SharedValue::new(&shared_data, "my.value");
+
+    assert_eq!(value.key(), "my.value");
+    assert_eq!(value.get()?, None);
+
+    value.set(&vec!["a".to_owned()])?;
+    assert_eq!(value.get()?, Some(vec!["a".to_owned()]));
+
+    let updated = value.update(|old| {
+        let mut new = old.unwrap_or_default();
+        new.push("b".to_owned());
+        new
+    })?;
+    assert_eq!(updated, vec!["a".to_owned(), "b".to_owned()]);
+    assert_eq!(value.get()?, Some(updated));
+
+    assert_eq!(value.try_update(|_| None)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_value_update_retries_on_cas_mismatch() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let value: SharedValue<u64> = SharedValue::new(&shared_data, "my.value");
+    value.set(&1)?;
+
+    shared_data.simulate_concurrent_writes(2);
+    let mut calls = 0;
+    assert_eq!(
+        value.update(|old| {
+            calls += 1;
+            old.unwrap_or_default() + 1
+        })?,
+        2
+    );
+    assert_eq!(calls, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_value_update_gives_up_after_max_attempts() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let value: SharedValue<u64> = SharedValue::new(&shared_data, "my.value").with_max_attempts(3);
+    value.set(&1)?;
+
+    shared_data.simulate_concurrent_writes(3);
+    let err = value.update(|old| old.unwrap_or_default() + 1).unwrap_err();
+    assert!(err.is::<CasMismatchError>());
+    assert_eq!(
+        err.to_string(),
+        "failed to update shared value \"my.value\" after 3 attempt(s)"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_value_update_creates_missing_value_under_lock() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let value: SharedValue<u64> = SharedValue::new(&shared_data, "my.value");
+    let other: SharedValue<u64> = SharedValue::new(&shared_data, "my.value");
+
+    let mut calls = 0;
+    assert_eq!(
+        value.update(|old| {
+            calls += 1;
+            if calls == 2 {
+                // another worker creates the value concurrently
+                other.set(&5).unwrap();
+            }
+            old.unwrap_or_default() + 1
+        })?,
+        6
+    );
+    assert_eq!(calls, 3);
+    assert_eq!(value.get()?, Some(6));
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_value_decoding_error() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    shared_data.set("my.value", b"not json", None)?;
+
+    let value: SharedValue<u64> = SharedValue::new(&shared_data, "my.value");
+    let err = value.get().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "failed to decode shared value \"my.value\""
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_counter() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let counter = SharedCounter::new(&shared_data, "my.counter");
+
+    assert_eq!(counter.value()?, 0);
+    assert_eq!(counter.inc()?, 1);
+
+    shared_data.simulate_concurrent_writes(1);
+    assert_eq!(counter.add(5)?, 6);
+    assert_eq!(SharedCounter::new(&shared_data, "my.counter").value()?, 6);
+
+    counter.reset()?;
+    assert_eq!(counter.value()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_map() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let map: SharedMap<u32> = SharedMap::new(&shared_data, "my.map");
+
+    assert_eq!(map.get("a")?, None);
+    assert_eq!(map.insert("a", 1)?, None);
+    assert_eq!(map.insert("b", 2)?, None);
+    assert_eq!(map.insert("a", 3)?, Some(1));
+    assert_eq!(map.get("a")?, Some(3));
+
+    assert_eq!(map.remove("a")?, Some(3));
+    assert_eq!(map.remove("a")?, None);
+    assert_eq!(
+        map.entries()?.into_iter().collect::<Vec<_>>(),
+        vec![("b".to_owned(), 2)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_cache_expiration() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let cache: SharedCache<String> = SharedCache::new(&shared_data, &clock, "tokens")
+        .with_ttl(Duration::from_secs(60))
+        .with_negative_ttl(Duration::from_secs(10));
+
+    assert_eq!(cache.get("a")?, CacheLookup::Miss);
+
+    cache.put("a", "token-a".to_owned())?;
+    cache.put_negative("b")?;
+
+    // another worker shares the same cache
+    let other: SharedCache<String> = SharedCache::new(&shared_data, &clock, "tokens");
+    assert_eq!(other.get("a")?, CacheLookup::Hit("token-a".to_owned()));
+    assert_eq!(other.get("b")?, CacheLookup::NegativeHit);
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(cache.get("a")?, CacheLookup::Hit("token-a".to_owned()));
+    assert_eq!(cache.get("b")?, CacheLookup::Miss);
+
+    clock.advance(Duration::from_secs(50));
+    assert_eq!(cache.get("a")?, CacheLookup::Miss);
+
+    cache.put("a", "token-a2".to_owned())?;
+    cache.invalidate("a")?;
+    assert_eq!(cache.get("a")?, CacheLookup::Miss);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_cache_coalesces_refreshes() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let cache: SharedCache<String> = SharedCache::new(&shared_data, &clock, "tokens")
+        .with_refresh_timeout(Duration::from_secs(5));
+    let other: SharedCache<String> = SharedCache::new(&shared_data, &clock, "tokens");
+
+    assert!(cache.try_begin_refresh("a")?);
+    assert!(!other.try_begin_refresh("a")?);
+    assert!(other.try_begin_refresh("b")?);
+
+    cache.put("a", "token-a".to_owned())?;
+    assert!(other.try_begin_refresh("a")?);
+    other.abort_refresh("a")?;
+
+    // an abandoned refresh times out
+    assert!(cache.try_begin_refresh("a")?);
+    clock.advance(Duration::from_secs(5));
+    assert!(other.try_begin_refresh("a")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_cache_eviction() -> Result<()> {
+    let shared_data = FakeSharedData::default();
+    let clock = FakeClock::default();
+    let cache: SharedCache<u32> = SharedCache::new(&shared_data, &clock, "numbers")
+        .with_ttl(Duration::from_secs(60))
+        .with_max_entries(2);
+
+    cache.put("a", 1)?;
+    clock.advance(Duration::from_secs(1));
+    cache.put("b", 2)?;
+    clock.advance(Duration::from_secs(1));
+    cache.put("c", 3)?;
+
+    // the entry that would expire the soonest gets evicted
+    assert_eq!(cache.get("a")?, CacheLookup::Miss);
+    assert_eq!(cache.get("b")?, CacheLookup::Hit(2));
+    assert_eq!(cache.get("c")?, CacheLookup::Hit(3));
+
+    // updating an existing entry doesn't evict anything
+    cache.put("b", 20)?;
+    assert_eq!(cache.get("b")?, CacheLookup::Hit(20));
+    assert_eq!(cache.get("c")?, CacheLookup::Hit(3));
+
+    Ok(())
+}
+
+#[test]
+fn test_fake_shared_data_versions() -> Result<()> {
+    let shared_data = FakeSharedData::default()
+        .with_entry("my.a", "1")
+        .with_entry("other.b", "2");
+
+    let (value, version) = shared_data.get("my.a")?;
+    assert_eq!(value, Some("1".into()));
+    assert_eq!(version, shared_data.version("my.a"));
+    assert_eq!(shared_data.get("my.missing")?, (None, None));
+
+    // a write with a version succeeds if the key hasn't been written since
+    shared_data.set("my.a", b"3", version)?;
+    assert_ne!(shared_data.version("my.a"), version);
+
+    // a stale write is rejected
+    let err = shared_data.set("my.a", b"4", version).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<CasMismatchError>(),
+        Some(&CasMismatchError::new("my.a"))
+    );
+    assert_eq!(shared_data.value("my.a"), Some("3".into()));
+    assert_eq!(shared_data.cas_mismatches(), 1);
+
+    // a write with a version of a missing key always succeeds
+    shared_data.set("my.c", b"5", version)?;
+
+    assert_eq!(shared_data.keys(), vec!["my.a", "my.c", "other.b"]);
+    assert_eq!(
+        shared_data.entries_with_prefix("my."),
+        vec![
+            ("my.a".to_owned(), "3".into()),
+            ("my.c".to_owned(), "5".into())
+        ]
+    );
+
+    shared_data.clear();
+    assert!(shared_data.keys().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_fake_shared_data_concurrent_writes() -> Result<()> {
+    let shared_data = FakeSharedData::default().with_entry("my.key", "1");
+
+    shared_data.simulate_concurrent_writes(1);
+
+    let (_, version) = shared_data.get("my.key")?;
+    assert!(shared_data.set("my.key", b"2", version).is_err());
+    assert_eq!(shared_data.value("my.key"), Some("1".into()));
+
+    let (_, version) = shared_data.get("my.key")?;
+    shared_data.set("my.key", b"2", version)?;
+    assert_eq!(shared_data.value("my.key"), Some("2".into()));
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use envoy::host::shared_queue::{Receiver, Sender};
+use envoy::host::{Result, SharedQueue};
+
+use envoy_sdk_test as envoy_test;
+use envoy_test::FakeSharedQueue;
+
+#[test]
+fn test_send_and_receive() -> Result<()> {
+    let shared_queue = FakeSharedQueue::new("singleton");
+
+    let receiver: Receiver<(String, u32)> = Receiver::register(&shared_queue, "events")?;
+    let sender: Sender<(String, u32)> = Sender::new(&shared_queue, "singleton", "events");
+
+    assert!(sender.send(&("a".to_owned(), 1))?);
+    assert!(sender.send(&("b".to_owned(), 2))?);
+    assert!(sender.send(&("c".to_owned(), 3))?);
+    assert_eq!(shared_queue.lookups(), 1);
+
+    assert_eq!(receiver.recv()?, Some(("a".to_owned(), 1)));
+
+    let mut received = Vec::new();
+    assert_eq!(receiver.drain(|message| received.push(message))?, 2);
+    assert_eq!(received, vec![("b".to_owned(), 2), ("c".to_owned(), 3)]);
+    assert_eq!(receiver.recv()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_sender_resolves_queue_lazily() -> Result<()> {
+    let shared_queue = FakeSharedQueue::new("singleton");
+
+    let sender: Sender<u32> = Sender::new(&shared_queue, "singleton", "events");
+    assert_eq!(shared_queue.lookups(), 0);
+
+    // the queue hasn't been registered yet
+    assert!(!sender.send(&1)?);
+    assert_eq!(sender.dropped(), 1);
+
+    let receiver: Receiver<u32> = Receiver::register(&shared_queue, "events")?;
+    assert!(sender.send(&2)?);
+    assert_eq!(receiver.recv()?, Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn test_sender_reports_drops() -> Result<()> {
+    let shared_queue = FakeSharedQueue::new("singleton").with_capacity(1);
+
+    let receiver: Receiver<u32> = Receiver::register(&shared_queue, "events")?;
+    let sender: Sender<u32> = Sender::new(&shared_queue, "singleton", "events");
+
+    assert!(sender.send(&1)?);
+    assert!(!sender.send(&2)?);
+    assert_eq!(sender.dropped(), 1);
+
+    assert_eq!(receiver.drain(|_| {})?, 1);
+    assert!(sender.send(&3)?);
+    assert_eq!(sender.dropped(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_receiver_decoding_error() -> Result<()> {
+    let shared_queue = FakeSharedQueue::new("singleton");
+
+    let receiver: Receiver<u32> = Receiver::register(&shared_queue, "events")?;
+    shared_queue.enqueue(receiver.handle(), b"not json")?;
+
+    let err = receiver.recv().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "failed to decode a message from the shared queue \"events\""
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_fake_shared_queue_multiple_vms() -> Result<()> {
+    let shared_queue = FakeSharedQueue::new("my_vm");
+
+    let local = shared_queue.register("events")?;
+    let remote = shared_queue.register_in_vm("singleton", "events");
+    assert_ne!(local, remote);
+    assert_eq!(shared_queue.register("events")?, local);
+
+    assert_eq!(shared_queue.lookup("my_vm", "events")?, Some(local));
+    assert_eq!(shared_queue.lookup("singleton", "events")?, Some(remote));
+    assert_eq!(shared_queue.lookup("singleton", "other")?, None);
+    assert_eq!(shared_queue.lookups(), 3);
+
+    shared_queue.enqueue(remote, b"a")?;
+    shared_queue.enqueue(local, b"b")?;
+    shared_queue.enqueue(remote, b"c")?;
+
+    assert_eq!(
+        shared_queue.drain_ready_notifications(),
+        vec![remote, local, remote]
+    );
+    assert!(shared_queue.drain_ready_notifications().is_empty());
+
+    assert_eq!(shared_queue.messages(remote), vec!["a", "c"]);
+    assert_eq!(shared_queue.len(local), 1);
+    assert!(shared_queue.dequeue(remote).is_err());
+    assert_eq!(shared_queue.dequeue(local)?, Some("b".into()));
+    assert!(shared_queue.is_empty(local));
+
+    Ok(())
+}
@@ -12,6 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::rc::Rc;
+
+use regex::Regex;
+
+use envoy::host::stats::{
+    self, Counter, CounterFamily, Gauge, GaugeFamily, Histogram, HistogramFamily,
+};
 use envoy::host::{Result, Stats};
 
 use envoy_sdk_test as envoy_test;
@@ -93,3 +100,198 @@ fn test_fake_histogram() -> Result<()> {
 
     Ok(())
 }
+
+#[derive(stats::Stats)]
+#[stats(prefix = "my.extension")]
+struct DerivedStats {
+    requests_total: Box<dyn Counter>,
+    #[stats(name = "active")]
+    requests_active: Rc<dyn Gauge>,
+    #[stats(histogram)]
+    response_size: Box<dyn Histogram>,
+}
+
+#[test]
+fn test_derived_stats() -> Result<()> {
+    let fake_stats = FakeStats::default();
+
+    let stats = DerivedStats::new(&fake_stats)?;
+    stats.requests_total.inc()?;
+    stats.requests_active.set(3)?;
+    stats.response_size.record(512)?;
+
+    assert_eq!(
+        fake_stats.counter("my.extension.requests_total")?.value()?,
+        1
+    );
+    assert_eq!(fake_stats.gauge("my.extension.active")?.value()?, 3);
+    assert_eq!(
+        fake_stats.histogram_values("my.extension.response_size"),
+        Some(vec![512])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_derived_stats_with_prefix() -> Result<()> {
+    let fake_stats = FakeStats::default();
+
+    let stats = DerivedStats::with_prefix(&fake_stats, "other.")?;
+    stats.requests_total.add(2)?;
+    assert_eq!(fake_stats.counter("other.requests_total")?.value()?, 2);
+    assert_eq!(
+        fake_stats.counter("my.extension.requests_total")?.value()?,
+        0
+    );
+
+    let stats = DerivedStats::with_prefix(&fake_stats, "")?;
+    stats.requests_active.inc()?;
+    assert_eq!(fake_stats.gauge("active")?.value()?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_counter_family() -> Result<()> {
+    let stats = FakeStats::default();
+
+    let requests_total = CounterFamily::new(&stats, "requests_total", &["route", "status_class"])?;
+    assert_eq!(requests_total.name(), "requests_total");
+    assert_eq!(requests_total.tag_names(), &["route", "status_class"]);
+
+    requests_total.with_tag_values(&["default", "2xx"])?.inc()?;
+    requests_total.with_tag_values(&["default", "2xx"])?.inc()?;
+    requests_total
+        .with_tags(&[("status_class", "5xx"), ("route", "default")])?
+        .add(3)?;
+
+    assert_eq!(
+        stats
+            .counter("requests_total.route.default.status_class.2xx")?
+            .value()?,
+        2
+    );
+    assert_eq!(
+        stats.counter_with_tags(
+            "requests_total",
+            &[("status_class", "2xx"), ("route", "default")]
+        ),
+        Some(2)
+    );
+    assert_eq!(
+        stats.counter_with_tags(
+            "requests_total",
+            &[("route", "default"), ("status_class", "5xx")]
+        ),
+        Some(3)
+    );
+    assert_eq!(
+        stats.counter_with_tags(
+            "requests_total",
+            &[("route", "default"), ("status_class", "4xx")]
+        ),
+        None
+    );
+    assert_eq!(
+        stats.family_tags("requests_total"),
+        vec![
+            vec![
+                ("route".to_string(), "default".to_string()),
+                ("status_class".to_string(), "2xx".to_string()),
+            ],
+            vec![
+                ("route".to_string(), "default".to_string()),
+                ("status_class".to_string(), "5xx".to_string()),
+            ],
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_gauge_and_histogram_families() -> Result<()> {
+    let stats = FakeStats::default();
+
+    let connections_active = GaugeFamily::new(&stats, "connections_active", &["upstream"])?;
+    connections_active.with_tag_values(&["10.0.0.1"])?.set(5)?;
+
+    let response_times = HistogramFamily::new(&stats, "response_times", &["route"])?;
+    response_times.with_tag_values(&["default"])?.record(10)?;
+    response_times.with_tag_values(&["default"])?.record(20)?;
+
+    // `.` is reserved as a separator in `Envoy` stat names
+    assert_eq!(
+        connections_active.stat_name(&["10.0.0.1"])?,
+        "connections_active.upstream.10_0_0_1"
+    );
+    assert_eq!(
+        stats.gauge_with_tags("connections_active", &[("upstream", "10.0.0.1")]),
+        Some(5)
+    );
+    assert_eq!(
+        stats.histogram_with_tags("response_times", &[("route", "default")]),
+        Some(vec![10, 20])
+    );
+    assert_eq!(
+        stats.histogram_values("response_times.route.default"),
+        Some(vec![10, 20])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_metric_family_errors() -> Result<()> {
+    let stats = FakeStats::default();
+
+    assert!(CounterFamily::new(&stats, "", &["route"]).is_err());
+    assert!(CounterFamily::new(&stats, "requests_total", &["route.name"]).is_err());
+    assert!(CounterFamily::new(&stats, "requests_total", &["route", "route"]).is_err());
+
+    let requests_total = CounterFamily::new(&stats, "requests_total", &["route"])?;
+    assert!(requests_total.with_tag_values(&["a", "b"]).is_err());
+    assert!(requests_total.with_tags(&[("cluster", "a")]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_metric_family_tag_extraction_regex() -> Result<()> {
+    let stats = FakeStats::default();
+
+    let requests_total = CounterFamily::new(&stats, "requests_total", &["route", "status_class"])?;
+    assert_eq!(requests_total.tag_extraction_regex("cluster"), None);
+
+    let stat_name = format!(
+        "wasmcustom.{}",
+        requests_total.stat_name(&["default", "2xx"])?
+    );
+    for (tag_name, expected_value, expected_name) in &[
+        (
+            "route",
+            "default",
+            "wasmcustom.requests_total.status_class.2xx",
+        ),
+        (
+            "status_class",
+            "2xx",
+            "wasmcustom.requests_total.route.default",
+        ),
+    ] {
+        let regex = Regex::new(&requests_total.tag_extraction_regex(tag_name).unwrap())?;
+        let captures = regex.captures(&stat_name).unwrap();
+        assert_eq!(&captures[2], *expected_value);
+        // `Envoy` removes the first capture group from the stat name
+        let removed = captures.get(1).unwrap();
+        let remaining = format!(
+            "{}{}",
+            &stat_name[..removed.start()],
+            &stat_name[removed.end()..]
+        );
+        assert_eq!(remaining, *expected_name);
+    }
+
+    Ok(())
+}
@@ -12,11 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::Cell;
 use std::time::{Duration, SystemTime};
 
 use envoy::extension::access_logger;
-use envoy::host::stream_info::{ResponseFlags, TrafficDirection};
-use envoy::host::{HeaderMap, Result, StreamInfo};
+use envoy::host::stream_info::{
+    encoding, CachedStreamInfo, FilterStateOptions, LifeSpan, Metadata, Property, ResponseFlags,
+    TrafficDirection,
+};
+use envoy::host::{ByteString, HeaderMap, Result, StreamInfo};
+
+use serde_json::json;
 
 use envoy_sdk_test as envoy_test;
 use envoy_test::FakeStreamInfo;
@@ -76,6 +82,31 @@ fn test_connection() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_connection_details() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.connection()
+            .termination_details("idle timeout")
+            .tls()
+            .sha256_peer_certificate_digest("ab12");
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(
+        stream_info.connection().termination_details()?,
+        Some("idle timeout".to_owned())
+    );
+    assert_eq!(
+        stream_info
+            .connection()
+            .tls()
+            .sha256_peer_certificate_digest()?,
+        Some("ab12".into())
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_http_request() -> Result<()> {
     let fake_info = FakeStreamInfo::new().with(|info| {
@@ -133,6 +164,36 @@ fn test_http_request() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_http_request_headers() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request()
+            .method("GET")
+            .scheme("https")
+            .host("www.example.com")
+            .path("/search?q=example")
+            .header("content-type", "application/json")
+            .header("user-agent", "curl");
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(
+        stream_info.request().headers()?,
+        Some(
+            HeaderMap::builder()
+                .header(":method", "GET")
+                .header(":scheme", "https")
+                .header(":authority", "www.example.com")
+                .header(":path", "/search?q=example")
+                .header("content-type", "application/json")
+                .header("user-agent", "curl")
+                .build()
+        )
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_http_response() -> Result<()> {
     let fake_info = FakeStreamInfo::new().with(|info| {
@@ -170,6 +231,44 @@ fn test_http_response() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_http_response_details() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.response()
+            .status_code(201)
+            .header("content-type", "application/json")
+            .trailer("grpc-message", "UNKNOWN")
+            .grpc_status(1)
+            .code_details("via_upstream");
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(
+        stream_info.response().headers()?,
+        Some(
+            HeaderMap::builder()
+                .header(":status", "201")
+                .header("content-type", "application/json")
+                .build()
+        )
+    );
+    assert_eq!(
+        stream_info.response().trailers()?,
+        Some(
+            HeaderMap::builder()
+                .header("grpc-message", "UNKNOWN")
+                .header("grpc-status", "1")
+                .build()
+        )
+    );
+    assert_eq!(
+        stream_info.response().code_details()?,
+        Some("via_upstream".to_owned())
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_upstream() -> Result<()> {
     let fake_info = FakeStreamInfo::new().with(|info| {
@@ -234,6 +333,28 @@ fn test_upstream() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_upstream_details() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.upstream()
+            .request_attempt_count(3)
+            .tls()
+            .sha256_peer_certificate_digest("cd34");
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(stream_info.upstream().request_attempt_count()?, Some(3));
+    assert_eq!(
+        stream_info
+            .upstream()
+            .tls()
+            .sha256_peer_certificate_digest()?,
+        Some("cd34".into())
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_source() -> Result<()> {
     let fake_info = FakeStreamInfo::new().with(|info| {
@@ -323,6 +444,249 @@ fn test_plugin() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_xds() -> Result<()> {
+    let cluster_metadata = Metadata::builder()
+        .filter_metadata(
+            "envoy.lb",
+            json!({"canary": true, "weight": 10.0, "zones": ["a", "b"]}),
+        )
+        .filter_metadata("example", json!({"nested": {"key": null}}))
+        .build();
+    let route_metadata = Metadata::builder()
+        .filter_metadata("example", json!({"tier": "gold"}))
+        .build();
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.xds()
+            .node_id("sidecar~10.0.0.1~pod.ns~ns.svc.cluster.local")
+            .cluster_metadata(cluster_metadata.clone())
+            .route_metadata(route_metadata.clone())
+            .upstream_host_metadata(Metadata::new());
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(
+        stream_info.xds().node_id()?,
+        Some("sidecar~10.0.0.1~pod.ns~ns.svc.cluster.local".to_owned())
+    );
+    assert_eq!(
+        stream_info.xds().cluster_metadata()?,
+        Some(cluster_metadata)
+    );
+    assert_eq!(stream_info.xds().route_metadata()?, Some(route_metadata));
+    assert_eq!(
+        stream_info.xds().upstream_host_metadata()?,
+        Some(Metadata::new())
+    );
+    assert_eq!(stream_info.xds().listener_metadata()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_metadata() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.metadata(
+            Metadata::builder()
+                .filter_metadata("envoy.filters.http.jwt_authn", json!({"sub": "alice"}))
+                .build(),
+        );
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    let metadata = stream_info.metadata()?.unwrap();
+    assert_eq!(
+        metadata.value("envoy.filters.http.jwt_authn", "sub"),
+        Some(&json!("alice"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_property_out_of_range() -> Result<()> {
+    const COUNT: &Property<u64, encoding::Int64> = &Property::new(&["vendor.count"]);
+
+    let fake_info = FakeStreamInfo::new();
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    stream_info.set_property(COUNT, i64::MAX as u64)?;
+
+    let err = stream_info
+        .set_property(COUNT, i64::MAX as u64 + 1)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "value 9223372036854775808 is out of range of int64"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_property() -> Result<()> {
+    const CANARY: &Property<bool, encoding::Bool> = &Property::new(&["vendor", "canary"]);
+    const ATTEMPTS: &Property<i64, encoding::Int64> = &Property::new(&["vendor", "attempts"]);
+    const RATIO: &Property<f64, encoding::Float64> = &Property::new(&["vendor.ratio"]);
+    const TIMEOUT: &Property<Duration, encoding::Duration> = &Property::new(&["vendor.timeout"]);
+    const DEADLINE: &Property<SystemTime, encoding::Timestamp> =
+        &Property::new(&["vendor.deadline"]);
+    const LABELS: &Property<HeaderMap, encoding::ProtoMap> = &Property::new(&["vendor.labels"]);
+    let tenant = "tenant-a".to_owned();
+    let tenant_property: Property<String, encoding::ByteString> =
+        Property::with_path(vec!["vendor", &tenant]);
+
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.property(CANARY, true)
+            .property(ATTEMPTS, -2)
+            .property(&tenant_property, "gold".to_owned())
+            // custom values take precedence over built-in ones
+            .property(
+                &Property::<String, encoding::ByteString>::new(&["request", "method"]),
+                "PATCH".to_owned(),
+            );
+        info.request().method("GET");
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(stream_info.property(CANARY)?, Some(true));
+    assert_eq!(stream_info.property(ATTEMPTS)?, Some(-2));
+    assert_eq!(
+        stream_info.property(&tenant_property)?,
+        Some("gold".to_owned())
+    );
+    assert_eq!(stream_info.request().method()?, Some("PATCH".to_owned()));
+    assert_eq!(stream_info.property(RATIO)?, None);
+
+    // values that have been set can be read back
+    let deadline = SystemTime::UNIX_EPOCH + Duration::from_millis(1500);
+    let labels = HeaderMap::builder().header("app", "web").build();
+    stream_info.set_property(RATIO, 0.25)?;
+    stream_info.set_property(TIMEOUT, Duration::from_secs(3))?;
+    stream_info.set_property(DEADLINE, deadline)?;
+    stream_info.set_property(LABELS, labels.clone())?;
+
+    assert_eq!(stream_info.property(RATIO)?, Some(0.25));
+    assert_eq!(stream_info.property(TIMEOUT)?, Some(Duration::from_secs(3)));
+    assert_eq!(stream_info.property(DEADLINE)?, Some(deadline));
+    assert_eq!(stream_info.property(LABELS)?, Some(labels));
+
+    // values are decoded according to the property declaration
+    const INVALID: &Property<bool, encoding::Bool> = &Property::new(&["vendor", "attempts"]);
+    assert!(stream_info.property(INVALID).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_filter_state() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.filter_state()
+            .value("example.tenant", "tenant-a")
+            .value("example.retries", &3);
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    assert_eq!(
+        stream_info.filter_state().get::<String>("example.tenant")?,
+        Some("tenant-a".to_owned())
+    );
+    assert_eq!(
+        stream_info.filter_state().get::<u32>("example.retries")?,
+        Some(3)
+    );
+    assert_eq!(
+        stream_info.filter_state().get::<u32>("example.unknown")?,
+        None
+    );
+    assert!(stream_info
+        .filter_state()
+        .get::<u32>("example.tenant")
+        .is_err());
+
+    // mutable entry
+    stream_info
+        .filter_state()
+        .put("example.tenant", "tenant-b")?;
+    assert_eq!(
+        stream_info.filter_state().get::<String>("example.tenant")?,
+        Some("tenant-b".to_owned())
+    );
+
+    // read-only entry
+    let options = FilterStateOptions::new()
+        .life_span(LifeSpan::DownstreamConnection)
+        .read_only(true);
+    stream_info
+        .filter_state()
+        .declare("example.user", options)?;
+    assert_eq!(
+        fake_info.declared_filter_state("example.user"),
+        Some(options)
+    );
+
+    stream_info
+        .filter_state()
+        .put("example.user", &vec!["alice"])?;
+    assert!(stream_info
+        .filter_state()
+        .put("example.user", &vec!["bob"])
+        .is_err());
+    assert_eq!(
+        stream_info
+            .filter_state()
+            .get::<Vec<String>>("example.user")?,
+        Some(vec!["alice".to_owned()])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_filter_state_routing() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.filter_state().value("example.tenant", "tenant-a");
+    });
+    let stream_info: &dyn StreamInfo = &fake_info;
+
+    // keys that are neither declared nor put are not `filter state` entries
+    stream_info.set_stream_property(&["example.unknown"], b"value")?;
+    assert_eq!(
+        stream_info.stream_property(&["example.unknown"])?,
+        Some("value".into())
+    );
+    assert_eq!(fake_info.declared_filter_state("example.unknown"), None);
+
+    // read-only entries are rejected the way `Envoy` rejects them
+    stream_info
+        .filter_state()
+        .declare("example.user", FilterStateOptions::new().read_only(true))?;
+    stream_info.filter_state().put("example.user", "alice")?;
+    let err = stream_info
+        .filter_state()
+        .put("example.user", "bob")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "call to the host ABI function \"env.proxy_set_property\" has failed with status code 2"
+    );
+
+    // values can only be saved under a single-segment path
+    let err = stream_info
+        .set_stream_property(&["example", "tenant"], b"tenant-b")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "call to the host ABI function \"env.proxy_set_property\" has failed with status code 2"
+    );
+    assert_eq!(
+        stream_info.filter_state().get::<String>("example.tenant")?,
+        Some("tenant-a".to_owned())
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_headers() -> Result<()> {
     let fake_info = FakeStreamInfo::new().with(|info| {
@@ -394,3 +758,80 @@ fn test_headers() -> Result<()> {
 
     Ok(())
 }
+
+/// Counts calls to the wrapped `StreamInfo`.
+struct CountingStreamInfo<'a> {
+    inner: &'a dyn StreamInfo,
+    calls: Cell<usize>,
+}
+
+impl<'a> StreamInfo for CountingStreamInfo<'a> {
+    fn stream_property(&self, path: &[&str]) -> Result<Option<ByteString>> {
+        self.calls.set(self.calls.get() + 1);
+        self.inner.stream_property(path)
+    }
+
+    fn set_stream_property(&self, path: &[&str], value: &[u8]) -> Result<()> {
+        self.inner.set_stream_property(path, value)
+    }
+}
+
+#[test]
+fn test_declare_stream_property_unsupported_by_default() {
+    let fake_info = FakeStreamInfo::new();
+    let counting_info = CountingStreamInfo {
+        inner: &fake_info,
+        calls: Cell::new(0),
+    };
+
+    let err = counting_info
+        .declare_stream_property("my_extension.tenant", FilterStateOptions::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "declaring stream property \"my_extension.tenant\" is not supported"
+    );
+}
+
+#[test]
+fn test_cached_stream_info() -> Result<()> {
+    let fake_info = FakeStreamInfo::new().with(|info| {
+        info.request().method("GET").path("/");
+        info.response().status_code(200);
+    });
+    let counting_info = CountingStreamInfo {
+        inner: &fake_info,
+        calls: Cell::new(0),
+    };
+    let cached_info = CachedStreamInfo::new(&counting_info);
+
+    cached_info.prefetch(&[&["request", "method"], &["request", "path"]])?;
+    assert_eq!(counting_info.calls.get(), 2);
+    assert!(cached_info.is_cached(&["request", "method"]));
+    assert!(!cached_info.is_cached(&["response", "code"]));
+
+    let stream_info: &dyn StreamInfo = &cached_info;
+    for _ in 0..3 {
+        assert_eq!(stream_info.request().method()?, Some("GET".to_owned()));
+        assert_eq!(stream_info.request().path()?, Some("/".to_owned()));
+        assert_eq!(stream_info.response().status_code()?, Some(200));
+        // absent values are cached too
+        assert_eq!(stream_info.request().id()?, None);
+    }
+    assert_eq!(counting_info.calls.get(), 4);
+
+    // saving a value invalidates the cached one
+    stream_info.set_stream_property(&["my_extension.key"], b"1")?;
+    stream_info.set_stream_property(&["my_extension.key"], b"2")?;
+    assert_eq!(
+        stream_info.stream_property(&["my_extension.key"])?,
+        Some("2".into())
+    );
+    assert_eq!(counting_info.calls.get(), 5);
+
+    cached_info.clear();
+    assert_eq!(stream_info.request().method()?, Some("GET".to_owned()));
+    assert_eq!(counting_info.calls.get(), 6);
+
+    Ok(())
+}
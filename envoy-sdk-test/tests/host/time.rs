@@ -12,12 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::rc::Rc;
 use std::time::{Duration, SystemTime};
 
-use envoy::host::{Clock, Result};
+use envoy::host::stats::Histogram;
+use envoy::host::time::{TimeUnit, Timer};
+use envoy::host::{Clock, Result, Stats};
 
 use envoy_sdk_test as envoy_test;
-use envoy_test::FakeClock;
+use envoy_test::{FakeClock, FakeStats};
 
 #[test]
 fn test_default_fake_clock() -> Result<()> {
@@ -57,3 +60,51 @@ fn test_advance_fake_clock() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_timer_stop() -> Result<()> {
+    let clock = FakeClock::default();
+    let stats = FakeStats::default();
+    let histogram = stats.histogram("my.latency_ms")?;
+
+    let timer = Timer::start(&clock, &*histogram, TimeUnit::Milliseconds)?;
+    clock.advance(Duration::from_millis(250));
+    assert_eq!(timer.elapsed()?, Duration::from_millis(250));
+
+    clock.advance(Duration::from_millis(50));
+    assert_eq!(timer.stop()?, Duration::from_millis(300));
+
+    assert_eq!(stats.histogram_values("my.latency_ms"), Some(vec![300]));
+
+    Ok(())
+}
+
+#[test]
+fn test_timer_records_on_drop() -> Result<()> {
+    let clock = FakeClock::default();
+    let stats = FakeStats::default();
+    let histogram: Rc<dyn Histogram> = stats.histogram("my.latency_us")?.into();
+
+    let timer = Timer::start(&clock, Rc::clone(&histogram), TimeUnit::Microseconds)?;
+    clock.advance(Duration::from_millis(2));
+    drop(timer);
+
+    assert_eq!(stats.histogram_values("my.latency_us"), Some(vec![2000]));
+
+    Ok(())
+}
+
+#[test]
+fn test_timer_cancel() -> Result<()> {
+    let clock = FakeClock::default();
+    let stats = FakeStats::default();
+    let histogram = stats.histogram("my.latency_ms")?;
+
+    let timer = Timer::start(&clock, &*histogram, TimeUnit::Milliseconds)?;
+    clock.advance(Duration::from_millis(10));
+    timer.cancel();
+
+    assert_eq!(stats.histogram_values("my.latency_ms"), Some(vec![]));
+
+    Ok(())
+}
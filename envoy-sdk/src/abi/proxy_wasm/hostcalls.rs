@@ -18,13 +18,16 @@
 
 use std::time::{Duration, SystemTime};
 
+use proxy_wasm::error::HostCallError;
 use proxy_wasm::hostcalls;
 
+use super::scope;
 use super::types::{
     BufferType, HttpRequestHandle, MapType, MetricHandle, MetricType, OptimisticLockVersion,
     SharedQueueHandle, Status, StreamType,
 };
 use crate::error::format_err;
+use crate::host::shared_data::CasMismatchError;
 use crate::host::{self, ByteString, HeaderMap};
 
 // Configuration API
@@ -37,7 +40,18 @@ pub fn get_plugin_configuration(start: usize, max_size: usize) -> host::Result<B
 // Lifecycle API
 
 pub fn done() -> host::Result<()> {
-    hostcalls::done().map_err(|err| format_err!(err))
+    // postpone notification until requests made by the root context complete
+    if scope::request_done() {
+        hostcalls::done().map_err(|err| format_err!(err))
+    } else {
+        Ok(())
+    }
+}
+
+// Timer API
+
+pub fn set_tick_period(period: Duration) -> host::Result<()> {
+    hostcalls::set_tick_period(period).map_err(|err| format_err!(err))
 }
 
 // Headers/Body manipulation API
@@ -95,6 +109,37 @@ pub fn resume_http_response() -> host::Result<()> {
     hostcalls::continue_stream(StreamType::Response).map_err(|err| format_err!(err))
 }
 
+pub fn reset_http_stream() -> host::Result<()> {
+    hostcalls::close_stream(StreamType::Request).map_err(|err| format_err!(err))
+}
+
+// TCP Flow API
+
+/// Stream type of the downstream connection.
+///
+/// `proxy_wasm::types::StreamType` only covers HTTP streams.
+///
+/// The value is `WasmStreamType::Downstream` of `Proxy Wasm` ABI v0.1.0, which is
+/// the version this module declares support for by exporting `proxy_abi_version_0_1_0`
+/// (see `proxy-wasm` crate). The value must be revisited once the module declares
+/// support for another ABI version.
+const STREAM_TYPE_DOWNSTREAM: u32 = 2;
+
+extern "C" {
+    fn proxy_close_stream(stream_type: u32) -> Status;
+}
+
+pub fn close_downstream_connection() -> host::Result<()> {
+    unsafe {
+        match proxy_close_stream(STREAM_TYPE_DOWNSTREAM) {
+            Status::Ok => Ok(()),
+            status => Err(host::function("env", "proxy_close_stream")
+                .into_call_error(status)
+                .into()),
+        }
+    }
+}
+
 // Shared Queue
 
 pub fn register_shared_queue(name: &str) -> host::Result<SharedQueueHandle> {
@@ -143,7 +188,10 @@ where
     B: AsRef<[u8]>,
 {
     hostcalls::dispatch_http_call(upstream, headers, body, trailers, timeout)
-        .map(HttpRequestHandle::from)
+        .map(|token_id| {
+            scope::register_http_call(token_id);
+            HttpRequestHandle::from(token_id)
+        })
         .map_err(|err| format_err!(err))
 }
 
@@ -164,6 +212,88 @@ where
     hostcalls::set_property(path, Some(value)).map_err(|err| format_err!(err))
 }
 
+// Foreign function API
+
+extern "C" {
+    fn proxy_call_foreign_function(
+        function_name_data: *const u8,
+        function_name_size: usize,
+        arguments_data: *const u8,
+        arguments_size: usize,
+        results_data: *mut *mut u8,
+        results_size: *mut usize,
+    ) -> Status;
+}
+
+pub fn call_foreign_function(
+    function_name: &str,
+    arguments: &[u8],
+) -> host::Result<Option<ByteString>> {
+    let mut return_data: *mut u8 = std::ptr::null_mut();
+    let mut return_size: usize = 0;
+    unsafe {
+        match proxy_call_foreign_function(
+            function_name.as_ptr(),
+            function_name.len(),
+            arguments.as_ptr(),
+            arguments.len(),
+            &mut return_data,
+            &mut return_size,
+        ) {
+            Status::Ok => {
+                if !return_data.is_null() {
+                    Ok(Some(
+                        Vec::from_raw_parts(return_data, return_size, return_size).into(),
+                    ))
+                } else {
+                    Ok(None)
+                }
+            }
+            Status::NotFound => Ok(None),
+            status => Err(host::function("env", "proxy_call_foreign_function")
+                .into_call_error(status)
+                .into()),
+        }
+    }
+}
+
+/// Declares a property with the given semantics by calling `Envoy`-specific
+/// foreign function `declare_property`.
+///
+/// Arguments of the foreign function are encoded as a `Protobuf` message
+/// `envoy.source.extensions.common.wasm.DeclarePropertyArguments`.
+pub fn declare_property(
+    name: &str,
+    read_only: bool,
+    wasm_type: u64,
+    life_span: u64,
+) -> host::Result<()> {
+    let mut arguments = Vec::with_capacity(name.len() + 16);
+    // field 1: string name
+    arguments.push(0x0a);
+    encode_varint(&mut arguments, name.len() as u64);
+    arguments.extend_from_slice(name.as_bytes());
+    // field 2: bool readonly
+    arguments.push(0x10);
+    encode_varint(&mut arguments, read_only as u64);
+    // field 3: WasmType type
+    arguments.push(0x18);
+    encode_varint(&mut arguments, wasm_type);
+    // field 5: LifeSpan span
+    arguments.push(0x28);
+    encode_varint(&mut arguments, life_span);
+
+    call_foreign_function("declare_property", &arguments).map(|_| ())
+}
+
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
 // Shared data API
 
 pub fn get_shared_data(
@@ -182,7 +312,10 @@ pub fn set_shared_data(
         if value.is_empty() { None } else { Some(value) },
         version,
     )
-    .map_err(|err| format_err!(err))
+    .map_err(|err| match err.downcast_ref::<HostCallError>() {
+        Some(err) if err.status() == Status::CasMismatch => CasMismatchError::new(key).into(),
+        _ => format_err!(err),
+    })
 }
 
 // Stats API
@@ -23,3 +23,5 @@ pub use proxy_wasm::{set_log_level, set_root_context, traits};
 
 pub mod hostcalls;
 pub mod types;
+
+pub(crate) mod scope;
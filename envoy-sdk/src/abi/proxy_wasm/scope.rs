@@ -0,0 +1,178 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bookkeeping of host calls made on behalf of a root context.
+//!
+//! `proxy-wasm` delivers a response to an HTTP request to the context that
+//! was active at the time the request was made.
+//! [`RootScope`] mirrors that by recording requests made while the scope
+//! is entered, which makes it possible to tell whether a root context still
+//! has work in flight when it is being drained.
+//!
+//! Callbacks of child contexts, e.g. HTTP streams of an HTTP Filter Factory,
+//! run within a [`ChildScope`] of their root context instead. Requests made
+//! there are delivered to the child context and are not recorded, while
+//! completion of draining requested there is attributed to the root context.
+//!
+//! [`RootScope`]: struct.RootScope.html
+//! [`ChildScope`]: struct.ChildScope.html
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Current>> = const { RefCell::new(None) };
+}
+
+/// Scope of the callback that is currently running.
+#[derive(Clone)]
+enum Current {
+    Root(RootScope),
+    Child(RootScope),
+}
+
+impl Current {
+    /// Runs a given function with this scope being the current one.
+    fn enter<T, F>(self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        struct Restore(Option<Current>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self))));
+        f()
+    }
+}
+
+/// Host calls in flight on behalf of a single root context.
+#[derive(Clone, Default)]
+pub(crate) struct RootScope(Rc<RefCell<State>>);
+
+#[derive(Default)]
+struct State {
+    http_calls: HashSet<u32>,
+    draining: bool,
+    done_requested: bool,
+    done: bool,
+}
+
+impl RootScope {
+    /// Runs a given function with this scope being the current one.
+    pub fn enter<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        Current::Root(self.clone()).enter(f)
+    }
+
+    /// Returns a scope for callbacks of child contexts of the root context.
+    pub fn child(&self) -> ChildScope {
+        ChildScope(self.clone())
+    }
+
+    /// Forgets an HTTP request that has received a response.
+    ///
+    /// Returns `false` if the request hasn't been made within this scope.
+    pub fn complete_http_call(&self, token_id: u32) -> bool {
+        self.0.borrow_mut().http_calls.remove(&token_id)
+    }
+
+    /// Returns number of HTTP requests that haven't received a response yet.
+    pub fn http_calls_in_flight(&self) -> usize {
+        self.0.borrow().http_calls.len()
+    }
+
+    /// Marks the root context as being drained.
+    pub fn start_draining(&self) {
+        self.0.borrow_mut().draining = true;
+    }
+
+    /// Returns `true` if the root context is being drained.
+    pub fn is_draining(&self) -> bool {
+        self.0.borrow().draining
+    }
+
+    /// Records that the extension itself has nothing left to do.
+    pub fn request_done(&self) {
+        self.0.borrow_mut().done_requested = true;
+    }
+
+    /// Returns `true` if the extension has nothing left to do and there are
+    /// no requests in flight.
+    pub fn is_drained(&self) -> bool {
+        let state = self.0.borrow();
+        state.draining && state.done_requested && state.http_calls.is_empty()
+    }
+
+    /// Returns `true` if `Envoy` has been notified that the root context is done.
+    pub fn is_done(&self) -> bool {
+        self.0.borrow().done
+    }
+
+    /// Marks the root context as done.
+    ///
+    /// Returns `false` if it has already been marked before, i.e. `Envoy`
+    /// must not be notified again.
+    pub fn mark_done(&self) -> bool {
+        let mut state = self.0.borrow_mut();
+        !std::mem::replace(&mut state.done, true)
+    }
+}
+
+/// Host calls made on behalf of a child context of a root context.
+#[derive(Clone)]
+pub(crate) struct ChildScope(RootScope);
+
+impl ChildScope {
+    /// Runs a given function with this scope being the current one.
+    pub fn enter<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        Current::Child(self.0.clone()).enter(f)
+    }
+}
+
+/// Records an HTTP request made within the current root scope, if any.
+pub(crate) fn register_http_call(token_id: u32) {
+    CURRENT.with(|current| {
+        if let Some(Current::Root(scope)) = &*current.borrow() {
+            scope.0.borrow_mut().http_calls.insert(token_id);
+        }
+    })
+}
+
+/// Records that the extension of the current scope, if any, has nothing left to do.
+///
+/// Within a child scope, completion is attributed to the root context.
+///
+/// Returns `true` if `Envoy` should be notified right away, i.e. when there is no
+/// current scope, the scope is not being drained, or there is nothing left in flight.
+pub(crate) fn request_done() -> bool {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(Current::Root(scope)) | Some(Current::Child(scope)) if scope.is_draining() => {
+            scope.request_done();
+            scope.is_drained() && scope.mark_done()
+        }
+        _ => true,
+    })
+}
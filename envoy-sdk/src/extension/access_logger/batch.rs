@@ -0,0 +1,584 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Batched shipping of access log entries.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use super::DrainOps;
+use crate::extension::DrainStatus;
+use crate::host::http::client::{HttpClient, HttpClientRequestHandle, HttpClientResponseOps};
+use crate::host::stats::Counter;
+use crate::host::{self, Clock};
+
+/// Encoding of a batch of log entries in the body of an HTTP request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchEncoding {
+    /// Entries separated by `\n` (`application/x-ndjson`).
+    Ndjson,
+    /// Entries as elements of a JSON array (`application/json`).
+    ///
+    /// Every entry must be a valid JSON value, e.g. the output of [`JsonLogFormat`].
+    ///
+    /// [`JsonLogFormat`]: struct.JsonLogFormat.html
+    JsonArray,
+}
+
+/// Configuration of a [`BatchSink`].
+///
+/// [`BatchSink`]: struct.BatchSink.html
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct BatchConfig {
+    /// Name of `Envoy` `Cluster` to send log entries to.
+    pub upstream: String,
+    /// Value of the `:authority` header.
+    pub authority: String,
+    /// Value of the `:path` header.
+    pub path: String,
+    /// Additional request headers, e.g. `authorization`.
+    pub headers: Vec<(String, String)>,
+    /// Encoding of the request body.
+    pub encoding: BatchEncoding,
+    /// Maximum number of entries in a single batch.
+    pub max_batch_entries: usize,
+    /// Size of a batch (in bytes) that triggers a flush.
+    pub max_batch_bytes: usize,
+    /// Age of the oldest buffered entry that triggers a flush.
+    pub max_batch_age: Duration,
+    /// Upper bound on memory (in bytes) occupied by buffered entries,
+    /// including the batch that is being sent.
+    pub max_buffered_bytes: usize,
+    /// Maximum number of attempts to send a batch before it gets dropped.
+    pub max_attempts: u32,
+    /// Delay before a failed batch is sent again.
+    pub retry_backoff: Duration,
+    /// Timeout of a single HTTP request.
+    pub timeout: Duration,
+}
+
+impl BatchConfig {
+    /// Returns configuration with reasonable defaults for a given `Envoy` `Cluster`.
+    pub fn new<U>(upstream: U) -> Self
+    where
+        U: Into<String>,
+    {
+        let upstream = upstream.into();
+        BatchConfig {
+            authority: upstream.clone(),
+            upstream,
+            path: "/".to_owned(),
+            headers: Vec::new(),
+            encoding: BatchEncoding::Ndjson,
+            max_batch_entries: 500,
+            max_batch_bytes: 256 * 1024,
+            max_batch_age: Duration::from_secs(5),
+            max_buffered_bytes: 4 * 1024 * 1024,
+            max_attempts: 3,
+            retry_backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets the value of the `:authority` header.
+    pub fn authority<T: Into<String>>(mut self, authority: T) -> Self {
+        self.authority = authority.into();
+        self
+    }
+
+    /// Sets the value of the `:path` header.
+    pub fn path<T: Into<String>>(mut self, path: T) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Adds a request header.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets encoding of the request body.
+    pub fn encoding(mut self, encoding: BatchEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets maximum number of entries in a single batch.
+    pub fn max_batch_entries(mut self, max_batch_entries: usize) -> Self {
+        self.max_batch_entries = max_batch_entries;
+        self
+    }
+
+    /// Sets size of a batch (in bytes) that triggers a flush.
+    pub fn max_batch_bytes(mut self, max_batch_bytes: usize) -> Self {
+        self.max_batch_bytes = max_batch_bytes;
+        self
+    }
+
+    /// Sets age of the oldest buffered entry that triggers a flush.
+    pub fn max_batch_age(mut self, max_batch_age: Duration) -> Self {
+        self.max_batch_age = max_batch_age;
+        self
+    }
+
+    /// Sets upper bound on memory (in bytes) occupied by buffered entries.
+    pub fn max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self
+    }
+
+    /// Sets maximum number of attempts to send a batch.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets delay before a failed batch is sent again.
+    pub fn retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Sets timeout of a single HTTP request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Buffers formatted access log entries and ships them in batches
+/// through [`HttpClient`].
+///
+/// * A batch is sent once it reaches [`max_batch_entries`] or [`max_batch_bytes`],
+///   or once the oldest entry gets older than [`max_batch_age`].
+///   Since `Access Logger` has no timer, thresholds are checked on every [`push`]
+///   and on every call to [`flush_if_due`].
+/// * At most one request is in flight at a time; entries keep being buffered meanwhile.
+/// * A failed batch is retried after [`retry_backoff`] up to [`max_attempts`] times.
+/// * Once buffered entries occupy [`max_buffered_bytes`], new entries are dropped
+///   and counted in [`dropped_entries`].
+/// * [`on_drain`] flushes all buffered entries and acknowledges `Envoy`
+///   through [`DrainOps::done`] when the last batch has been sent.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{AccessLogger, DrainStatus, Result};
+/// use envoy::extension::access_logger::{BatchConfig, BatchSink, JsonLogFormat, LogOps};
+/// use envoy::host::{HttpClientRequestHandle, HttpClientResponseOps};
+///
+/// struct MyAccessLogger<'a> {
+///     format: JsonLogFormat,
+///     sink: BatchSink<'a>,
+/// }
+///
+/// impl<'a> MyAccessLogger<'a> {
+///     fn new() -> Result<Self> {
+///         Ok(MyAccessLogger {
+///             format: JsonLogFormat::parse(vec![("code", "%RESPONSE_CODE%")])?,
+///             sink: BatchSink::default(
+///                 BatchConfig::new("log_collector").path("/v1/logs"),
+///             ),
+///         })
+///     }
+/// }
+///
+/// impl<'a> AccessLogger for MyAccessLogger<'a> {
+///     fn name() -> &'static str { "my_access_logger" }
+///
+///     fn on_log(&mut self, ops: &dyn LogOps) -> Result<()> {
+///         self.sink.push(self.format.format(ops)?)?;
+///         Ok(())
+///     }
+///
+///     fn on_drain(&mut self) -> Result<DrainStatus> {
+///         Ok(self.sink.on_drain()?)
+///     }
+///
+///     fn on_http_call_response(
+///         &mut self,
+///         request: HttpClientRequestHandle,
+///         _num_headers: usize,
+///         _body_size: usize,
+///         _num_trailers: usize,
+///         http_client_ops: &dyn HttpClientResponseOps,
+///     ) -> Result<()> {
+///         self.sink.on_http_call_response(request, http_client_ops)?;
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// [`HttpClient`]: ../../host/http/client/trait.HttpClient.html
+/// [`DrainOps::done`]: trait.DrainOps.html#tymethod.done
+/// [`max_batch_entries`]: struct.BatchConfig.html#structfield.max_batch_entries
+/// [`max_batch_bytes`]: struct.BatchConfig.html#structfield.max_batch_bytes
+/// [`max_batch_age`]: struct.BatchConfig.html#structfield.max_batch_age
+/// [`max_buffered_bytes`]: struct.BatchConfig.html#structfield.max_buffered_bytes
+/// [`max_attempts`]: struct.BatchConfig.html#structfield.max_attempts
+/// [`retry_backoff`]: struct.BatchConfig.html#structfield.retry_backoff
+/// [`push`]: #method.push
+/// [`flush_if_due`]: #method.flush_if_due
+/// [`dropped_entries`]: #method.dropped_entries
+/// [`on_drain`]: #method.on_drain
+pub struct BatchSink<'a> {
+    config: BatchConfig,
+    clock: &'a dyn Clock,
+    http_client: &'a dyn HttpClient,
+    drain_ops: &'a dyn DrainOps,
+    dropped_counter: Option<Box<dyn Counter>>,
+
+    queue: VecDeque<Entry>,
+    buffered_bytes: usize,
+    pending: Option<Batch>,
+    in_flight: Option<HttpClientRequestHandle>,
+    dropped_entries: u64,
+    draining: bool,
+}
+
+struct Entry {
+    time: SystemTime,
+    line: String,
+}
+
+/// Batch that is either in flight or waiting to be retried.
+struct Batch {
+    lines: Vec<String>,
+    size: usize,
+    attempts: u32,
+    retry_at: Option<SystemTime>,
+}
+
+impl<'a> BatchSink<'a> {
+    /// Creates a new sink parameterized with given host APIs.
+    pub fn new(
+        config: BatchConfig,
+        clock: &'a dyn Clock,
+        http_client: &'a dyn HttpClient,
+        drain_ops: &'a dyn DrainOps,
+    ) -> Self {
+        BatchSink {
+            config,
+            clock,
+            http_client,
+            drain_ops,
+            dropped_counter: None,
+            queue: VecDeque::new(),
+            buffered_bytes: 0,
+            pending: None,
+            in_flight: None,
+            dropped_entries: 0,
+            draining: false,
+        }
+    }
+
+    /// Creates a new sink bound to the actual `Envoy` ABI.
+    pub fn default(config: BatchConfig) -> Self {
+        Self::new(
+            config,
+            <dyn Clock>::default(),
+            <dyn HttpClient>::default(),
+            <dyn DrainOps>::default(),
+        )
+    }
+
+    /// Sets a counter to be incremented for every dropped entry.
+    pub fn with_dropped_counter(mut self, counter: Box<dyn Counter>) -> Self {
+        self.dropped_counter = Some(counter);
+        self
+    }
+
+    /// Returns configuration of the sink.
+    pub fn config(&self) -> &BatchConfig {
+        &self.config
+    }
+
+    /// Returns number of entries that have been dropped so far.
+    pub fn dropped_entries(&self) -> u64 {
+        self.dropped_entries
+    }
+
+    /// Returns number of entries that haven't been delivered yet.
+    pub fn buffered_entries(&self) -> usize {
+        self.queue.len() + self.pending.as_ref().map_or(0, |batch| batch.lines.len())
+    }
+
+    /// Returns `true` if there is a request in flight.
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Adds an entry to the buffer and sends a batch if one is due.
+    ///
+    /// If the buffer is full, the entry is dropped.
+    pub fn push<T>(&mut self, line: T) -> host::Result<()>
+    where
+        T: Into<String>,
+    {
+        let line = line.into();
+        if self.buffered_bytes + line.len() > self.config.max_buffered_bytes {
+            self.drop_entries(1)?;
+        } else {
+            self.buffered_bytes += line.len();
+            self.queue.push_back(Entry {
+                time: self.clock.now()?,
+                line,
+            });
+        }
+        self.flush_if_due().map(|_| ())
+    }
+
+    /// Sends a batch if a size or age threshold has been reached.
+    ///
+    /// Returns `true` if a request has been sent.
+    pub fn flush_if_due(&mut self) -> host::Result<bool> {
+        if self.in_flight.is_some() {
+            return Ok(false);
+        }
+        let now = self.clock.now()?;
+        let is_due = match &self.pending {
+            Some(batch) => self.draining || batch.retry_at.is_none_or(|at| at <= now),
+            None => self.draining || self.is_batch_ready(now),
+        };
+        if is_due {
+            self.send()
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Sends a batch regardless of thresholds unless another request is in flight.
+    ///
+    /// Returns `true` if a request has been sent.
+    pub fn flush(&mut self) -> host::Result<bool> {
+        if self.in_flight.is_some() {
+            return Ok(false);
+        }
+        self.send()
+    }
+
+    /// Handles a response to the request made by the sink.
+    ///
+    /// Returns `false` if the response belongs to a request made by someone else.
+    pub fn on_http_call_response(
+        &mut self,
+        request: HttpClientRequestHandle,
+        http_client_ops: &dyn HttpClientResponseOps,
+    ) -> host::Result<bool> {
+        if self.in_flight != Some(request) {
+            return Ok(false);
+        }
+        self.in_flight = None;
+
+        let is_success = http_client_ops
+            .http_call_response_header(":status")?
+            .and_then(|status| status.to_string().parse::<u16>().ok())
+            .is_some_and(|status| (200..300).contains(&status));
+        if let Some(mut batch) = self.pending.take() {
+            if is_success {
+                self.buffered_bytes -= batch.size;
+            } else if batch.attempts >= self.config.max_attempts {
+                self.buffered_bytes -= batch.size;
+                self.drop_entries(batch.lines.len())?;
+            } else {
+                batch.retry_at = Some(self.clock.now()? + self.config.retry_backoff);
+                self.pending = Some(batch);
+            }
+        }
+
+        let flushed = self.flush_if_due();
+        if !self.draining {
+            return flushed.map(|_| true);
+        }
+        // a batch that cannot be sent while draining is dropped
+        // not to keep `Envoy` waiting for `done`
+        let discarded = match flushed {
+            Ok(_) => Ok(()),
+            Err(_) => self.discard(),
+        };
+        if self.is_idle() {
+            self.drain_ops.done()?;
+        }
+        discarded.map(|_| true)
+    }
+
+    /// Starts draining, i.e. sending all buffered entries regardless of thresholds.
+    ///
+    /// Returns [`DrainStatus::Complete`] if there is nothing left to send.
+    /// Otherwise, returns [`DrainStatus::Ongoing`] and calls [`DrainOps::done`]
+    /// once the last batch has been sent.
+    ///
+    /// If a batch cannot be sent, the remaining entries are dropped to let `Envoy`
+    /// proceed with removal of the extension.
+    ///
+    /// [`DrainStatus::Complete`]: ../factory/enum.DrainStatus.html#variant.Complete
+    /// [`DrainStatus::Ongoing`]: ../factory/enum.DrainStatus.html#variant.Ongoing
+    /// [`DrainOps::done`]: trait.DrainOps.html#tymethod.done
+    pub fn on_drain(&mut self) -> host::Result<DrainStatus> {
+        self.draining = true;
+        if self.flush_if_due().is_err() {
+            self.discard()?;
+        }
+        if self.is_idle() {
+            Ok(DrainStatus::Complete)
+        } else {
+            Ok(DrainStatus::Ongoing)
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.in_flight.is_none() && self.pending.is_none() && self.queue.is_empty()
+    }
+
+    fn is_batch_ready(&self, now: SystemTime) -> bool {
+        let oldest = match self.queue.front() {
+            Some(entry) => entry.time,
+            None => return false,
+        };
+        if self.queue.len() >= self.config.max_batch_entries {
+            return true;
+        }
+        let queued_bytes = self
+            .queue
+            .iter()
+            .map(|entry| entry.line.len())
+            .sum::<usize>();
+        if queued_bytes >= self.config.max_batch_bytes {
+            return true;
+        }
+        now.duration_since(oldest).unwrap_or_default() >= self.config.max_batch_age
+    }
+
+    fn next_batch(&mut self) -> Option<Batch> {
+        if let Some(batch) = self.pending.take() {
+            return Some(batch);
+        }
+        let mut lines = Vec::new();
+        let mut size = 0;
+        while let Some(entry) = self.queue.front() {
+            let is_full = lines.len() >= self.config.max_batch_entries
+                || size + entry.line.len() > self.config.max_batch_bytes;
+            if is_full && !lines.is_empty() {
+                break;
+            }
+            size += entry.line.len();
+            lines.extend(self.queue.pop_front().map(|entry| entry.line));
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(Batch {
+                lines,
+                size,
+                attempts: 0,
+                retry_at: None,
+            })
+        }
+    }
+
+    fn send(&mut self) -> host::Result<bool> {
+        let mut batch = match self.next_batch() {
+            Some(batch) => batch,
+            None => return Ok(false),
+        };
+        let body = self.encode(&batch.lines);
+        let content_type = match self.config.encoding {
+            BatchEncoding::Ndjson => "application/x-ndjson",
+            BatchEncoding::JsonArray => "application/json",
+        };
+        let mut headers = vec![
+            (":method", "POST"),
+            (":path", self.config.path.as_str()),
+            (":authority", self.config.authority.as_str()),
+            ("content-type", content_type),
+        ];
+        headers.extend(
+            self.config
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        let result = self.http_client.send_request(
+            &self.config.upstream,
+            &headers,
+            Some(body.as_bytes()),
+            None,
+            self.config.timeout,
+        );
+        batch.attempts += 1;
+        match result {
+            Ok(request) => {
+                self.in_flight = Some(request);
+                self.pending = Some(batch);
+                Ok(true)
+            }
+            Err(err) => {
+                if batch.attempts >= self.config.max_attempts {
+                    self.buffered_bytes -= batch.size;
+                    self.drop_entries(batch.lines.len())?;
+                } else {
+                    batch.retry_at = Some(self.clock.now()? + self.config.retry_backoff);
+                    self.pending = Some(batch);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn encode(&self, lines: &[String]) -> String {
+        let size = lines.iter().map(|line| line.len() + 1).sum::<usize>() + 1;
+        let mut body = String::with_capacity(size);
+        match self.config.encoding {
+            BatchEncoding::Ndjson => {
+                for line in lines {
+                    body.push_str(line);
+                    body.push('\n');
+                }
+            }
+            BatchEncoding::JsonArray => {
+                body.push('[');
+                for (i, line) in lines.iter().enumerate() {
+                    if i > 0 {
+                        body.push(',');
+                    }
+                    body.push_str(line);
+                }
+                body.push(']');
+            }
+        }
+        body
+    }
+
+    fn discard(&mut self) -> host::Result<()> {
+        let count = self.buffered_entries();
+        self.queue.clear();
+        self.pending = None;
+        self.buffered_bytes = 0;
+        self.drop_entries(count)
+    }
+
+    fn drop_entries(&mut self, count: usize) -> host::Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        self.dropped_entries += count as u64;
+        if let Some(counter) = &self.dropped_counter {
+            counter.add(count as u64)?;
+        }
+        Ok(())
+    }
+}
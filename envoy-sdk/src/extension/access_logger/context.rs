@@ -14,8 +14,10 @@
 
 use super::{AccessLogger, ContextOps, Ops};
 use crate::abi::proxy_wasm::traits::{Context, RootContext};
-use crate::extension::error::ErrorSink;
-use crate::extension::{ConfigStatus, DrainStatus};
+use crate::extension::drain::Drainer;
+use crate::extension::error::{ErrorCategory, ErrorReporter};
+use crate::extension::isolation::Isolation;
+use crate::extension::{ConfigStatus, DrainStatus, Result};
 use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
 use crate::host::ByteString;
 
@@ -27,7 +29,9 @@ where
     context_ops: &'a dyn ContextOps,
     logger_ops: &'a dyn Ops,
     http_client_ops: &'a dyn HttpClientResponseOps,
-    error_sink: &'a dyn ErrorSink,
+    error_reporter: ErrorReporter,
+    isolation: Isolation,
+    drainer: Drainer<'a>,
 }
 
 impl<'a, L> RootContext for AccessLoggerContext<'a, L>
@@ -41,25 +45,38 @@ where
             self.context_ops.configuration(0, configuration_size)
         };
         match config.and_then(|config| {
-            self.logger
-                .on_configure(config, self.logger_ops.as_configure_ops())
+            self.call(|logger, ops| logger.on_configure(config, ops.as_configure_ops()))
         }) {
             Ok(status) => status.as_bool(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to configure extension", &err);
+                self.error_reporter.observe(
+                    ErrorCategory::Configuration,
+                    "failed to configure extension",
+                    &err,
+                );
                 ConfigStatus::Rejected.as_bool()
             }
         }
     }
 
     fn on_log(&mut self) {
-        if let Err(err) = self.logger.on_log(self.logger_ops.as_log_ops()) {
-            self.error_sink.observe("failed to log a request", &err);
+        if let Err(err) = self.call(|logger, ops| logger.on_log(ops.as_log_ops())) {
+            self.error_reporter
+                .observe(ErrorCategory::Callback, "failed to log a request", &err);
 
             // TODO(yskopets): can we do anything other than crashing Envoy ?
         }
     }
+
+    fn on_tick(&mut self) {
+        if let Err(err) = self.drainer.on_tick() {
+            self.error_reporter.observe(
+                ErrorCategory::Drain,
+                "failed to drain the extension",
+                &err,
+            );
+        }
+    }
 }
 
 impl<'a, L> Context for AccessLoggerContext<'a, L>
@@ -67,12 +84,30 @@ where
     L: AccessLogger,
 {
     fn on_done(&mut self) -> bool {
-        match self.logger.on_drain() {
+        let status = if self.isolation.is_failed() {
+            Some(DrainStatus::Complete)
+        } else {
+            match self.call(|logger, _| logger.on_drain()) {
+                Ok(status) => Some(status),
+                Err(err) => {
+                    self.error_reporter.observe(
+                        ErrorCategory::Drain,
+                        "failed to initiate draining of the extension",
+                        &err,
+                    );
+                    None
+                }
+            }
+        };
+        match self.drainer.on_drain(status) {
             Ok(status) => status.as_bool(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to initiate draining of the extension", &err);
-                DrainStatus::Ongoing.as_bool()
+                self.error_reporter.observe(
+                    ErrorCategory::Drain,
+                    "failed to initiate draining of the extension",
+                    &err,
+                );
+                status.unwrap_or(DrainStatus::Ongoing).as_bool()
             }
         }
     }
@@ -86,20 +121,32 @@ where
         body_size: usize,
         num_trailers: usize,
     ) {
-        if let Err(err) = self.logger.on_http_call_response(
-            HttpClientRequestHandle::from(token_id),
-            num_headers,
-            body_size,
-            num_trailers,
-            self.http_client_ops,
-        ) {
-            self.error_sink.observe(
+        self.drainer.on_http_call_response(token_id);
+        let http_client_ops = self.http_client_ops;
+        if let Err(err) = self.call(|logger, _| {
+            logger.on_http_call_response(
+                HttpClientRequestHandle::from(token_id),
+                num_headers,
+                body_size,
+                num_trailers,
+                http_client_ops,
+            )
+        }) {
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
                 "failed to process a response to an HTTP request made by the extension",
                 &err,
             );
 
             // TODO(yskopets): can we do anything other than crashing Envoy ?
         }
+        if let Err(err) = self.drainer.poll() {
+            self.error_reporter.observe(
+                ErrorCategory::Drain,
+                "failed to drain the extension",
+                &err,
+            );
+        }
     }
 }
 
@@ -112,25 +159,47 @@ where
         context_ops: &'a dyn ContextOps,
         logger_ops: &'a dyn Ops,
         http_client_ops: &'a dyn HttpClientResponseOps,
-        error_sink: &'a dyn ErrorSink,
+        error_reporter: ErrorReporter,
+        drainer: Drainer<'a>,
+        isolation: Isolation,
     ) -> Self {
         AccessLoggerContext {
             logger,
             context_ops,
             logger_ops,
             http_client_ops,
-            error_sink,
+            error_reporter,
+            isolation,
+            drainer,
         }
     }
 
     /// Creates a new Access logger context bound to the actual Envoy ABI.
-    pub fn with_default_ops(logger: L) -> Self {
+    pub fn with_default_ops(
+        logger: L,
+        error_reporter: ErrorReporter,
+        drainer: Drainer<'a>,
+        isolation: Isolation,
+    ) -> Self {
         Self::new(
             logger,
             ContextOps::default(),
             Ops::default(),
             HttpClientResponseOps::default(),
-            ErrorSink::default(),
+            error_reporter,
+            drainer,
+            isolation,
         )
     }
+
+    /// Calls the logger unless it has panicked earlier.
+    fn call<T, C>(&mut self, callback: C) -> Result<T>
+    where
+        C: FnOnce(&mut L, &'a dyn Ops) -> Result<T>,
+    {
+        let (logger, logger_ops) = (&mut self.logger, self.logger_ops);
+        let isolation = &self.isolation;
+        self.drainer
+            .enter(|| isolation.call(|| callback(logger, logger_ops)))
+    }
 }
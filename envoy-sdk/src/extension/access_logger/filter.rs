@@ -0,0 +1,357 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sampling and filtering of access log entries.
+
+use std::cell::Cell;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use super::{AccessLogger, ConfigureOps, LogOps, LogView};
+use crate::extension::{ConfigStatus, DrainStatus, Result};
+use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
+use crate::host::stream_info::ResponseFlags;
+use crate::host::{self, ByteString};
+
+/// Precision of the sample rate.
+const SAMPLE_SCALE: u64 = 1_000_000;
+
+/// A rule that decides whether an HTTP stream or TCP connection should be logged,
+/// similar to `Envoy` [`access log filters`].
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::extension::Result;
+/// # fn action() -> Result<()> {
+/// use std::time::Duration;
+/// use envoy::extension::access_logger::LogFilter;
+///
+/// // log 10% of requests that either failed or took longer than 1s,
+/// // except for health checks
+/// let filter = LogFilter::all(vec![
+///     LogFilter::any(vec![
+///         LogFilter::status_code(500..=599),
+///         LogFilter::min_duration(Duration::from_secs(1)),
+///     ]),
+///     !LogFilter::header_present("x-health-check"),
+///     LogFilter::sample(0.1),
+/// ]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`access log filters`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/config/accesslog/v3/accesslog.proto#config-accesslog-v3-accesslogfilter
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    rule: Rule,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Sample {
+        threshold: u64,
+        counter: Cell<u64>,
+    },
+    StatusCode(RangeInclusive<u16>),
+    MinDuration(Duration),
+    ResponseFlags(ResponseFlags),
+    HeaderPresent(String),
+    #[cfg(feature = "regex")]
+    HeaderMatches(String, Regex),
+    RouteName(String),
+    ClusterName(String),
+    All(Vec<LogFilter>),
+    Any(Vec<LogFilter>),
+    Not(Box<LogFilter>),
+}
+
+impl LogFilter {
+    /// Matches a given fraction of entries, e.g. `0.01` for 1%.
+    ///
+    /// Sampling decision is derived from the request id (`x-request-id`),
+    /// so that all logs of a given request are sampled consistently.
+    /// Entries without a request id, e.g. TCP connections, are sampled
+    /// evenly in the order they are logged.
+    pub fn sample(rate: f64) -> Self {
+        let rate = if rate.is_nan() {
+            0.0
+        } else {
+            rate.clamp(0.0, 1.0)
+        };
+        Self::from(Rule::Sample {
+            threshold: (rate * SAMPLE_SCALE as f64).round() as u64,
+            counter: Cell::new(0),
+        })
+    }
+
+    /// Matches HTTP streams with a response code in a given range.
+    pub fn status_code(range: RangeInclusive<u16>) -> Self {
+        Self::from(Rule::StatusCode(range))
+    }
+
+    /// Matches HTTP streams or TCP connections that lasted at least a given duration.
+    pub fn min_duration(duration: Duration) -> Self {
+        Self::from(Rule::MinDuration(duration))
+    }
+
+    /// Matches HTTP streams or TCP connections that have any of the given response flags set.
+    ///
+    /// Empty flags match entries that have any response flag set.
+    pub fn response_flags(flags: ResponseFlags) -> Self {
+        Self::from(Rule::ResponseFlags(flags))
+    }
+
+    /// Matches HTTP streams that have a given request header.
+    pub fn header_present<T>(name: T) -> Self
+    where
+        T: AsRef<str>,
+    {
+        Self::from(Rule::HeaderPresent(name.as_ref().to_ascii_lowercase()))
+    }
+
+    /// Matches HTTP streams that have a request header with a value matching a given regex.
+    ///
+    /// Just like `safe_regex` in `Envoy`, the regex must match the entire value.
+    ///
+    /// Requires `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn header_matches<T>(name: T, pattern: &str) -> Result<Self>
+    where
+        T: AsRef<str>,
+    {
+        Ok(Self::from(Rule::HeaderMatches(
+            name.as_ref().to_ascii_lowercase(),
+            Regex::new(&format!("^(?:{})$", pattern))?,
+        )))
+    }
+
+    /// Matches HTTP streams handled by a given route.
+    pub fn route_name<T>(name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self::from(Rule::RouteName(name.into()))
+    }
+
+    /// Matches HTTP streams or TCP connections proxied to a given upstream cluster.
+    pub fn cluster_name<T>(name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self::from(Rule::ClusterName(name.into()))
+    }
+
+    /// Matches entries that match all of the given filters.
+    ///
+    /// Filters are evaluated in order until the first mismatch, so it pays off
+    /// to put [`sample`] last.
+    ///
+    /// [`sample`]: #method.sample
+    pub fn all(filters: Vec<LogFilter>) -> Self {
+        Self::from(Rule::All(filters))
+    }
+
+    /// Matches entries that match any of the given filters.
+    ///
+    /// Use `!` operator to negate a filter.
+    pub fn any(filters: Vec<LogFilter>) -> Self {
+        Self::from(Rule::Any(filters))
+    }
+
+    /// Returns `true` if the HTTP stream or TCP connection being logged matches the filter.
+    pub fn matches(&self, ops: &dyn LogOps) -> host::Result<bool> {
+        self.matches_view(&LogView::new(ops))
+    }
+
+    fn matches_view(&self, view: &LogView<'_>) -> host::Result<bool> {
+        let matches = match &self.rule {
+            Rule::Sample { threshold, counter } => {
+                if *threshold == 0 || *threshold >= SAMPLE_SCALE {
+                    return Ok(*threshold != 0);
+                }
+                match view.request_header("x-request-id")? {
+                    Some(id) => fnv1a(id.as_bytes()) % SAMPLE_SCALE < *threshold,
+                    None => {
+                        // spread matches evenly, e.g. 1st, 3rd, 5th, ... entries for a 50% rate
+                        let n = counter.get();
+                        counter.set((n + 1) % SAMPLE_SCALE);
+                        (n * threshold) % SAMPLE_SCALE < *threshold
+                    }
+                }
+            }
+            Rule::StatusCode(range) => view
+                .response_code()?
+                .is_some_and(|code| range.contains(&code)),
+            Rule::MinDuration(min) => view.duration()?.is_some_and(|d| d >= *min),
+            Rule::ResponseFlags(flags) => view.response_flags()?.is_some_and(|actual| {
+                if flags.is_empty() {
+                    !actual.is_empty()
+                } else {
+                    actual.intersects(*flags)
+                }
+            }),
+            Rule::HeaderPresent(name) => view.request_header(name)?.is_some(),
+            #[cfg(feature = "regex")]
+            Rule::HeaderMatches(name, regex) => view
+                .request_header(name)?
+                .is_some_and(|value| regex.is_match(&value.to_string())),
+            Rule::RouteName(name) => view.route_name()?.as_ref() == Some(name),
+            Rule::ClusterName(name) => view.upstream_cluster()?.as_ref() == Some(name),
+            Rule::All(filters) => {
+                for filter in filters {
+                    if !filter.matches_view(view)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            Rule::Any(filters) => {
+                for filter in filters {
+                    if filter.matches_view(view)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Rule::Not(filter) => !filter.matches_view(view)?,
+        };
+        Ok(matches)
+    }
+}
+
+impl std::ops::Not for LogFilter {
+    type Output = LogFilter;
+
+    /// Matches entries that don't match a given filter.
+    fn not(self) -> Self::Output {
+        Self::from(Rule::Not(Box::new(self)))
+    }
+}
+
+impl From<Rule> for LogFilter {
+    fn from(rule: Rule) -> Self {
+        LogFilter { rule }
+    }
+}
+
+/// 64-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// [`AccessLogger`] that only passes entries matching a [`LogFilter`]
+/// to the wrapped logger.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{AccessLogger, Module, Result};
+/// use envoy::extension::access_logger::{FilteredAccessLogger, LogFilter};
+///
+/// # struct MyAccessLogger;
+/// # impl AccessLogger for MyAccessLogger {
+/// #     fn name() -> &'static str { "my_access_logger" }
+/// # }
+/// #
+/// fn initialize() -> Result<Module> {
+///     Module::new().add_access_logger(|_instance_id| {
+///         Ok(FilteredAccessLogger::new(
+///             MyAccessLogger,
+///             LogFilter::status_code(500..=599),
+///         ))
+///     })
+/// }
+/// ```
+///
+/// [`AccessLogger`]: trait.AccessLogger.html
+/// [`LogFilter`]: struct.LogFilter.html
+pub struct FilteredAccessLogger<L> {
+    logger: L,
+    filter: LogFilter,
+}
+
+impl<L> FilteredAccessLogger<L>
+where
+    L: AccessLogger,
+{
+    /// Wraps a given logger.
+    pub fn new(logger: L, filter: LogFilter) -> Self {
+        FilteredAccessLogger { logger, filter }
+    }
+
+    /// Returns the wrapped logger.
+    pub fn logger(&self) -> &L {
+        &self.logger
+    }
+
+    /// Returns the filter.
+    pub fn filter(&self) -> &LogFilter {
+        &self.filter
+    }
+
+    /// Replaces the filter, e.g. after the configuration has changed.
+    pub fn set_filter(&mut self, filter: LogFilter) {
+        self.filter = filter;
+    }
+}
+
+impl<L> AccessLogger for FilteredAccessLogger<L>
+where
+    L: AccessLogger,
+{
+    fn name() -> &'static str {
+        L::name()
+    }
+
+    fn on_configure(&mut self, config: ByteString, ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+        self.logger.on_configure(config, ops)
+    }
+
+    fn on_log(&mut self, ops: &dyn LogOps) -> Result<()> {
+        if self.filter.matches(ops)? {
+            self.logger.on_log(ops)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        self.logger.on_drain()
+    }
+
+    fn on_http_call_response(
+        &mut self,
+        request_id: HttpClientRequestHandle,
+        num_headers: usize,
+        body_size: usize,
+        num_trailers: usize,
+        http_client_ops: &dyn HttpClientResponseOps,
+    ) -> Result<()> {
+        self.logger.on_http_call_response(
+            request_id,
+            num_headers,
+            body_size,
+            num_trailers,
+            http_client_ops,
+        )
+    }
+}
@@ -0,0 +1,623 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `Envoy` access log format strings.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value as JsonValue;
+
+use super::{LogOps, LogView};
+use crate::error::{bail, format_err};
+use crate::extension::Result;
+use crate::host::stream_info::CachedStreamInfo;
+use crate::host::{self, StreamInfo};
+
+/// Placeholder for values that are not available.
+const UNSPECIFIED_VALUE: &str = "-";
+
+/// Default format of `%START_TIME%`.
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S.%3fZ";
+
+/// Access log format string in the same syntax as [`Envoy`] uses.
+///
+/// A format string is parsed once, e.g. in [`on_configure`], and then evaluated
+/// against every HTTP stream or TCP connection being logged.
+///
+/// Supported command operators:
+///
+/// * `%START_TIME%`, `%START_TIME(FORMAT)%`
+/// * `%REQ(X?Y):Z%`, `%RESP(X?Y):Z%`, `%TRAILER(X?Y):Z%`
+/// * `%PROTOCOL%`, `%RESPONSE_CODE%`, `%RESPONSE_CODE_DETAILS%`, `%RESPONSE_FLAGS%`, `%GRPC_STATUS%`
+/// * `%BYTES_RECEIVED%`, `%BYTES_SENT%`, `%DURATION%`
+/// * `%CONNECTION_ID%`, `%CONNECTION_TERMINATION_DETAILS%`, `%REQUESTED_SERVER_NAME%`, `%ROUTE_NAME%`
+/// * `%UPSTREAM_HOST%`, `%UPSTREAM_CLUSTER%`, `%UPSTREAM_LOCAL_ADDRESS%`,
+///   `%UPSTREAM_TRANSPORT_FAILURE_REASON%`
+/// * `%DOWNSTREAM_REMOTE_ADDRESS%`, `%DOWNSTREAM_REMOTE_ADDRESS_WITHOUT_PORT%`,
+///   `%DOWNSTREAM_LOCAL_ADDRESS%`, `%DOWNSTREAM_LOCAL_ADDRESS_WITHOUT_PORT%`
+/// * `%DOWNSTREAM_TLS_VERSION%`, `%DOWNSTREAM_LOCAL_SUBJECT%`, `%DOWNSTREAM_PEER_SUBJECT%`,
+///   `%DOWNSTREAM_LOCAL_URI_SAN%`, `%DOWNSTREAM_PEER_URI_SAN%`, `%DOWNSTREAM_PEER_FINGERPRINT_256%`
+/// * `%UPSTREAM_TLS_VERSION%`
+/// * `%FILTER_STATE(KEY):Z%`, `%DYNAMIC_METADATA(NAMESPACE:KEY*):Z%`
+///
+/// Values that are not available are rendered as `-`, and a literal `%` is written as `%%`.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{AccessLogger, ConfigStatus, Result};
+/// use envoy::extension::access_logger::{ConfigureOps, LogFormat, LogOps};
+/// use envoy::host::{log, ByteString};
+///
+/// struct MyAccessLogger {
+///     format: LogFormat,
+/// }
+///
+/// impl AccessLogger for MyAccessLogger {
+///     fn name() -> &'static str { "my_access_logger" }
+///
+///     fn on_configure(&mut self, config: ByteString, _ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+///         // e.g., `[%START_TIME%] "%REQ(:METHOD)% %REQ(X-ENVOY-ORIGINAL-PATH?:PATH)% %PROTOCOL%" %RESPONSE_CODE%`
+///         self.format = LogFormat::parse(std::str::from_utf8(&config)?)?;
+///         Ok(ConfigStatus::Accepted)
+///     }
+///
+///     fn on_log(&mut self, ops: &dyn LogOps) -> Result<()> {
+///         log::info!("{}", self.format.format(ops)?);
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// [`Envoy`]: https://www.envoyproxy.io/docs/envoy/latest/configuration/observability/access_log/usage
+/// [`on_configure`]: trait.AccessLogger.html#method.on_configure
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFormat {
+    segments: Vec<Segment>,
+}
+
+/// Access log format that produces JSON objects, similarly to `json_format` in `Envoy`.
+///
+/// Just like in `Envoy`, a field that consists of a single command operator keeps
+/// the type of its value, e.g. `%RESPONSE_CODE%` and `%DURATION%` are rendered as
+/// JSON numbers, `%DYNAMIC_METADATA(...)%` as arbitrary JSON values and values that
+/// are not available as `null`. Fields that mix command operators with text are
+/// always rendered as JSON strings.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{AccessLogger, Result};
+/// use envoy::extension::access_logger::{JsonLogFormat, LogOps};
+/// use envoy::host::log;
+///
+/// struct MyAccessLogger {
+///     format: JsonLogFormat,
+/// }
+///
+/// impl MyAccessLogger {
+///     fn new() -> Result<Self> {
+///         let format = JsonLogFormat::parse(vec![
+///             ("method", "%REQ(:METHOD)%"),
+///             ("path", "%REQ(X-ENVOY-ORIGINAL-PATH?:PATH)%"),
+///             ("code", "%RESPONSE_CODE%"),
+///         ])?;
+///         Ok(MyAccessLogger { format })
+///     }
+/// }
+///
+/// impl AccessLogger for MyAccessLogger {
+///     fn name() -> &'static str { "my_access_logger" }
+///
+///     fn on_log(&mut self, ops: &dyn LogOps) -> Result<()> {
+///         log::info!("{}", self.format.format(ops)?);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonLogFormat {
+    fields: Vec<(String, LogFormat)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Operator {
+        operator: Operator,
+        max_length: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operator {
+    StartTime(String),
+    RequestHeader(HeaderName),
+    ResponseHeader(HeaderName),
+    ResponseTrailer(HeaderName),
+    Protocol,
+    ResponseCode,
+    ResponseCodeDetails,
+    ResponseFlags,
+    GrpcStatus,
+    BytesReceived,
+    BytesSent,
+    Duration,
+    ConnectionId,
+    ConnectionTerminationDetails,
+    RequestedServerName,
+    RouteName,
+    UpstreamHost,
+    UpstreamCluster,
+    UpstreamLocalAddress,
+    UpstreamTransportFailureReason,
+    DownstreamRemoteAddress,
+    DownstreamRemoteAddressWithoutPort,
+    DownstreamLocalAddress,
+    DownstreamLocalAddressWithoutPort,
+    DownstreamTlsVersion,
+    DownstreamLocalSubject,
+    DownstreamPeerSubject,
+    DownstreamLocalUriSan,
+    DownstreamPeerUriSan,
+    DownstreamPeerFingerprint256,
+    UpstreamTlsVersion,
+    FilterState(String),
+    DynamicMetadata(String, Vec<String>),
+}
+
+/// Represents `X?Y` argument of `%REQ%`, `%RESP%` and `%TRAILER%` operators.
+#[derive(Debug, Clone, PartialEq)]
+struct HeaderName {
+    main: String,
+    alternative: Option<String>,
+}
+
+impl LogFormat {
+    /// Parses a given format string.
+    pub fn parse(format: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = format;
+        while let Some(start) = rest.find('%') {
+            text.push_str(&rest[..start]);
+            if rest[start + 1..].starts_with('%') {
+                text.push('%');
+                rest = &rest[start + 2..];
+                continue;
+            }
+            let (segment, tail) = Self::parse_operator(&rest[start + 1..]).map_err(|err| {
+                format_err!(
+                    "invalid access log format \"{}\" at position {}: {}",
+                    format,
+                    format.len() - rest.len() + start,
+                    err
+                )
+            })?;
+            if !text.is_empty() {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+            }
+            segments.push(segment);
+            rest = tail;
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(LogFormat { segments })
+    }
+
+    /// Parses `NAME(ARG):Z%` that follows the opening `%`.
+    fn parse_operator(input: &str) -> Result<(Segment, &str)> {
+        let name_len = input
+            .find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'))
+            .unwrap_or(input.len());
+        let (name, mut rest) = input.split_at(name_len);
+        if name.is_empty() {
+            bail!("expected a command operator after '%'");
+        }
+        let mut arg = None;
+        if rest.starts_with('(') {
+            let end = rest
+                .find(')')
+                .ok_or_else(|| format_err!("missing ')' after {}", name))?;
+            arg = Some(&rest[1..end]);
+            rest = &rest[end + 1..];
+        }
+        let mut max_length = None;
+        if rest.starts_with(':') {
+            let digits = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or_else(|| rest.len() - 1);
+            max_length = Some(rest[1..=digits].parse::<usize>()?);
+            rest = &rest[1 + digits..];
+        }
+        if !rest.starts_with('%') {
+            bail!("missing closing '%' after {}", name);
+        }
+        let operator = Operator::parse(name, arg)?;
+        Ok((
+            Segment::Operator {
+                operator,
+                max_length,
+            },
+            &rest[1..],
+        ))
+    }
+
+    /// Evaluates the format string against the HTTP stream or TCP connection being logged.
+    pub fn format(&self, ops: &dyn LogOps) -> host::Result<String> {
+        let stream_info = CachedStreamInfo::new(ops.stream_info());
+        let context = Context {
+            view: LogView::new(ops),
+            stream_info: &stream_info,
+        };
+        self.format_with(&context)
+    }
+
+    fn format_with(&self, context: &Context<'_>) -> host::Result<String> {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Operator {
+                    operator,
+                    max_length,
+                } => match operator.evaluate(context)?.and_then(to_text) {
+                    Some(value) => output.push_str(truncate(&value, *max_length)),
+                    None => output.push_str(UNSPECIFIED_VALUE),
+                },
+            }
+        }
+        Ok(output)
+    }
+
+    /// Evaluates the format string into a typed JSON value.
+    ///
+    /// Only a format string that consists of a single command operator keeps
+    /// the type of its value.
+    fn format_json_with(&self, context: &Context<'_>) -> host::Result<JsonValue> {
+        if let [Segment::Operator {
+            operator,
+            max_length,
+        }] = self.segments.as_slice()
+        {
+            let value = match operator.evaluate(context)? {
+                Some(JsonValue::String(value)) if value.is_empty() => JsonValue::Null,
+                Some(JsonValue::String(value)) => truncate(&value, *max_length).into(),
+                Some(value) => value,
+                None => JsonValue::Null,
+            };
+            return Ok(value);
+        }
+        self.format_with(context).map(JsonValue::String)
+    }
+}
+
+impl JsonLogFormat {
+    /// Parses format strings of individual fields.
+    pub fn parse<I, K, V>(fields: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: AsRef<str>,
+    {
+        let fields = fields
+            .into_iter()
+            .map(|(key, format)| Ok((key.into(), LogFormat::parse(format.as_ref())?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(JsonLogFormat { fields })
+    }
+
+    /// Evaluates format strings against the HTTP stream or TCP connection being logged
+    /// and returns a JSON object with fields in the configured order.
+    pub fn format(&self, ops: &dyn LogOps) -> host::Result<String> {
+        let stream_info = CachedStreamInfo::new(ops.stream_info());
+        let context = Context {
+            view: LogView::new(ops),
+            stream_info: &stream_info,
+        };
+        let mut output = String::from("{");
+        for (i, (key, format)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            output.push_str(&serde_json::to_string(key)?);
+            output.push(':');
+            output.push_str(&serde_json::to_string(&format.format_json_with(&context)?)?);
+        }
+        output.push('}');
+        Ok(output)
+    }
+}
+
+/// Data available to command operators.
+struct Context<'a> {
+    view: LogView<'a>,
+    stream_info: &'a dyn StreamInfo,
+}
+
+impl Operator {
+    fn parse(name: &str, arg: Option<&str>) -> Result<Self> {
+        use Operator::*;
+        let operator = match (name, arg) {
+            ("START_TIME", None) => StartTime(DEFAULT_TIME_FORMAT.to_owned()),
+            ("START_TIME", Some(format)) => StartTime(format.to_owned()),
+            ("REQ", Some(arg)) => RequestHeader(HeaderName::parse(arg)?),
+            ("RESP", Some(arg)) => ResponseHeader(HeaderName::parse(arg)?),
+            ("TRAILER", Some(arg)) => ResponseTrailer(HeaderName::parse(arg)?),
+            ("PROTOCOL", None) => Protocol,
+            ("RESPONSE_CODE", None) => ResponseCode,
+            ("RESPONSE_CODE_DETAILS", None) => ResponseCodeDetails,
+            ("RESPONSE_FLAGS", None) => ResponseFlags,
+            ("GRPC_STATUS", None) => GrpcStatus,
+            ("BYTES_RECEIVED", None) => BytesReceived,
+            ("BYTES_SENT", None) => BytesSent,
+            ("DURATION", None) => Duration,
+            ("CONNECTION_ID", None) => ConnectionId,
+            ("CONNECTION_TERMINATION_DETAILS", None) => ConnectionTerminationDetails,
+            ("REQUESTED_SERVER_NAME", None) => RequestedServerName,
+            ("ROUTE_NAME", None) => RouteName,
+            ("UPSTREAM_HOST", None) => UpstreamHost,
+            ("UPSTREAM_CLUSTER", None) => UpstreamCluster,
+            ("UPSTREAM_LOCAL_ADDRESS", None) => UpstreamLocalAddress,
+            ("UPSTREAM_TRANSPORT_FAILURE_REASON", None) => UpstreamTransportFailureReason,
+            ("DOWNSTREAM_REMOTE_ADDRESS", None) => DownstreamRemoteAddress,
+            ("DOWNSTREAM_REMOTE_ADDRESS_WITHOUT_PORT", None) => DownstreamRemoteAddressWithoutPort,
+            ("DOWNSTREAM_LOCAL_ADDRESS", None) => DownstreamLocalAddress,
+            ("DOWNSTREAM_LOCAL_ADDRESS_WITHOUT_PORT", None) => DownstreamLocalAddressWithoutPort,
+            ("DOWNSTREAM_TLS_VERSION", None) => DownstreamTlsVersion,
+            ("DOWNSTREAM_LOCAL_SUBJECT", None) => DownstreamLocalSubject,
+            ("DOWNSTREAM_PEER_SUBJECT", None) => DownstreamPeerSubject,
+            ("DOWNSTREAM_LOCAL_URI_SAN", None) => DownstreamLocalUriSan,
+            ("DOWNSTREAM_PEER_URI_SAN", None) => DownstreamPeerUriSan,
+            ("DOWNSTREAM_PEER_FINGERPRINT_256", None) => DownstreamPeerFingerprint256,
+            ("UPSTREAM_TLS_VERSION", None) => UpstreamTlsVersion,
+            ("FILTER_STATE", Some(key)) if !key.is_empty() => FilterState(key.to_owned()),
+            ("DYNAMIC_METADATA", Some(arg)) => {
+                let mut parts = arg.split(':').map(str::to_owned);
+                let namespace = parts.next().filter(|namespace| !namespace.is_empty());
+                match namespace {
+                    Some(namespace) => DynamicMetadata(namespace, parts.collect()),
+                    None => bail!("DYNAMIC_METADATA requires a namespace"),
+                }
+            }
+            ("REQ", None)
+            | ("RESP", None)
+            | ("TRAILER", None)
+            | ("FILTER_STATE", _)
+            | ("DYNAMIC_METADATA", None) => bail!("{} requires an argument", name),
+            (_, Some(_)) if Self::parse(name, None).is_ok() => {
+                bail!("{} does not take an argument", name)
+            }
+            _ => bail!("unknown command operator {}", name),
+        };
+        Ok(operator)
+    }
+
+    fn evaluate(&self, context: &Context<'_>) -> host::Result<Option<JsonValue>> {
+        use Operator::*;
+        let stream_info = context.stream_info;
+        let view = &context.view;
+        let text = |value: Option<String>| value.map(JsonValue::String);
+        let value = match self {
+            StartTime(format) => text(
+                stream_info
+                    .request()
+                    .time()?
+                    .map(|time| format_time(time, format)),
+            ),
+            RequestHeader(name) => text(name.lookup(|name| view.request_header(name))?),
+            ResponseHeader(name) => text(name.lookup(|name| view.response_header(name))?),
+            ResponseTrailer(name) => text(name.lookup(|name| view.response_trailer(name))?),
+            Protocol => text(stream_info.request().protocol()?),
+            ResponseCode => Some(view.response_code()?.unwrap_or_default().into()),
+            ResponseCodeDetails => text(stream_info.response().code_details()?),
+            ResponseFlags => text(stream_info.response().flags()?.map(|f| f.to_string())),
+            GrpcStatus => text(
+                stream_info
+                    .response()
+                    .grpc_status()?
+                    .map(|status| status.to_string()),
+            ),
+            BytesReceived => view.bytes_received()?.map(JsonValue::from),
+            BytesSent => view.bytes_sent()?.map(JsonValue::from),
+            Duration => view
+                .duration()?
+                .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX).into()),
+            ConnectionId => stream_info.connection().id()?.map(JsonValue::from),
+            ConnectionTerminationDetails => text(stream_info.connection().termination_details()?),
+            RequestedServerName => text(stream_info.connection().requested_server_name()?),
+            RouteName => text(stream_info.route().name()?),
+            UpstreamHost => text(stream_info.upstream().address()?),
+            UpstreamCluster => text(stream_info.cluster().name()?),
+            UpstreamLocalAddress => text(stream_info.upstream().local_address()?),
+            UpstreamTransportFailureReason => {
+                text(stream_info.upstream().transport_failure_reason()?)
+            }
+            DownstreamRemoteAddress => text(stream_info.source().address()?),
+            DownstreamRemoteAddressWithoutPort => {
+                text(stream_info.source().address()?.map(strip_port))
+            }
+            DownstreamLocalAddress => text(stream_info.destination().address()?),
+            DownstreamLocalAddressWithoutPort => {
+                text(stream_info.destination().address()?.map(strip_port))
+            }
+            DownstreamTlsVersion => text(stream_info.connection().tls().version()?),
+            DownstreamLocalSubject => {
+                text(stream_info.connection().tls().subject_local_certificate()?)
+            }
+            DownstreamPeerSubject => {
+                text(stream_info.connection().tls().subject_peer_certificate()?)
+            }
+            DownstreamLocalUriSan => {
+                text(stream_info.connection().tls().uri_san_local_certificate()?)
+            }
+            DownstreamPeerUriSan => {
+                text(stream_info.connection().tls().uri_san_peer_certificate()?)
+            }
+            DownstreamPeerFingerprint256 => text(
+                stream_info
+                    .connection()
+                    .tls()
+                    .sha256_peer_certificate_digest()?,
+            ),
+            UpstreamTlsVersion => text(stream_info.upstream().tls().version()?),
+            FilterState(key) => text(
+                stream_info
+                    .stream_property(&[key])?
+                    .map(|value| value.to_string()),
+            ),
+            DynamicMetadata(namespace, path) => stream_info.metadata()?.and_then(|metadata| {
+                let mut value = metadata.filter_metadata(namespace)?;
+                for key in path {
+                    value = value.get(key)?;
+                }
+                Some(value.clone())
+            }),
+        };
+        Ok(value)
+    }
+}
+
+impl HeaderName {
+    fn parse(arg: &str) -> Result<Self> {
+        let mut names = arg.splitn(2, '?').map(str::trim);
+        let main = names.next().unwrap_or_default();
+        if main.is_empty() {
+            bail!("header name must not be empty");
+        }
+        Ok(HeaderName {
+            main: main.to_ascii_lowercase(),
+            alternative: names.next().map(str::to_ascii_lowercase),
+        })
+    }
+
+    fn lookup<F>(&self, get: F) -> host::Result<Option<String>>
+    where
+        F: Fn(&str) -> host::Result<Option<host::ByteString>>,
+    {
+        let mut value = get(&self.main)?;
+        if value.is_none() {
+            if let Some(alternative) = &self.alternative {
+                value = get(alternative)?;
+            }
+        }
+        Ok(value.map(|value| value.to_string()))
+    }
+}
+
+/// Renders a typed value as text, treating empty strings as missing values.
+fn to_text(value: JsonValue) -> Option<String> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::String(value) if value.is_empty() => None,
+        JsonValue::String(value) => Some(value),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Truncates a value to a given number of bytes without splitting UTF-8 characters.
+fn truncate(value: &str, max_length: Option<usize>) -> &str {
+    match max_length {
+        Some(mut max_length) if max_length < value.len() => {
+            while !value.is_char_boundary(max_length) {
+                max_length -= 1;
+            }
+            &value[..max_length]
+        }
+        _ => value,
+    }
+}
+
+/// Strips port from `ip:port` or `[ipv6]:port` address.
+fn strip_port(address: String) -> String {
+    if let Some(rest) = address.strip_prefix('[') {
+        if let Some(end) = rest.find(']') {
+            return rest[..end].to_owned();
+        }
+    }
+    match address.rfind(':') {
+        Some(pos) if address[..pos].find(':').is_none() => address[..pos].to_owned(),
+        _ => address,
+    }
+}
+
+/// Formats time in UTC according to a subset of `strftime` specifiers
+/// supported by `Envoy`: `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%s`, `%z`, `%Z`,
+/// `%f` (nanoseconds) and `%1f`-`%9f` (fractions of a second).
+fn format_time(time: SystemTime, format: &str) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let nanos = since_epoch.subsec_nanos();
+    let days = i64::try_from(secs / 86_400).unwrap_or_default();
+    let (year, month, day) = civil_from_days(days);
+    let secs_of_day = secs % 86_400;
+    let (hour, minute, second) = (
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+    );
+
+    let mut output = String::with_capacity(format.len() + 16);
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let _ = match chars.next() {
+            Some('Y') => write!(output, "{:04}", year),
+            Some('m') => write!(output, "{:02}", month),
+            Some('d') => write!(output, "{:02}", day),
+            Some('H') => write!(output, "{:02}", hour),
+            Some('M') => write!(output, "{:02}", minute),
+            Some('S') => write!(output, "{:02}", second),
+            Some('s') => write!(output, "{}", secs),
+            Some('z') => write!(output, "+0000"),
+            Some('Z') => write!(output, "UTC"),
+            Some('f') => write!(output, "{:09}", nanos),
+            Some(digit @ '1'..='9') if chars.peek() == Some(&'f') => {
+                chars.next();
+                let digits = digit as usize - '0' as usize;
+                let fraction = format!("{:09}", nanos);
+                write!(output, "{}", &fraction[..digits])
+            }
+            Some('%') => write!(output, "%"),
+            Some(other) => write!(output, "%{}", other),
+            None => write!(output, "%"),
+        };
+    }
+    output
+}
+
+/// Converts a number of days since UNIX epoch into a (year, month, day) triple.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -61,14 +61,24 @@
 //! [`AccessLogger`]: trait.AccessLogger.html
 //! [`Register`]: ../../macro.entrypoint.html
 
+use crate::extension::filter::http::{REQUEST_BODY_KEY, RESPONSE_BODY_KEY};
 use crate::extension::{ConfigStatus, DrainStatus, Result};
 use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
 use crate::host::{self, ByteString, HeaderMap, StreamInfo};
 
+pub use self::batch::{BatchConfig, BatchEncoding, BatchSink};
+pub use self::filter::{FilteredAccessLogger, LogFilter};
+pub use self::format::{JsonLogFormat, LogFormat};
+pub use self::view::LogView;
+
 pub(crate) use self::context::AccessLoggerContext;
 
+mod batch;
 mod context;
+mod filter;
+mod format;
 mod ops;
+mod view;
 
 /// An interface of the `Envoy` `Access Logger` extension.
 ///
@@ -161,7 +171,12 @@ pub trait AccessLogger {
     /// [`DrainStatus`] telling `Envoy` whether `Access Logger` has already been drained
     /// and can be now removed safely.
     ///
+    /// Either way, `Envoy` is not acknowledged until all HTTP requests made by
+    /// `Access Logger` receive a response or the drain timeout configured by
+    /// [`Module::with_drain_timeout`] expires.
+    ///
     /// [`DrainStatus`]: ../factory/enum.DrainStatus.html
+    /// [`Module::with_drain_timeout`]: ../struct.Module.html#method.with_drain_timeout
     fn on_drain(&mut self) -> Result<DrainStatus> {
         Ok(DrainStatus::Complete)
     }
@@ -224,6 +239,16 @@ pub trait DrainOps {
     fn done(&self) -> host::Result<()>;
 }
 
+impl dyn DrainOps {
+    /// Returns the default implementation that interacts with `Envoy`
+    /// through its [`ABI`].
+    ///
+    /// [`ABI`]: https://github.com/proxy-wasm/spec
+    pub fn default() -> &'static dyn DrainOps {
+        &ops::Host
+    }
+}
+
 /// An interface for accessing data of the HTTP stream or TCP connection that is being logged.
 pub trait LogOps {
     /// Returns request headers.
@@ -232,6 +257,18 @@ pub trait LogOps {
     /// Returns request header by name.
     fn request_header(&self, name: &str) -> host::Result<Option<ByteString>>;
 
+    /// Returns request trailers.
+    ///
+    /// The default implementation returns no trailers.
+    fn request_trailers(&self) -> host::Result<HeaderMap> {
+        Ok(HeaderMap::default())
+    }
+
+    /// Returns request trailer by name.
+    fn request_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        Ok(self.request_trailers()?.get(name).cloned())
+    }
+
     /// Returns response headers.
     fn response_headers(&self) -> host::Result<HeaderMap>;
 
@@ -246,6 +283,22 @@ pub trait LogOps {
 
     /// Provides access to properties of the stream.
     fn stream_info(&self) -> &dyn StreamInfo;
+
+    /// Returns a prefix of the request body captured by [`BodyCapture`]
+    /// or `None` if the body hasn't been captured.
+    ///
+    /// [`BodyCapture`]: ../filter/http/struct.BodyCapture.html
+    fn request_body(&self) -> host::Result<Option<ByteString>> {
+        self.stream_info().stream_property(&[REQUEST_BODY_KEY])
+    }
+
+    /// Returns a prefix of the response body captured by [`BodyCapture`]
+    /// or `None` if the body hasn't been captured.
+    ///
+    /// [`BodyCapture`]: ../filter/http/struct.BodyCapture.html
+    fn response_body(&self) -> host::Result<Option<ByteString>> {
+        self.stream_info().stream_property(&[RESPONSE_BODY_KEY])
+    }
 }
 
 #[doc(hidden)]
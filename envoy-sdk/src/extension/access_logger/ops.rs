@@ -36,6 +36,14 @@ impl LogOps for Host {
         hostcalls::get_map_value(MapType::HttpRequestHeaders, name)
     }
 
+    fn request_trailers(&self) -> host::Result<HeaderMap> {
+        hostcalls::get_map(MapType::HttpRequestTrailers)
+    }
+
+    fn request_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        hostcalls::get_map_value(MapType::HttpRequestTrailers, name)
+    }
+
     fn response_headers(&self) -> host::Result<HeaderMap> {
         hostcalls::get_map(MapType::HttpResponseHeaders)
     }
@@ -0,0 +1,230 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Protocol-agnostic view of the data being logged.
+
+use std::cell::Cell;
+use std::time::{Duration, SystemTime};
+
+use super::LogOps;
+use crate::host::stream_info::{ConnectionInfo, ResponseFlags, UpstreamInfo};
+use crate::host::{self, ByteString, HeaderMap};
+
+/// A view of the HTTP stream or TCP connection that is being logged.
+///
+/// Connection-level data is available in both cases, while HTTP-specific data
+/// is reported as `None` when a TCP connection is being logged.
+///
+/// This way, the same [`AccessLogger`] can be attached both to
+/// HTTP and TCP listeners.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{AccessLogger, Result};
+/// use envoy::extension::access_logger::{LogOps, LogView};
+/// use envoy::host::log;
+///
+/// struct MyAccessLogger;
+///
+/// impl AccessLogger for MyAccessLogger {
+///     fn name() -> &'static str { "my_access_logger" }
+///
+///     fn on_log(&mut self, ops: &dyn LogOps) -> Result<()> {
+///         let view = LogView::new(ops);
+///         log::info!(
+///             "{} bytes received, {} bytes sent, response code {:?}",
+///             view.bytes_received()?.unwrap_or_default(),
+///             view.bytes_sent()?.unwrap_or_default(),
+///             view.response_code()?, // `None` in case of TCP connections
+///         );
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// [`AccessLogger`]: trait.AccessLogger.html
+pub struct LogView<'a> {
+    ops: &'a dyn LogOps,
+    is_http: Cell<Option<bool>>,
+}
+
+impl<'a> LogView<'a> {
+    /// Returns a view of the data provided by a given [`LogOps`].
+    ///
+    /// [`LogOps`]: trait.LogOps.html
+    pub fn new(ops: &'a dyn LogOps) -> Self {
+        LogView {
+            ops,
+            is_http: Cell::new(None),
+        }
+    }
+
+    /// Returns `true` if an HTTP stream is being logged
+    /// and `false` if it is a TCP connection.
+    pub fn is_http(&self) -> host::Result<bool> {
+        if let Some(is_http) = self.is_http.get() {
+            return Ok(is_http);
+        }
+        // `request.protocol` is only known for HTTP streams
+        let is_http = self.ops.stream_info().request().protocol()?.is_some();
+        self.is_http.set(Some(is_http));
+        Ok(is_http)
+    }
+
+    /// Returns time of the first byte received.
+    pub fn start_time(&self) -> host::Result<Option<SystemTime>> {
+        self.ops.stream_info().request().time()
+    }
+
+    /// Returns total duration of the HTTP stream or TCP connection.
+    pub fn duration(&self) -> host::Result<Option<Duration>> {
+        self.ops.stream_info().request().duration()
+    }
+
+    /// Returns number of bytes received from the downstream.
+    pub fn bytes_received(&self) -> host::Result<Option<u64>> {
+        if self.is_http()? {
+            self.ops.stream_info().request().total_size()
+        } else {
+            self.ops.stream_info().request().size()
+        }
+    }
+
+    /// Returns number of bytes sent to the downstream.
+    pub fn bytes_sent(&self) -> host::Result<Option<u64>> {
+        if self.is_http()? {
+            self.ops.stream_info().response().total_size()
+        } else {
+            self.ops.stream_info().response().size()
+        }
+    }
+
+    /// Returns address of the upstream host.
+    pub fn upstream_host(&self) -> host::Result<Option<String>> {
+        self.ops.stream_info().upstream().address()
+    }
+
+    /// Returns name of the upstream cluster.
+    pub fn upstream_cluster(&self) -> host::Result<Option<String>> {
+        self.ops.stream_info().cluster().name()
+    }
+
+    /// Returns name of the route.
+    pub fn route_name(&self) -> host::Result<Option<String>> {
+        self.ops.stream_info().route().name()
+    }
+
+    /// Returns additional details about the reason of termination,
+    /// e.g. a connection timeout or a failure to connect to the upstream.
+    pub fn response_flags(&self) -> host::Result<Option<ResponseFlags>> {
+        self.ops.stream_info().response().flags()
+    }
+
+    /// Returns internal termination details of the downstream connection.
+    pub fn termination_details(&self) -> host::Result<Option<String>> {
+        self.ops.stream_info().connection().termination_details()
+    }
+
+    /// Returns the upstream transport failure reason, e.g. certificate validation failed.
+    pub fn upstream_transport_failure_reason(&self) -> host::Result<Option<String>> {
+        self.ops.stream_info().upstream().transport_failure_reason()
+    }
+
+    /// Provides access to properties of the downstream connection, including `TLS` details.
+    pub fn connection(&self) -> ConnectionInfo<'a> {
+        self.ops.stream_info().connection()
+    }
+
+    /// Provides access to properties of the upstream connection, including `TLS` details.
+    pub fn upstream(&self) -> UpstreamInfo<'a> {
+        self.ops.stream_info().upstream()
+    }
+
+    /// Returns HTTP request headers or `None` in case of a TCP connection.
+    pub fn request_headers(&self) -> host::Result<Option<HeaderMap>> {
+        self.if_http(|ops| ops.request_headers())
+    }
+
+    /// Returns HTTP request header by name or `None` in case of a TCP connection.
+    pub fn request_header(&self, name: &str) -> host::Result<Option<ByteString>> {
+        self.if_http(|ops| ops.request_header(name))
+            .map(Option::flatten)
+    }
+
+    /// Returns HTTP request trailers or `None` in case of a TCP connection.
+    pub fn request_trailers(&self) -> host::Result<Option<HeaderMap>> {
+        self.if_http(|ops| ops.request_trailers())
+    }
+
+    /// Returns HTTP request trailer by name or `None` in case of a TCP connection.
+    pub fn request_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        self.if_http(|ops| ops.request_trailer(name))
+            .map(Option::flatten)
+    }
+
+    /// Returns a prefix of the HTTP request body captured by [`BodyCapture`].
+    ///
+    /// [`BodyCapture`]: ../filter/http/struct.BodyCapture.html
+    pub fn request_body(&self) -> host::Result<Option<ByteString>> {
+        self.if_http(|ops| ops.request_body()).map(Option::flatten)
+    }
+
+    /// Returns HTTP response headers or `None` in case of a TCP connection.
+    pub fn response_headers(&self) -> host::Result<Option<HeaderMap>> {
+        self.if_http(|ops| ops.response_headers())
+    }
+
+    /// Returns HTTP response header by name or `None` in case of a TCP connection.
+    pub fn response_header(&self, name: &str) -> host::Result<Option<ByteString>> {
+        self.if_http(|ops| ops.response_header(name))
+            .map(Option::flatten)
+    }
+
+    /// Returns HTTP response trailers or `None` in case of a TCP connection.
+    pub fn response_trailers(&self) -> host::Result<Option<HeaderMap>> {
+        self.if_http(|ops| ops.response_trailers())
+    }
+
+    /// Returns HTTP response trailer by name or `None` in case of a TCP connection.
+    pub fn response_trailer(&self, name: &str) -> host::Result<Option<ByteString>> {
+        self.if_http(|ops| ops.response_trailer(name))
+            .map(Option::flatten)
+    }
+
+    /// Returns a prefix of the HTTP response body captured by [`BodyCapture`].
+    ///
+    /// [`BodyCapture`]: ../filter/http/struct.BodyCapture.html
+    pub fn response_body(&self) -> host::Result<Option<ByteString>> {
+        self.if_http(|ops| ops.response_body()).map(Option::flatten)
+    }
+
+    /// Returns HTTP response code or `None` in case of a TCP connection.
+    pub fn response_code(&self) -> host::Result<Option<u16>> {
+        self.if_http(|ops| ops.stream_info().response().status_code())
+            .map(Option::flatten)
+    }
+
+    fn if_http<T, F>(&self, f: F) -> host::Result<Option<T>>
+    where
+        F: FnOnce(&'a dyn LogOps) -> host::Result<T>,
+    {
+        if self.is_http()? {
+            f(self.ops).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
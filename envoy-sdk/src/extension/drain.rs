@@ -0,0 +1,214 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Draining of root contexts.
+//!
+//! When `Envoy` is about to remove an extension, it asks the extension to drain,
+//! i.e. to finish outstanding work. On top of the `on_drain` callback of the extension,
+//! [`Drainer`] keeps the root context alive until HTTP requests made by the extension
+//! receive a response, and forces completion once the drain timeout expires.
+//!
+//! The drain timeout is checked on `on_tick` callbacks. If the extension has set
+//! a tick period of its own, the timeout is checked at that pace. Otherwise, the drain
+//! timeout becomes the tick period until draining completes.
+//!
+//! Only HTTP requests made by the root context itself are tracked. Timers and
+//! shared queues are not, i.e. an extension that relies on `on_tick` or `on_queue_ready`
+//! to finish its work must keep reporting [`DrainStatus::Ongoing`] and call `done`
+//! once it's through.
+//!
+//! [`Drainer`]: struct.Drainer.html
+//! [`DrainStatus::Ongoing`]: ../factory/enum.DrainStatus.html#variant.Ongoing
+
+use std::cell::Cell;
+use std::time::{Duration, SystemTime};
+
+use crate::abi::proxy_wasm::scope::{ChildScope, RootScope};
+use crate::error::format_err;
+use crate::extension::{DrainStatus, Result};
+use crate::host::{self, Clock};
+
+/// Tracks draining of a single root context.
+///
+/// Keeps the root context alive while HTTP requests it has made are in flight.
+/// Timers and shared queues of the extension are not tracked.
+pub(crate) struct Drainer<'a> {
+    scope: RootScope,
+    timeout: Option<Duration>,
+    deadline: Option<SystemTime>,
+    tick_period: Cell<Duration>,
+    clock: &'a dyn Clock,
+    ops: &'a dyn Ops,
+}
+
+/// An interface for notifying `Envoy` about the progress of draining.
+pub(crate) trait Ops {
+    /// Acknowledges `Envoy` that extension has been drained.
+    fn done(&self) -> host::Result<()>;
+
+    /// Sets a period of `on_tick` callbacks.
+    fn set_tick_period(&self, period: Duration) -> host::Result<()>;
+}
+
+impl dyn Ops {
+    /// Returns the default implementation that interacts with `Envoy`
+    /// through its [`ABI`].
+    ///
+    /// [`ABI`]: https://github.com/proxy-wasm/spec
+    pub fn default() -> &'static dyn Ops {
+        &impls::Host
+    }
+}
+
+impl<'a> Drainer<'a> {
+    pub fn new(timeout: Option<Duration>, clock: &'a dyn Clock, ops: &'a dyn Ops) -> Self {
+        Drainer {
+            scope: RootScope::default(),
+            timeout,
+            deadline: None,
+            tick_period: Cell::new(Duration::default()),
+            clock,
+            ops,
+        }
+    }
+
+    /// Creates a new drainer bound to the actual Envoy ABI.
+    pub fn with_default_ops(timeout: Option<Duration>) -> Self {
+        Self::new(timeout, <dyn Clock>::default(), <dyn Ops>::default())
+    }
+
+    /// Runs an extension callback, attributing host calls it makes to the root context.
+    pub fn enter<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        self.scope.enter(f)
+    }
+
+    /// Returns a scope for callbacks of child contexts of the root context,
+    /// which attributes completion of draining they request to the root context.
+    pub fn child_scope(&self) -> ChildScope {
+        self.scope.child()
+    }
+
+    /// Forgets an HTTP request that has received a response.
+    pub fn on_http_call_response(&self, token_id: u32) {
+        self.scope.complete_http_call(token_id);
+    }
+
+    /// Records a tick period the extension has set.
+    ///
+    /// If the extension disables its timer while the drain timeout is pending,
+    /// the timer gets re-enabled to keep track of the timeout.
+    pub fn on_tick_period(&self, period: Duration) -> host::Result<()> {
+        self.tick_period.set(period);
+        match (self.timeout, self.deadline) {
+            (Some(timeout), Some(_)) if period == Duration::default() => {
+                self.ops.set_tick_period(timeout)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` if `on_tick` callbacks are caused by the drain timeout
+    /// rather than a tick period set by the extension.
+    pub fn is_drain_tick(&self) -> bool {
+        self.deadline.is_some() && self.tick_period.get() == Duration::default()
+    }
+
+    /// Starts draining once the extension has handled `on_drain`.
+    ///
+    /// `status` is `None` if the extension has failed to handle `on_drain`.
+    pub fn on_drain(&mut self, status: Option<DrainStatus>) -> host::Result<DrainStatus> {
+        self.scope.start_draining();
+        if let Some(DrainStatus::Complete) = status {
+            self.scope.request_done();
+        }
+        if self.scope.is_drained() && self.scope.mark_done() {
+            return Ok(DrainStatus::Complete);
+        }
+        if let Some(timeout) = self.timeout {
+            self.deadline = Some(self.clock.now()? + timeout);
+            // don't interfere with a tick period set by the extension itself
+            if self.tick_period.get() == Duration::default() {
+                self.ops.set_tick_period(timeout)?;
+            }
+        }
+        Ok(DrainStatus::Ongoing)
+    }
+
+    /// Acknowledges `Envoy` if draining has completed since the last call.
+    pub fn poll(&mut self) -> Result<()> {
+        if self.scope.is_drained() && self.scope.mark_done() {
+            self.ops.done()?;
+            self.stop_drain_ticks()?;
+        }
+        Ok(())
+    }
+
+    /// Forces completion of draining once the drain timeout has expired.
+    ///
+    /// Returns an error if completion has been forced.
+    pub fn on_tick(&mut self) -> Result<()> {
+        if self.deadline.is_some() && self.scope.is_done() {
+            // completion has already been reported by the extension itself
+            self.stop_drain_ticks()?;
+            return Ok(());
+        }
+        match (self.timeout, self.deadline) {
+            (Some(timeout), Some(deadline))
+                if self.clock.now()? >= deadline && self.scope.mark_done() =>
+            {
+                self.ops.done()?;
+                self.stop_drain_ticks()?;
+                Err(format_err!(
+                    "drain timeout of {:?} has expired with {} HTTP request(s) still in flight",
+                    timeout,
+                    self.scope.http_calls_in_flight(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Disables the timer installed to keep track of the drain timeout, if any.
+    fn stop_drain_ticks(&mut self) -> host::Result<()> {
+        let is_drain_tick = self.is_drain_tick();
+        self.deadline = None;
+        if is_drain_tick {
+            self.ops.set_tick_period(Duration::default())?;
+        }
+        Ok(())
+    }
+}
+
+mod impls {
+    use std::time::Duration;
+
+    use super::Ops;
+    use crate::abi::proxy_wasm::hostcalls;
+    use crate::host;
+
+    pub(super) struct Host;
+
+    impl Ops for Host {
+        fn done(&self) -> host::Result<()> {
+            hostcalls::done()
+        }
+
+        fn set_tick_period(&self, period: Duration) -> host::Result<()> {
+            hostcalls::set_tick_period(period)
+        }
+    }
+}
@@ -15,10 +15,14 @@
 //! Errors specific to extension callback methods.
 
 use std::fmt;
+use std::rc::Rc;
 
 pub use crate::error::{Error, ErrorContext, Result};
+use crate::extension::InstanceId;
 pub use crate::host::log;
 
+pub(crate) use self::impls::DefaultErrorSink;
+
 /// An error at the initialization stage of the WebAssembly module.
 #[derive(Debug)]
 pub(crate) enum ModuleError {
@@ -76,8 +80,173 @@ impl std::error::Error for ConfigurationError {
     }
 }
 
-pub(crate) trait ErrorSink {
-    fn observe(&self, context: &str, err: &Error);
+/// An error caused by a panic inside an extension.
+#[derive(Debug)]
+pub(crate) enum ExtensionError {
+    /// Extension callback has panicked.
+    Panicked(String),
+    /// Extension instance has panicked earlier and can no longer be used.
+    Failed,
+    /// Extension callback has never returned, e.g. due to a panic on a target
+    /// without stack unwinding, so the extension instance can no longer be used.
+    Aborted,
+}
+
+impl fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ExtensionError::*;
+        match self {
+            Panicked(message) => write!(f, "extension has panicked: {}", message),
+            Failed => write!(
+                f,
+                "extension instance can no longer be used since it has panicked earlier"
+            ),
+            Aborted => write!(
+                f,
+                "extension instance can no longer be used since its previous callback has been aborted"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExtensionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+/// Stage of the extension lifecycle an error has occurred at.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// Initialization of the WebAssembly module or creation of an extension factory.
+    Initialization,
+    /// Configuration of an extension, i.e. `on_configure`.
+    Configuration,
+    /// Creation of a new extension instance, i.e. `new_extension`.
+    ExtensionCreation,
+    /// Extension callback, e.g. `on_request_headers` or `on_log`.
+    Callback,
+    /// Draining of an extension, i.e. `on_drain`.
+    Drain,
+    /// Panic inside an extension callback.
+    ///
+    /// On targets that don't support stack unwinding, a panic aborts the callback
+    /// and only gets reported if the host calls the extension instance again.
+    Panic,
+}
+
+impl ErrorCategory {
+    /// Returns a short name of the category suitable for use in metric names.
+    pub fn as_str(&self) -> &'static str {
+        use ErrorCategory::*;
+        match self {
+            Initialization => "initialization",
+            Configuration => "configuration",
+            ExtensionCreation => "extension_creation",
+            Callback => "callback",
+            Drain => "drain",
+            Panic => "panic",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Description of an error returned by an extension.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ErrorReport<'a> {
+    /// Stage of the extension lifecycle the error has occurred at.
+    pub category: ErrorCategory,
+    /// Human-readable description of the failed operation,
+    /// e.g. `failed to handle HTTP request headers`.
+    pub context: &'a str,
+    /// The error itself.
+    pub error: &'a Error,
+    /// `root_id` of the extension, if known.
+    pub root_id: Option<&'a str>,
+    /// Identifier of the extension instance, if known.
+    pub instance_id: Option<InstanceId>,
+}
+
+impl<'a> ErrorReport<'a> {
+    /// Creates a new report.
+    pub fn new(category: ErrorCategory, context: &'a str, error: &'a Error) -> Self {
+        ErrorReport {
+            category,
+            context,
+            error,
+            root_id: None,
+            instance_id: None,
+        }
+    }
+
+    /// Sets `root_id` of the extension.
+    pub fn root_id(mut self, root_id: &'a str) -> Self {
+        self.root_id = Some(root_id);
+        self
+    }
+
+    /// Sets identifier of the extension instance.
+    pub fn instance_id(mut self, instance_id: InstanceId) -> Self {
+        self.instance_id = Some(instance_id);
+        self
+    }
+}
+
+/// An interface for observing errors returned by extensions.
+///
+/// Errors returned by extension callbacks cannot be propagated any further,
+/// so `Envoy SDK` reports them to an `ErrorSink` configured on the [`Module`].
+///
+/// By default, errors are logged through `Envoy Log API`.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{Module, Result};
+/// use envoy::extension::error::{ErrorReport, ErrorSink};
+/// use envoy::host::Stats;
+/// use envoy::host::stats::Counter;
+///
+/// /// Counts errors per category and logs them afterwards.
+/// struct CountingErrorSink {
+///     configuration_errors: Box<dyn Counter>,
+///     callback_errors: Box<dyn Counter>,
+/// }
+///
+/// impl ErrorSink for CountingErrorSink {
+///     fn observe(&self, report: &ErrorReport<'_>) {
+///         use envoy::extension::error::ErrorCategory::*;
+///         let _ = match report.category {
+///             Configuration => self.configuration_errors.inc(),
+///             Callback => self.callback_errors.inc(),
+///             _ => Ok(()),
+///         };
+///         <dyn ErrorSink>::default().observe(report);
+///     }
+/// }
+///
+/// fn initialize() -> Result<Module> {
+///     let stats = Stats::default();
+///     let sink = CountingErrorSink {
+///         configuration_errors: stats.counter("my_module.errors.configuration")?,
+///         callback_errors: stats.counter("my_module.errors.callback")?,
+///     };
+///     Ok(Module::new().with_error_sink(sink))
+/// }
+/// ```
+///
+/// [`Module`]: ../struct.Module.html
+pub trait ErrorSink {
+    /// Observes an error returned by an extension.
+    fn observe(&self, report: &ErrorReport<'_>);
 }
 
 impl dyn ErrorSink {
@@ -90,15 +259,70 @@ impl dyn ErrorSink {
     }
 }
 
+/// Reports errors of a particular extension (instance) to an [`ErrorSink`].
+///
+/// [`ErrorSink`]: trait.ErrorSink.html
+#[derive(Clone)]
+pub(crate) struct ErrorReporter {
+    sink: Rc<dyn ErrorSink>,
+    root_id: Option<Rc<str>>,
+    instance_id: Option<InstanceId>,
+}
+
+impl ErrorReporter {
+    pub fn new(sink: Rc<dyn ErrorSink>) -> Self {
+        ErrorReporter {
+            sink,
+            root_id: None,
+            instance_id: None,
+        }
+    }
+
+    pub fn with_root_id<R>(mut self, root_id: R) -> Self
+    where
+        R: Into<Rc<str>>,
+    {
+        self.root_id = Some(root_id.into());
+        self
+    }
+
+    pub fn with_instance_id(mut self, instance_id: InstanceId) -> Self {
+        self.instance_id = Some(instance_id);
+        self
+    }
+
+    pub fn observe(&self, category: ErrorCategory, context: &str, err: &Error) {
+        let category = match err.downcast_ref::<ExtensionError>() {
+            Some(ExtensionError::Panicked(_)) | Some(ExtensionError::Aborted) => {
+                ErrorCategory::Panic
+            }
+            _ => category,
+        };
+        self.sink.observe(&ErrorReport {
+            category,
+            context,
+            error: err,
+            root_id: self.root_id.as_deref(),
+            instance_id: self.instance_id,
+        });
+    }
+}
+
+impl Default for ErrorReporter {
+    fn default() -> Self {
+        Self::new(Rc::new(DefaultErrorSink))
+    }
+}
+
 mod impls {
-    use super::{Error, ErrorSink};
+    use super::{ErrorReport, ErrorSink};
     use crate::host::log;
 
-    pub(super) struct DefaultErrorSink;
+    pub(crate) struct DefaultErrorSink;
 
     impl ErrorSink for DefaultErrorSink {
-        fn observe(&self, context: &str, err: &Error) {
-            log::error!("{}: {}", context, err);
+        fn observe(&self, report: &ErrorReport<'_>) {
+            log::error!("{}: {}", report.context, report.error);
         }
     }
 }
@@ -13,9 +13,13 @@
 // limitations under the License.
 
 use super::{ContextOps, DrainStatus, ExtensionFactory, Ops};
+use crate::abi::proxy_wasm::scope::ChildScope;
 use crate::abi::proxy_wasm::traits::{ChildContext, Context, RootContext};
-use crate::extension::error::ErrorSink;
-use crate::extension::{ConfigStatus, InstanceId};
+use crate::extension::drain::Drainer;
+use crate::extension::error::{ErrorCategory, ErrorReporter};
+use crate::extension::filter::FailurePolicy;
+use crate::extension::isolation::Isolation;
+use crate::extension::{ConfigStatus, InstanceId, Result};
 use crate::host::ByteString;
 
 pub(crate) struct ExtensionFactoryContext<'a, F>
@@ -25,8 +29,16 @@ where
     factory: F,
     context_ops: &'a dyn ContextOps,
     factory_ops: &'a dyn Ops,
-    error_sink: &'a dyn ErrorSink,
-    child_context_factory: fn(&mut F, InstanceId) -> ChildContext,
+    error_reporter: ErrorReporter,
+    isolation: Isolation,
+    drainer: Drainer<'a>,
+    child_context_factory: fn(
+        Result<F::Extension>,
+        FailurePolicy,
+        ErrorReporter,
+        Isolation,
+        ChildScope,
+    ) -> ChildContext,
 }
 
 impl<'a, F> RootContext for ExtensionFactoryContext<'a, F>
@@ -40,13 +52,15 @@ where
             self.context_ops.configuration(0, configuration_size)
         };
         match config.and_then(|config| {
-            self.factory
-                .on_configure(config, self.factory_ops.as_configure_ops())
+            self.call(|factory, ops| factory.on_configure(config, ops.as_configure_ops()))
         }) {
             Ok(status) => status.as_bool(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to configure extension", &err);
+                self.error_reporter.observe(
+                    ErrorCategory::Configuration,
+                    "failed to configure extension",
+                    &err,
+                );
                 ConfigStatus::Rejected.as_bool()
             }
         }
@@ -54,11 +68,29 @@ where
 
     fn on_create_child_context(&mut self, context_id: u32) -> Option<ChildContext> {
         let new_child_context = self.child_context_factory;
+        let instance_id = InstanceId::from(context_id);
+        let failure_policy = self
+            .call(|factory, _| Ok(factory.failure_policy()))
+            .unwrap_or_default();
+        let extension = self.call(|factory, _| factory.new_extension(instance_id));
         Some(new_child_context(
-            &mut self.factory,
-            InstanceId::from(context_id),
+            extension,
+            failure_policy,
+            self.error_reporter.clone().with_instance_id(instance_id),
+            Isolation::new(self.isolation.is_enabled()),
+            self.drainer.child_scope(),
         ))
     }
+
+    fn on_tick(&mut self) {
+        if let Err(err) = self.drainer.on_tick() {
+            self.error_reporter.observe(
+                ErrorCategory::Drain,
+                "failed to drain the extension",
+                &err,
+            );
+        }
+    }
 }
 
 impl<'a, F> Context for ExtensionFactoryContext<'a, F>
@@ -66,15 +98,52 @@ where
     F: ExtensionFactory,
 {
     fn on_done(&mut self) -> bool {
-        match self.factory.on_drain() {
+        let status = if self.isolation.is_failed() {
+            Some(DrainStatus::Complete)
+        } else {
+            match self.call(|factory, _| factory.on_drain()) {
+                Ok(status) => Some(status),
+                Err(err) => {
+                    self.error_reporter.observe(
+                        ErrorCategory::Drain,
+                        "failed to initiate draining of the extension",
+                        &err,
+                    );
+                    None
+                }
+            }
+        };
+        match self.drainer.on_drain(status) {
             Ok(status) => status.as_bool(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to initiate draining of the extension", &err);
-                DrainStatus::Ongoing.as_bool()
+                self.error_reporter.observe(
+                    ErrorCategory::Drain,
+                    "failed to initiate draining of the extension",
+                    &err,
+                );
+                status.unwrap_or(DrainStatus::Ongoing).as_bool()
             }
         }
     }
+
+    // Http Client callbacks
+
+    fn on_http_call_response(
+        &mut self,
+        token_id: u32,
+        _num_headers: usize,
+        _body_size: usize,
+        _num_trailers: usize,
+    ) {
+        self.drainer.on_http_call_response(token_id);
+        if let Err(err) = self.drainer.poll() {
+            self.error_reporter.observe(
+                ErrorCategory::Drain,
+                "failed to drain the extension",
+                &err,
+            );
+        }
+    }
 }
 
 impl<'a, F> ExtensionFactoryContext<'a, F>
@@ -85,14 +154,24 @@ where
         factory: F,
         context_ops: &'a dyn ContextOps,
         factory_ops: &'a dyn Ops,
-        error_sink: &'a dyn ErrorSink,
-        child_context_factory: fn(&mut F, InstanceId) -> ChildContext,
+        error_reporter: ErrorReporter,
+        drainer: Drainer<'a>,
+        isolation: Isolation,
+        child_context_factory: fn(
+            Result<F::Extension>,
+            FailurePolicy,
+            ErrorReporter,
+            Isolation,
+            ChildScope,
+        ) -> ChildContext,
     ) -> Self {
         ExtensionFactoryContext {
             factory,
             context_ops,
             factory_ops,
-            error_sink,
+            error_reporter,
+            isolation,
+            drainer,
             child_context_factory,
         }
     }
@@ -100,14 +179,36 @@ where
     /// Creates a new factory context bound to the actual Envoy ABI.
     pub fn with_default_ops(
         factory: F,
-        child_context_factory: fn(&mut F, InstanceId) -> ChildContext,
+        error_reporter: ErrorReporter,
+        drainer: Drainer<'a>,
+        isolation: Isolation,
+        child_context_factory: fn(
+            Result<F::Extension>,
+            FailurePolicy,
+            ErrorReporter,
+            Isolation,
+            ChildScope,
+        ) -> ChildContext,
     ) -> Self {
         Self::new(
             factory,
-            ContextOps::default(),
-            Ops::default(),
-            ErrorSink::default(),
+            <dyn ContextOps>::default(),
+            <dyn Ops>::default(),
+            error_reporter,
+            drainer,
+            isolation,
             child_context_factory,
         )
     }
+
+    /// Calls the factory unless it has panicked earlier.
+    fn call<T, C>(&mut self, callback: C) -> Result<T>
+    where
+        C: FnOnce(&mut F, &'a dyn Ops) -> Result<T>,
+    {
+        let (factory, factory_ops) = (&mut self.factory, self.factory_ops);
+        let isolation = &self.isolation;
+        self.drainer
+            .enter(|| isolation.call(|| callback(factory, factory_ops)))
+    }
 }
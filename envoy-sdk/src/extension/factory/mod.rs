@@ -47,13 +47,17 @@
 //!
 //! [`ExtensionFactory`]: trait.ExtensionFactory.html
 
+use crate::extension::filter::FailurePolicy;
 use crate::extension::{factory, InstanceId, Result};
 use crate::host::{self, ByteString};
 
+pub use self::reconfigure::{ConfigChange, Reconfigurable, ReconfigurableFactory};
+
 pub(crate) use self::context::ExtensionFactoryContext;
 
 mod context;
 mod ops;
+mod reconfigure;
 
 /// Possible responses to the request to (re-)configure the extension.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -287,6 +291,21 @@ pub trait ExtensionFactory {
     /// [`NetworkFilter`]: ../filter/network/trait.NetworkFilter.html
     fn new_extension(&mut self, _instance_id: InstanceId) -> Result<Self::Extension>;
 
+    /// Returns a policy that defines how to handle errors returned by extension instances,
+    /// as well as failures to create them.
+    ///
+    /// Only applies to [`HttpFilter`] and [`NetworkFilter`] extensions.
+    ///
+    /// Called every time a new instance of the extension is being created,
+    /// so the policy can be changed in [`on_configure`].
+    ///
+    /// [`HttpFilter`]: ../filter/http/trait.HttpFilter.html
+    /// [`NetworkFilter`]: ../filter/network/trait.NetworkFilter.html
+    /// [`on_configure`]: #method.on_configure
+    fn failure_policy(&self) -> FailurePolicy {
+        FailurePolicy::default()
+    }
+
     /// Called when `ExtensionFactory` is about to be destroyed.
     ///
     /// # Return value
@@ -294,7 +313,12 @@ pub trait ExtensionFactory {
     /// [`DrainStatus`] telling `Envoy` whether `ExtensionFactory` has already been drained
     /// and can be now removed safely.
     ///
+    /// Either way, `Envoy` is not acknowledged until all HTTP requests made by
+    /// `ExtensionFactory` receive a response or the drain timeout configured by
+    /// [`Module::with_drain_timeout`] expires.
+    ///
     /// [`DrainStatus`]: enum.DrainStatus.html
+    /// [`Module::with_drain_timeout`]: ../struct.Module.html#method.with_drain_timeout
     fn on_drain(&mut self) -> Result<DrainStatus> {
         Ok(DrainStatus::Complete)
     }
@@ -0,0 +1,290 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Structured (re-)configuration of an `Extension Factory`.
+
+use std::rc::Rc;
+
+use super::{ConfigStatus, ConfigureOps, DrainStatus, ExtensionFactory};
+use crate::error::format_err;
+use crate::extension::filter::FailurePolicy;
+use crate::extension::{InstanceId, Result};
+use crate::host::ByteString;
+
+/// An interface of the `Envoy` `Extension Factory` with a typed configuration.
+///
+/// Unlike [`ExtensionFactory::on_configure`] that receives raw bytes and leaves it up to
+/// the factory to decide how to swap its state, [`ReconfigurableFactory`] splits
+/// (re-)configuration into steps:
+/// 1. parsing raw configuration into a typed one,
+/// 2. validating the new configuration against the previous one,
+/// 3. creating extension instances that share the current configuration.
+///
+/// Use [`Reconfigurable`] to register a [`ReconfigurableFactory`] with a [`Module`].
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::extension::HttpFilter;
+/// #
+/// # /// My very own `HttpFilter`.
+/// # struct MyHttpFilter {
+/// #     config: std::rc::Rc<MyConfig>,
+/// # }
+/// # impl HttpFilter for MyHttpFilter {}
+/// #
+/// use std::rc::Rc;
+/// use envoy::extension::factory::{ConfigChange, ConfigureOps, Reconfigurable, ReconfigurableFactory};
+/// use envoy::extension::{ConfigStatus, InstanceId, Module, Result};
+/// use envoy::host::ByteString;
+///
+/// /// Typed configuration of `MyHttpFilter`.
+/// #[derive(PartialEq)]
+/// struct MyConfig {
+///     upstream: String,
+/// }
+///
+/// /// `ExtensionFactory` for `MyHttpFilter`.
+/// struct MyHttpFilterFactory;
+///
+/// impl ReconfigurableFactory for MyHttpFilterFactory {
+///     type Config = MyConfig;
+///     type Extension = MyHttpFilter;
+///
+///     fn name() -> &'static str { "my_http_filter" }
+///
+///     fn parse_config(&mut self, config: ByteString) -> Result<Self::Config> {
+///         Ok(MyConfig {
+///             upstream: String::from_utf8(config.into_bytes())?,
+///         })
+///     }
+///
+///     fn on_config_change(
+///         &mut self,
+///         change: ConfigChange<'_, Self::Config>,
+///         _ops: &dyn ConfigureOps,
+///     ) -> Result<ConfigStatus> {
+///         if change.changed(|config| &config.upstream) && change.current().upstream.is_empty() {
+///             return Ok(ConfigStatus::Rejected);
+///         }
+///         Ok(ConfigStatus::Accepted)
+///     }
+///
+///     fn new_extension(&mut self, config: Rc<Self::Config>, _instance_id: InstanceId) -> Result<Self::Extension> {
+///         Ok(MyHttpFilter { config })
+///     }
+/// }
+///
+/// fn initialize() -> Result<Module> {
+///     Module::new()
+///         .add_http_filter(|_instance_id| Ok(Reconfigurable::new(MyHttpFilterFactory)))
+/// }
+/// ```
+///
+/// [`ExtensionFactory::on_configure`]: trait.ExtensionFactory.html#method.on_configure
+/// [`ReconfigurableFactory`]: trait.ReconfigurableFactory.html
+/// [`Reconfigurable`]: struct.Reconfigurable.html
+/// [`Module`]: ../struct.Module.html
+pub trait ReconfigurableFactory {
+    type Config;
+    type Extension;
+
+    /// Returns a name the extension should be referred to in `Envoy` configuration.
+    fn name() -> &'static str
+    where
+        Self: Sized;
+
+    /// Parses raw configuration received from `Envoy`.
+    ///
+    /// Returning an error rejects the configuration.
+    fn parse_config(&mut self, config: ByteString) -> Result<Self::Config>;
+
+    /// Called once a new configuration has been parsed, before it replaces
+    /// the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `_change` - the previous configuration, if any, alongside the new one.
+    /// * `_ops`    - a [`trait object`][`ConfigureOps`] with operations available in this context.
+    ///
+    /// # Return value
+    ///
+    /// [`ConfigStatus`] telling whether the new configuration should replace the current one.
+    ///
+    /// [`ConfigStatus`]: enum.ConfigStatus.html
+    /// [`ConfigureOps`]: trait.ConfigureOps.html
+    fn on_config_change(
+        &mut self,
+        _change: ConfigChange<'_, Self::Config>,
+        _ops: &dyn ConfigureOps,
+    ) -> Result<ConfigStatus> {
+        Ok(ConfigStatus::Accepted)
+    }
+
+    /// Called to create a new instance of the extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`      - configuration that is current at the moment.
+    /// * `instance_id` - opaque identifier of the extension instance.
+    fn new_extension(
+        &mut self,
+        config: Rc<Self::Config>,
+        instance_id: InstanceId,
+    ) -> Result<Self::Extension>;
+
+    /// Returns a policy that defines how to handle errors returned by extension instances,
+    /// as well as failures to create them.
+    fn failure_policy(&self, _config: &Self::Config) -> FailurePolicy {
+        FailurePolicy::default()
+    }
+
+    /// Called when `ExtensionFactory` is about to be destroyed.
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        Ok(DrainStatus::Complete)
+    }
+}
+
+/// The previous configuration of an extension alongside the new one.
+#[derive(Debug)]
+pub struct ConfigChange<'a, C> {
+    previous: Option<&'a C>,
+    current: &'a C,
+}
+
+impl<'a, C> Clone for ConfigChange<'a, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, C> Copy for ConfigChange<'a, C> {}
+
+impl<'a, C> ConfigChange<'a, C> {
+    /// Creates a new change from the previous configuration, if any, to the new one.
+    pub fn new(previous: Option<&'a C>, current: &'a C) -> Self {
+        ConfigChange { previous, current }
+    }
+
+    /// Returns the configuration that is being replaced, if any.
+    pub fn previous(&self) -> Option<&'a C> {
+        self.previous
+    }
+
+    /// Returns the new configuration.
+    pub fn current(&self) -> &'a C {
+        self.current
+    }
+
+    /// Returns `true` if this is the very first configuration of the extension.
+    pub fn is_initial(&self) -> bool {
+        self.previous.is_none()
+    }
+
+    /// Returns `true` if a part of configuration selected by a given function
+    /// differs from the previous configuration.
+    ///
+    /// The very first configuration is always considered changed.
+    pub fn changed<T, F>(&self, f: F) -> bool
+    where
+        T: PartialEq + ?Sized,
+        F: Fn(&C) -> &T,
+    {
+        self.previous
+            .is_none_or(|previous| f(previous) != f(self.current))
+    }
+}
+
+/// Adapts a [`ReconfigurableFactory`] to [`ExtensionFactory`].
+///
+/// The shared configuration only gets replaced once the new one has been parsed
+/// and accepted. Extension instances created earlier keep using the configuration
+/// they have been created with until they finish.
+///
+/// [`ReconfigurableFactory`]: trait.ReconfigurableFactory.html
+/// [`ExtensionFactory`]: trait.ExtensionFactory.html
+pub struct Reconfigurable<F>
+where
+    F: ReconfigurableFactory,
+{
+    factory: F,
+    config: Option<Rc<F::Config>>,
+}
+
+impl<F> Reconfigurable<F>
+where
+    F: ReconfigurableFactory,
+{
+    /// Wraps a given factory.
+    pub fn new(factory: F) -> Self {
+        Reconfigurable {
+            factory,
+            config: None,
+        }
+    }
+
+    /// Returns the wrapped factory.
+    pub fn factory(&self) -> &F {
+        &self.factory
+    }
+
+    /// Returns the current configuration, if any.
+    pub fn config(&self) -> Option<&Rc<F::Config>> {
+        self.config.as_ref()
+    }
+}
+
+impl<F> ExtensionFactory for Reconfigurable<F>
+where
+    F: ReconfigurableFactory,
+{
+    type Extension = F::Extension;
+
+    fn name() -> &'static str
+    where
+        Self: Sized,
+    {
+        F::name()
+    }
+
+    fn on_configure(&mut self, config: ByteString, ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+        let config = self.factory.parse_config(config)?;
+        let change = ConfigChange::new(self.config.as_deref(), &config);
+        let status = self.factory.on_config_change(change, ops)?;
+        if status == ConfigStatus::Accepted {
+            // extension instances created earlier keep their own reference
+            self.config = Some(Rc::new(config));
+        }
+        Ok(status)
+    }
+
+    fn new_extension(&mut self, instance_id: InstanceId) -> Result<Self::Extension> {
+        match &self.config {
+            Some(config) => self.factory.new_extension(Rc::clone(config), instance_id),
+            None => Err(format_err!("extension has not been configured yet")),
+        }
+    }
+
+    fn failure_policy(&self) -> FailurePolicy {
+        match &self.config {
+            Some(config) => self.factory.failure_policy(config),
+            None => FailurePolicy::default(),
+        }
+    }
+
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        self.factory.on_drain()
+    }
+}
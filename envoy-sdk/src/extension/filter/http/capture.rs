@@ -0,0 +1,219 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Capturing of request/response body for `Access Loggers`.
+
+use super::{RequestBodyOps, ResponseBodyOps};
+use crate::host::stream_info::{FilterStateOptions, LifeSpan};
+use crate::host::{self, StreamInfo};
+
+/// Name of the filter state entry that holds captured request body.
+pub(crate) const REQUEST_BODY_KEY: &str = "envoy_sdk.request_body";
+
+/// Name of the filter state entry that holds captured response body.
+pub(crate) const RESPONSE_BODY_KEY: &str = "envoy_sdk.response_body";
+
+/// Captures bounded prefixes of request and response body so that they are
+/// available to `Access Loggers` through [`LogOps::request_body`] and
+/// [`LogOps::response_body`].
+///
+/// Body is not available to `Access Loggers` otherwise, so capturing is opt-in:
+/// an [`HttpFilter`] has to feed body chunks into `BodyCapture`. Captured
+/// prefixes are kept in the filter state of the stream under read-only entries
+/// that have to be [`declared`] beforehand, e.g. when extension is being configured.
+///
+/// A prefix is saved once it is complete, i.e. either at the end of the stream
+/// or when the limit is reached. If the stream gets reset before that,
+/// nothing is saved.
+///
+/// `BodyCapture` expects every call to [`on_request_body`] and [`on_response_body`]
+/// to see a new chunk of data, i.e. the filter should not buffer the body.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{HttpFilter, Result};
+/// use envoy::extension::filter::http::{
+///     BodyCapture, FilterDataStatus, RequestBodyOps, ResponseBodyOps,
+/// };
+/// use envoy::host::StreamInfo;
+///
+/// // e.g., in `ExtensionFactory::on_configure`
+/// fn on_configure() -> Result<()> {
+///     BodyCapture::declare(StreamInfo::default())?;
+///     Ok(())
+/// }
+///
+/// struct AuditFilter<'a> {
+///     capture: BodyCapture<'a>,
+/// }
+///
+/// impl<'a> AuditFilter<'a> {
+///     fn new() -> Self {
+///         // capture up to 1KiB of request body and 256 bytes of response body
+///         AuditFilter { capture: BodyCapture::default(1024, 256) }
+///     }
+/// }
+///
+/// impl<'a> HttpFilter for AuditFilter<'a> {
+///     fn on_request_body(&mut self, data_size: usize, end_of_stream: bool, ops: &dyn RequestBodyOps) -> Result<FilterDataStatus> {
+///         self.capture.on_request_body(data_size, end_of_stream, ops)?;
+///         Ok(FilterDataStatus::Continue)
+///     }
+///
+///     fn on_response_body(&mut self, data_size: usize, end_of_stream: bool, ops: &dyn ResponseBodyOps) -> Result<FilterDataStatus> {
+///         self.capture.on_response_body(data_size, end_of_stream, ops)?;
+///         Ok(FilterDataStatus::Continue)
+///     }
+/// }
+/// ```
+///
+/// [`HttpFilter`]: trait.HttpFilter.html
+/// [`declared`]: #method.declare
+/// [`LogOps::request_body`]: ../../access_logger/trait.LogOps.html#method.request_body
+/// [`LogOps::response_body`]: ../../access_logger/trait.LogOps.html#method.response_body
+/// [`on_request_body`]: #method.on_request_body
+/// [`on_response_body`]: #method.on_response_body
+pub struct BodyCapture<'a> {
+    stream_info: &'a dyn StreamInfo,
+    request: Prefix,
+    response: Prefix,
+}
+
+/// Bounded prefix of a body.
+struct Prefix {
+    limit: usize,
+    data: Vec<u8>,
+    is_complete: bool,
+}
+
+impl<'a> BodyCapture<'a> {
+    /// Declares filter state entries that hold captured body.
+    ///
+    /// Entries are read-only and kept for the entire downstream request,
+    /// so that they are still around when `Access Loggers` get called.
+    pub fn declare(stream_info: &dyn StreamInfo) -> host::Result<()> {
+        let options = FilterStateOptions::new()
+            .life_span(LifeSpan::DownstreamRequest)
+            .read_only(true);
+        stream_info.declare_stream_property(REQUEST_BODY_KEY, options)?;
+        stream_info.declare_stream_property(RESPONSE_BODY_KEY, options)
+    }
+
+    /// Creates a new capture that stores body prefixes through a given [`StreamInfo`].
+    ///
+    /// # Arguments
+    ///
+    /// * `request_limit`  - maximum number of request body bytes to capture.
+    /// * `response_limit` - maximum number of response body bytes to capture.
+    ///
+    /// [`StreamInfo`]: ../../../host/stream_info/trait.StreamInfo.html
+    pub fn new(
+        stream_info: &'a dyn StreamInfo,
+        request_limit: usize,
+        response_limit: usize,
+    ) -> Self {
+        BodyCapture {
+            stream_info,
+            request: Prefix::new(request_limit),
+            response: Prefix::new(response_limit),
+        }
+    }
+
+    /// Creates a new capture bound to the actual `Envoy` ABI.
+    pub fn default(request_limit: usize, response_limit: usize) -> Self {
+        Self::new(<dyn StreamInfo>::default(), request_limit, response_limit)
+    }
+
+    /// Captures a chunk of request body.
+    pub fn on_request_body(
+        &mut self,
+        data_size: usize,
+        end_of_stream: bool,
+        ops: &dyn RequestBodyOps,
+    ) -> host::Result<()> {
+        let stream_info = self.stream_info;
+        self.request.capture(
+            data_size,
+            end_of_stream,
+            |max_size| ops.request_data(0, max_size).map(|data| data.into_bytes()),
+            |data| stream_info.set_stream_property(&[REQUEST_BODY_KEY], data),
+        )
+    }
+
+    /// Captures a chunk of response body.
+    pub fn on_response_body(
+        &mut self,
+        data_size: usize,
+        end_of_stream: bool,
+        ops: &dyn ResponseBodyOps,
+    ) -> host::Result<()> {
+        let stream_info = self.stream_info;
+        self.response.capture(
+            data_size,
+            end_of_stream,
+            |max_size| ops.response_data(0, max_size).map(|data| data.into_bytes()),
+            |data| stream_info.set_stream_property(&[RESPONSE_BODY_KEY], data),
+        )
+    }
+
+    /// Returns request body captured so far.
+    pub fn request_body(&self) -> &[u8] {
+        &self.request.data
+    }
+
+    /// Returns response body captured so far.
+    pub fn response_body(&self) -> &[u8] {
+        &self.response.data
+    }
+}
+
+impl Prefix {
+    fn new(limit: usize) -> Self {
+        Prefix {
+            limit,
+            data: Vec::new(),
+            is_complete: limit == 0,
+        }
+    }
+
+    fn capture<R, S>(
+        &mut self,
+        data_size: usize,
+        end_of_stream: bool,
+        read: R,
+        store: S,
+    ) -> host::Result<()>
+    where
+        R: FnOnce(usize) -> host::Result<Vec<u8>>,
+        S: FnOnce(&[u8]) -> host::Result<()>,
+    {
+        if self.is_complete {
+            return Ok(());
+        }
+        let max_size = data_size.min(self.limit - self.data.len());
+        if max_size > 0 {
+            let chunk = read(max_size)?;
+            self.data
+                .extend_from_slice(&chunk[..chunk.len().min(max_size)]);
+        }
+        self.is_complete = end_of_stream || self.data.len() >= self.limit;
+        // filter state entry is read-only, so it can only be saved once
+        if self.is_complete {
+            store(&self.data)?;
+        }
+        Ok(())
+    }
+}
@@ -16,8 +16,11 @@ use crate::abi::proxy_wasm::traits::{Context, HttpContext};
 use crate::abi::proxy_wasm::types::Action;
 
 use super::{FilterDataStatus, FilterHeadersStatus, FilterTrailersStatus, HttpFilter, Ops};
-use crate::extension::error::ErrorSink;
-use crate::extension::Error;
+use crate::abi::proxy_wasm::scope::ChildScope;
+use crate::extension::error::{ErrorCategory, ErrorReporter};
+use crate::extension::filter::{FailurePolicy, LocalReply};
+use crate::extension::isolation::Isolation;
+use crate::extension::{Error, ErrorContext, Result};
 use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
 
 pub(crate) struct HttpFilterContext<'a, F>
@@ -27,7 +30,12 @@ where
     filter: F,
     filter_ops: &'a dyn Ops,
     http_client_ops: &'a dyn HttpClientResponseOps,
-    error_sink: &'a dyn ErrorSink,
+    error_reporter: ErrorReporter,
+    failure_policy: FailurePolicy,
+    isolation: Isolation,
+    scope: ChildScope,
+    is_response_started: bool,
+    is_paused: bool,
 }
 
 impl<'a, F> HttpContext for HttpFilterContext<'a, F>
@@ -35,97 +43,130 @@ where
     F: HttpFilter,
 {
     fn on_http_request_headers(&mut self, num_headers: usize, end_of_stream: bool) -> Action {
-        match self.filter.on_request_headers(
-            num_headers,
-            end_of_stream,
-            self.filter_ops.as_request_headers_ops(),
-        ) {
+        let action = match self.call(|filter, ops| {
+            filter.on_request_headers(num_headers, end_of_stream, ops.as_request_headers_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle HTTP request headers", &err);
-                self.handle_error(err);
-                FilterHeadersStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle HTTP request headers",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterHeadersStatus::Continue.as_action()
+                } else {
+                    FilterHeadersStatus::StopIteration.as_action()
+                }
             }
-        }
+        };
+        self.track(action)
     }
 
     fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
-        match self.filter.on_request_body(
-            body_size,
-            end_of_stream,
-            self.filter_ops.as_request_body_ops(),
-        ) {
+        let action = match self.call(|filter, ops| {
+            filter.on_request_body(body_size, end_of_stream, ops.as_request_body_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle HTTP request body", &err);
-                self.handle_error(err);
-                FilterDataStatus::StopIterationAndBuffer.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle HTTP request body",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterDataStatus::Continue.as_action()
+                } else {
+                    FilterDataStatus::StopIterationAndBuffer.as_action()
+                }
             }
-        }
+        };
+        self.track(action)
     }
 
     fn on_http_request_trailers(&mut self, num_trailers: usize) -> Action {
-        match self
-            .filter
-            .on_request_trailers(num_trailers, self.filter_ops.as_request_trailers_ops())
-        {
+        let action = match self.call(|filter, ops| {
+            filter.on_request_trailers(num_trailers, ops.as_request_trailers_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle HTTP request trailers", &err);
-                self.handle_error(err);
-                FilterTrailersStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle HTTP request trailers",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterTrailersStatus::Continue.as_action()
+                } else {
+                    FilterTrailersStatus::StopIteration.as_action()
+                }
             }
-        }
+        };
+        self.track(action)
     }
 
     fn on_http_response_headers(&mut self, num_headers: usize, end_of_stream: bool) -> Action {
-        match self.filter.on_response_headers(
-            num_headers,
-            end_of_stream,
-            self.filter_ops.as_response_headers_ops(),
-        ) {
+        self.is_response_started = true;
+        let action = match self.call(|filter, ops| {
+            filter.on_response_headers(num_headers, end_of_stream, ops.as_response_headers_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle HTTP response headers", &err);
-                self.handle_error(err);
-                FilterHeadersStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle HTTP response headers",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterHeadersStatus::Continue.as_action()
+                } else {
+                    FilterHeadersStatus::StopIteration.as_action()
+                }
             }
-        }
+        };
+        self.track(action)
     }
 
     fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
-        match self.filter.on_response_body(
-            body_size,
-            end_of_stream,
-            self.filter_ops.as_response_body_ops(),
-        ) {
+        let action = match self.call(|filter, ops| {
+            filter.on_response_body(body_size, end_of_stream, ops.as_response_body_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle HTTP response body", &err);
-                self.handle_error(err);
-                FilterDataStatus::StopIterationAndBuffer.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle HTTP response body",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterDataStatus::Continue.as_action()
+                } else {
+                    FilterDataStatus::StopIterationAndBuffer.as_action()
+                }
             }
-        }
+        };
+        self.track(action)
     }
 
     fn on_http_response_trailers(&mut self, num_trailers: usize) -> Action {
-        match self
-            .filter
-            .on_response_trailers(num_trailers, self.filter_ops.as_response_trailers_ops())
-        {
+        let action = match self.call(|filter, ops| {
+            filter.on_response_trailers(num_trailers, ops.as_response_trailers_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle HTTP response trailers", &err);
-                self.handle_error(err);
-                FilterTrailersStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle HTTP response trailers",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterTrailersStatus::Continue.as_action()
+                } else {
+                    FilterTrailersStatus::StopIteration.as_action()
+                }
             }
-        }
+        };
+        self.track(action)
     }
 }
 
@@ -134,12 +175,14 @@ where
     F: HttpFilter,
 {
     fn on_done(&mut self) -> bool {
-        if let Err(err) = self
-            .filter
-            .on_exchange_complete(self.filter_ops.as_exchange_complete_ops())
+        if let Err(err) =
+            self.call(|filter, ops| filter.on_exchange_complete(ops.as_exchange_complete_ops()))
         {
-            self.error_sink
-                .observe("failed to handle completion of an HTTP stream", &err);
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to handle completion of an HTTP stream",
+                &err,
+            );
             // HTTP stream is already being terminated, so there is no need to do it explicitly
         }
         true
@@ -154,19 +197,25 @@ where
         body_size: usize,
         num_trailers: usize,
     ) {
-        if let Err(err) = self.filter.on_http_call_response(
-            HttpClientRequestHandle::from(token_id),
-            num_headers,
-            body_size,
-            num_trailers,
-            self.filter_ops,
-            self.http_client_ops,
-        ) {
-            self.error_sink.observe(
+        let http_client_ops = self.http_client_ops;
+        if let Err(err) = self.call(|filter, ops| {
+            filter.on_http_call_response(
+                HttpClientRequestHandle::from(token_id),
+                num_headers,
+                body_size,
+                num_trailers,
+                ops,
+                http_client_ops,
+            )
+        }) {
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
                 "failed to process a response to an HTTP request made by the extension",
                 &err,
             );
-            self.handle_error(err);
+            if self.handle_error(err) && self.is_paused {
+                self.resume();
+            }
         }
     }
 }
@@ -179,36 +228,127 @@ where
         filter: F,
         filter_ops: &'a dyn Ops,
         http_client_ops: &'a dyn HttpClientResponseOps,
-        error_sink: &'a dyn ErrorSink,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+        isolation: Isolation,
+        scope: ChildScope,
     ) -> Self {
         HttpFilterContext {
             filter,
             filter_ops,
             http_client_ops,
-            error_sink,
+            error_reporter,
+            failure_policy,
+            isolation,
+            scope,
+            is_response_started: false,
+            is_paused: false,
         }
     }
 
     /// Creates a new HTTP filter context bound to the actual Envoy ABI.
-    pub fn with_default_ops(filter: F) -> Self {
+    pub fn with_default_ops(
+        filter: F,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+        isolation: Isolation,
+        scope: ChildScope,
+    ) -> Self {
         Self::new(
             filter,
-            Ops::default(),
-            HttpClientResponseOps::default(),
-            ErrorSink::default(),
+            <dyn Ops>::default(),
+            <dyn HttpClientResponseOps>::default(),
+            error_reporter,
+            failure_policy,
+            isolation,
+            scope,
         )
     }
 
-    fn handle_error(&self, _err: Error) {
-        if let Err(err) = self.filter_ops.send_response(500, &[], None) {
-            self.error_sink.observe(
-                "failed to terminate processing of the HTTP request: failed to send a direct reply",
+    /// Calls the filter unless it has panicked earlier.
+    fn call<T, C>(&mut self, callback: C) -> Result<T>
+    where
+        C: FnOnce(&mut F, &'a dyn Ops) -> Result<T>,
+    {
+        let (filter, filter_ops, isolation) = (&mut self.filter, self.filter_ops, &self.isolation);
+        self.scope
+            .enter(|| isolation.call(|| callback(filter, filter_ops)))
+    }
+
+    /// Applies the failure policy.
+    ///
+    /// Returns `true` if processing of the HTTP stream should continue.
+    fn handle_error(&self, _err: Error) -> bool {
+        apply_failure_policy(&self.failure_policy, self.filter_ops, &self.error_reporter)
+    }
+
+    /// Remembers whether processing of the HTTP stream has been paused.
+    fn track(&mut self, action: Action) -> Action {
+        self.is_paused = action == Action::Pause;
+        action
+    }
+
+    /// Resumes processing of the HTTP stream that has been paused
+    /// in anticipation of a response to an HTTP request made by the extension.
+    fn resume(&mut self) {
+        self.is_paused = false;
+        let result = if self.is_response_started {
+            self.filter_ops.resume_response()
+        } else {
+            self.filter_ops.resume_request()
+        };
+        if let Err(err) = result {
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to resume processing of the HTTP stream",
                 &err,
             );
         }
     }
 }
 
+/// Terminates processing of the HTTP stream unless the policy is to continue.
+///
+/// Returns `true` if processing of the HTTP stream should continue.
+fn apply_failure_policy(
+    failure_policy: &FailurePolicy,
+    filter_ops: &dyn Ops,
+    error_reporter: &ErrorReporter,
+) -> bool {
+    let result = match failure_policy {
+        FailurePolicy::Continue => return true,
+        FailurePolicy::LocalReply(reply) => send_local_reply(filter_ops, reply),
+        FailurePolicy::Reset => filter_ops.reset_stream().or_else(|err| {
+            // stream must be terminated one way or another
+            error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to reset the HTTP stream, sending a local reply instead",
+                &err,
+            );
+            send_local_reply(filter_ops, &LocalReply::default())
+        }),
+    };
+    if let Err(err) = result {
+        error_reporter.observe(
+            ErrorCategory::Callback,
+            "failed to terminate processing of the HTTP request",
+            &err,
+        );
+    }
+    false
+}
+
+fn send_local_reply(filter_ops: &dyn Ops, reply: &LocalReply) -> Result<()> {
+    let headers: Vec<(&str, &str)> = reply
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    filter_ops
+        .send_response(reply.status_code, &headers, reply.body.as_deref())
+        .context("failed to send a direct reply")
+}
+
 /// Fake `Proxy Wasm` [`HttpContext`] that is used to postpone error handling
 /// until a proper moment in the request lifecycle.
 ///
@@ -227,35 +367,47 @@ where
 pub(crate) struct VoidHttpFilterContext<'a> {
     err: Error,
     filter_ops: &'a dyn Ops,
-    error_sink: &'a dyn ErrorSink,
+    error_reporter: ErrorReporter,
+    failure_policy: FailurePolicy,
 }
 
 impl<'a> VoidHttpFilterContext<'a> {
-    pub fn new(err: Error, filter_ops: &'a dyn Ops, error_sink: &'a dyn ErrorSink) -> Self {
+    pub fn new(
+        err: Error,
+        filter_ops: &'a dyn Ops,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+    ) -> Self {
         VoidHttpFilterContext {
             err,
             filter_ops,
-            error_sink,
+            error_reporter,
+            failure_policy,
         }
     }
 
     /// Creates a new HTTP filter context bound to the actual Envoy ABI.
-    pub fn with_default_ops(err: Error) -> Self {
-        Self::new(err, Ops::default(), ErrorSink::default())
+    pub fn with_default_ops(
+        err: Error,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+    ) -> Self {
+        Self::new(err, <dyn Ops>::default(), error_reporter, failure_policy)
     }
 }
 
 impl<'a> HttpContext for VoidHttpFilterContext<'a> {
     fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
-        self.error_sink
-            .observe("failed to create Proxy Wasm Http Context", &self.err);
-        if let Err(err) = self.filter_ops.send_response(500, &[], None) {
-            self.error_sink.observe(
-                "failed to terminate processing of the HTTP request: failed to send a direct reply",
-                &err,
-            );
+        self.error_reporter.observe(
+            ErrorCategory::ExtensionCreation,
+            "failed to create Proxy Wasm Http Context",
+            &self.err,
+        );
+        if apply_failure_policy(&self.failure_policy, self.filter_ops, &self.error_reporter) {
+            FilterHeadersStatus::Continue.as_action()
+        } else {
+            FilterHeadersStatus::StopIteration.as_action()
         }
-        FilterHeadersStatus::StopIteration.as_action()
     }
 }
 
@@ -100,15 +100,22 @@
 //! [`Register`]: ../../../macro.entrypoint.html
 
 use crate::abi::proxy_wasm::types::Action;
+use crate::error::format_err;
 use crate::extension::Result;
 use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
 use crate::host::{self, ByteString, HeaderMap};
 
+pub use self::capture::BodyCapture;
+
+pub(crate) use self::capture::{REQUEST_BODY_KEY, RESPONSE_BODY_KEY};
 pub(crate) use self::context::{HttpFilterContext, VoidHttpFilterContext};
 
+mod capture;
 mod context;
 mod ops;
 
+pub mod ratelimit;
+
 /// Return codes for [`on_request_headers`] and [`on_response_headers`] filter
 /// invocations.
 ///
@@ -523,6 +530,18 @@ pub trait RequestFlowOps {
         headers: &[(&str, &str)],
         body: Option<&[u8]>,
     ) -> host::Result<()>;
+
+    /// Resets the HTTP stream, i.e. terminates it without sending a response.
+    ///
+    /// Operations bound to `Envoy` always support it. A custom implementation
+    /// that keeps the default one fails to reset the stream, in which case
+    /// [`FailurePolicy::Reset`] reports the error and terminates the stream
+    /// with a `500` local reply instead.
+    ///
+    /// [`FailurePolicy::Reset`]: ../enum.FailurePolicy.html#variant.Reset
+    fn reset_stream(&self) -> host::Result<()> {
+        Err(format_err!("resetting HTTP stream is not supported"))
+    }
 }
 
 /// An interface for manipulating response headers.
@@ -135,6 +135,10 @@ impl RequestFlowOps for Host {
     ) -> host::Result<()> {
         hostcalls::send_http_response(status_code, headers, body)
     }
+
+    fn reset_stream(&self) -> host::Result<()> {
+        hostcalls::reset_http_stream()
+    }
 }
 
 impl ResponseFlowOps for Host {
@@ -0,0 +1,162 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extraction of rate limit keys out of HTTP requests.
+
+use std::borrow::Cow;
+use std::net::SocketAddr;
+
+use crate::extension::filter::http::RequestHeadersOps;
+use crate::host::{self, StreamInfo};
+
+/// Source of a descriptor entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EntrySource {
+    RequestHeader(String),
+    SourceAddress,
+    RouteName,
+    Constant(String),
+}
+
+/// Describes how to build a rate limit key out of an HTTP request,
+/// similar to `Envoy` [`rate limit actions`].
+///
+/// A key consists of one or more `key=value` entries separated by `|`.
+/// Occurrences of `%`, `=` and `|` inside keys and values are percent-encoded,
+/// so that different requests never resolve into the same key by accident.
+/// If any of the values is not available, e.g. a request header is missing,
+/// the request is not subject to the rate limit.
+///
+/// Every distinct key gets its own entry in `SharedData` that lives as long
+/// as the `Envoy` instance does. Values of request headers and client addresses
+/// are controlled by clients, so an entry with such a value should only be used
+/// where the number of distinct values is bounded, e.g. by authentication
+/// that happens earlier in the filter chain.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::filter::http::ratelimit::Descriptor;
+///
+/// // limit every API key on every route separately
+/// let descriptor = Descriptor::new()
+///     .route_name("route")
+///     .request_header("api_key", "x-api-key");
+/// ```
+///
+/// [`rate limit actions`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/config/route/v3/route_components.proto#config-route-v3-ratelimit-action
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Descriptor {
+    entries: Vec<(String, EntrySource)>,
+}
+
+impl Descriptor {
+    /// Creates a new descriptor without entries.
+    ///
+    /// A descriptor without entries makes all requests share the same limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entry with a value of a given request header.
+    ///
+    /// Notice that every distinct header value sent by clients results
+    /// in a separate rate limit state.
+    pub fn request_header<K, N>(self, key: K, header_name: N) -> Self
+    where
+        K: Into<String>,
+        N: Into<String>,
+    {
+        self.entry(key, EntrySource::RequestHeader(header_name.into()))
+    }
+
+    /// Adds an entry with an IP address of the downstream client.
+    pub fn source_address<K: Into<String>>(self, key: K) -> Self {
+        self.entry(key, EntrySource::SourceAddress)
+    }
+
+    /// Adds an entry with a name of the route.
+    pub fn route_name<K: Into<String>>(self, key: K) -> Self {
+        self.entry(key, EntrySource::RouteName)
+    }
+
+    /// Adds an entry with a given value.
+    pub fn constant<K, V>(self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.entry(key, EntrySource::Constant(value.into()))
+    }
+
+    /// Builds a rate limit key for a given request.
+    ///
+    /// Returns `None` if any of the entries is not available.
+    pub fn resolve(
+        &self,
+        request: &dyn RequestHeadersOps,
+        stream_info: &dyn StreamInfo,
+    ) -> host::Result<Option<String>> {
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for (key, source) in &self.entries {
+            let value = match source {
+                EntrySource::RequestHeader(name) => {
+                    request.request_header(name)?.map(|value| value.to_string())
+                }
+                EntrySource::SourceAddress => stream_info
+                    .source()
+                    .address()?
+                    .map(|address| strip_port(&address)),
+                EntrySource::RouteName => stream_info.route().name()?,
+                EntrySource::Constant(value) => Some(value.clone()),
+            };
+            match value {
+                Some(value) => entries.push(format!("{}={}", escape(key), escape(&value))),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(entries.join("|")))
+    }
+
+    fn entry<K: Into<String>>(mut self, key: K, source: EntrySource) -> Self {
+        self.entries.push((key.into(), source));
+        self
+    }
+}
+
+/// Percent-encodes characters that have a special meaning in a rate limit key.
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['%', '=', '|']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 4);
+    for c in text.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '=' => escaped.push_str("%3D"),
+            '|' => escaped.push_str("%7C"),
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Strips port from a socket address, if any.
+fn strip_port(address: &str) -> String {
+    match address.parse::<SocketAddr>() {
+        Ok(address) => address.ip().to_string(),
+        Err(_) => address.to_owned(),
+    }
+}
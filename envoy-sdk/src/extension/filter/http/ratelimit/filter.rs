@@ -0,0 +1,231 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `HTTP Filter` that enforces local rate limits.
+
+use std::rc::Rc;
+
+use super::{Descriptor, RateLimit, RateLimitDecision, RateLimiter};
+use crate::extension::filter::http::{
+    FilterHeadersStatus, HttpFilter, RequestHeadersOps, ResponseHeadersOps,
+};
+use crate::extension::filter::FailurePolicy;
+use crate::extension::{ExtensionFactory, InstanceId, Result};
+use crate::host::{Clock, SharedData, StreamInfo};
+
+/// Body of the response to rate limited requests.
+const RATE_LIMITED_BODY: &[u8] = b"local_rate_limited";
+
+/// A rate limit applied to HTTP requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    name: String,
+    limit: RateLimit,
+    descriptor: Descriptor,
+}
+
+impl RateLimitPolicy {
+    /// Creates a new policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`       - name of the policy; policies with the same name share the same state.
+    /// * `limit`      - limit to enforce.
+    /// * `descriptor` - describes how to build a rate limit key out of a request.
+    pub fn new<N: Into<String>>(name: N, limit: RateLimit, descriptor: Descriptor) -> Self {
+        RateLimitPolicy {
+            name: name.into(),
+            limit,
+            descriptor,
+        }
+    }
+
+    /// Returns name of the policy.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the limit.
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Returns the descriptor.
+    pub fn descriptor(&self) -> &Descriptor {
+        &self.descriptor
+    }
+}
+
+/// [`ExtensionFactory`] for [`RateLimitFilter`].
+///
+/// Requests that fail to be checked against rate limits, e.g. due to unavailable
+/// [`SharedData`], are let through.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use std::time::Duration;
+/// use envoy::extension::{entrypoint, Module, Result};
+/// use envoy::extension::filter::http::ratelimit::{
+///     Descriptor, RateLimit, RateLimitFilterFactory, RateLimitPolicy,
+/// };
+///
+/// entrypoint! { initialize }
+///
+/// fn initialize() -> Result<Module> {
+///     Module::new().add_http_filter(|_instance_id| {
+///         Ok(RateLimitFilterFactory::default(vec![RateLimitPolicy::new(
+///             "per_client",
+///             RateLimit::sliding_window(100, Duration::from_secs(60)),
+///             Descriptor::new().source_address("client"),
+///         )]))
+///     })
+/// }
+/// ```
+///
+/// [`ExtensionFactory`]: ../../../factory/trait.ExtensionFactory.html
+/// [`RateLimitFilter`]: struct.RateLimitFilter.html
+/// [`SharedData`]: ../../../../host/shared_data/trait.SharedData.html
+pub struct RateLimitFilterFactory<'a> {
+    policies: Rc<Vec<RateLimitPolicy>>,
+    shared_data: &'a dyn SharedData,
+    clock: &'a dyn Clock,
+    stream_info: &'a dyn StreamInfo,
+}
+
+impl<'a> RateLimitFilterFactory<'a> {
+    /// Creates a new factory.
+    pub fn new(
+        policies: Vec<RateLimitPolicy>,
+        shared_data: &'a dyn SharedData,
+        clock: &'a dyn Clock,
+        stream_info: &'a dyn StreamInfo,
+    ) -> Self {
+        RateLimitFilterFactory {
+            policies: Rc::new(policies),
+            shared_data,
+            clock,
+            stream_info,
+        }
+    }
+
+    /// Creates a new factory bound to the actual `Envoy` ABI.
+    pub fn default(policies: Vec<RateLimitPolicy>) -> Self {
+        Self::new(
+            policies,
+            <dyn SharedData>::default(),
+            <dyn Clock>::default(),
+            <dyn StreamInfo>::default(),
+        )
+    }
+}
+
+impl<'a> ExtensionFactory for RateLimitFilterFactory<'a> {
+    type Extension = RateLimitFilter<'a>;
+
+    fn name() -> &'static str {
+        "envoy_sdk.ratelimit"
+    }
+
+    fn new_extension(&mut self, _instance_id: InstanceId) -> Result<Self::Extension> {
+        Ok(RateLimitFilter {
+            policies: Rc::clone(&self.policies),
+            shared_data: self.shared_data,
+            clock: self.clock,
+            stream_info: self.stream_info,
+            decision: None,
+        })
+    }
+
+    fn failure_policy(&self) -> FailurePolicy {
+        // fail open
+        FailurePolicy::Continue
+    }
+}
+
+/// `HTTP Filter` that rejects requests exceeding rate limits with `429 Too Many Requests`.
+///
+/// Every request is checked against all applicable [`RateLimitPolicy`]s.
+/// Responses carry `x-ratelimit-*` headers describing the most restrictive of them.
+///
+/// See [`RateLimitFilterFactory`].
+///
+/// [`RateLimitPolicy`]: struct.RateLimitPolicy.html
+/// [`RateLimitFilterFactory`]: struct.RateLimitFilterFactory.html
+pub struct RateLimitFilter<'a> {
+    policies: Rc<Vec<RateLimitPolicy>>,
+    shared_data: &'a dyn SharedData,
+    clock: &'a dyn Clock,
+    stream_info: &'a dyn StreamInfo,
+    decision: Option<RateLimitDecision>,
+}
+
+impl<'a> RateLimitFilter<'a> {
+    /// Returns the most restrictive decision made for the request, if any.
+    pub fn decision(&self) -> Option<RateLimitDecision> {
+        self.decision
+    }
+}
+
+impl<'a> HttpFilter for RateLimitFilter<'a> {
+    fn on_request_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        ops: &dyn RequestHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        for policy in self.policies.iter() {
+            let key = match policy.descriptor.resolve(ops, self.stream_info)? {
+                Some(key) => key,
+                None => continue,
+            };
+            let limiter =
+                RateLimiter::new(self.shared_data, self.clock, &policy.name, policy.limit);
+            let decision = limiter.check(&key)?;
+            self.decision = Some(match self.decision {
+                Some(previous) => previous.most_restrictive(decision),
+                None => decision,
+            });
+        }
+        match self.decision {
+            Some(decision) if !decision.is_allowed() => {
+                let headers = decision.headers();
+                let headers: Vec<(&str, &str)> = headers
+                    .iter()
+                    .map(|(name, value)| (*name, value.as_str()))
+                    .collect();
+                ops.send_response(429, &headers, Some(RATE_LIMITED_BODY))?;
+                Ok(FilterHeadersStatus::StopIteration)
+            }
+            _ => Ok(FilterHeadersStatus::Continue),
+        }
+    }
+
+    fn on_response_headers(
+        &mut self,
+        _num_headers: usize,
+        _end_of_stream: bool,
+        ops: &dyn ResponseHeadersOps,
+    ) -> Result<FilterHeadersStatus> {
+        if let Some(decision) = self.decision {
+            if decision.is_allowed() {
+                for (name, value) in decision.headers() {
+                    ops.set_response_header(name, &value)?;
+                }
+            }
+        }
+        Ok(FilterHeadersStatus::Continue)
+    }
+}
@@ -0,0 +1,369 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rate limiting state shared by all `Wasm VM`s through `SharedData`.
+
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+use crate::host::shared_data::SharedValue;
+use crate::host::{self, Clock, SharedData};
+
+/// Precision of fractional tokens.
+const TOKEN_SCALE: u128 = 1000;
+
+/// State of a limit: its meaning depends on the algorithm.
+///
+/// * token bucket:   (available tokens scaled by `TOKEN_SCALE`, last update time, unused)
+/// * sliding window: (start of the current window, current count, previous count)
+///
+/// All times are in milliseconds since UNIX epoch.
+type State = (u64, u64, u64);
+
+/// Algorithm and parameters of a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RateLimit {
+    /// A bucket that holds up to `capacity` tokens and gets `tokens_per_fill`
+    /// tokens every `fill_interval`. Every request takes a token.
+    ///
+    /// Allows bursts of up to `capacity` requests.
+    TokenBucket {
+        capacity: u64,
+        tokens_per_fill: u64,
+        fill_interval: Duration,
+    },
+    /// Up to `limit` requests within any `window`.
+    ///
+    /// The number of requests in a window is estimated out of the counts of
+    /// the current and the previous fixed windows.
+    SlidingWindow { limit: u64, window: Duration },
+}
+
+impl RateLimit {
+    /// Creates a token bucket limit.
+    pub fn token_bucket(capacity: u64, tokens_per_fill: u64, fill_interval: Duration) -> Self {
+        RateLimit::TokenBucket {
+            capacity,
+            tokens_per_fill,
+            fill_interval,
+        }
+    }
+
+    /// Creates a sliding window limit.
+    pub fn sliding_window(limit: u64, window: Duration) -> Self {
+        RateLimit::SlidingWindow { limit, window }
+    }
+
+    /// Returns the maximum number of requests allowed at once.
+    pub fn limit(&self) -> u64 {
+        match self {
+            RateLimit::TokenBucket { capacity, .. } => *capacity,
+            RateLimit::SlidingWindow { limit, .. } => *limit,
+        }
+    }
+
+    /// Applies a request of a given cost to a given state.
+    fn apply(&self, state: Option<State>, now: u64, cost: u64) -> (State, RateLimitDecision) {
+        match *self {
+            RateLimit::TokenBucket {
+                capacity,
+                tokens_per_fill,
+                fill_interval,
+            } => {
+                let interval = (millis(fill_interval) as u128).max(1);
+                let rate = (tokens_per_fill as u128 * TOKEN_SCALE).max(1);
+                let capacity_scaled = capacity as u128 * TOKEN_SCALE;
+                let cost_scaled = cost as u128 * TOKEN_SCALE;
+                // time (in milliseconds) it takes to refill a given number of scaled tokens
+                let time_to_fill = |tokens: u128| saturate((tokens * interval).div_ceil(rate));
+
+                let (tokens, updated_at) = match state {
+                    Some((tokens, updated_at, _)) => {
+                        // tolerate clock going backwards
+                        let elapsed = now.saturating_sub(updated_at) as u128;
+                        let refill = elapsed * rate / interval;
+                        (
+                            (tokens as u128 + refill).min(capacity_scaled),
+                            updated_at.max(now),
+                        )
+                    }
+                    None => (capacity_scaled, now),
+                };
+                let allowed = tokens >= cost_scaled;
+                let tokens = if allowed {
+                    tokens - cost_scaled
+                } else {
+                    tokens
+                };
+                let decision = RateLimitDecision {
+                    allowed,
+                    limit: capacity,
+                    remaining: saturate(tokens / TOKEN_SCALE),
+                    reset_after: Duration::from_millis(time_to_fill(capacity_scaled - tokens)),
+                    retry_after: if allowed {
+                        Duration::default()
+                    } else {
+                        Duration::from_millis(time_to_fill(cost_scaled - tokens))
+                    },
+                };
+                ((saturate(tokens), updated_at, 0), decision)
+            }
+            RateLimit::SlidingWindow { limit, window } => {
+                let window = millis(window).max(1);
+                let (start, current, previous) = match state {
+                    Some((start, current, previous)) if now >= start => {
+                        let elapsed_windows = (now - start) / window;
+                        let start = start + elapsed_windows * window;
+                        match elapsed_windows {
+                            0 => (start, current, previous),
+                            1 => (start, 0, current),
+                            _ => (start, 0, 0),
+                        }
+                    }
+                    // tolerate clock going backwards
+                    Some(state) => state,
+                    None => (now, 0, 0),
+                };
+                let elapsed = now.saturating_sub(start).min(window);
+                let estimated = saturate(
+                    (previous as u128 * (window - elapsed) as u128).div_ceil(window as u128),
+                )
+                .saturating_add(current);
+                let allowed = estimated.saturating_add(cost) <= limit;
+                let (current, estimated) = if allowed {
+                    (current + cost, estimated + cost)
+                } else {
+                    (current, estimated)
+                };
+                let window_end = start + window - now.min(start + window);
+                let decision = RateLimitDecision {
+                    allowed,
+                    limit,
+                    remaining: limit.saturating_sub(estimated),
+                    reset_after: Duration::from_millis(if current > 0 {
+                        window_end + window
+                    } else if previous > 0 {
+                        window_end
+                    } else {
+                        0
+                    }),
+                    retry_after: if allowed {
+                        Duration::default()
+                    } else {
+                        Duration::from_millis(window_end)
+                    },
+                };
+                ((start, current, previous), decision)
+            }
+        }
+    }
+}
+
+/// Outcome of a rate limit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    allowed: bool,
+    limit: u64,
+    remaining: u64,
+    reset_after: Duration,
+    retry_after: Duration,
+}
+
+impl RateLimitDecision {
+    /// Returns `true` if the request is allowed.
+    pub fn is_allowed(&self) -> bool {
+        self.allowed
+    }
+
+    /// Returns the maximum number of requests allowed at once.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the number of requests still allowed.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Returns time after which the quota will be fully replenished.
+    pub fn reset_after(&self) -> Duration {
+        self.reset_after
+    }
+
+    /// Returns time after which a rejected request could be retried.
+    ///
+    /// Zero if the request is allowed.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// Returns `x-ratelimit-*` response headers describing the decision.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("x-ratelimit-limit", self.limit.to_string()),
+            ("x-ratelimit-remaining", self.remaining.to_string()),
+            ("x-ratelimit-reset", seconds(self.reset_after).to_string()),
+        ];
+        if !self.allowed {
+            headers.push(("retry-after", seconds(self.retry_after).to_string()));
+        }
+        headers
+    }
+
+    /// Returns the most restrictive of two decisions.
+    pub(super) fn most_restrictive(self, other: Self) -> Self {
+        match (self.allowed, other.allowed) {
+            (true, false) => other,
+            (false, true) => self,
+            (false, false) if other.retry_after > self.retry_after => other,
+            (true, true) if other.remaining < self.remaining => other,
+            _ => self,
+        }
+    }
+}
+
+/// Rate limiter that keeps its state in [`SharedData`], so that all `Wasm VM`s
+/// (i.e., all worker threads) share the same quota.
+///
+/// Concurrent updates of the state are detected through the optimistic lock
+/// version of [`SharedData`] and retried up to a configured number of attempts.
+/// Notice that the very first update of a given key is not protected against a race,
+/// so a few extra requests might get through at that point.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use std::time::Duration;
+/// use envoy::host::{Clock, SharedData};
+/// use envoy::extension::filter::http::ratelimit::{RateLimit, RateLimiter};
+///
+/// // up to 100 requests per second with bursts of up to 200 requests
+/// let limiter = RateLimiter::new(
+///     SharedData::default(),
+///     Clock::default(),
+///     "per_api_key",
+///     RateLimit::token_bucket(200, 100, Duration::from_secs(1)),
+/// );
+///
+/// if !limiter.check("api_key=123")?.is_allowed() {
+///     // reject the request
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedData`]: ../../../../host/shared_data/trait.SharedData.html
+pub struct RateLimiter<'a> {
+    shared_data: &'a dyn SharedData,
+    clock: &'a dyn Clock,
+    name: String,
+    limit: RateLimit,
+    max_attempts: usize,
+}
+
+impl<'a> RateLimiter<'a> {
+    /// Default number of attempts to update the shared state before giving up.
+    pub const DEFAULT_MAX_ATTEMPTS: usize = 10;
+
+    /// Creates a new rate limiter.
+    ///
+    /// All rate limiters with the same name share the same state.
+    pub fn new<N: Into<String>>(
+        shared_data: &'a dyn SharedData,
+        clock: &'a dyn Clock,
+        name: N,
+        limit: RateLimit,
+    ) -> Self {
+        RateLimiter {
+            shared_data,
+            clock,
+            name: name.into(),
+            limit,
+            max_attempts: Self::DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Sets the number of attempts to update the shared state before giving up.
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Returns name of the rate limiter.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the limit.
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Checks whether a request with a given key is allowed, and if so, takes it into account.
+    pub fn check(&self, key: &str) -> host::Result<RateLimitDecision> {
+        self.check_n(key, 1)
+    }
+
+    /// Checks whether a request of a given cost is allowed, and if so, takes it into account.
+    ///
+    /// State of every distinct key is kept in a separate `SharedData` entry
+    /// that is never removed, so keys must come from a bounded set of values.
+    /// Avoid keys built out of arbitrary client input, e.g. raw header values.
+    pub fn check_n(&self, key: &str, cost: u64) -> host::Result<RateLimitDecision> {
+        let now = millis(
+            self.clock
+                .now()?
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default(),
+        );
+        let state: SharedValue<State> = SharedValue::new(
+            self.shared_data,
+            format!("envoy_sdk.ratelimit.{}.{}", self.name, key),
+        )
+        .with_max_attempts(self.max_attempts);
+        let mut decision = None;
+        state.try_update(|state| {
+            let (state, outcome) = self.limit.apply(state, now, cost);
+            decision = Some(outcome);
+            // don't bother updating the state of rejected requests
+            if outcome.allowed {
+                Some(state)
+            } else {
+                None
+            }
+        })?;
+        Ok(decision.expect("rate limit has been applied at least once"))
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    saturate(duration.as_millis())
+}
+
+fn seconds(duration: Duration) -> u64 {
+    let secs = duration.as_secs();
+    if duration.subsec_nanos() > 0 {
+        secs + 1
+    } else {
+        secs
+    }
+}
+
+fn saturate(value: u128) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,35 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local rate limiting of HTTP requests.
+//!
+//! Rate limits are enforced by every `Envoy` instance on its own, without an external
+//! rate limit service. State of rate limits is kept in `SharedData`, so that all
+//! worker threads of an `Envoy` instance share the same quota.
+//!
+//! * [`RateLimiter`] is a building block to enforce a [`RateLimit`] in any extension.
+//! * [`RateLimitFilter`] is a ready-to-use `HTTP Filter` that rejects requests
+//!   exceeding rate limits with `429 Too Many Requests`.
+//!
+//! [`RateLimiter`]: struct.RateLimiter.html
+//! [`RateLimit`]: enum.RateLimit.html
+//! [`RateLimitFilter`]: struct.RateLimitFilter.html
+
+pub use self::descriptor::Descriptor;
+pub use self::filter::{RateLimitFilter, RateLimitFilterFactory, RateLimitPolicy};
+pub use self::limiter::{RateLimit, RateLimitDecision, RateLimiter};
+
+mod descriptor;
+mod filter;
+mod limiter;
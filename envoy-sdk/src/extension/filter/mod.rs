@@ -14,5 +14,9 @@
 
 //! `Envoy` filter extensions.
 
+pub use self::policy::{FailurePolicy, LocalReply};
+
+mod policy;
+
 pub mod http;
 pub mod network;
@@ -13,11 +13,15 @@
 // limitations under the License.
 
 use super::{FilterStatus, NetworkFilter, Ops};
+use crate::abi::proxy_wasm::scope::ChildScope;
 use crate::abi::proxy_wasm::traits::{Context, StreamContext};
 use crate::abi::proxy_wasm::types::{Action, PeerType};
-use crate::extension::error::ErrorSink;
-use crate::extension::Error;
+use crate::extension::error::{ErrorCategory, ErrorReporter};
+use crate::extension::filter::FailurePolicy;
+use crate::extension::isolation::Isolation;
+use crate::extension::{Error, Result};
 use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
+use crate::host::log;
 
 pub(crate) struct NetworkFilterContext<'a, F>
 where
@@ -26,7 +30,10 @@ where
     filter: F,
     filter_ops: &'a dyn Ops,
     http_client_ops: &'a dyn HttpClientResponseOps,
-    error_sink: &'a dyn ErrorSink,
+    error_reporter: ErrorReporter,
+    failure_policy: FailurePolicy,
+    isolation: Isolation,
+    scope: ChildScope,
 }
 
 impl<'a, F> StreamContext for NetworkFilterContext<'a, F>
@@ -34,70 +41,84 @@ where
     F: NetworkFilter,
 {
     fn on_new_connection(&mut self) -> Action {
-        match self.filter.on_new_connection() {
+        match self.call(|filter, _| filter.on_new_connection()) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle connection opening", &err);
-                self.handle_error(err);
-                FilterStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle connection opening",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterStatus::Continue.as_action()
+                } else {
+                    FilterStatus::StopIteration.as_action()
+                }
             }
         }
     }
 
     fn on_downstream_data(&mut self, data_size: usize, end_of_stream: bool) -> Action {
-        match self.filter.on_downstream_data(
-            data_size,
-            end_of_stream,
-            self.filter_ops.as_downstream_data_ops(),
-        ) {
+        match self.call(|filter, ops| {
+            filter.on_downstream_data(data_size, end_of_stream, ops.as_downstream_data_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle data from the downstream", &err);
-                self.handle_error(err);
-                FilterStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle data from the downstream",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterStatus::Continue.as_action()
+                } else {
+                    FilterStatus::StopIteration.as_action()
+                }
             }
         }
     }
 
     fn on_downstream_close(&mut self, peer_type: PeerType) {
-        if let Err(err) = self
-            .filter
-            .on_downstream_close(peer_type, self.filter_ops.as_downstream_close_ops())
-        {
-            self.error_sink
-                .observe("failed to handle connection close by the downstream", &err);
+        if let Err(err) = self.call(|filter, ops| {
+            filter.on_downstream_close(peer_type, ops.as_downstream_close_ops())
+        }) {
+            let context = "failed to handle connection close by the downstream";
+            self.error_reporter
+                .observe(ErrorCategory::Callback, context, &err);
             // TODO(yskopets): do we still need to do anything to terminate the connection?
-            self.handle_error(err);
+            self.handle_error_out_of_band(err, context);
         }
     }
 
     fn on_upstream_data(&mut self, data_size: usize, end_of_stream: bool) -> Action {
-        match self.filter.on_upstream_data(
-            data_size,
-            end_of_stream,
-            self.filter_ops.as_upstream_data_ops(),
-        ) {
+        match self.call(|filter, ops| {
+            filter.on_upstream_data(data_size, end_of_stream, ops.as_upstream_data_ops())
+        }) {
             Ok(status) => status.as_action(),
             Err(err) => {
-                self.error_sink
-                    .observe("failed to handle data from the upstream", &err);
-                self.handle_error(err);
-                FilterStatus::StopIteration.as_action()
+                self.error_reporter.observe(
+                    ErrorCategory::Callback,
+                    "failed to handle data from the upstream",
+                    &err,
+                );
+                if self.handle_error(err) {
+                    FilterStatus::Continue.as_action()
+                } else {
+                    FilterStatus::StopIteration.as_action()
+                }
             }
         }
     }
 
     fn on_upstream_close(&mut self, peer_type: PeerType) {
         if let Err(err) = self
-            .filter
-            .on_upstream_close(peer_type, self.filter_ops.as_upstream_close_ops())
+            .call(|filter, ops| filter.on_upstream_close(peer_type, ops.as_upstream_close_ops()))
         {
-            self.error_sink
-                .observe("failed to handle connection close by the upstream", &err);
+            let context = "failed to handle connection close by the upstream";
+            self.error_reporter
+                .observe(ErrorCategory::Callback, context, &err);
             // TODO(yskopets): do we still need to do anything to terminate the connection?
-            self.handle_error(err);
+            self.handle_error_out_of_band(err, context);
         }
     }
 }
@@ -107,12 +128,14 @@ where
     F: NetworkFilter,
 {
     fn on_done(&mut self) -> bool {
-        if let Err(err) = self
-            .filter
-            .on_connection_complete(self.filter_ops.as_connection_complete_ops())
+        if let Err(err) =
+            self.call(|filter, ops| filter.on_connection_complete(ops.as_connection_complete_ops()))
         {
-            self.error_sink
-                .observe("failed to handle completion of a connection", &err);
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to handle completion of a connection",
+                &err,
+            );
             // connection is already being terminated, so there is no need to do it explicitly
         }
         true
@@ -127,19 +150,21 @@ where
         body_size: usize,
         num_trailers: usize,
     ) {
-        if let Err(err) = self.filter.on_http_call_response(
-            HttpClientRequestHandle::from(token_id),
-            num_headers,
-            body_size,
-            num_trailers,
-            self.filter_ops,
-            self.http_client_ops,
-        ) {
-            self.error_sink.observe(
-                "failed to process a response to an HTTP request made by the extension",
-                &err,
-            );
-            self.handle_error(err);
+        let http_client_ops = self.http_client_ops;
+        if let Err(err) = self.call(|filter, ops| {
+            filter.on_http_call_response(
+                HttpClientRequestHandle::from(token_id),
+                num_headers,
+                body_size,
+                num_trailers,
+                ops,
+                http_client_ops,
+            )
+        }) {
+            let context = "failed to process a response to an HTTP request made by the extension";
+            self.error_reporter
+                .observe(ErrorCategory::Callback, context, &err);
+            self.handle_error_out_of_band(err, context);
         }
     }
 }
@@ -152,32 +177,96 @@ where
         filter: F,
         filter_ops: &'a dyn Ops,
         http_client_ops: &'a dyn HttpClientResponseOps,
-        error_sink: &'a dyn ErrorSink,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+        isolation: Isolation,
+        scope: ChildScope,
     ) -> Self {
         NetworkFilterContext {
             filter,
             filter_ops,
             http_client_ops,
-            error_sink,
+            error_reporter,
+            failure_policy,
+            isolation,
+            scope,
         }
     }
 
     /// Creates a new network filter context bound to the actual Envoy ABI.
-    pub fn with_default_ops(filter: F) -> Self {
+    pub fn with_default_ops(
+        filter: F,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+        isolation: Isolation,
+        scope: ChildScope,
+    ) -> Self {
         Self::new(
             filter,
-            Ops::default(),
-            HttpClientResponseOps::default(),
-            ErrorSink::default(),
+            <dyn Ops>::default(),
+            <dyn HttpClientResponseOps>::default(),
+            error_reporter,
+            failure_policy,
+            isolation,
+            scope,
         )
     }
 
-    fn handle_error(&self, _err: Error) {
-        // TODO(yskopets): Proxy Wasm should provide ABI for closing the downstream connection
-        // https://github.com/tetratelabs/envoy-wasm-rust-sdk/issues/29
+    /// Calls the filter unless it has panicked earlier.
+    fn call<T, C>(&mut self, callback: C) -> Result<T>
+    where
+        C: FnOnce(&mut F, &'a dyn Ops) -> Result<T>,
+    {
+        let (filter, filter_ops, isolation) = (&mut self.filter, self.filter_ops, &self.isolation);
+        self.scope
+            .enter(|| isolation.call(|| callback(filter, filter_ops)))
+    }
+
+    /// Applies the failure policy.
+    ///
+    /// Returns `true` if processing of the connection should continue.
+    fn handle_error(&self, _err: Error) -> bool {
+        apply_failure_policy(&self.failure_policy, self.filter_ops, &self.error_reporter)
+    }
+
+    /// Applies the failure policy outside of a callback that decides whether
+    /// processing of the connection should continue.
+    ///
+    /// Since the outcome cannot be acted upon, it only gets logged.
+    fn handle_error_out_of_band(&self, err: Error, context: &str) {
+        if self.handle_error(err) {
+            log::debug!("{}: connection proceeds as per failure policy", context);
+        } else {
+            log::debug!(
+                "{}: processing of the connection has been terminated as per failure policy",
+                context
+            );
+        }
     }
 }
 
+/// Closes the connection unless the policy is to continue.
+///
+/// Returns `true` if processing of the connection should continue.
+fn apply_failure_policy(
+    failure_policy: &FailurePolicy,
+    filter_ops: &dyn Ops,
+    error_reporter: &ErrorReporter,
+) -> bool {
+    if let FailurePolicy::Continue = failure_policy {
+        return true;
+    }
+    // there is no way to send a local reply over a TCP connection
+    if let Err(err) = filter_ops.close_connection() {
+        error_reporter.observe(
+            ErrorCategory::Callback,
+            "failed to terminate processing of the connection: failed to close the connection",
+            &err,
+        );
+    }
+    false
+}
+
 /// Fake `Proxy Wasm` [`StreamContext`] that is used to postpone error handling
 /// until a proper moment in the connection lifecycle.
 ///
@@ -187,40 +276,56 @@ where
 /// at this point.
 ///
 /// Instead, we have to memorize the error and wait until [`proxy_on_new_connection`]
-/// callback when it will be safe to use [`not yet supported ABI`] to stop further processing.
+/// callback when it will be safe to use [`proxy_close_stream`] to stop further processing.
 ///
 /// [`StreamContext`]: https://docs.rs/proxy-wasm/0.1.0/proxy_wasm/traits/trait.StreamContext.html
 /// [`proxy_on_context_create`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#proxy_on_context_create
 /// [`proxy_on_new_connection`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#proxy_on_new_connection
-/// [`not yet supported ABI`]: https://github.com/tetratelabs/envoy-wasm-rust-sdk/issues/29
+/// [`proxy_close_stream`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#proxy_close_stream
 pub(crate) struct VoidNetworkFilterContext<'a> {
     err: Error,
-    _filter_ops: &'a dyn Ops,
-    error_sink: &'a dyn ErrorSink,
+    filter_ops: &'a dyn Ops,
+    error_reporter: ErrorReporter,
+    failure_policy: FailurePolicy,
 }
 
 impl<'a> VoidNetworkFilterContext<'a> {
-    pub fn new(err: Error, _filter_ops: &'a dyn Ops, error_sink: &'a dyn ErrorSink) -> Self {
+    pub fn new(
+        err: Error,
+        filter_ops: &'a dyn Ops,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+    ) -> Self {
         VoidNetworkFilterContext {
             err,
-            _filter_ops,
-            error_sink,
+            filter_ops,
+            error_reporter,
+            failure_policy,
         }
     }
 
     /// Creates a new HTTP filter context bound to the actual Envoy ABI.
-    pub fn with_default_ops(err: Error) -> Self {
-        Self::new(err, Ops::default(), ErrorSink::default())
+    pub fn with_default_ops(
+        err: Error,
+        error_reporter: ErrorReporter,
+        failure_policy: FailurePolicy,
+    ) -> Self {
+        Self::new(err, <dyn Ops>::default(), error_reporter, failure_policy)
     }
 }
 
 impl<'a> StreamContext for VoidNetworkFilterContext<'a> {
     fn on_new_connection(&mut self) -> Action {
-        self.error_sink
-            .observe("failed to create Proxy Wasm Stream Context", &self.err);
-        // TODO(yskopets): Proxy Wasm should provide ABI for closing the downstream connection
-        // https://github.com/tetratelabs/envoy-wasm-rust-sdk/issues/29
-        FilterStatus::StopIteration.as_action()
+        self.error_reporter.observe(
+            ErrorCategory::ExtensionCreation,
+            "failed to create Proxy Wasm Stream Context",
+            &self.err,
+        );
+        if apply_failure_policy(&self.failure_policy, self.filter_ops, &self.error_reporter) {
+            FilterStatus::Continue.as_action()
+        } else {
+            FilterStatus::StopIteration.as_action()
+        }
     }
 }
 
@@ -100,6 +100,7 @@
 //! [`Register`]: ../../../macro.entrypoint.html
 
 use crate::abi::proxy_wasm::types::{Action, PeerType};
+use crate::error::format_err;
 use crate::extension::Result;
 use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
 use crate::host::{self, ByteString};
@@ -357,9 +358,29 @@ pub trait ConnectionCompleteOps {
     // TODO(yskopets): TBD
 }
 
+/// An interface for changing connection flow.
+pub trait ConnectionFlowOps {
+    /// Closes the downstream connection.
+    ///
+    /// Operations bound to `Envoy` always support it. A custom implementation
+    /// that keeps the default one cannot close the connection, in which case
+    /// a [`FailurePolicy`] other than `Continue` reports the error and leaves
+    /// the connection paused.
+    ///
+    /// [`FailurePolicy`]: ../enum.FailurePolicy.html
+    fn close_connection(&self) -> host::Result<()> {
+        Err(format_err!("closing connection is not supported"))
+    }
+}
+
 /// An interface for manipulating data in both read and write buffers.
 pub trait Ops:
-    DownstreamDataOps + UpstreamDataOps + DownstreamCloseOps + UpstreamCloseOps + ConnectionCompleteOps
+    DownstreamDataOps
+    + UpstreamDataOps
+    + DownstreamCloseOps
+    + UpstreamCloseOps
+    + ConnectionCompleteOps
+    + ConnectionFlowOps
 {
     fn as_downstream_data_ops(&self) -> &dyn DownstreamDataOps;
 
@@ -378,7 +399,8 @@ where
         + UpstreamDataOps
         + DownstreamCloseOps
         + UpstreamCloseOps
-        + ConnectionCompleteOps,
+        + ConnectionCompleteOps
+        + ConnectionFlowOps,
 {
     fn as_downstream_data_ops(&self) -> &dyn DownstreamDataOps {
         self
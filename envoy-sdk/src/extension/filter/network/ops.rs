@@ -16,7 +16,8 @@ use crate::abi::proxy_wasm::hostcalls;
 use crate::abi::proxy_wasm::types::BufferType;
 
 use super::{
-    ConnectionCompleteOps, DownstreamCloseOps, DownstreamDataOps, UpstreamCloseOps, UpstreamDataOps,
+    ConnectionCompleteOps, ConnectionFlowOps, DownstreamCloseOps, DownstreamDataOps,
+    UpstreamCloseOps, UpstreamDataOps,
 };
 use crate::host::{self, ByteString};
 
@@ -39,3 +40,9 @@ impl DownstreamCloseOps for Host {}
 impl UpstreamCloseOps for Host {}
 
 impl ConnectionCompleteOps for Host {}
+
+impl ConnectionFlowOps for Host {
+    fn close_connection(&self) -> host::Result<()> {
+        hostcalls::close_downstream_connection()
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handling of errors returned by filter callbacks.
+
+/// Defines what happens to an HTTP stream or a TCP connection once a filter
+/// returns an error from any of its callbacks.
+///
+/// `FailurePolicy` is configured per [`ExtensionFactory`] through
+/// [`ExtensionFactory::failure_policy`] and applies both to errors returned by
+/// filter callbacks, including [`on_http_call_response`], and to errors returned by
+/// [`ExtensionFactory::new_extension`].
+///
+/// By default, an HTTP stream gets terminated with a `500` response,
+/// while a TCP connection gets closed.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{ExtensionFactory, HttpFilter, InstanceId, Result};
+/// use envoy::extension::filter::{FailurePolicy, LocalReply};
+///
+/// # struct MyHttpFilter;
+/// # impl HttpFilter for MyHttpFilter {}
+/// #
+/// struct MyHttpFilterFactory;
+///
+/// impl ExtensionFactory for MyHttpFilterFactory {
+///     type Extension = MyHttpFilter;
+///
+///     fn name() -> &'static str { "my_http_filter" }
+///
+///     fn new_extension(&mut self, _instance_id: InstanceId) -> Result<Self::Extension> {
+///         Ok(MyHttpFilter)
+///     }
+///
+///     fn failure_policy(&self) -> FailurePolicy {
+///         FailurePolicy::LocalReply(
+///             LocalReply::new(503)
+///                 .header("content-type", "text/plain")
+///                 .body("service is temporarily unavailable"),
+///         )
+///     }
+/// }
+/// ```
+///
+/// [`ExtensionFactory`]: ../factory/trait.ExtensionFactory.html
+/// [`ExtensionFactory::failure_policy`]: ../factory/trait.ExtensionFactory.html#method.failure_policy
+/// [`ExtensionFactory::new_extension`]: ../factory/trait.ExtensionFactory.html#tymethod.new_extension
+/// [`on_http_call_response`]: http/trait.HttpFilter.html#method.on_http_call_response
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FailurePolicy {
+    /// Ignore the error and let the HTTP stream or TCP connection proceed (fail open).
+    Continue,
+    /// Terminate the HTTP stream with a given local reply.
+    ///
+    /// Since there is no way to reply to a TCP connection, `Network Filters`
+    /// close the connection instead.
+    LocalReply(LocalReply),
+    /// Reset the HTTP stream or close the TCP connection.
+    Reset,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        FailurePolicy::LocalReply(LocalReply::default())
+    }
+}
+
+/// HTTP response sent to the downstream by [`FailurePolicy::LocalReply`].
+///
+/// [`FailurePolicy::LocalReply`]: enum.FailurePolicy.html#variant.LocalReply
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LocalReply {
+    /// HTTP status code.
+    pub status_code: u32,
+    /// HTTP response headers.
+    pub headers: Vec<(String, String)>,
+    /// HTTP response body.
+    pub body: Option<Vec<u8>>,
+}
+
+impl LocalReply {
+    /// Creates a new local reply with a given status code and no body.
+    pub fn new(status_code: u32) -> Self {
+        LocalReply {
+            status_code,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// Adds a response header.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets response body.
+    pub fn body<B>(mut self, body: B) -> Self
+    where
+        B: Into<Vec<u8>>,
+    {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+impl Default for LocalReply {
+    fn default() -> Self {
+        Self::new(500)
+    }
+}
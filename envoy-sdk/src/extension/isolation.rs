@@ -0,0 +1,125 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Isolation of panics inside extension callbacks.
+//!
+//! A panic inside an extension callback must not take down other extension
+//! instances sharing the same WebAssembly VM.
+//!
+//! On targets that support stack unwinding, a panic gets caught and converted
+//! into an error that is then reported to the [`ErrorSink`].
+//! The extension instance that has panicked is marked as failed, i.e.
+//! all subsequent callbacks fail right away and the [`FailurePolicy`] applies.
+//!
+//! On targets that don't support stack unwinding, e.g. `wasm32-unknown-unknown`,
+//! a panic still aborts the VM. If the host keeps calling the extension instance
+//! regardless, the callback that has never returned gets detected and the instance
+//! is marked as failed all the same.
+//!
+//! Isolation can be disabled through [`Module::with_panic_isolation`].
+//!
+//! [`ErrorSink`]: ../error/trait.ErrorSink.html
+//! [`FailurePolicy`]: ../filter/enum.FailurePolicy.html
+//! [`Module::with_panic_isolation`]: ../struct.Module.html#method.with_panic_isolation
+
+use std::any::Any;
+use std::cell::Cell;
+
+use crate::extension::error::ExtensionError;
+use crate::extension::Result;
+
+/// Guards callbacks of a single extension instance.
+pub(crate) struct Isolation {
+    is_enabled: bool,
+    is_failed: Cell<bool>,
+    is_running: Cell<bool>,
+}
+
+impl Isolation {
+    pub fn new(is_enabled: bool) -> Self {
+        Isolation {
+            is_enabled,
+            is_failed: Cell::new(false),
+            is_running: Cell::new(false),
+        }
+    }
+
+    /// Returns `true` if callbacks of the extension instance are guarded.
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    /// Returns `true` if the extension instance has panicked earlier.
+    pub fn is_failed(&self) -> bool {
+        self.is_failed.get()
+    }
+
+    /// Calls an extension callback unless the extension instance has failed earlier.
+    pub fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        if !self.is_enabled {
+            return f();
+        }
+        if self.is_failed() {
+            return Err(ExtensionError::Failed.into());
+        }
+        if self.is_running.replace(true) {
+            // the previous callback has never returned, i.e. it has been aborted
+            self.is_failed.set(true);
+            return Err(ExtensionError::Aborted.into());
+        }
+        let result = catch_panic(f);
+        self.is_running.set(false);
+        if let Some(ExtensionError::Panicked(_)) =
+            result.as_ref().err().and_then(|err| err.downcast_ref())
+        {
+            self.is_failed.set(true);
+        }
+        result
+    }
+}
+
+/// Converts a panic inside a given function into an error, if the target supports it.
+#[cfg(panic = "unwind")]
+pub(crate) fn catch_panic<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    // the extension instance is never used again after a panic,
+    // so it's fine if it has been left in an inconsistent state
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(ExtensionError::Panicked(panic_message(&*payload)).into()))
+}
+
+/// Converts a panic inside a given function into an error, if the target supports it.
+#[cfg(not(panic = "unwind"))]
+pub(crate) fn catch_panic<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    f()
+}
+
+#[cfg_attr(not(panic = "unwind"), allow(dead_code))]
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
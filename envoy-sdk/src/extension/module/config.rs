@@ -12,21 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{ContextFactory, ContextFactoryHashMap};
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::{ContextFactory, ContextFactoryRegistry, RootId, Settings};
 
 use crate::abi::proxy_wasm::traits::{ChildContext, HttpContext, RootContext, StreamContext};
 use crate::extension::access_logger::{AccessLogger, AccessLoggerContext};
-use crate::extension::error::ModuleError;
+use crate::extension::drain::Drainer;
+use crate::extension::error::{DefaultErrorSink, ErrorReporter, ErrorSink, ModuleError};
 use crate::extension::factory::{ExtensionFactory, ExtensionFactoryContext};
 use crate::extension::filter::http::{HttpFilter, HttpFilterContext, VoidHttpFilterContext};
 use crate::extension::filter::network::{
     NetworkFilter, NetworkFilterContext, VoidNetworkFilterContext,
 };
+use crate::extension::isolation::Isolation;
+use crate::extension::service::{Service, ServiceContext};
 use crate::extension::{InstanceId, Result};
 
 /// Registry of extensions provided by the WebAssembly module.
 pub struct Module {
-    factories: ContextFactoryHashMap,
+    factories: ContextFactoryRegistry,
+    settings: Settings,
 }
 
 impl Default for Module {
@@ -38,97 +45,371 @@ impl Default for Module {
 impl Module {
     pub fn new() -> Self {
         Module {
-            factories: ContextFactoryHashMap::new(),
+            factories: ContextFactoryRegistry::default(),
+            settings: Settings {
+                error_sink: Rc::new(DefaultErrorSink),
+                drain_timeout: None,
+                panic_isolation: true,
+            },
         }
     }
 
-    fn add_extension(mut self, name: &'static str, factory: Box<ContextFactory>) -> Result<Self> {
-        if self.factories.insert(name.to_string(), factory).is_some() {
-            Err(ModuleError::DuplicateRegistration(name.to_string()).into())
-        } else {
-            Ok(self)
+    /// Replaces the [`ErrorSink`] that observes errors returned by extensions
+    /// of this module.
+    ///
+    /// By default, errors are logged through `Envoy Log API`.
+    ///
+    /// [`ErrorSink`]: error/trait.ErrorSink.html
+    pub fn with_error_sink<S>(mut self, error_sink: S) -> Self
+    where
+        S: ErrorSink + 'static,
+    {
+        self.settings.error_sink = Rc::new(error_sink);
+        self
+    }
+
+    /// Sets the maximum time extensions of this module may take to drain.
+    ///
+    /// While being drained, an extension is kept alive until all HTTP requests
+    /// it has made receive a response and the extension itself reports completion,
+    /// either by returning [`DrainStatus::Complete`] from `on_drain` or by calling
+    /// `DrainOps::done` later.
+    /// Once the timeout expires, completion is reported to `Envoy` regardless.
+    ///
+    /// The timeout is tracked by means of `on_tick` callbacks. A tick period set by
+    /// a [`Service`] stays intact, in which case the timeout is checked at that pace
+    /// and might be exceeded by up to one tick period.
+    ///
+    /// By default, there is no timeout.
+    ///
+    /// [`DrainStatus::Complete`]: factory/enum.DrainStatus.html#variant.Complete
+    /// [`Service`]: service/trait.Service.html
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.drain_timeout = Some(timeout);
+        self
+    }
+
+    /// Enables or disables isolation of panics inside extension callbacks.
+    ///
+    /// When enabled, a panic inside a callback is reported to the [`ErrorSink`]
+    /// and the extension instance that has panicked is marked as failed, i.e.
+    /// its subsequent callbacks fail right away and the [`FailurePolicy`] applies.
+    ///
+    /// Catching a panic requires a target that supports stack unwinding.
+    /// On other targets, e.g. `wasm32-unknown-unknown`, a panic still aborts the VM;
+    /// if `Envoy` keeps calling the extension instance afterwards, the instance
+    /// is marked as failed on the next callback.
+    ///
+    /// When disabled, a panic always aborts the VM.
+    ///
+    /// By default, isolation is enabled.
+    ///
+    /// [`ErrorSink`]: error/trait.ErrorSink.html
+    /// [`FailurePolicy`]: filter/enum.FailurePolicy.html
+    pub fn with_panic_isolation(mut self, enabled: bool) -> Self {
+        self.settings.panic_isolation = enabled;
+        self
+    }
+
+    pub(crate) fn settings(&self) -> Settings {
+        self.settings.clone()
+    }
+
+    fn add_extension(mut self, root_id: RootId, factory: Box<ContextFactory>) -> Result<Self> {
+        let name = root_id.to_string();
+        match self.factories.insert(root_id, factory) {
+            Ok(()) => Ok(self),
+            Err(_) => Err(ModuleError::DuplicateRegistration(name).into()),
         }
     }
 
+    /// Registers an Access Logger under the `root_id` returned by its `name()` method.
     pub fn add_access_logger<T, F>(self, mut new: F) -> Result<Self>
     where
         T: AccessLogger + 'static,
         F: FnMut(InstanceId) -> Result<T> + 'static,
     {
-        let factory = Box::new(move |context_id| -> Result<Box<dyn RootContext>> {
-            let logger = new(InstanceId::from(context_id))?;
+        self.add_access_logger_as(T::name(), move |_root_id: &str, instance_id| {
+            new(instance_id)
+        })
+    }
+
+    /// Registers an Access Logger under a given `root_id` or a `root_id` pattern.
+    ///
+    /// Makes it possible to register the same implementation several times,
+    /// e.g. to deploy it with different configurations.
+    ///
+    /// `new` receives the actual `root_id` of the root context being created,
+    /// which tells apart deployments registered under a `root_id` pattern.
+    pub fn add_access_logger_as<R, T, F>(self, root_id: R, mut new: F) -> Result<Self>
+    where
+        R: Into<RootId>,
+        T: AccessLogger + 'static,
+        F: FnMut(&str, InstanceId) -> Result<T> + 'static,
+    {
+        let factory = Box::new(
+            move |context_id, root_id: &str, settings: Settings| -> Result<Box<dyn RootContext>> {
+                let error_reporter = ErrorReporter::new(settings.error_sink)
+                    .with_root_id(root_id)
+                    .with_instance_id(InstanceId::from(context_id));
+                let logger = new(root_id, InstanceId::from(context_id))?;
+
+                // Bridge between Access Logger abstraction and Proxy Wasm ABI
+                Ok(Box::new(AccessLoggerContext::with_default_ops(
+                    logger,
+                    error_reporter,
+                    Drainer::with_default_ops(settings.drain_timeout),
+                    Isolation::new(settings.panic_isolation),
+                )))
+            },
+        );
+        self.add_extension(root_id.into(), factory)
+    }
+
+    /// Registers a Service under the `root_id` returned by its `name()` method.
+    ///
+    /// `new` is called every time `Envoy` creates a root context with that `root_id`,
+    /// e.g. once per `Envoy` process if the `Service` is deployed as a [`singleton`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use envoy_sdk as envoy;
+    /// # use envoy::extension::Service;
+    /// #
+    /// # struct MyService;
+    /// # impl Service for MyService {
+    /// #     fn name() -> &'static str { "my_service" }
+    /// # }
+    /// #
+    /// use envoy::extension::{Module, Result};
+    ///
+    /// fn initialize() -> Result<Module> {
+    ///     // serves `root_id` "my_service"
+    ///     Module::new().add_service(|_instance_id| Ok(MyService))
+    /// }
+    /// ```
+    ///
+    /// [`singleton`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/extensions/wasm/v3/wasm.proto#extensions-wasm-v3-wasmservice
+    pub fn add_service<T, F>(self, mut new: F) -> Result<Self>
+    where
+        T: Service + 'static,
+        F: FnMut(InstanceId) -> Result<T> + 'static,
+    {
+        self.add_service_as(T::name(), move |_root_id: &str, instance_id| {
+            new(instance_id)
+        })
+    }
+
+    /// Registers a Service under a given `root_id` or a `root_id` pattern.
+    ///
+    /// Makes it possible to register the same implementation several times,
+    /// e.g. to deploy it with different configurations.
+    ///
+    /// `new` receives the actual `root_id` of the root context being created,
+    /// which tells apart deployments registered under a `root_id` pattern.
+    pub fn add_service_as<R, T, F>(self, root_id: R, mut new: F) -> Result<Self>
+    where
+        R: Into<RootId>,
+        T: Service + 'static,
+        F: FnMut(&str, InstanceId) -> Result<T> + 'static,
+    {
+        let factory = Box::new(
+            move |context_id, root_id: &str, settings: Settings| -> Result<Box<dyn RootContext>> {
+                let error_reporter = ErrorReporter::new(settings.error_sink)
+                    .with_root_id(root_id)
+                    .with_instance_id(InstanceId::from(context_id));
+                let service = new(root_id, InstanceId::from(context_id))?;
 
-            // Bridge between Access Logger abstraction and Proxy Wasm ABI
-            Ok(Box::new(AccessLoggerContext::with_default_ops(logger)))
-        });
-        self.add_extension(T::name(), factory)
+                // Bridge between Service abstraction and Proxy Wasm ABI
+                Ok(Box::new(ServiceContext::with_default_ops(
+                    service,
+                    error_reporter,
+                    Drainer::with_default_ops(settings.drain_timeout),
+                    Isolation::new(settings.panic_isolation),
+                )))
+            },
+        );
+        self.add_extension(root_id.into(), factory)
     }
 
+    /// Registers a Network Filter Factory under the `root_id` returned by its `name()` method.
     pub fn add_network_filter<T, F>(self, mut new: F) -> Result<Self>
     where
         T: ExtensionFactory + 'static,
         T::Extension: NetworkFilter,
         F: FnMut(InstanceId) -> Result<T> + 'static,
     {
-        let factory = Box::new(move |context_id| -> Result<Box<dyn RootContext>> {
-            let network_filter_factory = new(InstanceId::from(context_id))?;
-
-            // Bridge between Network Filter Factory abstraction and Proxy Wasm ABI
-            Ok(Box::new(ExtensionFactoryContext::with_default_ops(
-                network_filter_factory,
-                |network_filter_factory, instance_id| -> ChildContext {
-                    let stream_context: Box<dyn StreamContext> =
-                        match <T as ExtensionFactory>::new_extension(
-                            network_filter_factory,
-                            instance_id,
-                        ) {
-                            Ok(network_filter) => {
-                                Box::new(NetworkFilterContext::with_default_ops(network_filter))
-                            }
-                            Err(err) => Box::new(VoidNetworkFilterContext::with_default_ops(err)),
+        self.add_network_filter_as(T::name(), move |_root_id: &str, instance_id| {
+            new(instance_id)
+        })
+    }
+
+    /// Registers a Network Filter Factory under a given `root_id` or a `root_id` pattern.
+    ///
+    /// Makes it possible to register the same implementation several times,
+    /// e.g. to deploy it with different configurations.
+    ///
+    /// `new` receives the actual `root_id` of the root context being created,
+    /// which tells apart deployments registered under a `root_id` pattern.
+    pub fn add_network_filter_as<R, T, F>(self, root_id: R, mut new: F) -> Result<Self>
+    where
+        R: Into<RootId>,
+        T: ExtensionFactory + 'static,
+        T::Extension: NetworkFilter,
+        F: FnMut(&str, InstanceId) -> Result<T> + 'static,
+    {
+        let factory = Box::new(
+            move |context_id, root_id: &str, settings: Settings| -> Result<Box<dyn RootContext>> {
+                let error_reporter = ErrorReporter::new(settings.error_sink)
+                    .with_root_id(root_id)
+                    .with_instance_id(InstanceId::from(context_id));
+                let network_filter_factory = new(root_id, InstanceId::from(context_id))?;
+
+                // Bridge between Network Filter Factory abstraction and Proxy Wasm ABI
+                Ok(Box::new(ExtensionFactoryContext::with_default_ops(
+                    network_filter_factory,
+                    error_reporter,
+                    Drainer::with_default_ops(settings.drain_timeout),
+                    Isolation::new(settings.panic_isolation),
+                    |network_filter,
+                     failure_policy,
+                     error_reporter,
+                     isolation,
+                     scope|
+                     -> ChildContext {
+                        let stream_context: Box<dyn StreamContext> = match network_filter {
+                            Ok(network_filter) => Box::new(NetworkFilterContext::with_default_ops(
+                                network_filter,
+                                error_reporter,
+                                failure_policy,
+                                isolation,
+                                scope,
+                            )),
+                            Err(err) => Box::new(VoidNetworkFilterContext::with_default_ops(
+                                err,
+                                error_reporter,
+                                failure_policy,
+                            )),
                         };
-                    // Bridge between Network Filter abstraction and Proxy Wasm ABI
-                    ChildContext::StreamContext(stream_context)
-                },
-            )))
-        });
-        self.add_extension(T::name(), factory)
+                        // Bridge between Network Filter abstraction and Proxy Wasm ABI
+                        ChildContext::StreamContext(stream_context)
+                    },
+                )))
+            },
+        );
+        self.add_extension(root_id.into(), factory)
     }
 
+    /// Registers an HTTP Filter Factory under the `root_id` returned by its `name()` method.
     pub fn add_http_filter<T, F>(self, mut new: F) -> Result<Self>
     where
         T: ExtensionFactory + 'static,
         T::Extension: HttpFilter,
         F: FnMut(InstanceId) -> Result<T> + 'static,
     {
-        let factory = Box::new(move |context_id| -> Result<Box<dyn RootContext>> {
-            let http_filter_factory = new(InstanceId::from(context_id))?;
-
-            // Bridge between HTTP Filter Factory abstraction and Proxy Wasm ABI
-            Ok(Box::new(ExtensionFactoryContext::with_default_ops(
-                http_filter_factory,
-                |http_filter_factory, instance_id| -> ChildContext {
-                    let http_context: Box<dyn HttpContext> =
-                        match <T as ExtensionFactory>::new_extension(
-                            http_filter_factory,
-                            instance_id,
-                        ) {
-                            Ok(http_filter) => {
-                                Box::new(HttpFilterContext::with_default_ops(http_filter))
-                            }
-                            Err(err) => Box::new(VoidHttpFilterContext::with_default_ops(err)),
+        self.add_http_filter_as(T::name(), move |_root_id: &str, instance_id| {
+            new(instance_id)
+        })
+    }
+
+    /// Registers an HTTP Filter Factory under a given `root_id` or a `root_id` pattern.
+    ///
+    /// Makes it possible to register the same implementation several times,
+    /// e.g. to deploy it with different configurations.
+    ///
+    /// `new` receives the actual `root_id` of the root context being created,
+    /// which tells apart deployments registered under a `root_id` pattern.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use envoy_sdk as envoy;
+    /// # use envoy::extension::{self, HttpFilter, InstanceId, ExtensionFactory};
+    /// #
+    /// # struct MyHttpFilter;
+    /// # impl HttpFilter for MyHttpFilter {}
+    /// #
+    /// # struct MyHttpFilterFactory;
+    /// # impl MyHttpFilterFactory {
+    /// #     fn default() -> extension::Result<Self> { Ok(MyHttpFilterFactory) }
+    /// # }
+    /// # impl ExtensionFactory for MyHttpFilterFactory {
+    /// #     type Extension = MyHttpFilter;
+    /// #
+    /// #     fn name() -> &'static str { "my_http_filter" }
+    /// #
+    /// #     fn new_extension(&mut self, instance_id: InstanceId) -> extension::Result<Self::Extension> {
+    /// #         Ok(MyHttpFilter)
+    /// #     }
+    /// # }
+    /// #
+    /// use envoy::extension::{Module, Result, RootId};
+    ///
+    /// fn initialize() -> Result<Module> {
+    ///     Module::new()
+    ///         // serves `root_id` "my_http_filter.ingress"
+    ///         .add_http_filter_as("my_http_filter.ingress", |_root_id, _instance_id| {
+    ///             MyHttpFilterFactory::default()
+    ///         })?
+    ///         // serves any other `root_id` that starts with "my_http_filter."
+    ///         .add_http_filter_as(RootId::prefix("my_http_filter."), |_root_id, _instance_id| {
+    ///             MyHttpFilterFactory::default()
+    ///         })
+    /// }
+    /// ```
+    pub fn add_http_filter_as<R, T, F>(self, root_id: R, mut new: F) -> Result<Self>
+    where
+        R: Into<RootId>,
+        T: ExtensionFactory + 'static,
+        T::Extension: HttpFilter,
+        F: FnMut(&str, InstanceId) -> Result<T> + 'static,
+    {
+        let factory = Box::new(
+            move |context_id, root_id: &str, settings: Settings| -> Result<Box<dyn RootContext>> {
+                let error_reporter = ErrorReporter::new(settings.error_sink)
+                    .with_root_id(root_id)
+                    .with_instance_id(InstanceId::from(context_id));
+                let http_filter_factory = new(root_id, InstanceId::from(context_id))?;
+
+                // Bridge between HTTP Filter Factory abstraction and Proxy Wasm ABI
+                Ok(Box::new(ExtensionFactoryContext::with_default_ops(
+                    http_filter_factory,
+                    error_reporter,
+                    Drainer::with_default_ops(settings.drain_timeout),
+                    Isolation::new(settings.panic_isolation),
+                    |http_filter,
+                     failure_policy,
+                     error_reporter,
+                     isolation,
+                     scope|
+                     -> ChildContext {
+                        let http_context: Box<dyn HttpContext> = match http_filter {
+                            Ok(http_filter) => Box::new(HttpFilterContext::with_default_ops(
+                                http_filter,
+                                error_reporter,
+                                failure_policy,
+                                isolation,
+                                scope,
+                            )),
+                            Err(err) => Box::new(VoidHttpFilterContext::with_default_ops(
+                                err,
+                                error_reporter,
+                                failure_policy,
+                            )),
                         };
-                    // Bridge between HTTP Filter abstraction and Proxy Wasm ABI
-                    ChildContext::HttpContext(http_context)
-                },
-            )))
-        });
-        self.add_extension(T::name(), factory)
+                        // Bridge between HTTP Filter abstraction and Proxy Wasm ABI
+                        ChildContext::HttpContext(http_context)
+                    },
+                )))
+            },
+        );
+        self.add_extension(root_id.into(), factory)
     }
 }
 
-impl Into<ContextFactoryHashMap> for Module {
-    fn into(self) -> ContextFactoryHashMap {
+impl Into<ContextFactoryRegistry> for Module {
+    fn into(self) -> ContextFactoryRegistry {
         self.factories
     }
 }
@@ -14,48 +14,51 @@
 
 use std::rc::Rc;
 
-use super::ContextFactoryHashMap;
+use super::{ContextFactoryRegistry, Settings};
 
 use crate::abi::proxy_wasm;
 use crate::abi::proxy_wasm::traits::{Context, RootContext};
 use crate::extension::error::ConfigurationError;
-use crate::extension::error::ErrorSink;
-use crate::extension::{Error, Result};
+use crate::extension::error::{ErrorCategory, ErrorReporter};
+use crate::extension::isolation::catch_panic;
+use crate::extension::{Error, InstanceId, Result};
 use crate::host::StreamInfo;
 
 pub(crate) struct ContextSelector<'a> {
-    factories: ContextFactoryHashMap,
+    factories: ContextFactoryRegistry,
     stream_info: &'a dyn StreamInfo,
+    settings: Settings,
 }
 
 impl<'a> ContextSelector<'a> {
-    pub fn new(factories: ContextFactoryHashMap, stream_info: &'a dyn StreamInfo) -> Self {
+    pub fn new(
+        factories: ContextFactoryRegistry,
+        stream_info: &'a dyn StreamInfo,
+        settings: Settings,
+    ) -> Self {
         ContextSelector {
             factories,
             stream_info,
+            settings,
         }
     }
 
-    pub fn with_default_ops(factories: ContextFactoryHashMap) -> Self {
-        Self::new(factories, StreamInfo::default())
+    pub fn with_default_ops(factories: ContextFactoryRegistry, settings: Settings) -> Self {
+        Self::new(factories, <dyn StreamInfo>::default(), settings)
     }
 
     fn new_root_context(&mut self, context_id: u32) -> Result<Box<dyn RootContext>> {
+        let settings = self.settings.clone();
         let name = match self.stream_info.plugin().root_id()? {
             Some(value) => value,
             None => String::default(),
         };
         if let Some(root_context_factory) = self.factories.get_mut(&name) {
-            return root_context_factory(context_id);
-        }
-        if name == "" && self.factories.keys().len() == 1 {
-            if let Some(root_context_factory) = self.factories.values_mut().next() {
-                return root_context_factory(context_id);
-            }
+            return catch_panic(|| root_context_factory(context_id, &name, settings));
         }
         Err(ConfigurationError::UnknownExtension {
             requested: name,
-            available: self.factories.keys().cloned().collect(),
+            available: self.factories.names(),
         }
         .into())
     }
@@ -75,8 +78,10 @@ impl ContextSelector<'static> {
             // Specifically, we're relying on the fact that every `proxy_on_context_create`
             // call will be followed by `proxy_on_configure` where we can legally
             // report back to Envoy that configuration is not valid.
+            let error_reporter = ErrorReporter::new(Rc::clone(&self.settings.error_sink))
+                .with_instance_id(InstanceId::from(context_id));
             self.new_root_context(context_id)
-                .unwrap_or_else(|e| Box::new(VoidRootContext::with_default_ops(e)))
+                .unwrap_or_else(|e| Box::new(VoidRootContext::with_default_ops(e, error_reporter)))
         });
     }
 }
@@ -95,30 +100,36 @@ impl ContextSelector<'static> {
 /// [`RootContext`]: https://docs.rs/proxy-wasm/0.1.0/proxy_wasm/traits/trait.RootContext.html
 /// [`proxy_on_context_create`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#proxy_on_context_create
 /// [`proxy_on_configure`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#proxy_on_configure
-struct VoidRootContext<'a> {
+struct VoidRootContext {
     err: Error,
-    error_sink: &'a dyn ErrorSink,
+    error_reporter: ErrorReporter,
 }
 
-impl<'a> VoidRootContext<'a> {
-    fn new(err: Error, error_sink: &'a dyn ErrorSink) -> Self {
-        VoidRootContext { err, error_sink }
+impl VoidRootContext {
+    fn new(err: Error, error_reporter: ErrorReporter) -> Self {
+        VoidRootContext {
+            err,
+            error_reporter,
+        }
     }
 
-    fn with_default_ops(err: Error) -> Self {
-        Self::new(err, ErrorSink::default())
+    fn with_default_ops(err: Error, error_reporter: ErrorReporter) -> Self {
+        Self::new(err, error_reporter)
     }
 }
 
-impl<'a> RootContext for VoidRootContext<'a> {
+impl RootContext for VoidRootContext {
     fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
-        self.error_sink
-            .observe("failed to create Proxy Wasm Root Context", &self.err);
+        self.error_reporter.observe(
+            ErrorCategory::Initialization,
+            "failed to create Proxy Wasm Root Context",
+            &self.err,
+        );
         false // indicate to Envoy that configuration is not valid
     }
 }
 
-impl<'a> Context for VoidRootContext<'a> {}
+impl Context for VoidRootContext {}
 
 pub(crate) struct VoidContextSelector {
     err: Error,
@@ -143,7 +154,10 @@ impl VoidContextSelector {
             // Specifically, we're relying on the fact that `_start`
             // call will be followed by `proxy_on_vm_start` where we can legally
             // report back to Envoy that VM state is not valid.
-            Box::new(VoidVmContext::with_default_ops(Rc::clone(&err)))
+            Box::new(VoidVmContext::with_default_ops(
+                Rc::clone(&err),
+                ErrorReporter::default(),
+            ))
         });
     }
 }
@@ -162,27 +176,33 @@ impl VoidContextSelector {
 /// [`RootContext`]: https://docs.rs/proxy-wasm/0.1.0/proxy_wasm/traits/trait.RootContext.html
 /// [`_start`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#_start
 /// [`proxy_on_vm_start`]: https://github.com/proxy-wasm/spec/tree/master/abi-versions/vNEXT#proxy_on_vm_start
-struct VoidVmContext<'a> {
+struct VoidVmContext {
     err: Rc<Error>,
-    error_sink: &'a dyn ErrorSink,
+    error_reporter: ErrorReporter,
 }
 
-impl<'a> VoidVmContext<'a> {
-    fn new(err: Rc<Error>, error_sink: &'a dyn ErrorSink) -> Self {
-        VoidVmContext { err, error_sink }
+impl VoidVmContext {
+    fn new(err: Rc<Error>, error_reporter: ErrorReporter) -> Self {
+        VoidVmContext {
+            err,
+            error_reporter,
+        }
     }
 
-    fn with_default_ops(err: Rc<Error>) -> Self {
-        Self::new(err, ErrorSink::default())
+    fn with_default_ops(err: Rc<Error>, error_reporter: ErrorReporter) -> Self {
+        Self::new(err, error_reporter)
     }
 }
 
-impl<'a> RootContext for VoidVmContext<'a> {
+impl RootContext for VoidVmContext {
     fn on_vm_start(&mut self, _vm_configuration_size: usize) -> bool {
-        self.error_sink
-            .observe("failed to initialize WebAssembly module", &self.err);
+        self.error_reporter.observe(
+            ErrorCategory::Initialization,
+            "failed to initialize WebAssembly module",
+            &self.err,
+        );
         false // indicate to Envoy that WebAssembly module is in invalid state
     }
 }
 
-impl<'a> Context for VoidVmContext<'a> {}
+impl Context for VoidVmContext {}
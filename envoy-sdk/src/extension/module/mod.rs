@@ -12,17 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
 
 use crate::abi::proxy_wasm::traits::RootContext;
+use crate::extension::error::ErrorSink;
 use crate::extension::Result;
 
 pub use self::config::Module;
+pub use self::registry::RootId;
 pub use self::start::install;
 
+use self::registry::ContextFactoryRegistry;
+
 mod config;
 mod dispatcher;
+mod registry;
 mod start;
 
-type ContextFactory = dyn FnMut(u32) -> Result<Box<dyn RootContext>>;
-type ContextFactoryHashMap = HashMap<String, Box<ContextFactory>>;
+type ContextFactory = dyn FnMut(u32, &str, Settings) -> Result<Box<dyn RootContext>>;
+
+/// Module-wide settings that apply to every extension.
+#[derive(Clone)]
+pub(crate) struct Settings {
+    error_sink: Rc<dyn ErrorSink>,
+    drain_timeout: Option<Duration>,
+    panic_isolation: bool,
+}
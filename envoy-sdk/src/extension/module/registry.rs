@@ -0,0 +1,166 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use super::ContextFactory;
+
+/// `root_id` value(s) an extension gets registered under.
+///
+/// By default, an extension is registered under the `root_id` returned by its
+/// `name()` method. `RootId` makes it possible to deploy the same extension
+/// under several `root_id`s, e.g. to apply different configurations.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::RootId;
+///
+/// // matches `root_id` "my_http_filter.ingress" only
+/// let exact: RootId = "my_http_filter.ingress".into();
+///
+/// // matches any `root_id` that starts with "my_http_filter.", e.g.
+/// // "my_http_filter.ingress" and "my_http_filter.egress"
+/// let prefix = RootId::prefix("my_http_filter.");
+///
+/// assert!(exact.matches("my_http_filter.ingress"));
+/// assert!(prefix.matches("my_http_filter.egress"));
+/// assert!(!prefix.matches("other_http_filter"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RootId(Pattern);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Pattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl RootId {
+    /// Matches a given `root_id` exactly.
+    pub fn exact<T: Into<String>>(root_id: T) -> Self {
+        RootId(Pattern::Exact(root_id.into()))
+    }
+
+    /// Matches every `root_id` that starts with a given prefix.
+    pub fn prefix<T: Into<String>>(prefix: T) -> Self {
+        RootId(Pattern::Prefix(prefix.into()))
+    }
+
+    /// Returns `true` if a given `root_id` matches this pattern.
+    pub fn matches(&self, root_id: &str) -> bool {
+        match &self.0 {
+            Pattern::Exact(name) => name == root_id,
+            Pattern::Prefix(prefix) => root_id.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl From<&str> for RootId {
+    fn from(root_id: &str) -> Self {
+        RootId::exact(root_id)
+    }
+}
+
+impl From<String> for RootId {
+    fn from(root_id: String) -> Self {
+        RootId::exact(root_id)
+    }
+}
+
+impl std::fmt::Display for RootId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.0 {
+            Pattern::Exact(name) => write!(f, "{}", name),
+            Pattern::Prefix(prefix) => write!(f, "{}*", prefix),
+        }
+    }
+}
+
+/// Registry of factories of `Proxy Wasm` Root Contexts keyed by `root_id`.
+#[derive(Default)]
+pub(crate) struct ContextFactoryRegistry {
+    exact: HashMap<String, Box<ContextFactory>>,
+    prefixes: Vec<(String, Box<ContextFactory>)>,
+}
+
+impl ContextFactoryRegistry {
+    /// Registers a factory under a given `root_id` pattern.
+    ///
+    /// Returns the factory back if the pattern is already taken.
+    pub fn insert(
+        &mut self,
+        root_id: RootId,
+        factory: Box<ContextFactory>,
+    ) -> std::result::Result<(), Box<ContextFactory>> {
+        match root_id.0 {
+            Pattern::Exact(name) => {
+                if self.exact.contains_key(&name) {
+                    return Err(factory);
+                }
+                self.exact.insert(name, factory);
+            }
+            Pattern::Prefix(prefix) => {
+                if self.prefixes.iter().any(|(other, _)| other == &prefix) {
+                    return Err(factory);
+                }
+                self.prefixes.push((prefix, factory));
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up a factory for a given `root_id`.
+    ///
+    /// Exact registrations take precedence over prefix ones; among prefix
+    /// registrations the longest matching prefix wins.
+    ///
+    /// Empty `root_id` is resolved to the only registered factory, if any.
+    pub fn get_mut(&mut self, root_id: &str) -> Option<&mut Box<ContextFactory>> {
+        if self.exact.contains_key(root_id) {
+            return self.exact.get_mut(root_id);
+        }
+        let longest_prefix = self
+            .prefixes
+            .iter()
+            .enumerate()
+            .filter(|(_, (prefix, _))| root_id.starts_with(prefix.as_str()))
+            .max_by_key(|(_, (prefix, _))| prefix.len())
+            .map(|(index, _)| index);
+        if let Some(index) = longest_prefix {
+            return Some(&mut self.prefixes[index].1);
+        }
+        if root_id.is_empty() && self.exact.len() == 1 && self.prefixes.is_empty() {
+            return self.exact.values_mut().next();
+        }
+        None
+    }
+
+    /// Returns `root_id` values registered so far, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .exact
+            .keys()
+            .cloned()
+            .chain(
+                self.prefixes
+                    .iter()
+                    .map(|(prefix, _)| RootId::prefix(prefix.as_str()).to_string()),
+            )
+            .collect();
+        names.sort();
+        names
+    }
+}
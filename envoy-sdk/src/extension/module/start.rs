@@ -128,7 +128,10 @@ macro_rules! entrypoint {
 #[doc(hidden)]
 pub fn install(config: Result<Module>) {
     match config {
-        Ok(module) => ContextSelector::with_default_ops(module.into()).install(),
+        Ok(module) => {
+            let settings = module.settings();
+            ContextSelector::with_default_ops(module.into(), settings).install()
+        }
         Err(err) => VoidContextSelector::new(err).install(),
     }
 }
@@ -0,0 +1,238 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use super::{ConfigureOps, ContextOps, Service};
+use crate::abi::proxy_wasm::traits::{Context, RootContext};
+use crate::extension::drain::Drainer;
+use crate::extension::error::{ErrorCategory, ErrorReporter};
+use crate::extension::isolation::Isolation;
+use crate::extension::{ConfigStatus, DrainStatus, Result};
+use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
+use crate::host::shared_queue::SharedQueueHandle;
+use crate::host::{self, ByteString};
+
+pub(crate) struct ServiceContext<'a, S>
+where
+    S: Service,
+{
+    service: S,
+    context_ops: &'a dyn ContextOps,
+    configure_ops: &'a dyn ConfigureOps,
+    http_client_ops: &'a dyn HttpClientResponseOps,
+    error_reporter: ErrorReporter,
+    isolation: Isolation,
+    drainer: Drainer<'a>,
+}
+
+impl<'a, S> RootContext for ServiceContext<'a, S>
+where
+    S: Service,
+{
+    fn on_configure(&mut self, configuration_size: usize) -> bool {
+        let config = if configuration_size == 0 {
+            Ok(ByteString::default())
+        } else {
+            self.context_ops.configuration(0, configuration_size)
+        };
+        match config.and_then(|config| self.call(|service, ops| service.on_configure(config, ops)))
+        {
+            Ok(status) => status.as_bool(),
+            Err(err) => {
+                self.error_reporter.observe(
+                    ErrorCategory::Configuration,
+                    "failed to configure extension",
+                    &err,
+                );
+                ConfigStatus::Rejected.as_bool()
+            }
+        }
+    }
+
+    fn on_tick(&mut self) {
+        // the drainer stops its own timer once draining completes
+        let is_drain_tick = self.drainer.is_drain_tick();
+        if let Err(err) = self.drainer.on_tick() {
+            self.error_reporter.observe(
+                ErrorCategory::Drain,
+                "failed to drain the extension",
+                &err,
+            );
+        }
+        if is_drain_tick {
+            // the extension doesn't expect `on_tick` callbacks
+            return;
+        }
+        if let Err(err) = self.call(|service, _| service.on_tick()) {
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to handle a timer tick",
+                &err,
+            );
+        }
+    }
+
+    fn on_queue_ready(&mut self, queue_id: u32) {
+        if let Err(err) =
+            self.call(|service, _| service.on_queue_ready(SharedQueueHandle::from(queue_id)))
+        {
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to process messages in a shared queue",
+                &err,
+            );
+        }
+    }
+}
+
+impl<'a, S> Context for ServiceContext<'a, S>
+where
+    S: Service,
+{
+    fn on_done(&mut self) -> bool {
+        let status = if self.isolation.is_failed() {
+            Some(DrainStatus::Complete)
+        } else {
+            match self.call(|service, _| service.on_drain()) {
+                Ok(status) => Some(status),
+                Err(err) => {
+                    self.error_reporter.observe(
+                        ErrorCategory::Drain,
+                        "failed to initiate draining of the extension",
+                        &err,
+                    );
+                    None
+                }
+            }
+        };
+        match self.drainer.on_drain(status) {
+            Ok(status) => status.as_bool(),
+            Err(err) => {
+                self.error_reporter.observe(
+                    ErrorCategory::Drain,
+                    "failed to initiate draining of the extension",
+                    &err,
+                );
+                status.unwrap_or(DrainStatus::Ongoing).as_bool()
+            }
+        }
+    }
+
+    // Http Client callbacks
+
+    fn on_http_call_response(
+        &mut self,
+        token_id: u32,
+        num_headers: usize,
+        body_size: usize,
+        num_trailers: usize,
+    ) {
+        self.drainer.on_http_call_response(token_id);
+        let http_client_ops = self.http_client_ops;
+        if let Err(err) = self.call(|service, _| {
+            service.on_http_call_response(
+                HttpClientRequestHandle::from(token_id),
+                num_headers,
+                body_size,
+                num_trailers,
+                http_client_ops,
+            )
+        }) {
+            self.error_reporter.observe(
+                ErrorCategory::Callback,
+                "failed to process a response to an HTTP request made by the extension",
+                &err,
+            );
+        }
+        if let Err(err) = self.drainer.poll() {
+            self.error_reporter.observe(
+                ErrorCategory::Drain,
+                "failed to drain the extension",
+                &err,
+            );
+        }
+    }
+}
+
+impl<'a, S> ServiceContext<'a, S>
+where
+    S: Service,
+{
+    pub fn new(
+        service: S,
+        context_ops: &'a dyn ContextOps,
+        configure_ops: &'a dyn ConfigureOps,
+        http_client_ops: &'a dyn HttpClientResponseOps,
+        error_reporter: ErrorReporter,
+        drainer: Drainer<'a>,
+        isolation: Isolation,
+    ) -> Self {
+        ServiceContext {
+            service,
+            context_ops,
+            configure_ops,
+            http_client_ops,
+            error_reporter,
+            isolation,
+            drainer,
+        }
+    }
+
+    /// Creates a new Service context bound to the actual Envoy ABI.
+    pub fn with_default_ops(
+        service: S,
+        error_reporter: ErrorReporter,
+        drainer: Drainer<'a>,
+        isolation: Isolation,
+    ) -> Self {
+        Self::new(
+            service,
+            <dyn ContextOps>::default(),
+            <dyn ConfigureOps>::default(),
+            <dyn HttpClientResponseOps>::default(),
+            error_reporter,
+            drainer,
+            isolation,
+        )
+    }
+
+    /// Calls the service unless it has panicked earlier.
+    fn call<T, C>(&mut self, callback: C) -> Result<T>
+    where
+        C: FnOnce(&mut S, &dyn ConfigureOps) -> Result<T>,
+    {
+        let (service, isolation, drainer) = (&mut self.service, &self.isolation, &self.drainer);
+        let ops = TickTrackingOps {
+            ops: self.configure_ops,
+            drainer,
+        };
+        drainer.enter(|| isolation.call(|| callback(service, &ops)))
+    }
+}
+
+/// Lets [`Drainer`] know about the tick period set by the service.
+///
+/// [`Drainer`]: ../../drain/struct.Drainer.html
+struct TickTrackingOps<'b, 'a> {
+    ops: &'a dyn ConfigureOps,
+    drainer: &'b Drainer<'a>,
+}
+
+impl<'b, 'a> ConfigureOps for TickTrackingOps<'b, 'a> {
+    fn set_tick_period(&self, period: Duration) -> host::Result<()> {
+        self.ops.set_tick_period(period)?;
+        self.drainer.on_tick_period(period)
+    }
+}
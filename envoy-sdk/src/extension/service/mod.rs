@@ -0,0 +1,285 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `Envoy` `Service` extension.
+//!
+//! `Service` is an extension that doesn't participate in handling of HTTP streams
+//! or TCP connections. Instead, it runs in the background, e.g. to periodically sync
+//! configuration or to aggregate telemetry reported by other extensions.
+//!
+//! In `Envoy` configuration, a `Service` is usually deployed as a [`singleton`],
+//! i.e. a single instance per `Envoy` process rather than one per worker thread.
+//!
+//! Creating a new `Service` extension using `Envoy SDK` consists of the following steps:
+//!
+//! 1. Implement [`Service`] trait to define core logic of your extension
+//! 2. [`Register`] your extension on WebAssembly module start up
+//!
+//! # Examples
+//!
+//! #### Basic [`Service`]:
+//!
+//! ```
+//! # use envoy_sdk as envoy;
+//! use std::time::Duration;
+//! use envoy::extension::{ConfigStatus, Result, Service};
+//! use envoy::extension::service::ConfigureOps;
+//! use envoy::host::{ByteString, log};
+//!
+//! /// My very own `Service`.
+//! struct MyService;
+//!
+//! impl Service for MyService {
+//!     fn name() -> &'static str { "my_service" }
+//!
+//!     fn on_configure(&mut self, _config: ByteString, ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+//!         ops.set_tick_period(Duration::from_secs(10))?;
+//!         Ok(ConfigStatus::Accepted)
+//!     }
+//!
+//!     fn on_tick(&mut self) -> Result<()> {
+//!         log::info!("tick");
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! #### Registration of `MyService` on start up:
+//!
+//! ```
+//! # use envoy_sdk as envoy;
+//! # use envoy::extension::Service;
+//! #
+//! # /// My very own `Service`.
+//! # struct MyService;
+//! #
+//! # impl Service for MyService {
+//! #     fn name() -> &'static str { "my_service" }
+//! # }
+//! #
+//! use envoy::extension::{entrypoint, Module, Result};
+//!
+//! entrypoint! { initialize } // put initialization logic into a function to make it unit testable
+//!
+//! fn initialize() -> Result<Module> {
+//!     Module::new()
+//!         .add_service(|_instance_id| Ok(MyService))
+//! }
+//! ```
+//!
+//! [`singleton`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/extensions/wasm/v3/wasm.proto#extensions-wasm-v3-wasmservice
+//! [`Service`]: trait.Service.html
+//! [`Register`]: ../../macro.entrypoint.html
+
+use std::time::Duration;
+
+use crate::extension::{ConfigStatus, DrainStatus, Result};
+use crate::host::http::client::{HttpClientRequestHandle, HttpClientResponseOps};
+use crate::host::shared_queue::SharedQueueHandle;
+use crate::host::{self, ByteString};
+
+pub(crate) use self::context::ServiceContext;
+
+mod context;
+mod ops;
+
+/// An interface of the `Envoy` `Service` extension.
+///
+/// # Examples
+///
+/// #### `Service` that aggregates events sent by other extensions through a shared queue:
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::extension::{ConfigStatus, Result, Service};
+/// use envoy::extension::service::ConfigureOps;
+/// use envoy::host::{ByteString, SharedQueue};
+/// use envoy::host::shared_queue::{Receiver, SharedQueueHandle};
+///
+/// struct EventAggregator<'a> {
+///     shared_queue: &'a dyn SharedQueue,
+///     events: Option<Receiver<'a, String>>,
+///     total: usize,
+/// }
+///
+/// impl<'a> Service for EventAggregator<'a> {
+///     fn name() -> &'static str { "event_aggregator" }
+///
+///     fn on_configure(&mut self, _config: ByteString, _ops: &dyn ConfigureOps) -> Result<ConfigStatus> {
+///         self.events = Some(Receiver::register(self.shared_queue, "events")?);
+///         Ok(ConfigStatus::Accepted)
+///     }
+///
+///     fn on_queue_ready(&mut self, queue: SharedQueueHandle) -> Result<()> {
+///         if let Some(events) = &self.events {
+///             if events.handle() == queue {
+///                 self.total += events.drain(|_event| {})?;
+///             }
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// # NOTE
+///
+/// **This trait MUST NOT panic!**
+///
+/// If a service invocation cannot proceed normally, it should return [`Result::Err(x)`].
+/// In that case, `Envoy SDK` will be able to handle the error gracefully.
+///
+/// [`Result::Err(x)`]: https://doc.rust-lang.org/core/result/enum.Result.html#variant.Err
+pub trait Service {
+    /// Returns a name the extension should be referred to in `Envoy` configuration.
+    fn name() -> &'static str
+    where
+        Self: Sized;
+
+    /// Called when `Service` is being (re-)configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `_config` - configuration.
+    /// * `_ops`    - a [`trait object`][`ConfigureOps`] with operations available in this context.
+    ///
+    /// # Return value
+    ///
+    /// [`ConfigStatus`] telling `Envoy` whether configuration has been successfully applied.
+    ///
+    /// [`ConfigStatus`]: ../factory/enum.ConfigStatus.html
+    /// [`ConfigureOps`]: trait.ConfigureOps.html
+    fn on_configure(
+        &mut self,
+        _config: ByteString,
+        _ops: &dyn ConfigureOps,
+    ) -> Result<ConfigStatus> {
+        Ok(ConfigStatus::Accepted)
+    }
+
+    /// Called periodically once a tick period has been set through [`ConfigureOps`].
+    ///
+    /// [`ConfigureOps`]: trait.ConfigureOps.html
+    fn on_tick(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when messages are available in a shared queue registered by this `Wasm VM`.
+    ///
+    /// # Arguments
+    ///
+    /// * `_queue` - opaque identifier of the queue.
+    fn on_queue_ready(&mut self, _queue: SharedQueueHandle) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when `Service` is about to be destroyed.
+    ///
+    /// # Return value
+    ///
+    /// [`DrainStatus`] telling `Envoy` whether `Service` has already been drained
+    /// and can be now removed safely.
+    ///
+    /// Either way, `Envoy` is not acknowledged until all HTTP requests made by
+    /// `Service` receive a response or the drain timeout configured by
+    /// [`Module::with_drain_timeout`] expires.
+    ///
+    /// [`DrainStatus`]: ../factory/enum.DrainStatus.html
+    /// [`Module::with_drain_timeout`]: ../struct.Module.html#method.with_drain_timeout
+    fn on_drain(&mut self) -> Result<DrainStatus> {
+        Ok(DrainStatus::Complete)
+    }
+
+    // Http Client callbacks
+
+    /// Called when the async HTTP request made through [`Envoy HTTP Client API`][`HttpClient`] is complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id`      - opaque identifier of the request that is now complete.
+    /// * `num_headers`     - number of headers in the response.
+    /// * `body_size`       - size of the response body.
+    /// * `num_trailers`    - number of tarilers in the response.
+    /// * `http_client_ops` - a [`trait object`][`HttpClientResponseOps`] through which `Service` can access
+    ///                       data of the response received by [`HttpClient`], including headers, body and trailers.
+    ///
+    /// [`HttpClient`]: ../../host/http/client/trait.HttpClient.html
+    /// [`HttpClientResponseOps`]: ../../host/http/client/trait.HttpClientResponseOps.html
+    fn on_http_call_response(
+        &mut self,
+        _request_id: HttpClientRequestHandle,
+        _num_headers: usize,
+        _body_size: usize,
+        _num_trailers: usize,
+        _http_client_ops: &dyn HttpClientResponseOps,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An interface for accessing extension config.
+pub(crate) trait ContextOps {
+    /// Returns extension config.
+    fn configuration(&self, start: usize, max_size: usize) -> host::Result<ByteString>;
+}
+
+impl dyn ContextOps {
+    /// Returns the default implementation that interacts with `Envoy`
+    /// through its [`ABI`].
+    ///
+    /// [`ABI`]: https://github.com/proxy-wasm/spec
+    pub fn default() -> &'static dyn ContextOps {
+        &ops::Host
+    }
+}
+
+/// An interface for operations available in the context of [`on_configure`]
+/// invocation.
+///
+/// [`on_configure`]: trait.Service.html#method.on_configure
+pub trait ConfigureOps {
+    /// Sets the period of [`on_tick`] invocations.
+    ///
+    /// A zero period disables the timer.
+    ///
+    /// [`on_tick`]: trait.Service.html#method.on_tick
+    fn set_tick_period(&self, period: Duration) -> host::Result<()>;
+}
+
+impl dyn ConfigureOps {
+    /// Returns the default implementation that interacts with `Envoy`
+    /// through its [`ABI`].
+    ///
+    /// [`ABI`]: https://github.com/proxy-wasm/spec
+    pub fn default() -> &'static dyn ConfigureOps {
+        &ops::Host
+    }
+}
+
+/// An interface for acknowledging `Envoy` that `Service` has been drained.
+///
+/// [`Service`]: trait.Service.html
+pub trait DrainOps {
+    /// Acknowledges `Envoy` that extension has been drained and can be safely removed now.
+    fn done(&self) -> host::Result<()>;
+}
+
+impl dyn DrainOps {
+    /// Returns the default implementation that interacts with `Envoy`
+    /// through its [`ABI`].
+    ///
+    /// [`ABI`]: https://github.com/proxy-wasm/spec
+    pub fn default() -> &'static dyn DrainOps {
+        &ops::Host
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache with expiration shared by all `Wasm VM`s through the `Envoy` `Shared Data API`.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{CasMismatchError, SharedData, SharedValue};
+use crate::host::time::TimeUnit;
+use crate::host::{self, Clock};
+
+/// Cache entry: expiration time (in milliseconds since UNIX epoch) and a value,
+/// where `None` stands for a negatively cached key.
+type Entry<V> = (u64, Option<V>);
+
+/// Index of cache entries: key -> expiration time (in milliseconds since UNIX epoch).
+type Index = BTreeMap<String, u64>;
+
+/// Result of a [`SharedCache`] lookup.
+///
+/// [`SharedCache`]: struct.SharedCache.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheLookup<V> {
+    /// A value is cached and hasn't expired yet.
+    Hit(V),
+    /// The key is known to have no value, and that knowledge hasn't expired yet.
+    NegativeHit,
+    /// There is no cached value or it has expired.
+    Miss,
+}
+
+/// A cache with expiration shared by all `Wasm VM`s (i.e., all worker threads)
+/// through [`SharedData`].
+///
+/// Values are stored together with their expiration time according to a [`Clock`].
+///
+/// * Absence of a value can be cached too, see [`put_negative`].
+/// * To avoid every worker refreshing the same expired value at the same time,
+///   a refresh can be coordinated through [`try_begin_refresh`].
+///   Coordination is best effort, e.g. the very first acquisition of a refresh lock
+///   is not protected against a race.
+/// * If the number of entries is bounded, entries that have already expired
+///   get evicted first, then entries that would expire the soonest.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use std::time::Duration;
+/// use envoy::host::{Clock, SharedData};
+/// use envoy::host::shared_data::{CacheLookup, SharedCache};
+///
+/// let tokens: SharedCache<String> = SharedCache::new(SharedData::default(), Clock::default(), "tokens")
+///     .with_ttl(Duration::from_secs(300))
+///     .with_max_entries(100);
+///
+/// match tokens.get("client-a")? {
+///     CacheLookup::Hit(token) => { /* use the token */ }
+///     CacheLookup::NegativeHit => { /* the client is known to have no token */ }
+///     CacheLookup::Miss => {
+///         if tokens.try_begin_refresh("client-a")? {
+///             // fetch the token, e.g. through `HttpClient`, and once it's available
+///             tokens.put("client-a", "token".to_owned())?;
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedData`]: trait.SharedData.html
+/// [`Clock`]: ../time/trait.Clock.html
+/// [`put_negative`]: #method.put_negative
+/// [`try_begin_refresh`]: #method.try_begin_refresh
+pub struct SharedCache<'a, V> {
+    shared_data: &'a dyn SharedData,
+    clock: &'a dyn Clock,
+    name: String,
+    ttl: Duration,
+    negative_ttl: Duration,
+    refresh_timeout: Duration,
+    max_entries: Option<usize>,
+    value_type: PhantomData<fn() -> V>,
+}
+
+impl<'a, V> SharedCache<'a, V>
+where
+    V: Serialize + DeserializeOwned,
+{
+    /// Default time to live of cached values.
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+    /// Default time to live of negatively cached keys.
+    pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(10);
+    /// Default time after which an unfinished refresh is considered abandoned.
+    pub const DEFAULT_REFRESH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Creates a new handle to a cache with a given name.
+    ///
+    /// All handles with the same name share the same cache.
+    pub fn new<N: Into<String>>(
+        shared_data: &'a dyn SharedData,
+        clock: &'a dyn Clock,
+        name: N,
+    ) -> Self {
+        SharedCache {
+            shared_data,
+            clock,
+            name: name.into(),
+            ttl: Self::DEFAULT_TTL,
+            negative_ttl: Self::DEFAULT_NEGATIVE_TTL,
+            refresh_timeout: Self::DEFAULT_REFRESH_TIMEOUT,
+            max_entries: None,
+            value_type: PhantomData,
+        }
+    }
+
+    /// Sets time to live of cached values.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets time to live of negatively cached keys.
+    pub fn with_negative_ttl(mut self, negative_ttl: Duration) -> Self {
+        self.negative_ttl = negative_ttl;
+        self
+    }
+
+    /// Sets time after which an unfinished refresh is considered abandoned.
+    pub fn with_refresh_timeout(mut self, refresh_timeout: Duration) -> Self {
+        self.refresh_timeout = refresh_timeout;
+        self
+    }
+
+    /// Bounds the number of cached entries.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries.max(1));
+        self
+    }
+
+    /// Returns name of the cache.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Looks up a cached value by key.
+    pub fn get(&self, key: &str) -> host::Result<CacheLookup<V>> {
+        let now = self.now()?;
+        Ok(match self.entry(key).get()? {
+            Some((expires_at, value)) if now < expires_at => match value {
+                Some(value) => CacheLookup::Hit(value),
+                None => CacheLookup::NegativeHit,
+            },
+            _ => CacheLookup::Miss,
+        })
+    }
+
+    /// Caches a value under a given key.
+    ///
+    /// Finishes a refresh of the key, if any.
+    pub fn put(&self, key: &str, value: V) -> host::Result<()> {
+        self.put_entry(key, Some(value), self.ttl)
+    }
+
+    /// Caches absence of a value under a given key.
+    ///
+    /// Finishes a refresh of the key, if any.
+    pub fn put_negative(&self, key: &str) -> host::Result<()> {
+        self.put_entry(key, None, self.negative_ttl)
+    }
+
+    /// Removes a cached value by key.
+    pub fn invalidate(&self, key: &str) -> host::Result<()> {
+        if self.max_entries.is_some() {
+            self.index().try_update(|index| {
+                let mut index = index?;
+                index.remove(key).map(|_| index)
+            })?;
+        }
+        self.entry(key).remove()
+    }
+
+    /// Tries to become the one responsible for refreshing a value under a given key.
+    ///
+    /// Returns `false` if another refresh of the same key is already in progress
+    /// and hasn't timed out yet.
+    ///
+    /// A refresh is finished by either [`put`], [`put_negative`] or [`abort_refresh`].
+    ///
+    /// [`put`]: #method.put
+    /// [`put_negative`]: #method.put_negative
+    /// [`abort_refresh`]: #method.abort_refresh
+    pub fn try_begin_refresh(&self, key: &str) -> host::Result<bool> {
+        let now = self.now()?;
+        let lock = self.lock(key);
+        let (deadline, version) = lock.get_with_version()?;
+        if deadline.is_some_and(|deadline| now < deadline) {
+            return Ok(false);
+        }
+        match lock.set_with_version(&(now + as_millis(self.refresh_timeout)), version) {
+            Ok(()) => Ok(true),
+            Err(err) if err.is::<CasMismatchError>() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Gives up on refreshing a value under a given key.
+    pub fn abort_refresh(&self, key: &str) -> host::Result<()> {
+        self.finish_refresh(key)
+    }
+
+    fn put_entry(&self, key: &str, value: Option<V>, ttl: Duration) -> host::Result<()> {
+        let expires_at = self.now()? + as_millis(ttl);
+        if let Some(max_entries) = self.max_entries {
+            self.evict(key, expires_at, max_entries)?;
+        }
+        self.entry(key).set(&(expires_at, value))?;
+        self.finish_refresh(key)
+    }
+
+    fn evict(&self, key: &str, expires_at: u64, max_entries: usize) -> host::Result<()> {
+        let now = self.now()?;
+        let mut evicted = Vec::new();
+        self.index().update(|index| {
+            evicted.clear();
+            let mut index = index.unwrap_or_default();
+            index.insert(key.to_owned(), expires_at);
+            if index.len() > max_entries {
+                let mut candidates: Vec<(u64, String)> = index
+                    .iter()
+                    .filter(|(candidate, _)| candidate.as_str() != key)
+                    .map(|(candidate, expires_at)| (*expires_at, candidate.clone()))
+                    .collect();
+                candidates.sort();
+                for (expires_at, candidate) in candidates {
+                    if index.len() <= max_entries && now < expires_at {
+                        break;
+                    }
+                    index.remove(&candidate);
+                    evicted.push(candidate);
+                }
+            }
+            index
+        })?;
+        for key in evicted {
+            self.entry(&key).remove()?;
+        }
+        Ok(())
+    }
+
+    fn finish_refresh(&self, key: &str) -> host::Result<()> {
+        let lock = self.lock(key);
+        if let (Some(_), version) = lock.get_with_version()? {
+            // the lock might have been taken over by another refresh in the meantime
+            if let Err(err) = lock.set_with_version(&0, version) {
+                if !err.is::<CasMismatchError>() {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, key: &str) -> SharedValue<'a, Entry<V>> {
+        SharedValue::new(self.shared_data, format!("{}.entries.{}", self.name, key))
+    }
+
+    fn lock(&self, key: &str) -> SharedValue<'a, u64> {
+        SharedValue::new(self.shared_data, format!("{}.locks.{}", self.name, key))
+    }
+
+    fn index(&self) -> SharedValue<'a, Index> {
+        SharedValue::new(self.shared_data, format!("{}.index", self.name))
+    }
+
+    fn now(&self) -> host::Result<u64> {
+        let now = self.clock.now()?;
+        Ok(as_millis(
+            now.duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default(),
+        ))
+    }
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    TimeUnit::Milliseconds.convert(duration)
+}
@@ -14,10 +14,17 @@
 
 //! `Envoy` `Shared Data API`.
 
+use std::fmt;
+
 use crate::host::{self, ByteString};
 
+pub use self::cache::{CacheLookup, SharedCache};
+pub use self::typed::{SharedCounter, SharedMap, SharedValue};
 pub use crate::abi::proxy_wasm::types::OptimisticLockVersion;
 
+mod cache;
+mod typed;
+
 /// An interface of the `Envoy` `Shared Data API`.
 ///
 /// Basic usage of [`SharedData`]:
@@ -76,6 +83,9 @@ pub trait SharedData {
 
     /// Shares data under a given key.
     ///
+    /// If `version` is given and doesn't match the current version of the data,
+    /// fails with [`CasMismatchError`].
+    ///
     /// # Arguments
     ///
     /// * `key`     - key.
@@ -89,6 +99,62 @@ pub trait SharedData {
     ) -> host::Result<()>;
 }
 
+/// An error returned by [`SharedData::set`] when the optimistic lock version
+/// of the shared data has changed since it was read.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::SharedData;
+/// use envoy::host::shared_data::CasMismatchError;
+///
+/// let shared_data = SharedData::default();
+///
+/// let (_, version) = shared_data.get("shared_key")?;
+///
+/// match shared_data.set("shared_key", b"shared value", version) {
+///     Err(err) if err.is::<CasMismatchError>() => {
+///         // the data has been modified concurrently
+///     }
+///     result => result?,
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedData::set`]: trait.SharedData.html#tymethod.set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CasMismatchError {
+    key: String,
+}
+
+impl CasMismatchError {
+    /// Creates a new error for a given key.
+    pub fn new<K: Into<String>>(key: K) -> Self {
+        CasMismatchError { key: key.into() }
+    }
+
+    /// Returns key of the shared data.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl fmt::Display for CasMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "shared data under the key \"{}\" has been modified concurrently",
+            self.key
+        )
+    }
+}
+
+impl std::error::Error for CasMismatchError {}
+
 impl dyn SharedData {
     /// Returns the default implementation that interacts with `Envoy`
     /// through its [`ABI`].
@@ -0,0 +1,371 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed layer on top of the `Envoy` `Shared Data API`.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{CasMismatchError, OptimisticLockVersion, SharedData};
+use crate::error::format_err;
+use crate::host::{self, ErrorContext};
+
+/// A value of type `T` shared under a given key of [`SharedData`].
+///
+/// Values are encoded as `JSON`.
+///
+/// Concurrent modifications are detected by means of the optimistic lock version.
+/// [`update`] applies a given function to the current value and retries
+/// on a version mismatch up to a configured number of attempts.
+///
+/// If the value doesn't exist yet, [`update`] first creates an empty entry
+/// so that the value itself is always written under the optimistic lock.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::SharedData;
+/// use envoy::host::shared_data::SharedValue;
+///
+/// let shared_data = SharedData::default();
+///
+/// let allowed_clients: SharedValue<Vec<String>> = SharedValue::new(shared_data, "allowed_clients");
+///
+/// allowed_clients.update(|clients| {
+///     let mut clients = clients.unwrap_or_default();
+///     clients.push("client-a".to_owned());
+///     clients
+/// })?;
+///
+/// let clients = allowed_clients.get()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedData`]: trait.SharedData.html
+/// [`update`]: #method.update
+pub struct SharedValue<'a, T> {
+    shared_data: &'a dyn SharedData,
+    key: String,
+    max_attempts: usize,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> SharedValue<'a, T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Default number of attempts [`update`] makes before giving up.
+    ///
+    /// [`update`]: #method.update
+    pub const DEFAULT_MAX_ATTEMPTS: usize = 10;
+
+    /// Creates a new handle to a value shared under a given key.
+    pub fn new<K: Into<String>>(shared_data: &'a dyn SharedData, key: K) -> Self {
+        SharedValue {
+            shared_data,
+            key: key.into(),
+            max_attempts: Self::DEFAULT_MAX_ATTEMPTS,
+            value_type: PhantomData,
+        }
+    }
+
+    /// Sets the number of attempts [`update`] makes before giving up.
+    ///
+    /// [`update`]: #method.update
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Returns the key the value is shared under.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the current value.
+    pub fn get(&self) -> host::Result<Option<T>> {
+        self.get_with_version().map(|(value, _)| value)
+    }
+
+    /// Returns the current value along with its optimistic lock version.
+    pub fn get_with_version(&self) -> host::Result<(Option<T>, Option<OptimisticLockVersion>)> {
+        let (data, version) = self.shared_data.get(&self.key)?;
+        let value = match data {
+            Some(data) if !data.is_empty() => Some(
+                serde_json::from_slice(&data)
+                    .with_context(|| format!("failed to decode shared value \"{}\"", self.key))?,
+            ),
+            _ => None,
+        };
+        Ok((value, version))
+    }
+
+    /// Replaces the current value unconditionally.
+    pub fn set(&self, value: &T) -> host::Result<()> {
+        self.set_with_version(value, None)
+    }
+
+    /// Removes the current value unconditionally.
+    pub fn remove(&self) -> host::Result<()> {
+        self.shared_data.set(&self.key, &[], None)
+    }
+
+    /// Replaces the current value if its optimistic lock version still matches a given one.
+    ///
+    /// Fails with [`CasMismatchError`] otherwise.
+    ///
+    /// [`CasMismatchError`]: struct.CasMismatchError.html
+    pub fn set_with_version(
+        &self,
+        value: &T,
+        version: Option<OptimisticLockVersion>,
+    ) -> host::Result<()> {
+        let data = serde_json::to_vec(value)
+            .with_context(|| format!("failed to encode shared value \"{}\"", self.key))?;
+        self.shared_data.set(&self.key, &data, version)
+    }
+
+    /// Replaces the current value with the one computed by a given function.
+    ///
+    /// The function might be called more than once if the value gets modified concurrently.
+    ///
+    /// Returns the new value.
+    pub fn update<F>(&self, mut f: F) -> host::Result<T>
+    where
+        F: FnMut(Option<T>) -> T,
+    {
+        self.try_update(|value| Some(f(value)))?.ok_or_else(|| {
+            format_err!(
+                "failed to update shared value \"{}\": no value has been computed",
+                self.key
+            )
+        })
+    }
+
+    /// Replaces the current value with the one computed by a given function,
+    /// unless the function returns `None`.
+    ///
+    /// The function might be called more than once if the value gets modified concurrently.
+    ///
+    /// Returns the new value or `None` if the value has been left unchanged.
+    pub fn try_update<F>(&self, mut f: F) -> host::Result<Option<T>>
+    where
+        F: FnMut(Option<T>) -> Option<T>,
+    {
+        let mut attempt = 0;
+        let mut is_seeded = false;
+        loop {
+            let (value, version) = self.get_with_version()?;
+            let value = match f(value) {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            if version.is_none() && !is_seeded {
+                // a write to a missing key cannot be checked against a version,
+                // so create an empty entry first and retry against its version
+                self.seed()?;
+                is_seeded = true;
+                continue;
+            }
+            attempt += 1;
+            match self.set_with_version(&value, version) {
+                Ok(()) => return Ok(Some(value)),
+                Err(err) if err.is::<CasMismatchError>() && attempt < self.max_attempts => continue,
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!(
+                            "failed to update shared value \"{}\" after {} attempt(s)",
+                            self.key, attempt
+                        )
+                    })
+                }
+            }
+        }
+    }
+
+    fn seed(&self) -> host::Result<()> {
+        self.shared_data
+            .set(&self.key, &[], None)
+            .with_context(|| format!("failed to create shared value \"{}\"", self.key))
+    }
+}
+
+/// A counter shared under a given key of [`SharedData`].
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::SharedData;
+/// use envoy::host::shared_data::SharedCounter;
+///
+/// let shared_data = SharedData::default();
+///
+/// let requests_total = SharedCounter::new(shared_data, "requests_total");
+///
+/// let value = requests_total.inc()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedData`]: trait.SharedData.html
+pub struct SharedCounter<'a> {
+    value: SharedValue<'a, u64>,
+}
+
+impl<'a> SharedCounter<'a> {
+    /// Creates a new handle to a counter shared under a given key.
+    pub fn new<K: Into<String>>(shared_data: &'a dyn SharedData, key: K) -> Self {
+        SharedCounter {
+            value: SharedValue::new(shared_data, key),
+        }
+    }
+
+    /// Sets the number of attempts an update makes before giving up.
+    pub fn with_max_attempts(self, max_attempts: usize) -> Self {
+        SharedCounter {
+            value: self.value.with_max_attempts(max_attempts),
+        }
+    }
+
+    /// Returns the key the counter is shared under.
+    pub fn key(&self) -> &str {
+        self.value.key()
+    }
+
+    /// Returns current value of the counter.
+    pub fn value(&self) -> host::Result<u64> {
+        self.value.get().map(Option::unwrap_or_default)
+    }
+
+    /// Increments counter by `1`.
+    ///
+    /// Returns the new value.
+    pub fn inc(&self) -> host::Result<u64> {
+        self.add(1)
+    }
+
+    /// Increments counter by a given offset.
+    ///
+    /// Returns the new value.
+    pub fn add(&self, offset: u64) -> host::Result<u64> {
+        self.value
+            .update(|value| value.unwrap_or_default().saturating_add(offset))
+    }
+
+    /// Resets counter to `0`.
+    pub fn reset(&self) -> host::Result<()> {
+        self.value.set(&0)
+    }
+}
+
+/// A map from string keys to values of type `V` shared under a given key of [`SharedData`].
+///
+/// The whole map is stored as a single value, so it's meant for small maps only.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::SharedData;
+/// use envoy::host::shared_data::SharedMap;
+///
+/// let shared_data = SharedData::default();
+///
+/// let upstream_weights: SharedMap<u32> = SharedMap::new(shared_data, "upstream_weights");
+///
+/// upstream_weights.insert("backend-a", 80)?;
+///
+/// let weight = upstream_weights.get("backend-a")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedData`]: trait.SharedData.html
+pub struct SharedMap<'a, V> {
+    value: SharedValue<'a, BTreeMap<String, V>>,
+}
+
+impl<'a, V> SharedMap<'a, V>
+where
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// Creates a new handle to a map shared under a given key.
+    pub fn new<K: Into<String>>(shared_data: &'a dyn SharedData, key: K) -> Self {
+        SharedMap {
+            value: SharedValue::new(shared_data, key),
+        }
+    }
+
+    /// Sets the number of attempts an update makes before giving up.
+    pub fn with_max_attempts(self, max_attempts: usize) -> Self {
+        SharedMap {
+            value: self.value.with_max_attempts(max_attempts),
+        }
+    }
+
+    /// Returns the key the map is shared under.
+    pub fn key(&self) -> &str {
+        self.value.key()
+    }
+
+    /// Returns a snapshot of all entries.
+    pub fn entries(&self) -> host::Result<BTreeMap<String, V>> {
+        self.value.get().map(Option::unwrap_or_default)
+    }
+
+    /// Returns a value by key.
+    pub fn get(&self, key: &str) -> host::Result<Option<V>> {
+        self.entries().map(|mut entries| entries.remove(key))
+    }
+
+    /// Inserts a value under a given key.
+    ///
+    /// Returns the previous value, if any.
+    pub fn insert<K: Into<String>>(&self, key: K, value: V) -> host::Result<Option<V>> {
+        let key = key.into();
+        let mut previous = None;
+        self.value.update(|entries| {
+            let mut entries = entries.unwrap_or_default();
+            previous = entries.insert(key.clone(), value.clone());
+            entries
+        })?;
+        Ok(previous)
+    }
+
+    /// Removes a value by key.
+    ///
+    /// Returns the removed value, if any.
+    pub fn remove(&self, key: &str) -> host::Result<Option<V>> {
+        let mut removed = None;
+        self.value.try_update(|entries| {
+            removed = None;
+            let mut entries = entries?;
+            removed = entries.remove(key);
+            removed.as_ref().map(|_| entries)
+        })?;
+        Ok(removed)
+    }
+}
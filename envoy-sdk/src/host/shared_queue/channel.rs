@@ -0,0 +1,227 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed channels on top of the `Envoy` `Shared Queue API`.
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{SharedQueue, SharedQueueHandle};
+use crate::host::{self, ErrorContext};
+
+/// Sending side of a typed channel on top of a [`SharedQueue`].
+///
+/// Messages are encoded as `JSON`.
+///
+/// The queue is resolved lazily by `(vm_id, name)` on the first [`send`]
+/// and once again after the host refuses a message, e.g. because the receiving
+/// `Wasm VM` has been restarted. Messages that cannot be delivered are dropped
+/// and counted, see [`dropped`].
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::SharedQueue;
+/// use envoy::host::shared_queue::Sender;
+///
+/// let events: Sender<String> = Sender::new(SharedQueue::default(), "my_singleton_vm", "events");
+///
+/// if !events.send(&"request completed".to_owned())? {
+///     // the message has been dropped
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedQueue`]: trait.SharedQueue.html
+/// [`send`]: #method.send
+/// [`dropped`]: #method.dropped
+pub struct Sender<'a, T> {
+    shared_queue: &'a dyn SharedQueue,
+    vm_id: String,
+    name: String,
+    queue: Cell<Option<SharedQueueHandle>>,
+    dropped: Cell<u64>,
+    message_type: PhantomData<fn(&T)>,
+}
+
+impl<'a, T> Sender<'a, T>
+where
+    T: Serialize,
+{
+    /// Creates a new sender to a queue registered under a given name by a given `Wasm VM`.
+    pub fn new<V, N>(shared_queue: &'a dyn SharedQueue, vm_id: V, name: N) -> Self
+    where
+        V: Into<String>,
+        N: Into<String>,
+    {
+        Sender {
+            shared_queue,
+            vm_id: vm_id.into(),
+            name: name.into(),
+            queue: Cell::new(None),
+            dropped: Cell::new(0),
+            message_type: PhantomData,
+        }
+    }
+
+    /// Returns ID of the `Wasm VM` the queue belongs to.
+    pub fn vm_id(&self) -> &str {
+        &self.vm_id
+    }
+
+    /// Returns name of the queue.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sends a message.
+    ///
+    /// Returns `false` if the message has been dropped, e.g. because the queue
+    /// hasn't been registered yet or the host refused to enqueue the message.
+    pub fn send(&self, message: &T) -> host::Result<bool> {
+        let data = serde_json::to_vec(message).with_context(|| {
+            format!(
+                "failed to encode a message to the shared queue \"{}\"",
+                self.name
+            )
+        })?;
+        let queue = match self.resolve()? {
+            Some(queue) => queue,
+            None => return Ok(self.drop_message()),
+        };
+        if self.shared_queue.enqueue(queue, &data).is_err() {
+            // the queue might have been re-registered, e.g. after a restart of the receiving VM
+            self.queue.set(None);
+            return Ok(self.drop_message());
+        }
+        Ok(true)
+    }
+
+    /// Returns the number of messages dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.get()
+    }
+
+    fn resolve(&self) -> host::Result<Option<SharedQueueHandle>> {
+        if let Some(queue) = self.queue.get() {
+            return Ok(Some(queue));
+        }
+        let queue = self.shared_queue.lookup(&self.vm_id, &self.name)?;
+        self.queue.set(queue);
+        Ok(queue)
+    }
+
+    fn drop_message(&self) -> bool {
+        self.dropped.set(self.dropped.get() + 1);
+        false
+    }
+}
+
+/// Receiving side of a typed channel on top of a [`SharedQueue`].
+///
+/// Messages are expected to be encoded as `JSON`, e.g. by a [`Sender`].
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::SharedQueue;
+/// use envoy::host::shared_queue::Receiver;
+///
+/// let events: Receiver<String> = Receiver::register(SharedQueue::default(), "events")?;
+///
+/// events.drain(|event| {
+///     // process the event
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedQueue`]: trait.SharedQueue.html
+/// [`Sender`]: struct.Sender.html
+pub struct Receiver<'a, T> {
+    shared_queue: &'a dyn SharedQueue,
+    name: String,
+    queue: SharedQueueHandle,
+    message_type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> Receiver<'a, T>
+where
+    T: DeserializeOwned,
+{
+    /// Registers a queue under a given name and creates a receiver from it.
+    pub fn register<N: Into<String>>(
+        shared_queue: &'a dyn SharedQueue,
+        name: N,
+    ) -> host::Result<Self> {
+        let name = name.into();
+        let queue = shared_queue.register(&name)?;
+        Ok(Receiver {
+            shared_queue,
+            name,
+            queue,
+            message_type: PhantomData,
+        })
+    }
+
+    /// Returns name of the queue.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns handle of the queue.
+    pub fn handle(&self) -> SharedQueueHandle {
+        self.queue
+    }
+
+    /// Receives a message, if any.
+    pub fn recv(&self) -> host::Result<Option<T>> {
+        match self.shared_queue.dequeue(self.queue)? {
+            Some(data) => serde_json::from_slice(&data).map(Some).with_context(|| {
+                format!(
+                    "failed to decode a message from the shared queue \"{}\"",
+                    self.name
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Receives messages until the queue is empty and passes them to a given function.
+    ///
+    /// Returns the number of received messages.
+    ///
+    /// Stops at the first message that cannot be decoded.
+    pub fn drain<F>(&self, mut f: F) -> host::Result<usize>
+    where
+        F: FnMut(T),
+    {
+        let mut count = 0;
+        while let Some(message) = self.recv()? {
+            f(message);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
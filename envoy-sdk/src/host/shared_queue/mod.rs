@@ -16,8 +16,11 @@
 
 use crate::host::{self, ByteString};
 
+pub use self::channel::{Receiver, Sender};
 pub use crate::abi::proxy_wasm::types::SharedQueueHandle;
 
+mod channel;
+
 /// An interface of the `Envoy` `Shared Queue API`.
 ///
 /// Basic usage of [`SharedQueue`]:
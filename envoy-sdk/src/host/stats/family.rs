@@ -0,0 +1,293 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metric families, i.e. metrics with tags.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{Counter, Gauge, Histogram, Stats};
+use crate::error::format_err;
+use crate::host;
+
+/// A family of metrics that share the same name and differ by values of their tags,
+/// e.g. `requests_total{route, status_class}`.
+///
+/// Every combination of tag values corresponds to a separate `Envoy` stat,
+/// e.g. `requests_total.route.default.status_class.2xx`.
+/// To get tagged metrics out of `Envoy`, configure [`stats_tags`] with regexes
+/// returned by [`tag_extraction_regex`].
+///
+/// Metrics are created on first use and cached for subsequent use.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::Stats;
+/// use envoy::host::stats::CounterFamily;
+///
+/// let requests_total = CounterFamily::new(
+///     Stats::default(),
+///     "requests_total",
+///     &["route", "status_class"],
+/// )?;
+///
+/// requests_total.with_tag_values(&["default", "2xx"])?.inc()?;
+///
+/// // regexes to use in `stats_config.stats_tags` of `Envoy` bootstrap config
+/// let route_regex = requests_total.tag_extraction_regex("route");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`stats_tags`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/config/metrics/v3/stats.proto#config-metrics-v3-tagspecifier
+/// [`tag_extraction_regex`]: #method.tag_extraction_regex
+pub struct MetricFamily<'a, M: ?Sized> {
+    stats: &'a dyn Stats,
+    name: String,
+    tag_names: Vec<String>,
+    define: fn(&dyn Stats, &str) -> host::Result<Box<M>>,
+    metrics: RefCell<HashMap<Vec<String>, Rc<M>>>,
+}
+
+/// A family of [`Counter`]s.
+///
+/// [`Counter`]: trait.Counter.html
+pub type CounterFamily<'a> = MetricFamily<'a, dyn Counter>;
+
+/// A family of [`Gauge`]s.
+///
+/// [`Gauge`]: trait.Gauge.html
+pub type GaugeFamily<'a> = MetricFamily<'a, dyn Gauge>;
+
+/// A family of [`Histogram`]s.
+///
+/// [`Histogram`]: trait.Histogram.html
+pub type HistogramFamily<'a> = MetricFamily<'a, dyn Histogram>;
+
+impl<'a> MetricFamily<'a, dyn Counter> {
+    /// Creates a new family of counters with a given name and tag names.
+    pub fn new<T>(stats: &'a dyn Stats, name: &str, tag_names: &[T]) -> host::Result<Self>
+    where
+        T: AsRef<str>,
+    {
+        Self::with_definition(stats, name, tag_names, |stats, name| stats.counter(name))
+    }
+}
+
+impl<'a> MetricFamily<'a, dyn Gauge> {
+    /// Creates a new family of gauges with a given name and tag names.
+    pub fn new<T>(stats: &'a dyn Stats, name: &str, tag_names: &[T]) -> host::Result<Self>
+    where
+        T: AsRef<str>,
+    {
+        Self::with_definition(stats, name, tag_names, |stats, name| stats.gauge(name))
+    }
+}
+
+impl<'a> MetricFamily<'a, dyn Histogram> {
+    /// Creates a new family of histograms with a given name and tag names.
+    pub fn new<T>(stats: &'a dyn Stats, name: &str, tag_names: &[T]) -> host::Result<Self>
+    where
+        T: AsRef<str>,
+    {
+        Self::with_definition(stats, name, tag_names, |stats, name| stats.histogram(name))
+    }
+}
+
+impl<'a, M: ?Sized> MetricFamily<'a, M> {
+    fn with_definition<T>(
+        stats: &'a dyn Stats,
+        name: &str,
+        tag_names: &[T],
+        define: fn(&dyn Stats, &str) -> host::Result<Box<M>>,
+    ) -> host::Result<Self>
+    where
+        T: AsRef<str>,
+    {
+        if name.is_empty() {
+            return Err(format_err!("name of a metric family must not be empty"));
+        }
+        let mut names: Vec<String> = Vec::with_capacity(tag_names.len());
+        for tag_name in tag_names.iter().map(AsRef::as_ref) {
+            if tag_name.is_empty() || tag_name.contains('.') {
+                return Err(format_err!(
+                    "tag name {:?} of metric family {:?} must be non-empty and must not contain '.'",
+                    tag_name,
+                    name
+                ));
+            }
+            if names.iter().any(|other| other == tag_name) {
+                return Err(format_err!(
+                    "metric family {:?} has duplicate tag name {:?}",
+                    name,
+                    tag_name
+                ));
+            }
+            names.push(tag_name.to_owned());
+        }
+        Ok(MetricFamily {
+            stats,
+            name: name.to_owned(),
+            tag_names: names,
+            define,
+            metrics: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns name of the family.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns tag names of the family.
+    pub fn tag_names(&self) -> &[String] {
+        &self.tag_names
+    }
+
+    /// Returns a metric with given tag values.
+    ///
+    /// Tag values must be given in the same order as tag names of the family.
+    pub fn with_tag_values<V>(&self, tag_values: &[V]) -> host::Result<Rc<M>>
+    where
+        V: AsRef<str>,
+    {
+        let key = self.key(tag_values)?;
+        if let Some(metric) = self.metrics.borrow().get(&key) {
+            return Ok(Rc::clone(metric));
+        }
+        let metric: Rc<M> = Rc::from((self.define)(self.stats, &self.stat_name_of(&key))?);
+        self.metrics.borrow_mut().insert(key, Rc::clone(&metric));
+        Ok(metric)
+    }
+
+    /// Returns a metric with given tags.
+    ///
+    /// Tags can be given in any order.
+    pub fn with_tags<K, V>(&self, tags: &[(K, V)]) -> host::Result<Rc<M>>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        if tags.len() != self.tag_names.len() {
+            return Err(self.tag_count_mismatch(tags.len()));
+        }
+        let mut tag_values = Vec::with_capacity(tags.len());
+        for tag_name in &self.tag_names {
+            match tags.iter().find(|(name, _)| name.as_ref() == tag_name) {
+                Some((_, value)) => tag_values.push(value.as_ref()),
+                None => {
+                    return Err(format_err!(
+                        "value of tag {:?} of metric family {:?} is missing",
+                        tag_name,
+                        self.name
+                    ))
+                }
+            }
+        }
+        self.with_tag_values(&tag_values)
+    }
+
+    /// Returns `Envoy` stat name of a metric with given tag values.
+    pub fn stat_name<V>(&self, tag_values: &[V]) -> host::Result<String>
+    where
+        V: AsRef<str>,
+    {
+        self.key(tag_values).map(|key| self.stat_name_of(&key))
+    }
+
+    /// Returns a regex that extracts a given tag from `Envoy` stat names of this family.
+    ///
+    /// The regex is meant to be used in [`stats_tags`] of `Envoy` bootstrap config.
+    ///
+    /// Returns `None` if the family has no such tag.
+    ///
+    /// [`stats_tags`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/config/metrics/v3/stats.proto#config-metrics-v3-tagspecifier
+    pub fn tag_extraction_regex(&self, tag_name: &str) -> Option<String> {
+        if !self.tag_names.iter().any(|name| name == tag_name) {
+            return None;
+        }
+        // the first capture group is removed from the stat name,
+        // the second one becomes the tag value
+        Some(format!(
+            r"(?:^|\.){}(?:\.[^.]+\.[^.]*)*?(\.{}\.([^.]*))",
+            escape_regex(&self.name),
+            escape_regex(tag_name),
+        ))
+    }
+
+    fn key<V>(&self, tag_values: &[V]) -> host::Result<Vec<String>>
+    where
+        V: AsRef<str>,
+    {
+        if tag_values.len() != self.tag_names.len() {
+            return Err(self.tag_count_mismatch(tag_values.len()));
+        }
+        Ok(tag_values
+            .iter()
+            .map(|value| sanitize(value.as_ref()))
+            .collect())
+    }
+
+    fn stat_name_of(&self, tag_values: &[String]) -> String {
+        let mut name = self.name.clone();
+        for (tag_name, tag_value) in self.tag_names.iter().zip(tag_values) {
+            name.push('.');
+            name.push_str(tag_name);
+            name.push('.');
+            name.push_str(tag_value);
+        }
+        name
+    }
+
+    fn tag_count_mismatch(&self, actual: usize) -> host::Error {
+        format_err!(
+            "metric family {:?} expects {} tag values, got {}",
+            self.name,
+            self.tag_names.len(),
+            actual
+        )
+    }
+}
+
+impl<'a, M: ?Sized> fmt::Debug for MetricFamily<'a, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MetricFamily")
+            .field("name", &self.name)
+            .field("tag_names", &self.tag_names)
+            .finish()
+    }
+}
+
+/// Replaces characters that have special meaning in `Envoy` stat names.
+fn sanitize(tag_value: &str) -> String {
+    tag_value.replace('.', "_")
+}
+
+/// Escapes regex meta characters in a given text.
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
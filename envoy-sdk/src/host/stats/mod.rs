@@ -19,6 +19,12 @@ use std::rc::Rc;
 
 use crate::host;
 
+pub use self::family::{CounterFamily, GaugeFamily, HistogramFamily, MetricFamily};
+#[cfg(feature = "derive")]
+pub use envoy_sdk_derive::Stats;
+
+mod family;
+
 /// An interface of the `Envoy` `Stats API`.
 ///
 /// # Examples
@@ -210,7 +216,7 @@ impl dyn Stats {
     }
 }
 
-impl<T: Counter> Counter for Rc<T> {
+impl<T: Counter + ?Sized> Counter for Rc<T> {
     /// Increments counter by a given offset.
     fn add(&self, offset: u64) -> host::Result<()> {
         self.deref().add(offset)
@@ -221,7 +227,7 @@ impl<T: Counter> Counter for Rc<T> {
     }
 }
 
-impl<T: Gauge> Gauge for Rc<T> {
+impl<T: Gauge + ?Sized> Gauge for Rc<T> {
     /// Increments gauge by a given offset.
     fn add(&self, offset: u64) -> host::Result<()> {
         self.deref().add(offset)
@@ -240,13 +246,27 @@ impl<T: Gauge> Gauge for Rc<T> {
     }
 }
 
-impl<T: Histogram> Histogram for Rc<T> {
+impl<T: Histogram + ?Sized> Histogram for Rc<T> {
     /// Records a given value.
     fn record(&self, value: u64) -> host::Result<()> {
         self.deref().record(value)
     }
 }
 
+impl<T: Histogram + ?Sized> Histogram for Box<T> {
+    /// Records a given value.
+    fn record(&self, value: u64) -> host::Result<()> {
+        self.deref().record(value)
+    }
+}
+
+impl<T: Histogram + ?Sized> Histogram for &T {
+    /// Records a given value.
+    fn record(&self, value: u64) -> host::Result<()> {
+        (**self).record(value)
+    }
+}
+
 mod impls {
     use std::cmp;
 
@@ -0,0 +1,127 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Caching `Stream Info`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::{FilterStateOptions, StreamInfo};
+use crate::host::{self, ByteString};
+
+/// [`StreamInfo`] that memoizes values of stream properties.
+///
+/// Every property is fetched from the wrapped [`StreamInfo`] at most once,
+/// which saves a call to the host on subsequent reads. A cached value is still
+/// returned as a copy, so reads are not allocation-free.
+///
+/// Since values of stream properties change while the stream is being processed,
+/// `CachedStreamInfo` is meant to be short-lived, e.g. it should be created at
+/// the beginning of [`on_log`] and dropped at the end of it.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::StreamInfo;
+/// use envoy::host::stream_info::CachedStreamInfo;
+///
+/// let stream_info = CachedStreamInfo::new(StreamInfo::default());
+///
+/// // warm up the cache with all properties the logger needs
+/// stream_info.prefetch(&[
+///     &["request", "method"],
+///     &["request", "path"],
+///     &["response", "code"],
+/// ])?;
+///
+/// let stream_info: &dyn StreamInfo = &stream_info;
+///
+/// let method = stream_info.request().method()?; // served from cache
+/// let status_code = stream_info.response().status_code()?; // served from cache
+/// let request_id = stream_info.request().id()?; // fetched from the host and cached
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`StreamInfo`]: trait.StreamInfo.html
+/// [`on_log`]: ../../extension/access_logger/trait.AccessLogger.html#method.on_log
+pub struct CachedStreamInfo<'a> {
+    inner: &'a dyn StreamInfo,
+    cache: RefCell<HashMap<Vec<String>, Option<ByteString>>>,
+}
+
+impl<'a> CachedStreamInfo<'a> {
+    /// Wraps a given [`StreamInfo`].
+    ///
+    /// [`StreamInfo`]: trait.StreamInfo.html
+    pub fn new(inner: &'a dyn StreamInfo) -> Self {
+        CachedStreamInfo {
+            inner,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Warms up the cache with values of given properties.
+    ///
+    /// This is not a bulk fetch: `Envoy` ABI has no call for reading multiple
+    /// properties at once, so every property that hasn't been cached yet
+    /// still costs a separate call to the host. The only benefit is that
+    /// all the calls are made up front, e.g. before the values get used in
+    /// a hot loop.
+    pub fn prefetch(&self, paths: &[&[&str]]) -> host::Result<()> {
+        for path in paths {
+            self.stream_property(path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the value of a given property has been cached.
+    pub fn is_cached(&self, path: &[&str]) -> bool {
+        self.cache.borrow().contains_key(&Self::key(path))
+    }
+
+    /// Discards all cached values.
+    pub fn clear(&self) {
+        self.cache.borrow_mut().clear()
+    }
+
+    fn key(path: &[&str]) -> Vec<String> {
+        path.iter().map(|&segment| segment.to_owned()).collect()
+    }
+}
+
+impl<'a> StreamInfo for CachedStreamInfo<'a> {
+    fn stream_property(&self, path: &[&str]) -> host::Result<Option<ByteString>> {
+        let key = Self::key(path);
+        if let Some(value) = self.cache.borrow().get(&key) {
+            return Ok(value.clone());
+        }
+        let value = self.inner.stream_property(path)?;
+        self.cache.borrow_mut().insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn set_stream_property(&self, path: &[&str], value: &[u8]) -> host::Result<()> {
+        // the host might transform the value, so it has to be fetched again
+        self.cache.borrow_mut().remove(&Self::key(path));
+        self.inner.set_stream_property(path, value)
+    }
+
+    fn declare_stream_property(&self, name: &str, options: FilterStateOptions) -> host::Result<()> {
+        self.inner.declare_stream_property(name, options)
+    }
+}
@@ -17,20 +17,41 @@
 use core::convert::{TryFrom, TryInto};
 use std::time::{Duration, SystemTime};
 
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
 use self::property::{
-    Cluster, Connection, Destination, Listener, Plugin, Property, Request, Response, Route, Source,
-    Upstream,
+    Cluster, Connection, Destination, Listener, Plugin, Request, Response, Route, Source, Stream,
+    Upstream, Xds,
 };
 use crate::error::format_err;
 use crate::host::error::function;
-use crate::host::{self, ByteString};
+use crate::host::{self, ByteString, HeaderMap};
 
-pub use self::types::{ResponseFlags, TrafficDirection};
+pub use self::cache::CachedStreamInfo;
+pub use self::property::Property;
+pub use self::types::{
+    FilterStateOptions, LifeSpan, Metadata, MetadataBuilder, ResponseFlags, TrafficDirection,
+};
 
+mod cache;
 mod property;
 mod proxy_wasm;
 mod types;
 
+/// Encodings of property values in the `Proxy Wasm` format.
+///
+/// See [`Property`] for details.
+///
+/// [`Property`]: ../struct.Property.html
+pub mod encoding {
+    pub use super::proxy_wasm::types::{
+        Bool, ByteString, Bytes, Duration, Float64, Int64, ProtoMap, ProtoMessage, Timestamp,
+        UInt64,
+    };
+    pub use super::proxy_wasm::Value;
+}
+
 /// An interface of the `Envoy` `Stream Info API`.
 ///
 /// Basic usage of [`StreamInfo`]:
@@ -79,6 +100,30 @@ pub trait StreamInfo {
     /// * `path`  - property path as an array of path segments
     /// * `value` - an opaque blob of bytes
     fn set_stream_property(&self, path: &[&str], value: &[u8]) -> host::Result<()>;
+
+    /// Declares semantics of a property that will be saved in the enclosing context.
+    ///
+    /// Declaration only affects values saved after it, so it should be made early,
+    /// e.g. when extension is being configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`    - property name
+    /// * `options` - life span and mutability of the property
+    ///
+    /// Implementations that keep the default one reject every declaration
+    /// with an error, so properties saved through them keep the semantics
+    /// the host applies to undeclared properties.
+    fn declare_stream_property(
+        &self,
+        name: &str,
+        _options: FilterStateOptions,
+    ) -> host::Result<()> {
+        Err(format_err!(
+            "declaring stream property \"{}\" is not supported",
+            name
+        ))
+    }
 }
 
 impl dyn StreamInfo {
@@ -92,6 +137,23 @@ impl dyn StreamInfo {
 }
 
 impl<'a> dyn StreamInfo + 'a {
+    /// Returns the value of a given property.
+    pub fn property<T, W>(&'a self, prop: &Property<T, W>) -> host::Result<Option<T>>
+    where
+        T: TryFrom<proxy_wasm::Value<W>, Error = host::Error>,
+    {
+        StreamInfoAccessor { stream_info: self }.property(prop)
+    }
+
+    /// Saves the value of a given property.
+    pub fn set_property<T, W>(&'a self, prop: &Property<T, W>, value: T) -> host::Result<()>
+    where
+        proxy_wasm::Value<W>: TryFrom<T, Error = host::Error>,
+    {
+        let encoded: proxy_wasm::Value<W> = value.try_into()?;
+        self.set_stream_property(prop.path(), encoded.as_bytes())
+    }
+
     /// Provides access to `request` properties.
     pub fn request(&'a self) -> RequestInfo<'a> {
         RequestInfo {
@@ -161,6 +223,25 @@ impl<'a> dyn StreamInfo + 'a {
             stream: StreamInfoAccessor { stream_info: self },
         }
     }
+
+    /// Provides access to `xds` properties.
+    pub fn xds(&'a self) -> XdsInfo<'a> {
+        XdsInfo {
+            stream: StreamInfoAccessor { stream_info: self },
+        }
+    }
+
+    /// Returns dynamic metadata.
+    pub fn metadata(&'a self) -> host::Result<Option<Metadata>> {
+        self.property(Stream::METADATA)
+    }
+
+    /// Provides access to `filter state` shared with other filters.
+    pub fn filter_state(&'a self) -> FilterStateInfo<'a> {
+        FilterStateInfo {
+            stream: StreamInfoAccessor { stream_info: self },
+        }
+    }
 }
 
 /// Provides access to properties of a stream.
@@ -205,6 +286,11 @@ impl<'a> RequestInfo<'a> {
         self.stream.property(&Request::header(name.as_ref()))
     }
 
+    /// Returns all request headers.
+    pub fn headers(&self) -> host::Result<Option<HeaderMap>> {
+        self.stream.property(Request::HEADERS)
+    }
+
     /// Returns request ID.
     pub fn id(&self) -> host::Result<Option<String>> {
         self.stream.property(Request::ID)
@@ -293,6 +379,16 @@ impl<'a> ResponseInfo<'a> {
         self.stream.property(&Response::trailer(name.as_ref()))
     }
 
+    /// Returns all response headers.
+    pub fn headers(&self) -> host::Result<Option<HeaderMap>> {
+        self.stream.property(Response::HEADERS)
+    }
+
+    /// Returns all response trailers.
+    pub fn trailers(&self) -> host::Result<Option<HeaderMap>> {
+        self.stream.property(Response::TRAILERS)
+    }
+
     /// Returns response HTTP status code.
     pub fn status_code(&self) -> host::Result<Option<u16>> {
         self.stream.property(Response::STATUS_CODE)
@@ -317,6 +413,11 @@ impl<'a> ResponseInfo<'a> {
     pub fn grpc_status(&self) -> host::Result<Option<i32>> {
         self.stream.property(Response::GRPC_STATUS)
     }
+
+    /// Returns internal response code details, e.g. `via_upstream`.
+    pub fn code_details(&self) -> host::Result<Option<String>> {
+        self.stream.property(Response::CODE_DETAILS)
+    }
 }
 
 /// Provides access to `connection` properties.
@@ -345,6 +446,11 @@ impl<'a> ConnectionInfo<'a> {
         self.stream.property(Connection::REQUESTED_SERVER_NAME)
     }
 
+    /// Returns internal termination details of the connection.
+    pub fn termination_details(&self) -> host::Result<Option<String>> {
+        self.stream.property(Connection::TERMINATION_DETAILS)
+    }
+
     /// Provides access to `TLS` properties of the downstream connection.
     pub fn tls(&'a self) -> DownstreamConnectionTlsInfo<'a> {
         DownstreamConnectionTlsInfo {
@@ -390,6 +496,12 @@ impl<'a> DownstreamConnectionTlsInfo<'a> {
     pub fn dns_san_peer_certificate(&self) -> host::Result<Option<String>> {
         self.stream.property(Connection::DNS_SAN_PEER_CERTIFICATE)
     }
+
+    /// Returns hex-encoded SHA256 digest of the peer certificate in the downstream TLS connection.
+    pub fn sha256_peer_certificate_digest(&self) -> host::Result<Option<String>> {
+        self.stream
+            .property(Connection::SHA256_PEER_CERTIFICATE_DIGEST)
+    }
 }
 
 /// Provides access to `upstream` properties.
@@ -423,6 +535,11 @@ impl<'a> UpstreamInfo<'a> {
         self.stream.property(Upstream::TRANSPORT_FAILURE_REASON)
     }
 
+    /// Returns the count of upstream request attempts, including retries.
+    pub fn request_attempt_count(&self) -> host::Result<Option<u32>> {
+        self.stream.property(Upstream::REQUEST_ATTEMPT_COUNT)
+    }
+
     /// Provides access to `TLS` properties of the upstream connection.
     pub fn tls(&'a self) -> UpstreamConnectionTlsInfo<'a> {
         UpstreamConnectionTlsInfo {
@@ -468,6 +585,12 @@ impl<'a> UpstreamConnectionTlsInfo<'a> {
     pub fn dns_san_peer_certificate(&self) -> host::Result<Option<String>> {
         self.stream.property(Upstream::DNS_SAN_PEER_CERTIFICATE)
     }
+
+    /// Returns hex-encoded SHA256 digest of the peer certificate in the upstream TLS connection.
+    pub fn sha256_peer_certificate_digest(&self) -> host::Result<Option<String>> {
+        self.stream
+            .property(Upstream::SHA256_PEER_CERTIFICATE_DIGEST)
+    }
 }
 
 /// Provides access to `source` properties.
@@ -562,10 +685,123 @@ impl<'a> PluginInfo<'a> {
     }
 }
 
+/// Provides access to `xds` properties.
+pub struct XdsInfo<'a> {
+    stream: StreamInfoAccessor<'a>,
+}
+
+impl<'a> XdsInfo<'a> {
+    /// Returns ID of the local node.
+    pub fn node_id(&self) -> host::Result<Option<String>> {
+        self.stream.property(Xds::NODE_ID)
+    }
+
+    /// Returns metadata of the upstream cluster.
+    pub fn cluster_metadata(&self) -> host::Result<Option<Metadata>> {
+        self.stream.property(Xds::CLUSTER_METADATA)
+    }
+
+    /// Returns metadata of the route.
+    pub fn route_metadata(&self) -> host::Result<Option<Metadata>> {
+        self.stream.property(Xds::ROUTE_METADATA)
+    }
+
+    /// Returns metadata of the upstream host.
+    pub fn upstream_host_metadata(&self) -> host::Result<Option<Metadata>> {
+        self.stream.property(Xds::UPSTREAM_HOST_METADATA)
+    }
+
+    /// Returns metadata of the listener.
+    pub fn listener_metadata(&self) -> host::Result<Option<Metadata>> {
+        self.stream.property(Xds::LISTENER_METADATA)
+    }
+}
+
+/// Provides access to `filter state` shared with other filters on the same
+/// HTTP stream or TCP connection.
+///
+/// Values are encoded as JSON, which makes them readable by filters
+/// written in other languages and by `Envoy` `Access Loggers`.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::StreamInfo;
+/// use envoy::host::stream_info::{FilterStateOptions, LifeSpan};
+///
+/// let stream_info = StreamInfo::default();
+///
+/// // e.g., at configuration time
+/// stream_info.filter_state().declare(
+///     "my_extension.tenant",
+///     FilterStateOptions::new()
+///         .life_span(LifeSpan::DownstreamRequest)
+///         .read_only(true),
+/// )?;
+///
+/// // e.g., in one filter
+/// stream_info.filter_state().put("my_extension.tenant", &"tenant-a")?;
+///
+/// // e.g., in another filter
+/// let tenant: Option<String> = stream_info.filter_state().get("my_extension.tenant")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FilterStateInfo<'a> {
+    stream: StreamInfoAccessor<'a>,
+}
+
+impl<'a> FilterStateInfo<'a> {
+    /// Declares life span and mutability of a filter state entry.
+    ///
+    /// Must be called before the entry is saved for the first time.
+    pub fn declare(&self, key: &str, options: FilterStateOptions) -> host::Result<()> {
+        self.stream
+            .stream_info
+            .declare_stream_property(key, options)
+    }
+
+    /// Saves a value under a given key.
+    pub fn put<T>(&self, key: &str, value: &T) -> host::Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let encoded = serde_json::to_vec(value)?;
+        self.stream
+            .stream_info
+            .set_stream_property(&[key], &encoded)
+    }
+
+    /// Returns a value saved under a given key.
+    pub fn get<T>(&self, key: &str) -> host::Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        if let Some(bytes) = self.stream.stream_info.stream_property(&[key])? {
+            serde_json::from_slice::<T>(bytes.as_bytes())
+                .map(Option::from)
+                .map_err(|err| {
+                    function("env", "proxy_get_property")
+                        .into_parse_error(format_err!(
+                            "value of filter state \"{}\" is not valid: {}",
+                            key,
+                            err
+                        ))
+                        .into()
+                })
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 mod impls {
     use crate::abi::proxy_wasm::hostcalls;
 
-    use super::StreamInfo;
+    use super::{FilterStateOptions, StreamInfo};
     use crate::host::{self, ByteString};
 
     pub(super) struct Host;
@@ -578,5 +814,14 @@ mod impls {
         fn set_stream_property(&self, path: &[&str], value: &[u8]) -> host::Result<()> {
             hostcalls::set_property(path, value)
         }
+
+        fn declare_stream_property(
+            &self,
+            name: &str,
+            options: FilterStateOptions,
+        ) -> host::Result<()> {
+            // values are always declared as `WasmType::String`
+            hostcalls::declare_property(name, options.read_only, 1, options.life_span as u64)
+        }
     }
 }
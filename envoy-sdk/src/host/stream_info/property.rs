@@ -18,8 +18,8 @@ use std::marker::PhantomData;
 use std::time::{Duration, SystemTime};
 
 use super::proxy_wasm;
-use super::types::{ResponseFlags, TrafficDirection};
-use crate::host::ByteString;
+use super::types::{Metadata, ResponseFlags, TrafficDirection};
+use crate::host::{ByteString, HeaderMap};
 
 /// Represents a property path.
 struct Path<'a> {
@@ -50,13 +50,66 @@ impl<'a> PathKind<'a> {
 
 /// Represents an individual property of a stream, e.g.
 /// request id, response status code, upstream address, etc.
-pub(super) struct Property<'a, T, W> {
+///
+/// `T` is the type of the property value and `W` is the [`encoding`]
+/// of that value in the `Proxy Wasm` format.
+///
+/// Extensions can declare properties of their own, e.g. to exchange typed values
+/// with other filters or to access vendor-specific attributes of `Envoy`.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::StreamInfo;
+/// use envoy::host::stream_info::{encoding, Property};
+///
+/// const IS_CANARY: &Property<bool, encoding::Bool> = &Property::new(&["my_extension.canary"]);
+///
+/// let stream_info = StreamInfo::default();
+///
+/// stream_info.set_property(IS_CANARY, true)?;
+///
+/// let is_canary = stream_info.property(IS_CANARY)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`encoding`]: encoding/index.html
+pub struct Property<'a, T, W> {
     path: Path<'a>,
     _type: PhantomData<T>,
     _proxy_wasm_type: PhantomData<W>,
 }
 
+impl<T, W> Property<'static, T, W> {
+    /// Returns a property with a given static path.
+    pub const fn new(path: &'static [&'static str]) -> Self {
+        Property {
+            path: Path {
+                inner: PathKind::Static(path),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        }
+    }
+}
+
 impl<'a, T, W> Property<'a, T, W> {
+    /// Returns a property with a given path.
+    pub fn with_path(path: Vec<&'a str>) -> Self {
+        Property {
+            path: Path {
+                inner: PathKind::Custom(path),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        }
+    }
+
+    /// Returns property path as an array of path segments.
     pub fn path(&'a self) -> &'a [&'a str] {
         self.path.as_ref()
     }
@@ -77,6 +130,16 @@ impl Request {
         }
     }
 
+    /// All request headers.
+    pub const HEADERS: &'static Property<'static, HeaderMap, proxy_wasm::types::ProtoMap> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["request", "headers"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
+
     /// Request ID.
     pub const ID: &'static Property<'static, String, proxy_wasm::types::ByteString> = &Property {
         path: Path {
@@ -229,6 +292,26 @@ impl Response {
         }
     }
 
+    /// All response headers.
+    pub const HEADERS: &'static Property<'static, HeaderMap, proxy_wasm::types::ProtoMap> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["response", "headers"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
+
+    /// All response trailers.
+    pub const TRAILERS: &'static Property<'static, HeaderMap, proxy_wasm::types::ProtoMap> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["response", "trailers"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
+
     /// Response HTTP status code.
     pub const STATUS_CODE: &'static Property<'static, u16, proxy_wasm::types::Int64> = &Property {
         path: Path {
@@ -266,6 +349,16 @@ impl Response {
             _proxy_wasm_type: PhantomData,
         };
 
+    /// Internal response code details.
+    pub const CODE_DETAILS: &'static Property<'static, String, proxy_wasm::types::ByteString> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["response", "code_details"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
+
     /// Response gRPC status code.
     pub const GRPC_STATUS: &'static Property<'static, i32, proxy_wasm::types::Int64> = &Property {
         path: Path {
@@ -298,6 +391,19 @@ impl Connection {
         _proxy_wasm_type: PhantomData,
     };
 
+    /// Internal termination details of the connection.
+    pub const TERMINATION_DETAILS: &'static Property<
+        'static,
+        String,
+        proxy_wasm::types::ByteString,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["connection", "termination_details"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
+
     /// Requested server name in the downstream TLS connection.
     pub const REQUESTED_SERVER_NAME: &'static Property<
         'static,
@@ -398,6 +504,19 @@ impl Connection {
         _type: PhantomData,
         _proxy_wasm_type: PhantomData,
     };
+
+    /// SHA256 digest of the peer certificate in the downstream TLS connection.
+    pub const SHA256_PEER_CERTIFICATE_DIGEST: &'static Property<
+        'static,
+        String,
+        proxy_wasm::types::ByteString,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["connection", "sha256_peer_certificate_digest"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
 }
 
 /// Enumerates `upstream` properties.
@@ -533,6 +652,29 @@ impl Upstream {
         _type: PhantomData,
         _proxy_wasm_type: PhantomData,
     };
+
+    /// SHA256 digest of the peer certificate in the upstream TLS connection.
+    pub const SHA256_PEER_CERTIFICATE_DIGEST: &'static Property<
+        'static,
+        String,
+        proxy_wasm::types::ByteString,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["upstream", "sha256_peer_certificate_digest"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
+
+    /// The count of upstream request attempts, including retries.
+    pub const REQUEST_ATTEMPT_COUNT: &'static Property<'static, u32, proxy_wasm::types::UInt64> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["upstream", "request_attempt_count"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
 }
 
 /// Enumerates `source` properties.
@@ -662,3 +804,85 @@ impl Route {
         _proxy_wasm_type: PhantomData,
     };
 }
+
+/// Enumerates `xds` properties.
+pub(super) struct Xds {}
+
+impl Xds {
+    /// ID of the local node.
+    pub const NODE_ID: &'static Property<'static, String, proxy_wasm::types::ByteString> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["xds", "node", "id"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
+
+    /// Metadata of the upstream cluster.
+    pub const CLUSTER_METADATA: &'static Property<
+        'static,
+        Metadata,
+        proxy_wasm::types::ProtoMessage,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["xds", "cluster_metadata"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
+
+    /// Metadata of the route.
+    pub const ROUTE_METADATA: &'static Property<
+        'static,
+        Metadata,
+        proxy_wasm::types::ProtoMessage,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["xds", "route_metadata"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
+
+    /// Metadata of the upstream host.
+    pub const UPSTREAM_HOST_METADATA: &'static Property<
+        'static,
+        Metadata,
+        proxy_wasm::types::ProtoMessage,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["xds", "upstream_host_metadata"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
+
+    /// Metadata of the listener.
+    pub const LISTENER_METADATA: &'static Property<
+        'static,
+        Metadata,
+        proxy_wasm::types::ProtoMessage,
+    > = &Property {
+        path: Path {
+            inner: PathKind::Static(&["xds", "listener_metadata"]),
+        },
+        _type: PhantomData,
+        _proxy_wasm_type: PhantomData,
+    };
+}
+
+/// Enumerates properties of a stream as a whole.
+pub(super) struct Stream {}
+
+impl Stream {
+    /// Dynamic metadata.
+    pub const METADATA: &'static Property<'static, Metadata, proxy_wasm::types::ProtoMessage> =
+        &Property {
+            path: Path {
+                inner: PathKind::Static(&["metadata"]),
+            },
+            _type: PhantomData,
+            _proxy_wasm_type: PhantomData,
+        };
+}
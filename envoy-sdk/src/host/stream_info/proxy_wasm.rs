@@ -16,10 +16,13 @@
 
 use std::convert::{TryFrom, TryInto};
 use std::marker::PhantomData;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::types::{ResponseFlags, TrafficDirection};
-use crate::host;
+use serde_json::{Map as JsonMap, Number, Value as JsonValue};
+
+use super::types::{Metadata, ResponseFlags, TrafficDirection};
+use crate::error::format_err;
+use crate::host::{self, HeaderMap};
 
 use self::types::*;
 
@@ -27,36 +30,53 @@ pub(super) mod types {
     /// A string that is not guaranteed to be UTF-8 encoded.
     pub struct ByteString;
     /// Opaque blob of bytes.
-    pub struct _Bytes;
+    pub struct Bytes;
     /// Little endian encoded i64 value.
     pub struct Int64;
     /// Little endian encoded u64 value.
     pub struct UInt64;
-    pub struct _Float64;
+    /// Little endian encoded f64 value.
+    pub struct Float64;
     /// 1 byte.
     pub struct Bool;
     /// Nanos represented by (i64) rather than (i64, i32).
     pub struct Duration;
     /// UNIX nanos represented by (i64) rather than (i64, i32).
     pub struct Timestamp;
-    pub struct _ProtoMessage;
-    pub struct _ProtoMap;
+    /// Protobuf message in the binary wire format.
+    pub struct ProtoMessage;
+    /// Map of strings in the same format as HTTP headers.
+    pub struct ProtoMap;
     pub struct _ProtoList;
 }
 
-/// Represents an encoded property value.
-pub(super) struct Value<T> {
+/// Represents a property value encoded according to `W`.
+///
+/// Custom value types can be supported by implementing `TryFrom<Value<W>>`
+/// (for reading) and `TryFrom<T> for Value<W>` (for writing).
+pub struct Value<W> {
     bytes: Vec<u8>,
-    _type: PhantomData<T>,
+    _type: PhantomData<W>,
 }
 
-impl<T> Value<T> {
+impl<W> Value<W> {
+    /// Wraps encoded bytes.
     pub fn new(bytes: Vec<u8>) -> Self {
         Value {
             bytes,
             _type: PhantomData,
         }
     }
+
+    /// Returns encoded bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Unwraps encoded bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
 }
 
 impl TryFrom<Value<ByteString>> for String {
@@ -75,6 +95,31 @@ impl TryFrom<Value<ByteString>> for host::ByteString {
     }
 }
 
+impl TryFrom<Value<Bytes>> for Vec<u8> {
+    type Error = host::Error;
+
+    fn try_from(value: Value<Bytes>) -> host::Result<Self> {
+        Ok(value.bytes)
+    }
+}
+
+impl TryFrom<Value<Bytes>> for host::ByteString {
+    type Error = host::Error;
+
+    fn try_from(value: Value<Bytes>) -> host::Result<Self> {
+        Ok(value.bytes.into())
+    }
+}
+
+impl TryFrom<Value<Float64>> for f64 {
+    type Error = host::Error;
+
+    fn try_from(value: Value<Float64>) -> host::Result<Self> {
+        let bytes: [u8; std::mem::size_of::<Self>()] = value.bytes.as_slice().try_into()?;
+        Ok(Self::from_le_bytes(bytes))
+    }
+}
+
 impl TryFrom<Value<Int64>> for i32 {
     type Error = host::Error;
 
@@ -139,6 +184,69 @@ impl TryFrom<Value<UInt64>> for u64 {
     }
 }
 
+impl TryFrom<Value<UInt64>> for u32 {
+    type Error = host::Error;
+
+    fn try_from(value: Value<UInt64>) -> host::Result<Self> {
+        let value: u64 = value.try_into()?;
+        Ok(value.try_into()?)
+    }
+}
+
+impl TryFrom<Value<ProtoMap>> for HeaderMap {
+    type Error = host::Error;
+
+    fn try_from(value: Value<ProtoMap>) -> host::Result<Self> {
+        let bytes = value.bytes.as_slice();
+        if bytes.is_empty() {
+            return Ok(HeaderMap::default());
+        }
+        let read_u32 = |offset: usize| -> host::Result<usize> {
+            let bytes: [u8; 4] = bytes
+                .get(offset..offset + 4)
+                .ok_or_else(|| format_err!("unexpected end of map"))?
+                .try_into()?;
+            Ok(u32::from_le_bytes(bytes) as usize)
+        };
+        let read_str = |offset: usize, size: usize| -> host::Result<&[u8]> {
+            bytes
+                .get(offset..offset + size)
+                .ok_or_else(|| format_err!("unexpected end of map"))
+        };
+        let count = read_u32(0)?;
+        let mut map = HeaderMap::with_capacity(count);
+        let mut offset = 4 + count * 8;
+        for n in 0..count {
+            let key_size = read_u32(4 + n * 8)?;
+            let value_size = read_u32(4 + n * 8 + 4)?;
+            let key = read_str(offset, key_size)?;
+            offset += key_size + 1;
+            let value = read_str(offset, value_size)?;
+            offset += value_size + 1;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+impl TryFrom<Value<ProtoMessage>> for Metadata {
+    type Error = host::Error;
+
+    /// Decodes `envoy.config.core.v3.Metadata` message.
+    fn try_from(value: Value<ProtoMessage>) -> host::Result<Self> {
+        let mut metadata = Metadata::new();
+        let mut message = protobuf::Reader::new(&value.bytes);
+        while let Some((field, data)) = message.next_field()? {
+            // map<string, google.protobuf.Struct> filter_metadata = 1;
+            if let (1, protobuf::Field::Bytes(entry)) = (field, data) {
+                let (filter, value) = protobuf::decode_map_entry(entry, protobuf::decode_struct)?;
+                metadata.insert(filter, value);
+            }
+        }
+        Ok(metadata)
+    }
+}
+
 impl TryFrom<Value<Int64>> for ResponseFlags {
     type Error = host::Error;
 
@@ -207,3 +315,289 @@ impl TryFrom<Value<Int64>> for TrafficDirection {
         })
     }
 }
+
+impl TryFrom<String> for Value<ByteString> {
+    type Error = host::Error;
+
+    fn try_from(value: String) -> host::Result<Self> {
+        Ok(Value::new(value.into_bytes()))
+    }
+}
+
+impl TryFrom<host::ByteString> for Value<ByteString> {
+    type Error = host::Error;
+
+    fn try_from(value: host::ByteString) -> host::Result<Self> {
+        Ok(Value::new(value.into_bytes()))
+    }
+}
+
+impl TryFrom<Vec<u8>> for Value<Bytes> {
+    type Error = host::Error;
+
+    fn try_from(value: Vec<u8>) -> host::Result<Self> {
+        Ok(Value::new(value))
+    }
+}
+
+impl TryFrom<host::ByteString> for Value<Bytes> {
+    type Error = host::Error;
+
+    fn try_from(value: host::ByteString) -> host::Result<Self> {
+        Ok(Value::new(value.into_bytes()))
+    }
+}
+
+impl TryFrom<i64> for Value<Int64> {
+    type Error = host::Error;
+
+    fn try_from(value: i64) -> host::Result<Self> {
+        Ok(Value::new(value.to_le_bytes().to_vec()))
+    }
+}
+
+impl TryFrom<i32> for Value<Int64> {
+    type Error = host::Error;
+
+    fn try_from(value: i32) -> host::Result<Self> {
+        i64::from(value).try_into()
+    }
+}
+
+impl TryFrom<u16> for Value<Int64> {
+    type Error = host::Error;
+
+    fn try_from(value: u16) -> host::Result<Self> {
+        i64::from(value).try_into()
+    }
+}
+
+impl TryFrom<u32> for Value<Int64> {
+    type Error = host::Error;
+
+    fn try_from(value: u32) -> host::Result<Self> {
+        i64::from(value).try_into()
+    }
+}
+
+impl TryFrom<u64> for Value<Int64> {
+    type Error = host::Error;
+
+    fn try_from(value: u64) -> host::Result<Self> {
+        i64::try_from(value)
+            .map_err(|_| format_err!("value {} is out of range of int64", value))?
+            .try_into()
+    }
+}
+
+impl TryFrom<u64> for Value<UInt64> {
+    type Error = host::Error;
+
+    fn try_from(value: u64) -> host::Result<Self> {
+        Ok(Value::new(value.to_le_bytes().to_vec()))
+    }
+}
+
+impl TryFrom<u32> for Value<UInt64> {
+    type Error = host::Error;
+
+    fn try_from(value: u32) -> host::Result<Self> {
+        u64::from(value).try_into()
+    }
+}
+
+impl TryFrom<f64> for Value<Float64> {
+    type Error = host::Error;
+
+    fn try_from(value: f64) -> host::Result<Self> {
+        Ok(Value::new(value.to_le_bytes().to_vec()))
+    }
+}
+
+impl TryFrom<bool> for Value<Bool> {
+    type Error = host::Error;
+
+    fn try_from(value: bool) -> host::Result<Self> {
+        Ok(Value::new(vec![value as u8]))
+    }
+}
+
+impl TryFrom<std::time::Duration> for Value<Duration> {
+    type Error = host::Error;
+
+    fn try_from(value: std::time::Duration) -> host::Result<Self> {
+        let nanos: i64 = value.as_nanos().try_into()?;
+        Ok(Value::new(nanos.to_le_bytes().to_vec()))
+    }
+}
+
+impl TryFrom<SystemTime> for Value<Timestamp> {
+    type Error = host::Error;
+
+    fn try_from(value: SystemTime) -> host::Result<Self> {
+        let nanos: i64 = match value.duration_since(UNIX_EPOCH) {
+            Ok(dur) => dur.as_nanos().try_into()?,
+            Err(err) => -i64::try_from(err.duration().as_nanos())?,
+        };
+        Ok(Value::new(nanos.to_le_bytes().to_vec()))
+    }
+}
+
+impl TryFrom<HeaderMap> for Value<ProtoMap> {
+    type Error = host::Error;
+
+    fn try_from(value: HeaderMap) -> host::Result<Self> {
+        let size = value.iter().fold(4, |size, (name, value)| {
+            size + name.len() + value.len() + 10
+        });
+        let mut bytes = Vec::with_capacity(size);
+        bytes.extend_from_slice(&u32::try_from(value.len())?.to_le_bytes());
+        for (name, value) in &value {
+            bytes.extend_from_slice(&u32::try_from(name.len())?.to_le_bytes());
+            bytes.extend_from_slice(&u32::try_from(value.len())?.to_le_bytes());
+        }
+        for (name, value) in &value {
+            bytes.extend_from_slice(name);
+            bytes.push(0);
+            bytes.extend_from_slice(value);
+            bytes.push(0);
+        }
+        Ok(Value::new(bytes))
+    }
+}
+
+/// Minimal decoder of `Protobuf` binary wire format.
+mod protobuf {
+    use super::*;
+
+    /// Represents a value of a message field.
+    pub enum Field<'a> {
+        Varint(u64),
+        Fixed64(u64),
+        Fixed32,
+        Bytes(&'a [u8]),
+    }
+
+    /// Reads fields of a message one by one.
+    pub struct Reader<'a> {
+        buf: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        pub fn new(buf: &'a [u8]) -> Self {
+            Reader { buf }
+        }
+
+        pub fn next_field(&mut self) -> host::Result<Option<(u64, Field<'a>)>> {
+            if self.buf.is_empty() {
+                return Ok(None);
+            }
+            let key = self.varint()?;
+            let field = match key & 0x7 {
+                0 => Field::Varint(self.varint()?),
+                1 => Field::Fixed64(u64::from_le_bytes(self.take(8)?.try_into()?)),
+                2 => {
+                    let len = self.varint()?.try_into()?;
+                    Field::Bytes(self.take(len)?)
+                }
+                5 => {
+                    self.take(4)?;
+                    Field::Fixed32
+                }
+                wire_type => return Err(format_err!("unsupported wire type: {}", wire_type)),
+            };
+            Ok(Some((key >> 3, field)))
+        }
+
+        fn varint(&mut self) -> host::Result<u64> {
+            let mut value: u64 = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.take(1)?[0];
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(format_err!("varint is too long"))
+        }
+
+        fn take(&mut self, len: usize) -> host::Result<&'a [u8]> {
+            if self.buf.len() < len {
+                return Err(format_err!("unexpected end of message"));
+            }
+            let (head, tail) = self.buf.split_at(len);
+            self.buf = tail;
+            Ok(head)
+        }
+    }
+
+    /// Decodes an entry of `map<string, V>`.
+    pub fn decode_map_entry<F>(buf: &[u8], decode_value: F) -> host::Result<(String, JsonValue)>
+    where
+        F: Fn(&[u8]) -> host::Result<JsonValue>,
+    {
+        let mut key = String::new();
+        let mut value = None;
+        let mut message = Reader::new(buf);
+        while let Some((field, data)) = message.next_field()? {
+            match (field, data) {
+                (1, Field::Bytes(bytes)) => key = String::from_utf8(bytes.to_vec())?,
+                (2, Field::Bytes(bytes)) => value = Some(decode_value(bytes)?),
+                _ => {}
+            }
+        }
+        let value = match value {
+            Some(value) => value,
+            // an absent value means a default (empty) message
+            None => decode_value(&[])?,
+        };
+        Ok((key, value))
+    }
+
+    /// Decodes `google.protobuf.Struct` message.
+    pub fn decode_struct(buf: &[u8]) -> host::Result<JsonValue> {
+        let mut fields = JsonMap::new();
+        let mut message = Reader::new(buf);
+        while let Some((field, data)) = message.next_field()? {
+            // map<string, Value> fields = 1;
+            if let (1, Field::Bytes(entry)) = (field, data) {
+                let (key, value) = decode_map_entry(entry, decode_value)?;
+                fields.insert(key, value);
+            }
+        }
+        Ok(JsonValue::Object(fields))
+    }
+
+    /// Decodes `google.protobuf.Value` message.
+    pub fn decode_value(buf: &[u8]) -> host::Result<JsonValue> {
+        let mut value = JsonValue::Null;
+        let mut message = Reader::new(buf);
+        while let Some((field, data)) = message.next_field()? {
+            value = match (field, data) {
+                (1, Field::Varint(_)) => JsonValue::Null,
+                (2, Field::Fixed64(bits)) => Number::from_f64(f64::from_bits(bits))
+                    .map(JsonValue::Number)
+                    .unwrap_or(JsonValue::Null),
+                (3, Field::Bytes(bytes)) => JsonValue::String(String::from_utf8(bytes.to_vec())?),
+                (4, Field::Varint(flag)) => JsonValue::Bool(flag != 0),
+                (5, Field::Bytes(bytes)) => decode_struct(bytes)?,
+                (6, Field::Bytes(bytes)) => decode_list(bytes)?,
+                _ => continue,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Decodes `google.protobuf.ListValue` message.
+    fn decode_list(buf: &[u8]) -> host::Result<JsonValue> {
+        let mut values = Vec::new();
+        let mut message = Reader::new(buf);
+        while let Some((field, data)) = message.next_field()? {
+            // repeated Value values = 1;
+            if let (1, Field::Bytes(bytes)) = (field, data) {
+                values.push(decode_value(bytes)?);
+            }
+        }
+        Ok(JsonValue::Array(values))
+    }
+}
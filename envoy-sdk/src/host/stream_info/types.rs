@@ -14,9 +14,11 @@
 
 //! Auxiliary `Stream Info` types.
 
+use std::collections::BTreeMap;
 use std::fmt;
 
 use bitflags::bitflags;
+use serde_json::Value;
 
 bitflags! {
     /// Response flags.
@@ -168,3 +170,154 @@ impl Default for TrafficDirection {
         TrafficDirection::UNSPECIFIED
     }
 }
+
+/// Identifies how long a piece of filter state should be kept around.
+#[repr(u32)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[non_exhaustive]
+pub enum LifeSpan {
+    /// Filter state is kept as long as the filter chain that has created it.
+    #[default]
+    FilterChain = 0,
+    /// Filter state is kept for the entire downstream request, including internal redirects.
+    DownstreamRequest = 1,
+    /// Filter state is kept as long as the downstream connection.
+    DownstreamConnection = 2,
+}
+
+/// Semantics of a filter state entry.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::host::stream_info::{FilterStateOptions, LifeSpan};
+///
+/// let options = FilterStateOptions::new()
+///     .life_span(LifeSpan::DownstreamRequest)
+///     .read_only(true);
+///
+/// assert_eq!(options.life_span, LifeSpan::DownstreamRequest);
+/// assert!(options.read_only);
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[non_exhaustive]
+pub struct FilterStateOptions {
+    /// How long the filter state entry will be kept around.
+    pub life_span: LifeSpan,
+    /// Whether the filter state entry can only be set once.
+    pub read_only: bool,
+}
+
+impl FilterStateOptions {
+    /// Returns options of a mutable filter state entry that lives as long as the filter chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long the filter state entry should be kept around.
+    pub fn life_span(mut self, life_span: LifeSpan) -> Self {
+        self.life_span = life_span;
+        self
+    }
+
+    /// Sets whether the filter state entry can only be set once.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+}
+
+/// Represents `Envoy` metadata, i.e. a collection of filter-specific values
+/// keyed by a filter name, e.g. `envoy.lb`.
+///
+/// # Examples
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use envoy::host::stream_info::Metadata;
+/// use serde_json::json;
+///
+/// let metadata = Metadata::builder()
+///     .filter_metadata("envoy.lb", json!({"canary": true}))
+///     .build();
+///
+/// assert_eq!(metadata.value("envoy.lb", "canary"), Some(&json!(true)));
+/// assert_eq!(metadata.value("envoy.lb", "version"), None);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metadata {
+    filter_metadata: BTreeMap<String, Value>,
+}
+
+impl Metadata {
+    /// Returns a builder for `Metadata`.
+    pub fn builder() -> MetadataBuilder {
+        MetadataBuilder::new()
+    }
+
+    /// Returns empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if there is no metadata.
+    pub fn is_empty(&self) -> bool {
+        self.filter_metadata.is_empty()
+    }
+
+    /// Returns metadata of a given filter.
+    pub fn filter_metadata(&self, filter: &str) -> Option<&Value> {
+        self.filter_metadata.get(filter)
+    }
+
+    /// Returns a value under a given key in metadata of a given filter.
+    pub fn value(&self, filter: &str, key: &str) -> Option<&Value> {
+        self.filter_metadata(filter)
+            .and_then(|metadata| metadata.get(key))
+    }
+
+    /// Sets metadata of a given filter.
+    pub fn insert<K>(&mut self, filter: K, metadata: Value) -> Option<Value>
+    where
+        K: Into<String>,
+    {
+        self.filter_metadata.insert(filter.into(), metadata)
+    }
+
+    /// Returns an iterator over metadata of all filters.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.filter_metadata
+            .iter()
+            .map(|(filter, metadata)| (filter.as_str(), metadata))
+    }
+}
+
+/// Builder for [`Metadata`].
+///
+/// [`Metadata`]: struct.Metadata.html
+#[derive(Debug, Default)]
+pub struct MetadataBuilder {
+    metadata: Metadata,
+}
+
+impl MetadataBuilder {
+    /// Returns a builder for empty `Metadata`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets metadata of a given filter.
+    pub fn filter_metadata<K>(mut self, filter: K, metadata: Value) -> Self
+    where
+        K: Into<String>,
+    {
+        self.metadata.insert(filter, metadata);
+        self
+    }
+
+    /// Returns `Metadata` with the filter metadata set so far.
+    pub fn build(self) -> Metadata {
+        self.metadata
+    }
+}
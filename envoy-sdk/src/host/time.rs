@@ -14,9 +14,11 @@
 
 //! `Envoy` `Time API`.
 
-use std::time::SystemTime;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
 
 use crate::host;
+use crate::host::stats::Histogram;
 
 /// An interface of the `Envoy` `System Clock`.
 ///
@@ -76,6 +78,174 @@ impl dyn Clock {
     }
 }
 
+/// Unit of time a [`Timer`] records durations in.
+///
+/// [`Timer`]: struct.Timer.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimeUnit {
+    /// Durations are recorded in milliseconds.
+    Milliseconds,
+    /// Durations are recorded in microseconds.
+    Microseconds,
+}
+
+impl TimeUnit {
+    /// Converts a given duration into a number of units.
+    ///
+    /// Durations that don't fit into `u64` are saturated.
+    pub fn convert(self, duration: Duration) -> u64 {
+        let value = match self {
+            TimeUnit::Milliseconds => duration.as_millis(),
+            TimeUnit::Microseconds => duration.as_micros(),
+        };
+        u64::try_from(value).unwrap_or(u64::MAX)
+    }
+}
+
+/// Measures time elapsed since its start according to a [`Clock`]
+/// and records it into a [`Histogram`].
+///
+/// The elapsed time gets recorded either when the timer is explicitly [`stopped`]
+/// or when it is dropped, whichever comes first. Use [`cancel`] to discard
+/// a timer without recording anything.
+///
+/// A timer can be kept inside an extension to measure time spent across
+/// multiple callbacks, e.g. time spent waiting for a response from [`HttpClient`].
+/// In that case, use a shared [`Histogram`], e.g. `Rc<dyn Histogram>`.
+///
+/// # Examples
+///
+/// #### Measuring time of a block of code:
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// # use envoy::host::Result;
+/// # fn action() -> Result<()> {
+/// use envoy::host::{Clock, Stats};
+/// use envoy::host::time::{TimeUnit, Timer};
+///
+/// let stats = Stats::default();
+/// let processing_time_millis = stats.histogram("processing_time_millis")?;
+///
+/// let timer = Timer::start(Clock::default(), &*processing_time_millis, TimeUnit::Milliseconds)?;
+///
+/// # stringify! {
+/// ... do some work ...
+/// # };
+///
+/// let elapsed = timer.stop()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// #### Measuring time spent waiting for a response from [`HttpClient`]:
+///
+/// ```
+/// # use envoy_sdk as envoy;
+/// use std::rc::Rc;
+///
+/// use envoy::host::{Clock, Result};
+/// use envoy::host::stats::Histogram;
+/// use envoy::host::time::{TimeUnit, Timer};
+///
+/// struct MyHttpFilter<'a> {
+///     clock: &'a dyn Clock,
+///     upstream_time_micros: Rc<dyn Histogram>,
+///     upstream_timer: Option<Timer<'a, Rc<dyn Histogram>>>,
+/// }
+///
+/// impl<'a> MyHttpFilter<'a> {
+///     fn on_request_dispatched(&mut self) -> Result<()> {
+///         self.upstream_timer = Some(Timer::start(
+///             self.clock,
+///             Rc::clone(&self.upstream_time_micros),
+///             TimeUnit::Microseconds,
+///         )?);
+///         Ok(())
+///     }
+///
+///     fn on_response_received(&mut self) -> Result<()> {
+///         if let Some(timer) = self.upstream_timer.take() {
+///             timer.stop()?;
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// [`Clock`]: trait.Clock.html
+/// [`Histogram`]: ../stats/trait.Histogram.html
+/// [`HttpClient`]: ../http/client/trait.HttpClient.html
+/// [`stopped`]: #method.stop
+/// [`cancel`]: #method.cancel
+pub struct Timer<'a, H: Histogram> {
+    clock: &'a dyn Clock,
+    histogram: H,
+    unit: TimeUnit,
+    started_at: SystemTime,
+    is_done: bool,
+}
+
+impl<'a, H: Histogram> Timer<'a, H> {
+    /// Starts a new timer that will record elapsed time into a given [`Histogram`].
+    ///
+    /// [`Histogram`]: ../stats/trait.Histogram.html
+    pub fn start(clock: &'a dyn Clock, histogram: H, unit: TimeUnit) -> host::Result<Self> {
+        let started_at = clock.now()?;
+        Ok(Timer {
+            clock,
+            histogram,
+            unit,
+            started_at,
+            is_done: false,
+        })
+    }
+
+    /// Returns time when the timer was started.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Returns time elapsed since the timer was started.
+    ///
+    /// If the clock went backwards, returns zero duration.
+    pub fn elapsed(&self) -> host::Result<Duration> {
+        let now = self.clock.now()?;
+        Ok(now.duration_since(self.started_at).unwrap_or_default())
+    }
+
+    /// Stops the timer and records elapsed time into the [`Histogram`].
+    ///
+    /// Returns the elapsed time.
+    ///
+    /// [`Histogram`]: ../stats/trait.Histogram.html
+    pub fn stop(mut self) -> host::Result<Duration> {
+        self.is_done = true;
+        self.record()
+    }
+
+    /// Stops the timer without recording anything.
+    pub fn cancel(mut self) {
+        self.is_done = true;
+    }
+
+    fn record(&self) -> host::Result<Duration> {
+        let elapsed = self.elapsed()?;
+        self.histogram.record(self.unit.convert(elapsed))?;
+        Ok(elapsed)
+    }
+}
+
+impl<'a, H: Histogram> Drop for Timer<'a, H> {
+    fn drop(&mut self) {
+        if !self.is_done {
+            // there is no way to report an error out of `drop`
+            let _ = self.record();
+        }
+    }
+}
+
 mod impls {
     use std::time::SystemTime;
 
@@ -39,6 +39,7 @@
 //! * [`HttpFilter`]
 //! * [`NetworkFilter`]
 //! * [`AccessLogger`]
+//! * [`Service`]
 //!
 //! ## Supported Envoy APIs
 //!
@@ -60,12 +61,14 @@
 //! ## How To
 //!
 //! * [How To make my extension configurable?][`HowToConfigure`]
+//! * [How To reconfigure my extension without affecting ongoing streams?][`HowToReconfigure`]
 //! * [How To share stats between filter instances?][`HowToShareStats`]
 //! * [How To use HttpClient?][`HowToUseHttpClient`]
 //!
 //! [`HttpFilter`]: extension/filter/http/index.html
 //! [`NetworkFilter`]: extension/filter/network/index.html
 //! [`AccessLogger`]: extension/access_logger/index.html
+//! [`Service`]: extension/service/index.html
 //!
 //! [`Clock`]: host/time/trait.Clock.html
 //! [`HttpClient`]: host/http/client/trait.HttpClient.html
@@ -80,6 +83,7 @@
 //! [`SampleAccessLogger`]: https://github.com/tetratelabs/envoy-wasm-rust-sdk/tree/master/examples/access-logger
 //!
 //! [`HowToConfigure`]: extension/factory/trait.ExtensionFactory.html#examples
+//! [`HowToReconfigure`]: extension/factory/trait.ReconfigurableFactory.html#examples
 //! [`HowToShareStats`]: extension/factory/trait.ExtensionFactory.html#examples
 //! [`HowToUseHttpClient`]: host/http/client/trait.HttpClient.html#examples
 
@@ -45,11 +45,7 @@ impl<'a> SampleAccessLogger<'a> {
         http_client: &'a dyn HttpClient,
         stats: &'a dyn Stats,
     ) -> Result<Self> {
-        let stats = SampleAccessLoggerStats::new(
-            stats.counter("examples.access_logger.requests_total")?,
-            stats.gauge("examples.access_logger.reports_active")?,
-            stats.counter("examples.access_logger.reports_total")?,
-        );
+        let stats = SampleAccessLoggerStats::new(stats)?;
         // Inject dependencies on Envoy host APIs
         Ok(SampleAccessLogger {
             config: SampleAccessLoggerConfig::default(),
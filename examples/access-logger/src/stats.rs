@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use envoy::host::stats::{Counter, Gauge};
+use envoy::host::stats::{Counter, Gauge, Stats};
 
 // Sample stats.
+#[derive(Stats)]
+#[stats(prefix = "examples.access_logger")]
 pub struct SampleAccessLoggerStats {
     requests_total: Box<dyn Counter>,
     reports_active: Box<dyn Gauge>,
@@ -22,18 +24,6 @@ pub struct SampleAccessLoggerStats {
 }
 
 impl SampleAccessLoggerStats {
-    pub fn new(
-        requests_total: Box<dyn Counter>,
-        reports_active: Box<dyn Gauge>,
-        reports_total: Box<dyn Counter>,
-    ) -> Self {
-        SampleAccessLoggerStats {
-            requests_total,
-            reports_active,
-            reports_total,
-        }
-    }
-
     pub fn requests_total(&self) -> &dyn Counter {
         &*self.requests_total
     }
@@ -0,0 +1,96 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use envoy::extension::filter::http::FilterHeadersStatus;
+use envoy::extension::{ExtensionFactory, InstanceId, Result};
+use envoy::host::Stats;
+
+use envoy_test::{
+    FakeClock, FakeHttpClient, FakeHttpClientResponse, FakeHttpStream, FakeStats, FakeStreamInfo,
+};
+
+use http_filter::SampleHttpFilterFactory;
+
+#[test]
+fn test_ping() -> Result<()> {
+    let clock = FakeClock::default();
+    let http_client = FakeHttpClient::default();
+    let stream_info = FakeStreamInfo::new();
+    let stats = FakeStats::default();
+    let mut factory = SampleHttpFilterFactory::new(&clock, &http_client, &stream_info, &stats)?;
+
+    let mut filter = factory.new_extension(InstanceId::from(1))?;
+    let stream = FakeHttpStream::new();
+    assert_eq!(
+        stream.send_request_headers(&mut filter, &[(":path", "/ping")], true)?,
+        FilterHeadersStatus::StopIteration
+    );
+    assert!(stream.upstream_request().is_none());
+
+    let local_reply = stream.local_reply().unwrap();
+    assert_eq!(local_reply.status_code, 200);
+    assert_eq!(
+        local_reply.headers.get("x-sample-response"),
+        Some(&"pong".into())
+    );
+    assert_eq!(local_reply.body, "Pong!\n");
+
+    stream.complete(&mut filter)?;
+    assert_eq!(
+        stats
+            .counter("examples.http_filter.requests_total")?
+            .value()?,
+        1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_secret() -> Result<()> {
+    let clock = FakeClock::default();
+    let http_client = FakeHttpClient::default();
+    let stream_info = FakeStreamInfo::new();
+    let stats = FakeStats::default();
+    let mut factory = SampleHttpFilterFactory::new(&clock, &http_client, &stream_info, &stats)?;
+
+    let mut filter = factory.new_extension(InstanceId::from(1))?;
+    let stream = FakeHttpStream::new();
+    stream.send_request_headers(&mut filter, &[(":path", "/secret")], true)?;
+    assert!(stream.is_request_paused());
+
+    // the request is on hold until authorization service responds
+    let pending_requests = http_client.drain_pending_requests();
+    assert_eq!(pending_requests.len(), 1);
+    assert_eq!(pending_requests[0].request.upstream, "mock_service");
+
+    let response = FakeHttpClientResponse::builder()
+        .header(":status", "200")
+        .build();
+    stream.deliver_http_call_response(&mut filter, pending_requests[0].handle, &response)?;
+    assert!(!stream.is_request_paused());
+    assert!(stream.upstream_request().is_some());
+
+    stream.send_response_headers(&mut filter, &[(":status", "200")], false)?;
+    stream.send_response_body(&mut filter, b"secret", true)?;
+    assert_eq!(stream.downstream_response().unwrap().body, "secret");
+
+    stream.complete(&mut filter)?;
+    assert_eq!(
+        stats.histogram_values("examples.http_filter.response_body_size_bytes"),
+        Some(vec![6])
+    );
+
+    Ok(())
+}